@@ -1,14 +1,18 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use rspotify::{
-    model::{AdditionalType, ArtistId, CurrentlyPlayingContext, PlayHistory, TimeLimits},
+    http::HttpError,
+    model::{AdditionalType, ArtistId, CurrentPlaybackContext, PlayHistory, TimeLimits},
     prelude::*,
-    scopes, AuthCodeSpotify, Config, Credentials, OAuth, Token,
+    scopes, AuthCodeSpotify, ClientError, Config, Credentials, OAuth, Token,
 };
-use std::{env, fs, path::PathBuf};
+use std::{env, fs, path::PathBuf, sync::Arc};
 
 use scrobblify_domain::{
-    bridge::spotify::SpotifyApi,
+    bridge::spotify::{SpotifyApi, SpotifyConnector},
+    config::SpotifyConfig,
+    db::TokenStore,
+    identity,
     models::{CurrentPlayingTrack, HistoryPlayedTrack, Tag},
 };
 
@@ -18,55 +22,36 @@ pub enum SpotifyError {
     Auth(#[from] rspotify::ClientError),
     #[error("failed to parse track response")]
     TrackResponse,
+    #[error("no token has been received")]
+    MissingToken,
+    #[error("failed to refresh the token, Spotify answered {status}: {body}")]
+    Refresh { status: u16, body: String },
 }
 
-#[derive(Clone, Debug)]
-struct SpotifyClientConfig {
-    client_id: String,
-    client_secret: String,
-    auth_callback_uri: String,
+/// A Spotify client acting on behalf of a single user. Its token is kept in a `TokenStore`
+/// and refreshed, then stored again, when it expires.
+#[derive(Clone)]
+pub struct SpotifyClient {
+    client: AuthCodeSpotify,
+    config: SpotifyConfig,
+    owner: Option<TokenOwner>,
 }
 
-impl SpotifyClientConfig {
-    pub fn new_from_env() -> Self {
-        let client_id = match env::var_os("SCROBBLIFY_SPOTIFY_CLIENT_ID") {
-            Some(v) => v.into_string().unwrap(),
-            None => panic!("SCROBBLIFY_SPOTIFY_CLIENT_ID is not set"),
-        };
-
-        let client_secret = match env::var_os("SCROBBLIFY_SPOTIFY_CLIENT_SECRET") {
-            Some(v) => v.into_string().unwrap(),
-            None => panic!("SCROBBLIFY_SPOTIFY_CLIENT_SECRET is not set"),
-        };
-
-        let auth_callback_uri = match env::var_os("SCROBBLIFY_SPOTIFY_AUTH_CALLBACK_URI") {
-            Some(v) => v.into_string().unwrap(),
-            None => panic!("SCROBBLIFY_SPOTIFY_AUTH_CALLBACK_URI is not set"),
-        };
-
-        Self {
-            client_id,
-            client_secret,
-            auth_callback_uri,
-        }
-    }
+/// The user a client acts for, and where their token is stored.
+#[derive(Clone)]
+struct TokenOwner {
+    user_id: i32,
+    store: Arc<dyn TokenStore>,
 }
 
-#[derive(Clone, Debug)]
-pub struct SpotifyClient(AuthCodeSpotify);
-
 impl SpotifyClient {
-    pub async fn new_from_env() -> Result<SpotifyClient> {
-        let client_config = SpotifyClientConfig::new_from_env();
-        Self::new(client_config).await
-    }
-
-    async fn new(client_config: SpotifyClientConfig) -> Result<SpotifyClient> {
+    pub async fn new(client_config: &SpotifyConfig) -> Result<SpotifyClient> {
         let creds = Credentials::new(
             client_config.client_id.as_str(),
             client_config.client_secret.as_str(),
         );
 
+        // every client gets its own random `state`, so callbacks can be checked against it
         let oauth = OAuth {
             scopes: scopes!(
                 "user-read-recently-played",
@@ -74,60 +59,147 @@ impl SpotifyClient {
                 "user-read-currently-playing"
             ),
             // URL must be the same of the one configured in Spotify app
-            redirect_uri: client_config.auth_callback_uri,
+            redirect_uri: client_config.auth_callback_uri.clone(),
             ..Default::default()
         };
 
+        // tokens are refreshed by `refresh_if_expired`, so that the new ones get stored
         let config = Config {
-            token_cached: true,
-            cache_path: get_or_create_cache_path(),
-            token_refreshing: true,
+            token_cached: false,
+            token_refreshing: false,
             ..Default::default()
         };
 
-        let client = SpotifyClient(AuthCodeSpotify::with_config(creds, oauth, config));
+        Ok(SpotifyClient {
+            client: AuthCodeSpotify::with_config(creds, oauth, config),
+            config: client_config.clone(),
+            owner: None,
+        })
+    }
 
-        if get_cache_path().exists() {
-            client.with_token().await
-        } else {
-            Ok(client)
+    /// Builds a new client with the same configuration acting for the given user,
+    /// authenticated with their stored token, if any.
+    pub async fn for_user(
+        &self,
+        user_id: i32,
+        store: Arc<dyn TokenStore>,
+    ) -> Result<SpotifyClient> {
+        let mut client = Self::new(&self.config).await?;
+
+        if let Some(token) = store.load_token(user_id).await? {
+            let token: Token = serde_json::from_str(&token)?;
+            *client.client.token.lock().await.unwrap() = Some(token);
         }
+        client.owner = Some(TokenOwner { user_id, store });
+
+        Ok(client)
     }
 
-    async fn with_token(&self) -> Result<Self> {
-        let token = load_token_from_cache()?;
-        *self.0.token.lock().await.unwrap() = Some(token.clone());
+    /// Reads the token cached on disk when Scrobblify had a single user, so it can be
+    /// moved to the database.
+    pub fn legacy_token() -> Result<Option<String>> {
+        let cache_path = get_legacy_cache_path()?;
+        if !cache_path.exists() {
+            return Ok(None);
+        }
+        Ok(fs::read_to_string(cache_path).ok())
+    }
 
-        Ok(self.clone())
+    async fn save_token(&self) -> Result<()> {
+        let owner = match &self.owner {
+            Some(owner) => owner,
+            None => return Ok(()),
+        };
+        let token = self
+            .client
+            .token
+            .lock()
+            .await
+            .unwrap()
+            .clone()
+            .ok_or(SpotifyError::MissingToken)?;
+
+        owner
+            .store
+            .save_token(owner.user_id, &serde_json::to_string(&token)?)
+            .await
+    }
+
+    /// Refreshes the access token when it's expired and stores the new one. A refresh token
+    /// rejected by Spotify, because the user revoked the access, gets forgotten: they need
+    /// to link the account again. Other failures, e.g. wrong app credentials, keep it.
+    async fn refresh_if_expired(&self) -> Result<()> {
+        let expired = match self.client.token.lock().await.unwrap().as_ref() {
+            Some(token) => token.is_expired(),
+            None => return Err(SpotifyError::MissingToken.into()),
+        };
+        if !expired {
+            return Ok(());
+        }
+
+        let response = match self.client.refresh_token().await {
+            Ok(()) => return self.save_token().await,
+            Err(ClientError::Http(http)) => match *http {
+                HttpError::StatusCode(response) => response,
+                http => return Err(SpotifyError::Auth(ClientError::Http(Box::new(http))).into()),
+            },
+            Err(err) => return Err(SpotifyError::Auth(err).into()),
+        };
+
+        let status = response.status().as_u16();
+        let body = response.text().await.unwrap_or_default();
+        if is_revoked(status, &body) {
+            *self.client.token.lock().await.unwrap() = None;
+            if let Some(owner) = &self.owner {
+                owner.store.delete_token(owner.user_id).await?;
+            }
+        } else {
+            tracing::warn!(msg = "failed to refresh the Spotify token", status, body);
+        }
+        Err(SpotifyError::Refresh { status, body }.into())
     }
 }
 
 #[async_trait::async_trait]
 impl SpotifyApi for SpotifyClient {
     // Auth
-    fn has_auth(&self) -> bool {
-        get_cache_path().exists()
+    async fn has_auth(&self) -> bool {
+        // an expired token is still good as long as it can be refreshed
+        match self.client.token.lock().await.unwrap().as_ref() {
+            Some(token) => !token.is_expired() || token.refresh_token.is_some(),
+            None => false,
+        }
+    }
+
+    fn get_auth_state(&self) -> &str {
+        &self.client.oauth.state
     }
 
     async fn get_auth_url(&self) -> Result<String> {
-        let auth_url = self.0.get_authorize_url(true)?;
+        let auth_url = self.client.get_authorize_url(true)?;
         Ok(auth_url)
     }
 
     async fn get_auth_token(&mut self, code: &str) -> Result<()> {
-        self.0.request_token(code).await?;
-        Ok(())
+        self.client.request_token(code).await?;
+        self.save_token().await
     }
 
     // API
     async fn get_currently_playing(&self) -> Result<Option<CurrentPlayingTrack>> {
+        self.refresh_if_expired().await?;
+
+        // the playback state, unlike the currently playing endpoint, includes the device
         let cpt: Option<CurrentPlayingTrack> = match self
-            .0
-            .current_playing(None, Some(&[AdditionalType::Track]))
+            .client
+            .current_playback(
+                None,
+                Some(&[AdditionalType::Track, AdditionalType::Episode]),
+            )
             .await?
         {
             Some(cp) => Some(
-                <CurrentlyPlayingContext as TryInto<super::shims::CurrentPlayingTrack>>::try_into(
+                <CurrentPlaybackContext as TryInto<super::shims::CurrentPlayingTrack>>::try_into(
                     cp,
                 )?
                 .into(),
@@ -142,10 +214,11 @@ impl SpotifyApi for SpotifyClient {
         &self,
         timestamp: DateTime<Utc>,
     ) -> Result<Vec<HistoryPlayedTrack>> {
+        self.refresh_if_expired().await?;
         let time_limit = TimeLimits::After(timestamp);
 
         let items = self
-            .0
+            .client
             .current_user_recently_played(Some(50), Some(time_limit))
             .await?
             .items;
@@ -163,9 +236,14 @@ impl SpotifyApi for SpotifyClient {
     async fn get_tags(&self, artists_ids: Vec<&str>) -> Result<Vec<Tag>> {
         let artists_ids: Vec<ArtistId> = artists_ids
             .into_iter()
-            .map(|artist_id| ArtistId::from_id(artist_id).unwrap())
+            .filter(|artist_id| !identity::is_synthetic(artist_id))
+            .filter_map(|artist_id| ArtistId::from_id(artist_id).ok())
             .collect();
-        let artists = self.0.artists(&artists_ids).await?;
+        if artists_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        self.refresh_if_expired().await?;
+        let artists = self.client.artists(artists_ids).await?;
 
         let mut tags: Vec<Tag> = artists
             .into_iter()
@@ -184,26 +262,36 @@ impl SpotifyApi for SpotifyClient {
     }
 }
 
-fn load_token_from_cache() -> Result<Token> {
-    let cache_path = get_or_create_cache_path();
-    Ok(Token::from_cache(cache_path)?)
+#[async_trait::async_trait]
+impl SpotifyConnector for SpotifyClient {
+    async fn for_user(
+        &self,
+        user_id: i32,
+        store: Arc<dyn TokenStore>,
+    ) -> Result<Box<dyn SpotifyApi>> {
+        Ok(Box::new(SpotifyClient::for_user(self, user_id, store).await?))
+    }
+
+    fn legacy_token(&self) -> Result<Option<String>> {
+        SpotifyClient::legacy_token()
+    }
 }
 
-fn get_cache_path() -> PathBuf {
-    let project_dir_path = env::current_dir().unwrap();
-    let mut cache_path = project_dir_path;
-    cache_path.push(".spotify_cache/");
-    cache_path.push("scrobblify");
+/// Whether Spotify refused to refresh the token because the access has been revoked,
+/// rather than because of a rate limit or of the credentials of the app: a 401, like an
+/// `invalid_client`, means the app is misconfigured and the tokens are still good.
+fn is_revoked(status: u16, body: &str) -> bool {
+    let error = serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|body| body["error"].as_str().map(String::from));
 
-    cache_path
+    status == 400 && error.as_deref() == Some("invalid_grant")
 }
 
-fn get_or_create_cache_path() -> PathBuf {
-    let cache_path = get_cache_path();
-    if !cache_path.exists() {
-        let mut path = cache_path.clone();
-        path.pop();
-        fs::create_dir_all(path).unwrap();
-    }
-    cache_path
+fn get_legacy_cache_path() -> Result<PathBuf> {
+    let mut cache_path = env::current_dir()?;
+    cache_path.push(".spotify_cache/");
+    cache_path.push("scrobblify");
+
+    Ok(cache_path)
 }
@@ -1,61 +1,148 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use rspotify::model::{
-    CurrentlyPlayingContext, FullTrack, PlayHistory, PlayableItem, SimplifiedAlbum,
-    SimplifiedArtist,
+    Context, CurrentPlaybackContext, Device, FullEpisode, FullTrack, Image, PlayHistory,
+    PlayableItem, SimplifiedAlbum, SimplifiedArtist, SimplifiedShow,
 };
 use std::time::Duration;
 
-use scrobblify_domain::models::{
-    Album as DomainAlbum, Artist as DomainArtist, CurrentPlayingTrack as DomainCurrentPlayingTrack,
-    HistoryPlayedTrack as DomainHistoryPlayedTrack, Tag, TrackInfo as DomainTrackInfo,
+use scrobblify_domain::{
+    identity,
+    models::{
+        Album as DomainAlbum, Artist as DomainArtist,
+        CurrentPlayingTrack as DomainCurrentPlayingTrack, EpisodeInfo as DomainEpisodeInfo,
+        HistoryPlayedTrack as DomainHistoryPlayedTrack, PlaybackContext as DomainPlaybackContext,
+        PlaybackDevice as DomainPlaybackDevice, PlayingItem as DomainPlayingItem,
+        Show as DomainShow, Tag, TrackInfo as DomainTrackInfo,
+    },
 };
 
 use super::client::SpotifyError;
 
 #[derive(Clone, Debug)]
 pub struct CurrentPlayingTrack {
-    pub track: TrackInfo,
+    pub item: PlayingItem,
     pub timestamp: DateTime<Utc>,
     pub progress_secs: Duration,
     pub scrobbled: bool,
+    pub device: Option<PlaybackDevice>,
+    pub context: Option<PlaybackContext>,
 }
 
 impl From<CurrentPlayingTrack> for DomainCurrentPlayingTrack {
     fn from(cpt: CurrentPlayingTrack) -> Self {
         Self {
-            track: cpt.track.into(),
+            item: cpt.item.into(),
             timestamp: cpt.timestamp,
             progress_secs: cpt.progress_secs,
             scrobbled: cpt.scrobbled,
+            device: cpt.device.map(Into::into),
+            context: cpt.context.map(Into::into),
         }
     }
 }
 
-impl TryFrom<CurrentlyPlayingContext> for CurrentPlayingTrack {
+impl TryFrom<CurrentPlaybackContext> for CurrentPlayingTrack {
     type Error = anyhow::Error;
 
-    fn try_from(cpt: CurrentlyPlayingContext) -> Result<Self> {
-        let full_track: FullTrack = match cpt.item {
-            Some(PlayableItem::Track(ft)) => ft,
-            _ => return Err(anyhow::Error::new(SpotifyError::TrackResponse)),
+    fn try_from(cpt: CurrentPlaybackContext) -> Result<Self> {
+        let item = match cpt.item {
+            Some(PlayableItem::Track(ft)) => PlayingItem::Track(ft.into()),
+            Some(PlayableItem::Episode(fe)) => PlayingItem::Episode(fe.into()),
+            None => return Err(anyhow::Error::new(SpotifyError::TrackResponse)),
         };
 
-        let progress_secs = cpt.progress.unwrap_or(Duration::new(0, 0));
+        // Spotify durations are never negative, a broken one counts as no progress
+        let progress_secs = cpt
+            .progress
+            .and_then(|progress| progress.to_std().ok())
+            .unwrap_or_default();
 
         Ok(CurrentPlayingTrack {
-            track: full_track.into(),
+            item,
             timestamp: cpt.timestamp,
             progress_secs,
             scrobbled: false,
+            device: Some(cpt.device.into()),
+            context: cpt.context.map(Into::into),
         })
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct PlaybackDevice {
+    pub id: Option<String>,
+    pub name: String,
+    pub kind: String,
+    pub is_private_session: bool,
+}
+
+impl From<PlaybackDevice> for DomainPlaybackDevice {
+    fn from(pd: PlaybackDevice) -> Self {
+        Self {
+            id: pd.id,
+            name: pd.name,
+            kind: pd.kind,
+            is_private_session: pd.is_private_session,
+        }
+    }
+}
+
+impl From<Device> for PlaybackDevice {
+    fn from(d: Device) -> Self {
+        PlaybackDevice {
+            id: d.id,
+            name: d.name,
+            kind: format!("{:?}", d._type).to_lowercase(),
+            is_private_session: d.is_private_session,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PlaybackContext {
+    pub kind: String,
+    pub uri: String,
+}
+
+impl From<PlaybackContext> for DomainPlaybackContext {
+    fn from(pc: PlaybackContext) -> Self {
+        Self {
+            kind: pc.kind,
+            uri: pc.uri,
+        }
+    }
+}
+
+impl From<Context> for PlaybackContext {
+    fn from(c: Context) -> Self {
+        PlaybackContext {
+            kind: format!("{:?}", c._type).to_lowercase(),
+            uri: c.uri,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum PlayingItem {
+    Track(TrackInfo),
+    Episode(EpisodeInfo),
+}
+
+impl From<PlayingItem> for DomainPlayingItem {
+    fn from(pi: PlayingItem) -> Self {
+        match pi {
+            PlayingItem::Track(track) => Self::Track(track.into()),
+            PlayingItem::Episode(episode) => Self::Episode(episode.into()),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct HistoryPlayedTrack {
     pub track: TrackInfo,
     pub played_at: DateTime<Utc>,
+    pub context: Option<PlaybackContext>,
 }
 
 impl From<HistoryPlayedTrack> for DomainHistoryPlayedTrack {
@@ -63,6 +150,7 @@ impl From<HistoryPlayedTrack> for DomainHistoryPlayedTrack {
         Self {
             track: hpt.track.into(),
             played_at: hpt.played_at,
+            context: hpt.context.map(Into::into),
         }
     }
 }
@@ -76,6 +164,7 @@ impl TryFrom<PlayHistory> for HistoryPlayedTrack {
         Ok(Self {
             track,
             played_at: ph.played_at,
+            context: ph.context.map(Into::into),
         })
     }
 }
@@ -94,7 +183,7 @@ pub struct TrackInfo {
 
 impl From<TrackInfo> for DomainTrackInfo {
     fn from(t: TrackInfo) -> Self {
-        Self {
+        let mut track = Self {
             id: t.id,
             title: t.title,
             album: t.album.into(),
@@ -103,7 +192,11 @@ impl From<TrackInfo> for DomainTrackInfo {
             tags: t.tags,
             isrc: t.isrc,
             cover: t.cover,
-        }
+        };
+
+        // local files have no IDs at all, give them stable ones from their metadata
+        identity::assign_missing_ids(&mut track);
+        track
     }
 }
 
@@ -113,18 +206,90 @@ impl From<FullTrack> for TrackInfo {
         let album: Album = ft.album.into();
 
         TrackInfo {
-            id: ft.id.unwrap().as_ref().to_string(),
+            id: ft.id.map(|id| id.as_ref().to_string()).unwrap_or_default(),
             title: ft.name,
             album: album.clone(),
             artists,
-            duration_secs: ft.duration,
+            duration_secs: ft.duration.to_std().unwrap_or_default(),
             tags: vec![],
-            isrc: ft.external_ids.get("isrc").unwrap().to_string(),
+            isrc: ft.external_ids.get("isrc").cloned().unwrap_or_default(),
             cover: album.cover,
         }
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct EpisodeInfo {
+    pub id: String,
+    pub title: String,
+    pub show: Show,
+    pub duration_secs: Duration,
+    pub release_date: String,
+    pub cover: String,
+}
+
+impl From<EpisodeInfo> for DomainEpisodeInfo {
+    fn from(e: EpisodeInfo) -> Self {
+        Self {
+            id: e.id,
+            title: e.title,
+            show: e.show.into(),
+            duration_secs: e.duration_secs,
+            release_date: e.release_date,
+            cover: e.cover,
+        }
+    }
+}
+
+impl From<FullEpisode> for EpisodeInfo {
+    fn from(fe: FullEpisode) -> Self {
+        let show: Show = fe.show.into();
+        let cover = match cover_from_images(fe.images) {
+            cover if cover.is_empty() => show.cover.clone(),
+            cover => cover,
+        };
+
+        EpisodeInfo {
+            id: fe.id.as_ref().to_string(),
+            title: fe.name,
+            show,
+            duration_secs: fe.duration.to_std().unwrap_or_default(),
+            release_date: fe.release_date,
+            cover,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Show {
+    pub id: String,
+    pub name: String,
+    pub publisher: String,
+    pub cover: String,
+}
+
+impl From<Show> for DomainShow {
+    fn from(s: Show) -> Self {
+        Self {
+            id: s.id,
+            name: s.name,
+            publisher: s.publisher,
+            cover: s.cover,
+        }
+    }
+}
+
+impl From<SimplifiedShow> for Show {
+    fn from(ss: SimplifiedShow) -> Self {
+        Show {
+            id: ss.id.as_ref().to_string(),
+            name: ss.name,
+            publisher: ss.publisher,
+            cover: cover_from_images(ss.images),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Album {
     pub id: String,
@@ -144,17 +309,10 @@ impl From<Album> for DomainAlbum {
 
 impl From<SimplifiedAlbum> for Album {
     fn from(sa: SimplifiedAlbum) -> Self {
-        let cover = sa
-            .images
-            .into_iter()
-            .find(|img| matches!(img.height, Some(640)))
-            .map(|img| img.url)
-            .unwrap_or_else(|| "".to_string());
-
         Album {
-            id: sa.id.unwrap().as_ref().to_string(),
+            id: sa.id.map(|id| id.as_ref().to_string()).unwrap_or_default(),
             title: sa.name,
-            cover,
+            cover: cover_from_images(sa.images),
         }
     }
 }
@@ -177,8 +335,17 @@ impl From<Artist> for DomainArtist {
 impl From<SimplifiedArtist> for Artist {
     fn from(sa: SimplifiedArtist) -> Self {
         Artist {
-            id: sa.id.unwrap().as_ref().to_string(),
+            id: sa.id.map(|id| id.as_ref().to_string()).unwrap_or_default(),
             name: sa.name,
         }
     }
 }
+
+/// Picks the 640px image, which is the size Spotify uses for covers.
+fn cover_from_images(images: Vec<Image>) -> String {
+    images
+        .into_iter()
+        .find(|img| matches!(img.height, Some(640)))
+        .map(|img| img.url)
+        .unwrap_or_else(|| "".to_string())
+}
@@ -0,0 +1,146 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use std::collections::HashMap;
+
+use scrobblify_domain::models::{
+    Activity, ActivityBucket, ActivityPoint, ClockCell, ClockRow, HeatmapDay, HeatmapWeek, PlayTime,
+};
+use scrobblify_domain::time::Tz;
+
+use super::charts::week_start;
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Buckets the scrobbles played between two local days of a time zone, both included.
+pub fn build_activity(
+    times: &[PlayTime],
+    start: NaiveDate,
+    end: NaiveDate,
+    bucket: ActivityBucket,
+    tz: Tz,
+) -> Activity {
+    let local_times: Vec<(NaiveDateTime, f64)> = times
+        .iter()
+        .map(|t| {
+            (
+                t.timestamp.with_timezone(&tz).naive_local(),
+                t.duration_secs,
+            )
+        })
+        .filter(|(local, _)| local.date() >= start && local.date() <= end)
+        .collect();
+
+    let mut buckets = HashMap::<NaiveDateTime, (u32, f64)>::new();
+    let mut days = HashMap::<NaiveDate, u32>::new();
+    let mut clock = [[0u32; 24]; 7];
+    for (local, duration_secs) in local_times.iter() {
+        let bucket = buckets.entry(bucket_start(*local, bucket)).or_default();
+        bucket.0 += 1;
+        bucket.1 += duration_secs;
+        *days.entry(local.date()).or_default() += 1;
+        clock[local.weekday().num_days_from_monday() as usize][local.hour() as usize] += 1;
+    }
+
+    // every bucket of the range is there, empty ones too, so gaps show up
+    let mut series = vec![];
+    let mut current = bucket_start(start.and_time(NaiveTime::MIN), bucket);
+    while current.date() <= end {
+        let (scrobbles, listened_secs) = buckets.get(&current).copied().unwrap_or_default();
+        series.push(ActivityPoint {
+            start: current,
+            scrobbles,
+            listened_secs,
+        });
+        current = match next_bucket(current, bucket) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+
+    Activity {
+        start,
+        end,
+        bucket,
+        series,
+        heatmap: heatmap(&days, start, end),
+        clock: listening_clock(&clock),
+    }
+}
+
+pub(crate) fn bucket_start(time: NaiveDateTime, bucket: ActivityBucket) -> NaiveDateTime {
+    match bucket {
+        ActivityBucket::Hour => {
+            time.date().and_time(NaiveTime::MIN) + Duration::hours(time.hour().into())
+        }
+        ActivityBucket::Day => time.date().and_time(NaiveTime::MIN),
+        ActivityBucket::Month => {
+            (time.date() - Duration::days(time.day0().into())).and_time(NaiveTime::MIN)
+        }
+    }
+}
+
+/// The start of the following bucket, `None` past the last day chrono knows.
+pub(crate) fn next_bucket(start: NaiveDateTime, bucket: ActivityBucket) -> Option<NaiveDateTime> {
+    match bucket {
+        ActivityBucket::Hour => start.checked_add_signed(Duration::hours(1)),
+        ActivityBucket::Day => start.checked_add_signed(Duration::days(1)),
+        ActivityBucket::Month if start.month() == 12 => month_start(start.year() + 1, 1),
+        ActivityBucket::Month => month_start(start.year(), start.month() + 1),
+    }
+}
+
+fn month_start(year: i32, month: u32) -> Option<NaiveDateTime> {
+    Some(NaiveDate::from_ymd_opt(year, month, 1)?.and_time(NaiveTime::MIN))
+}
+
+fn heatmap(days: &HashMap<NaiveDate, u32>, start: NaiveDate, end: NaiveDate) -> Vec<HeatmapWeek> {
+    let max = days.values().copied().max().unwrap_or(0);
+    let mut weeks = vec![];
+    let mut monday = week_start(start);
+    while monday <= end {
+        let days = (0..7)
+            .map(|i| monday + Duration::days(i))
+            .map(|day| {
+                (day >= start && day <= end).then(|| {
+                    let scrobbles = days.get(&day).copied().unwrap_or(0);
+                    HeatmapDay {
+                        day,
+                        scrobbles,
+                        level: level(scrobbles, max),
+                    }
+                })
+            })
+            .collect();
+        weeks.push(HeatmapWeek { days });
+        monday += Duration::weeks(1);
+    }
+
+    weeks
+}
+
+fn listening_clock(clock: &[[u32; 24]; 7]) -> Vec<ClockRow> {
+    let max = clock.iter().flatten().copied().max().unwrap_or(0);
+    WEEKDAYS
+        .iter()
+        .zip(clock.iter())
+        .map(|(weekday, hours)| ClockRow {
+            weekday: weekday.to_string(),
+            cells: hours
+                .iter()
+                .enumerate()
+                .map(|(hour, &scrobbles)| ClockCell {
+                    hour: hour as u32,
+                    scrobbles,
+                    level: level(scrobbles, max),
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// From 0, nothing played, to 4, as much as the busiest bucket.
+pub(crate) fn level(scrobbles: u32, max: u32) -> u8 {
+    match scrobbles {
+        0 => 0,
+        _ => (scrobbles * 4).div_ceil(max).clamp(1, 4) as u8,
+    }
+}
@@ -1,59 +1,260 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
-use scrobblify_bridge::spotify::SpotifyClient;
 use scrobblify_domain::{
     self,
-    bridge::spotify::SpotifyApi,
-    db::{ParamsForStatsQuery, Repository},
+    bridge::spotify::{SpotifyApi, SpotifyConnector},
+    db::{ParamsForStatsQuery, Repository, TokenStore},
+    errors::DatabaseResult,
+    identity,
     models::{
-        CurrentPlayingTrack, HistoryPlayedTrack, ScrobbleInfo, StatsArtist, StatsTag, StatsTrack,
+        Activity, ActivityBucket, Album, Artist, AuditEntry, ChartKind, ChartSnapshot, Comparison,
+        CurrentPlayingTrack, Discovery, DuplicateGroup, EntityName, EpisodeScrobbleInfo,
+        HistoryPlayedTrack, Milestones, OnThisDay, PlaybackContext, PlaybackDevice, RewriteField,
+        RewritePreview, RewriteRule, Scrobble, ScrobbleEdit, ScrobbleInfo, ScrobbleSelection,
+        Sessions, SkippedPlay, StatsAlbum, StatsArtist, StatsShow, StatsTag, StatsTrack, Streaks,
+        Track, User, WeeklyChart, Wrapped,
     },
+    time::{Clock, SystemClock, Tz},
 };
 
-pub struct App {
+use super::{
+    activity, auth, charts, comparison, discovery, rewrite, sessions, streaks, wrapped, Rewriter,
+    ScrobbleFilter,
+};
+
+/// The user owning the scrobbles stored before Scrobblify supported more users.
+const DEFAULT_USER_ID: i32 = 1;
+const SESSION_DURATION_DAYS: i64 = 30;
+/// Longest range an hourly time series can cover.
+const MAX_HOURLY_ACTIVITY_DAYS: i64 = 31;
+/// Longest range daily and monthly time series, and their heatmaps, can cover.
+const MAX_ACTIVITY_DAYS: i64 = 3660;
+/// How long a skipped play can be paused and still be recognized in the history.
+const SKIPPED_PLAY_PAUSE_MINUTES: i64 = 30;
+
+/// The Spotify account of a user and what they're currently playing.
+struct UserAccount {
+    user: User,
+    spotify: Box<dyn SpotifyApi>,
     current_track: Option<CurrentPlayingTrack>,
+}
+
+pub struct App {
     db: Box<dyn Repository>,
-    spotify: SpotifyClient,
+    tokens: Arc<dyn TokenStore>,
+    spotify: Box<dyn SpotifyConnector>,
+    accounts: HashMap<i32, UserAccount>,
+    filter: ScrobbleFilter,
+    time_zone: Tz,
+    session_gap: Duration,
+    clock: Arc<dyn Clock>,
+    /// The rewrite rules, compiled on the first scrobble and dropped whenever they change.
+    rewriter: Mutex<Option<Rewriter>>,
 }
 
 impl App {
-    pub fn new(db: Box<dyn Repository>, spotify: SpotifyClient) -> Self {
+    pub fn new(
+        db: Box<dyn Repository>,
+        tokens: Arc<dyn TokenStore>,
+        spotify: Box<dyn SpotifyConnector>,
+        filter: ScrobbleFilter,
+        time_zone: Tz,
+        session_gap: Duration,
+    ) -> Self {
         App {
             db,
+            tokens,
             spotify,
-            current_track: Default::default(),
+            filter,
+            time_zone,
+            session_gap,
+            clock: Arc::new(SystemClock),
+            accounts: HashMap::new(),
+            rewriter: Mutex::new(None),
+        }
+    }
+
+    /// Reads the time from the given clock rather than the system one, e.g. to control it
+    /// in tests.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        App { clock, ..self }
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    /// Sets up the account of every user, authenticated with their stored Spotify token.
+    /// The token cached on disk by single-user setups is moved to the default user.
+    pub async fn load_users(&mut self) -> Result<()> {
+        for user in self.db.list_users().await? {
+            if user.id == DEFAULT_USER_ID && self.tokens.load_token(user.id).await?.is_none() {
+                if let Some(legacy_token) = self.spotify.legacy_token()? {
+                    self.tokens.save_token(user.id, &legacy_token).await?;
+                }
+            }
+            self.add_account(user).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Gives admins without a password the given one, so that they can log in
+    /// the first time.
+    pub async fn set_initial_admin_password(&self, password: &str) -> Result<()> {
+        for user in self.db.list_users().await? {
+            if user.is_admin && self.db.get_password_hash(user.id).await?.is_none() {
+                tracing::info!(msg = "set initial admin password", user = user.name);
+                self.db
+                    .set_password_hash(user.id, &auth::hash_password(password)?)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn add_account(&mut self, user: User) -> Result<()> {
+        let spotify = self.spotify.for_user(user.id, self.tokens.clone()).await?;
+        self.accounts.insert(
+            user.id,
+            UserAccount {
+                user,
+                spotify,
+                current_track: None,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn account(&self, user_id: i32) -> Result<&UserAccount> {
+        self.accounts
+            .get(&user_id)
+            .ok_or_else(|| anyhow!("user `{}` not found", user_id))
+    }
+
+    /// Checks the configured scrobble rules for the given playback.
+    pub fn accepts_playback(
+        &self,
+        device: Option<&PlaybackDevice>,
+        context: Option<&PlaybackContext>,
+    ) -> bool {
+        self.filter.accepts(device, context)
+    }
+
+    async fn rewriter(&self) -> Result<Rewriter> {
+        if let Some(rewriter) = self.rewriter.lock().unwrap().clone() {
+            return Ok(rewriter);
         }
+
+        let rewriter = Rewriter::new(self.db.list_rewrite_rules().await?)?;
+        *self.rewriter.lock().unwrap() = Some(rewriter.clone());
+        Ok(rewriter)
+    }
+
+    fn forget_rewriter(&self) {
+        *self.rewriter.lock().unwrap() = None;
+    }
+
+    /// Remembers a track play denied by the scrobble rules, so that it isn't scrobbled from
+    /// the recently played history either. Plays older than the last scrobble are never
+    /// looked at again, so they're forgotten.
+    pub async fn skip_play(&self, scrobble: &ScrobbleInfo) -> Result<()> {
+        self.db
+            .insert_skipped_play(SkippedPlay {
+                user_id: scrobble.user_id,
+                track_id: scrobble.track.id.clone(),
+                timestamp: scrobble.timestamp,
+                duration_secs: scrobble.duration_secs,
+            })
+            .await?;
+        if let Some(last) = self.db.get_last_scrobble(scrobble.user_id).await? {
+            self.db
+                .delete_skipped_plays(scrobble.user_id, last.timestamp)
+                .await?;
+        }
+
+        Ok(())
     }
 }
 
 #[async_trait::async_trait]
 impl scrobblify_domain::app::App for App {
-    fn get_current_track(&self) -> &Option<CurrentPlayingTrack> {
-        &self.current_track
+    fn time_zone(&self) -> Tz {
+        self.time_zone
+    }
+
+    fn today(&self, tz: Tz) -> NaiveDate {
+        self.clock.today(tz)
+    }
+
+    fn get_current_track(&self, user_id: i32) -> Option<CurrentPlayingTrack> {
+        self.accounts
+            .get(&user_id)
+            .and_then(|account| account.current_track.clone())
     }
 
-    fn set_current_track(&mut self, current_track: Option<CurrentPlayingTrack>) {
-        self.current_track = current_track;
+    fn set_current_track(&mut self, user_id: i32, current_track: Option<CurrentPlayingTrack>) {
+        if let Some(account) = self.accounts.get_mut(&user_id) {
+            account.current_track = current_track;
+        }
     }
 
     // Spotify API
-    async fn get_recently_played(&self) -> Result<Vec<HistoryPlayedTrack>> {
-        if let Some(scrobble) = self.db.get_last_scrobble().await? {
-            let recently_played = self.spotify.get_recently_played(scrobble.timestamp).await?;
+    async fn get_recently_played(&self, user_id: i32) -> Result<Vec<HistoryPlayedTrack>> {
+        let account = self.account(user_id)?;
+        if let Some(scrobble) = self.db.get_last_scrobble(user_id).await? {
+            let recently_played = account
+                .spotify
+                .get_recently_played(scrobble.timestamp)
+                .await?;
+            let skipped = self
+                .db
+                .list_skipped_plays(user_id, scrobble.timestamp)
+                .await?;
 
-            return Ok(recently_played);
+            return Ok(without_skipped_plays(recently_played, skipped));
         }
         Ok(vec![])
     }
 
-    async fn get_currently_playing(&self) -> Result<Option<CurrentPlayingTrack>> {
-        self.spotify.get_currently_playing().await
+    async fn get_currently_playing(&self, user_id: i32) -> Result<Option<CurrentPlayingTrack>> {
+        self.account(user_id)?.spotify.get_currently_playing().await
     }
 
     // Scrobbling
     async fn scrobble(&self, scrobble: ScrobbleInfo) -> Result<()> {
+        let mut scrobble = scrobble;
         let mut track_info = scrobble.clone().track;
 
+        // normalize metadata before anything gets persisted, IDs derived from it included
+        self.rewriter()
+            .await?
+            .rewrite_track(&mut track_info, &scrobble.origin);
+        identity::reassign_synthetic_ids(&mut track_info);
+
+        // duplicates that have been merged keep being scrobbled to their canonical entities
+        track_info.id = self
+            .db
+            .resolve_merged_id(RewriteField::Title, &track_info.id)
+            .await?;
+        track_info.album.id = self
+            .db
+            .resolve_merged_id(RewriteField::Album, &track_info.album.id)
+            .await?;
+        for artist in track_info.artists.iter_mut() {
+            artist.id = self
+                .db
+                .resolve_merged_id(RewriteField::Artist, &artist.id)
+                .await?;
+        }
+
         // if a track is already on db, we don't need to fetch tags and insert stuff on db again
         if let Ok(None) = self.db.get_track_by_id(track_info.clone().id).await {
             self.db.insert_track(track_info.clone().into()).await?;
@@ -64,7 +265,8 @@ impl scrobblify_domain::app::App for App {
             }
 
             // fetching genres from the artist profile, it's the most reliable way to get some tags
-            let tags = self.spotify.get_tags(artists_ids).await?;
+            let spotify = &self.account(scrobble.user_id)?.spotify;
+            let tags = spotify.get_tags(artists_ids).await?;
             for tag in tags.iter() {
                 self.db.insert_tag(tag.clone()).await?;
             }
@@ -72,34 +274,566 @@ impl scrobblify_domain::app::App for App {
 
             self.db.insert_album(track_info.album.clone()).await?;
         }
+        scrobble.track = track_info;
         self.db.insert_scrobble(scrobble.clone()).await?;
 
         Ok(())
     }
 
+    async fn scrobble_episode(&self, scrobble: EpisodeScrobbleInfo) -> Result<()> {
+        let episode_info = scrobble.clone().episode;
+
+        // podcasts live in their own tables, so they never show up in music charts
+        if let Ok(None) = self.db.get_episode_by_id(episode_info.clone().id).await {
+            self.db.insert_show(episode_info.show.clone()).await?;
+            self.db.insert_episode(episode_info.clone().into()).await?;
+        }
+        self.db.insert_episode_scrobble(scrobble.clone()).await?;
+
+        Ok(())
+    }
+
     // Spotify Auth
-    fn is_spotify_authenticated(&self) -> bool {
-        self.spotify.has_auth()
+    async fn is_spotify_authenticated(&self, user_id: i32) -> bool {
+        match self.accounts.get(&user_id) {
+            Some(account) => account.spotify.has_auth().await,
+            None => false,
+        }
+    }
+
+    async fn get_spotify_auth_url(&self, user_id: i32) -> Result<String> {
+        self.account(user_id)?.spotify.get_auth_url().await
+    }
+
+    async fn store_spotify_auth_token(
+        &mut self,
+        user_id: i32,
+        state: &str,
+        code: &str,
+    ) -> Result<()> {
+        let account = self
+            .accounts
+            .get_mut(&user_id)
+            .ok_or_else(|| anyhow!("user `{}` not found", user_id))?;
+
+        // the state was generated for this user's authorization, anything else is a forged
+        // callback or an authorization started by someone else
+        if account.spotify.get_auth_state() != state {
+            return Err(anyhow!(
+                "authorization state doesn't match, please try again"
+            ));
+        }
+
+        account.spotify.get_auth_token(code).await
+    }
+
+    async fn unlink_spotify(&mut self, user_id: i32) -> Result<()> {
+        self.tokens.delete_token(user_id).await?;
+
+        // a fresh client forgets the token and gets a new state for the next authorization
+        let spotify = self.spotify.for_user(user_id, self.tokens.clone()).await?;
+        let account = self
+            .accounts
+            .get_mut(&user_id)
+            .ok_or_else(|| anyhow!("user `{}` not found", user_id))?;
+        account.spotify = spotify;
+        account.current_track = None;
+
+        tracing::info!(msg = "spotify unlinked", user = account.user.name);
+        Ok(())
+    }
+
+    // Users
+    async fn list_users(&self) -> DatabaseResult<Vec<User>> {
+        self.db.list_users().await
+    }
+
+    async fn get_user(&self, name: &str) -> Result<Option<User>> {
+        Ok(self.db.get_user_by_name(name).await?)
+    }
+
+    async fn create_user(&mut self, name: &str, password: Option<&str>) -> Result<User> {
+        // names show up in URLs, keep them simple
+        let name = name.trim();
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(anyhow!(
+                "user names can contain only letters, digits, `-` and `_`"
+            ));
+        }
+
+        let hash = password.map(auth::hash_password).transpose()?;
+
+        let user = self.db.insert_user(name).await?;
+        if let Some(hash) = hash {
+            self.db.set_password_hash(user.id, &hash).await?;
+        }
+        self.add_account(user.clone()).await?;
+
+        Ok(user)
+    }
+
+    async fn set_password(&self, user_id: i32, password: &str) -> Result<()> {
+        let hash = auth::hash_password(password)?;
+        self.db.set_password_hash(user_id, &hash).await?;
+        Ok(self.db.delete_user_sessions(user_id, None).await?)
+    }
+
+    async fn change_password(
+        &self,
+        user_id: i32,
+        current_password: &str,
+        new_password: &str,
+        session_token: Option<&str>,
+    ) -> Result<()> {
+        // users logged in by the reverse proxy may not have a password yet
+        if let Some(hash) = self.db.get_password_hash(user_id).await? {
+            if !auth::verify_password(current_password, &hash) {
+                return Err(anyhow!("the current password is wrong"));
+            }
+        }
+
+        let hash = auth::hash_password(new_password)?;
+        self.db.set_password_hash(user_id, &hash).await?;
+        let session_id = session_token.map(auth::session_id);
+        Ok(self
+            .db
+            .delete_user_sessions(user_id, session_id.as_deref())
+            .await?)
+    }
+
+    async fn set_public_stats(&self, user_id: i32, public_stats: bool) -> Result<()> {
+        Ok(self.db.set_public_stats(user_id, public_stats).await?)
+    }
+
+    // Authentication
+    async fn login(&self, name: &str, password: &str) -> Result<Option<String>> {
+        let user = match self.db.get_user_by_name(name).await? {
+            Some(user) => user,
+            None => return Ok(None),
+        };
+        // users without a password can log in only through the reverse proxy
+        let hash = match self.db.get_password_hash(user.id).await? {
+            Some(hash) => hash,
+            None => return Ok(None),
+        };
+        if !auth::verify_password(password, &hash) {
+            return Ok(None);
+        }
+
+        let token = auth::generate_session_token();
+        let expires_at = self.clock.now() + Duration::days(SESSION_DURATION_DAYS);
+        self.db
+            .insert_session(&auth::session_id(&token), user.id, expires_at)
+            .await?;
+
+        Ok(Some(token))
+    }
+
+    async fn logout(&self, session_token: &str) -> Result<()> {
+        Ok(self
+            .db
+            .delete_session(&auth::session_id(session_token))
+            .await?)
+    }
+
+    async fn get_session_user(&self, session_token: &str) -> Result<Option<User>> {
+        Ok(self
+            .db
+            .get_session_user(&auth::session_id(session_token), self.now())
+            .await?)
+    }
+
+    // Editing
+    async fn list_scrobbles(&self, opts: ParamsForStatsQuery) -> DatabaseResult<Vec<Scrobble>> {
+        self.db.list_scrobbles_by_date_range(opts).await
+    }
+
+    async fn get_scrobble(&self, id: i32) -> Result<Option<Scrobble>> {
+        Ok(self.db.get_scrobble(id).await?)
+    }
+
+    async fn update_scrobble(&self, id: i32, scrobble: ScrobbleEdit) -> Result<()> {
+        Ok(self.db.update_scrobble(id, scrobble).await?)
+    }
+
+    async fn delete_scrobble(&self, id: i32) -> Result<()> {
+        Ok(self.db.delete_scrobble(id).await?)
+    }
+
+    async fn delete_scrobbles(&self, selection: ScrobbleSelection) -> Result<u64> {
+        Ok(self.db.delete_scrobbles(selection).await?)
+    }
+
+    async fn reassign_scrobbles(
+        &self,
+        selection: ScrobbleSelection,
+        track_id: &str,
+    ) -> Result<u64> {
+        Ok(self.db.reassign_scrobbles(selection, track_id).await?)
+    }
+
+    async fn list_entity_names(&self, field: RewriteField) -> DatabaseResult<Vec<EntityName>> {
+        self.db.list_entity_names(field, None).await
+    }
+
+    async fn get_track(&self, id: &str) -> Result<Option<Track>> {
+        Ok(self.db.get_track_by_id(id.to_string()).await?)
+    }
+
+    async fn update_track(&self, track: Track) -> Result<()> {
+        Ok(self.db.update_track(track).await?)
+    }
+
+    async fn delete_track(&self, id: &str) -> Result<()> {
+        Ok(self.db.delete_track(id).await?)
+    }
+
+    async fn get_artist(&self, id: &str) -> Result<Option<Artist>> {
+        Ok(self.db.get_artist_by_id(id.to_string()).await?)
+    }
+
+    async fn update_artist(&self, artist: Artist) -> Result<()> {
+        Ok(self.db.update_artist(artist).await?)
+    }
+
+    async fn delete_artist(&self, id: &str) -> Result<()> {
+        Ok(self.db.delete_artist(id).await?)
     }
 
-    async fn get_spotify_auth_url(&self) -> Result<String> {
-        self.spotify.get_auth_url().await
+    async fn get_album(&self, id: &str) -> Result<Option<Album>> {
+        Ok(self.db.get_album_by_id(id.to_string()).await?)
     }
 
-    async fn store_spotify_auth_token(&self, code: &str) -> Result<()> {
-        self.spotify.clone().get_auth_token(code).await
+    async fn update_album(&self, album: Album) -> Result<()> {
+        Ok(self.db.update_album(album).await?)
+    }
+
+    async fn delete_album(&self, id: &str) -> Result<()> {
+        Ok(self.db.delete_album(id).await?)
+    }
+
+    async fn list_audit_log(&self, limit: u64) -> DatabaseResult<Vec<AuditEntry>> {
+        self.db.list_audit_log(limit).await
+    }
+
+    // Duplicates
+    async fn list_duplicates(&self, field: RewriteField) -> DatabaseResult<Vec<DuplicateGroup>> {
+        self.db.list_duplicates(field).await
+    }
+
+    async fn merge_entities(
+        &self,
+        field: RewriteField,
+        canonical_id: &str,
+        duplicate_ids: Vec<String>,
+    ) -> Result<()> {
+        Ok(self
+            .db
+            .merge_entities(field, canonical_id, duplicate_ids)
+            .await?)
+    }
+
+    // Rewrite rules
+    async fn list_rewrite_rules(&self) -> DatabaseResult<Vec<RewriteRule>> {
+        self.db.list_rewrite_rules().await
+    }
+
+    async fn add_rewrite_rule(&self, rule: RewriteRule) -> Result<()> {
+        rewrite::compile(&rule)?;
+        self.db.insert_rewrite_rule(rule).await?;
+        self.forget_rewriter();
+        Ok(())
+    }
+
+    async fn delete_rewrite_rule(&self, id: i32) -> Result<()> {
+        self.db.delete_rewrite_rule(id).await?;
+        self.forget_rewriter();
+        Ok(())
+    }
+
+    async fn preview_rewrite_rule(&self, rule: RewriteRule) -> Result<Vec<RewritePreview>> {
+        let rewriter = Rewriter::new(vec![rule.clone()])?;
+        let names = self
+            .db
+            .list_entity_names(rule.field, rule.origin.clone())
+            .await?;
+
+        let previews = names
+            .into_iter()
+            .filter_map(|name| {
+                let after = rewriter.rewrite(rule.field, &name.name, None);
+                if after == name.name {
+                    return None;
+                }
+                Some(RewritePreview {
+                    id: name.id,
+                    before: name.name,
+                    after,
+                    scrobbles: name.scrobbles,
+                })
+            })
+            .collect();
+
+        Ok(previews)
+    }
+
+    async fn apply_rewrite_rule(&self, rule: RewriteRule) -> Result<usize> {
+        // entities are shared by every origin, renaming them would reach the other ones too
+        if let Some(origin) = &rule.origin {
+            return Err(anyhow!(
+                "rules limited to the `{}` origin only apply to new scrobbles",
+                origin
+            ));
+        }
+
+        let previews = self.preview_rewrite_rule(rule.clone()).await?;
+        for preview in previews.iter() {
+            self.db
+                .rename_entity(rule.field, &preview.id, &preview.after)
+                .await?;
+        }
+
+        Ok(previews.len())
     }
 
     // Stats
-    async fn stats_for_popular_tracks(&self, opts: ParamsForStatsQuery) -> Vec<StatsTrack> {
+    async fn stats_for_popular_tracks(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsTrack>> {
         self.db.stats_for_popular_tracks(opts).await
     }
 
-    async fn stats_for_popular_tags(&self, opts: ParamsForStatsQuery) -> Vec<StatsTag> {
+    async fn stats_for_popular_tags(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsTag>> {
         self.db.stats_for_popular_tags(opts).await
     }
 
-    async fn stats_for_popular_artists(&self, opts: ParamsForStatsQuery) -> Vec<StatsArtist> {
+    async fn stats_for_popular_artists(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsArtist>> {
         self.db.stats_for_popular_artists(opts).await
     }
+
+    async fn stats_for_popular_shows(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsShow>> {
+        self.db.stats_for_popular_shows(opts).await
+    }
+
+    async fn stats_for_popular_albums(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsAlbum>> {
+        self.db.stats_for_popular_albums(opts).await
+    }
+
+    async fn compare_periods(
+        &self,
+        previous: ParamsForStatsQuery,
+        current: ParamsForStatsQuery,
+    ) -> Result<Comparison> {
+        if previous.start > previous.end || current.start > current.end {
+            return Err(anyhow!("a range starts after it ends"));
+        }
+
+        comparison::compare(&*self.db, previous, current).await
+    }
+
+    /// Completed weeks are snapshotted the first time they're needed, so later edits to the
+    /// scrobbles don't rewrite the history; the current week is ranked on the fly. Weeks
+    /// are the ones of the configured time zone, as snapshots are shared by every request.
+    async fn get_weekly_chart(
+        &self,
+        user_id: i32,
+        kind: ChartKind,
+        week: NaiveDate,
+    ) -> Result<WeeklyChart> {
+        let week_start = charts::week_start(week);
+        let current_week = charts::week_start(self.clock.today(self.time_zone));
+        if week_start > current_week {
+            return Err(anyhow!("the week of {} hasn't started yet", week_start));
+        }
+
+        let mut history = self
+            .db
+            .list_chart_snapshots(user_id, kind, week_start)
+            .await?;
+
+        if let Some(first) = self.db.get_first_scrobble_timestamp(user_id).await? {
+            let mut missing = charts::week_start(first.with_timezone(&self.time_zone).date_naive());
+            while missing <= week_start && missing < current_week {
+                if !history.iter().any(|s| s.week_start == missing) {
+                    let snapshot = ChartSnapshot {
+                        week_start: missing,
+                        items: charts::rank_week(&*self.db, user_id, kind, missing, self.time_zone)
+                            .await?,
+                    };
+                    self.db
+                        .insert_chart_snapshot(user_id, kind, snapshot.clone())
+                        .await?;
+                    history.push(snapshot);
+                }
+                missing += Duration::weeks(1);
+            }
+            history.sort_by_key(|s| s.week_start);
+        }
+
+        let complete = week_start < current_week;
+        let items = match history.iter().position(|s| s.week_start == week_start) {
+            Some(index) => history.remove(index).items,
+            None => charts::rank_week(&*self.db, user_id, kind, week_start, self.time_zone).await?,
+        };
+
+        Ok(WeeklyChart {
+            kind,
+            week_start,
+            complete,
+            entries: charts::with_movements(items, week_start, &history),
+        })
+    }
+
+    async fn get_wrapped(&self, user_id: i32, year: i32, tz: Tz) -> Result<Wrapped> {
+        if year > self.clock.today(tz).year() {
+            return Err(anyhow!("{} hasn't started yet", year));
+        }
+
+        wrapped::build_wrapped(&*self.db, user_id, year, tz).await
+    }
+
+    async fn get_activity(
+        &self,
+        user_id: i32,
+        start: NaiveDate,
+        end: NaiveDate,
+        bucket: ActivityBucket,
+        tz: Tz,
+    ) -> Result<Activity> {
+        if start > end {
+            return Err(anyhow!("the range starts after it ends"));
+        }
+        if bucket == ActivityBucket::Hour && (end - start).num_days() >= MAX_HOURLY_ACTIVITY_DAYS {
+            return Err(anyhow!(
+                "hourly activity covers at most {} days",
+                MAX_HOURLY_ACTIVITY_DAYS
+            ));
+        }
+        check_activity_range(start, end)?;
+
+        let opts = ParamsForStatsQuery::new(start, end, None)
+            .for_user(user_id)
+            .in_time_zone(tz);
+        let times = self.db.list_play_times(opts).await?;
+
+        Ok(activity::build_activity(&times, start, end, bucket, tz))
+    }
+
+    async fn get_discovery(
+        &self,
+        user_id: i32,
+        start: NaiveDate,
+        end: NaiveDate,
+        bucket: ActivityBucket,
+        tz: Tz,
+    ) -> Result<Discovery> {
+        if start > end {
+            return Err(anyhow!("the range starts after it ends"));
+        }
+        if bucket == ActivityBucket::Hour {
+            return Err(anyhow!("discoveries are bucketed by day or month"));
+        }
+        check_activity_range(start, end)?;
+
+        discovery::build_discovery(&*self.db, user_id, start, end, bucket, tz).await
+    }
+
+    async fn get_sessions(
+        &self,
+        user_id: i32,
+        start: NaiveDate,
+        end: NaiveDate,
+        tz: Tz,
+    ) -> Result<Sessions> {
+        if start > end {
+            return Err(anyhow!("the range starts after it ends"));
+        }
+        check_activity_range(start, end)?;
+
+        let opts = ParamsForStatsQuery::new(start, end, None)
+            .for_user(user_id)
+            .in_time_zone(tz);
+        let scrobbles = self.db.list_scrobbles_by_date_range(opts).await?;
+
+        Ok(sessions::build_sessions(
+            scrobbles,
+            start,
+            end,
+            self.session_gap,
+            tz,
+        ))
+    }
+
+    async fn get_streaks(&self, user_id: i32, tz: Tz) -> Result<Streaks> {
+        streaks::build_streaks(&*self.db, user_id, self.clock.today(tz), tz).await
+    }
+
+    async fn get_milestones(&self, user_id: i32) -> Result<Milestones> {
+        streaks::build_milestones(&*self.db, user_id).await
+    }
+
+    async fn get_on_this_day(&self, user_id: i32, tz: Tz) -> Result<Vec<OnThisDay>> {
+        streaks::on_this_day(&*self.db, user_id, self.clock.today(tz), tz).await
+    }
+}
+
+/// Time series get a point for every bucket of the range and sessions load every scrobble
+/// of it, so it can't be arbitrarily long.
+fn check_activity_range(start: NaiveDate, end: NaiveDate) -> Result<()> {
+    if (end - start).num_days() >= MAX_ACTIVITY_DAYS {
+        return Err(anyhow!(
+            "activity covers at most {} days",
+            MAX_ACTIVITY_DAYS
+        ));
+    }
+    Ok(())
+}
+
+/// Leaves out of the history, latest first, the plays skipped while playing. The history
+/// tells when a play ended, so a skipped play is the first play of its track that ended
+/// after it started, unless that ended later than the track could have.
+fn without_skipped_plays(
+    history: Vec<HistoryPlayedTrack>,
+    mut skipped: Vec<SkippedPlay>,
+) -> Vec<HistoryPlayedTrack> {
+    let mut kept: Vec<HistoryPlayedTrack> = history
+        .into_iter()
+        .rev()
+        .filter(|played| {
+            let found = skipped.iter().position(|play| {
+                let ends_by = play.timestamp
+                    + Duration::milliseconds((play.duration_secs * 1000.0) as i64)
+                    + Duration::minutes(SKIPPED_PLAY_PAUSE_MINUTES);
+                play.track_id == played.track.id
+                    && play.timestamp <= played.played_at
+                    && played.played_at <= ends_by
+            });
+            match found {
+                Some(index) => {
+                    skipped.remove(index);
+                    false
+                }
+                None => true,
+            }
+        })
+        .collect();
+    kept.reverse();
+    kept
 }
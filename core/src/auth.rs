@@ -0,0 +1,60 @@
+//! Passwords and sessions of the web UI users.
+//!
+//! Passwords are hashed with argon2. Session tokens are random strings handed over to the
+//! browser: only their SHA-256 digest is stored, so a leaked database can't be used to
+//! impersonate anyone.
+
+use anyhow::{anyhow, Result};
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
+
+const SESSION_TOKEN_LENGTH: usize = 48;
+const PASSWORD_MIN_LENGTH: usize = 8;
+
+pub fn hash_password(password: &str) -> Result<String> {
+    if password.chars().count() < PASSWORD_MIN_LENGTH {
+        return Err(anyhow!(
+            "passwords must be at least {} characters long",
+            PASSWORD_MIN_LENGTH
+        ));
+    }
+
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|err| anyhow!("failed to hash password: {}", err))?;
+
+    Ok(hash.to_string())
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok(),
+        Err(err) => {
+            tracing::error!(msg = "invalid password hash", error = format!("{:?}", err));
+            false
+        }
+    }
+}
+
+pub fn generate_session_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(SESSION_TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// The ID a session is stored with.
+pub fn session_id(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
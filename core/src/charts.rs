@@ -0,0 +1,124 @@
+use chrono::{Datelike, Duration, NaiveDate};
+use std::collections::HashMap;
+
+use scrobblify_domain::{
+    db::{ParamsForStatsQuery, Repository},
+    errors::DatabaseResult,
+    models::{ChartItem, ChartKind, ChartMovement, ChartSnapshot, WeeklyChartEntry},
+    time::Tz,
+};
+
+/// How many entries a weekly chart keeps.
+pub const WEEKLY_CHART_SIZE: u64 = 20;
+
+/// The Monday of the ISO week the day belongs to.
+pub fn week_start(day: NaiveDate) -> NaiveDate {
+    day - Duration::days(day.weekday().num_days_from_monday() as i64)
+}
+
+/// Ranks the scrobbles of a user from Monday to Sunday of the given week, in a time zone.
+pub async fn rank_week(
+    db: &dyn Repository,
+    user_id: i32,
+    kind: ChartKind,
+    week_start: NaiveDate,
+    tz: Tz,
+) -> DatabaseResult<Vec<ChartItem>> {
+    let opts = ParamsForStatsQuery::new(
+        week_start,
+        week_start + Duration::days(6),
+        Some(WEEKLY_CHART_SIZE),
+    )
+    .for_user(user_id)
+    .in_time_zone(tz);
+
+    let items: Vec<(String, String, String, u32)> = match kind {
+        ChartKind::Tracks => db
+            .stats_for_popular_tracks(opts)
+            .await?
+            .into_iter()
+            .map(|t| (t.id, t.title, join_artists(&t.artists), t.score))
+            .collect(),
+        ChartKind::Artists => db
+            .stats_for_popular_artists(opts)
+            .await?
+            .into_iter()
+            .map(|a| (a.id, a.name, String::new(), a.score))
+            .collect(),
+        ChartKind::Albums => db
+            .stats_for_popular_albums(opts)
+            .await?
+            .into_iter()
+            .map(|a| (a.id, a.title, join_artists(&a.artists), a.score))
+            .collect(),
+    };
+
+    Ok(items
+        .into_iter()
+        .enumerate()
+        .map(|(i, (id, name, artists, score))| ChartItem {
+            rank: i as u32 + 1,
+            id,
+            name,
+            artists,
+            score,
+        })
+        .collect())
+}
+
+/// Compares a week's chart against the previous ones, `history` being the snapshots of
+/// the weeks before it, oldest first.
+pub fn with_movements(
+    items: Vec<ChartItem>,
+    week_start: NaiveDate,
+    history: &[ChartSnapshot],
+) -> Vec<WeeklyChartEntry> {
+    let last_week = week_start - Duration::weeks(1);
+    let previous_ranks: HashMap<&str, u32> = history
+        .iter()
+        .filter(|snapshot| snapshot.week_start == last_week)
+        .flat_map(|snapshot| snapshot.items.iter())
+        .map(|item| (item.id.as_str(), item.rank))
+        .collect();
+
+    items
+        .into_iter()
+        .map(|item| {
+            let past: Vec<u32> = history
+                .iter()
+                .filter(|snapshot| snapshot.week_start < week_start)
+                .filter_map(|snapshot| snapshot.items.iter().find(|i| i.id == item.id))
+                .map(|i| i.rank)
+                .collect();
+
+            let movement = match previous_ranks.get(item.id.as_str()) {
+                Some(&previous) if previous > item.rank => ChartMovement::Up(previous - item.rank),
+                Some(&previous) if previous < item.rank => {
+                    ChartMovement::Down(item.rank - previous)
+                }
+                Some(_) => ChartMovement::Same,
+                None if past.is_empty() => ChartMovement::New,
+                None => ChartMovement::ReEntry,
+            };
+
+            WeeklyChartEntry {
+                movement,
+                weeks_on_chart: past.len() as u32 + 1,
+                peak: past
+                    .iter()
+                    .copied()
+                    .chain([item.rank])
+                    .min()
+                    .unwrap_or(item.rank),
+                item,
+            }
+        })
+        .collect()
+}
+
+/// Artists come from the stats queries as a JSON array of names.
+pub(crate) fn join_artists(artists: &str) -> String {
+    serde_json::from_str::<Vec<Option<String>>>(artists)
+        .map(|names| names.into_iter().flatten().collect::<Vec<_>>().join(", "))
+        .unwrap_or_else(|_| artists.to_string())
+}
@@ -0,0 +1,135 @@
+use anyhow::Result;
+use std::collections::HashMap;
+
+use scrobblify_domain::{
+    db::{ParamsForStatsQuery, Repository},
+    models::{Comparison, PeriodTotals, RankMove, StatsArtist, StatsTag, TagShift},
+};
+
+/// How many artists and genres each period's top keeps, unless the query has a limit.
+const COMPARISON_TOP_SIZE: u64 = 20;
+/// How many climbers, fallers and genre shifts a comparison shows.
+const MOVES_SIZE: usize = 5;
+
+/// Compares the scrobbles of a period against the ones of a previous period.
+pub async fn compare(
+    db: &dyn Repository,
+    previous: ParamsForStatsQuery,
+    current: ParamsForStatsQuery,
+) -> Result<Comparison> {
+    let top = |opts: &ParamsForStatsQuery| ParamsForStatsQuery {
+        limit: Some(opts.limit.unwrap_or(COMPARISON_TOP_SIZE)),
+        ..opts.clone()
+    };
+
+    let previous_artists = db.stats_for_popular_artists(top(&previous)).await?;
+    let current_artists = db.stats_for_popular_artists(top(&current)).await?;
+    let previous_tags = db.stats_for_popular_tags(top(&previous)).await?;
+    let current_tags = db.stats_for_popular_tags(top(&current)).await?;
+    let previous = totals(db, previous).await?;
+    let current = totals(db, current).await?;
+
+    let previous_ranks = ranks(&previous_artists);
+    let current_ranks = ranks(&current_artists);
+    let mut climbers = vec![];
+    let mut fallers = vec![];
+    for (i, artist) in current_artists.iter().enumerate() {
+        let current_rank = i as u32 + 1;
+        let previous_rank = match previous_ranks.get(artist.id.as_str()) {
+            Some(&previous_rank) => previous_rank,
+            None => continue,
+        };
+        let places = previous_rank.abs_diff(current_rank);
+        let rank_move = RankMove {
+            id: artist.id.clone(),
+            name: artist.name.clone(),
+            previous_rank,
+            current_rank,
+            places,
+        };
+        if current_rank < previous_rank {
+            climbers.push(rank_move);
+        } else if current_rank > previous_rank {
+            fallers.push(rank_move);
+        }
+    }
+    for moves in [&mut climbers, &mut fallers] {
+        moves.sort_by(|a, b| {
+            b.places
+                .cmp(&a.places)
+                .then_with(|| a.current_rank.cmp(&b.current_rank))
+        });
+        moves.truncate(MOVES_SIZE);
+    }
+
+    Ok(Comparison {
+        scrobbles_delta: current.scrobbles as i64 - previous.scrobbles as i64,
+        minutes_delta: current.listened_minutes as i64 - previous.listened_minutes as i64,
+        previous,
+        current,
+        gained: current_artists
+            .iter()
+            .filter(|artist| !previous_ranks.contains_key(artist.id.as_str()))
+            .cloned()
+            .collect(),
+        lost: previous_artists
+            .iter()
+            .filter(|artist| !current_ranks.contains_key(artist.id.as_str()))
+            .cloned()
+            .collect(),
+        climbers,
+        fallers,
+        tag_shifts: tag_shifts(&previous_tags, &current_tags),
+    })
+}
+
+async fn totals(db: &dyn Repository, opts: ParamsForStatsQuery) -> Result<PeriodTotals> {
+    let times = db.list_play_times(opts.clone()).await?;
+    let listened_secs: f64 = times.iter().map(|t| t.duration_secs).sum();
+
+    Ok(PeriodTotals {
+        start: opts.start,
+        end: opts.end,
+        scrobbles: times.len() as u32,
+        listened_minutes: (listened_secs / 60.0).round() as u64,
+    })
+}
+
+fn ranks(artists: &[StatsArtist]) -> HashMap<&str, u32> {
+    artists
+        .iter()
+        .enumerate()
+        .map(|(i, artist)| (artist.id.as_str(), i as u32 + 1))
+        .collect()
+}
+
+/// Genres missing from the top of a period count as having no share in it.
+fn tag_shifts(previous: &[StatsTag], current: &[StatsTag]) -> Vec<TagShift> {
+    let mut shares = HashMap::<&str, (u32, u32)>::new();
+    for tag in previous.iter() {
+        shares.entry(tag.name.as_str()).or_default().0 = tag.score;
+    }
+    for tag in current.iter() {
+        shares.entry(tag.name.as_str()).or_default().1 = tag.score;
+    }
+
+    let mut shifts: Vec<TagShift> = shares
+        .into_iter()
+        .map(|(name, (previous_share, current_share))| TagShift {
+            name: name.to_string(),
+            previous_share,
+            current_share,
+            shift: current_share as i32 - previous_share as i32,
+        })
+        .filter(|shift| shift.shift != 0)
+        .collect();
+    shifts.sort_by(|a, b| {
+        b.shift
+            .abs()
+            .cmp(&a.shift.abs())
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    shifts.truncate(MOVES_SIZE);
+
+    shifts
+}
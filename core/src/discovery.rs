@@ -0,0 +1,198 @@
+use anyhow::Result;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use std::collections::{HashMap, HashSet};
+
+use scrobblify_domain::{
+    db::{ParamsForStatsQuery, Repository},
+    models::{
+        ActivityBucket, Discovered, Discoveries, Discovery, DiscoveryPoint, Eclecticism,
+        EntityPlayTime,
+    },
+    time::Tz,
+};
+
+use super::activity::{bucket_start, next_bucket};
+
+/// How many of the artists, tracks and genres discovered in a range are shown.
+const DISCOVERIES_TOP_SIZE: usize = 10;
+
+/// Finds what a user listened to for the first time between two local days of a time
+/// zone, both included, and how eclectic their listening was.
+pub async fn build_discovery(
+    db: &dyn Repository,
+    user_id: i32,
+    start: NaiveDate,
+    end: NaiveDate,
+    bucket: ActivityBucket,
+    tz: Tz,
+) -> Result<Discovery> {
+    // first plays can be older than the range, so the whole history up to its end is needed
+    let history_start = match db.get_first_scrobble_timestamp(user_id).await? {
+        Some(first) => first.with_timezone(&tz).date_naive().min(start),
+        None => start,
+    };
+    let opts = ParamsForStatsQuery::new(history_start, end, None)
+        .for_user(user_id)
+        .in_time_zone(tz);
+
+    let artists = db.list_artist_play_times(opts.clone()).await?;
+    let tracks = db.list_track_play_times(opts.clone()).await?;
+    let tags = db.list_tag_play_times(opts).await?;
+
+    let artists_in_range = plays_in_range(&artists, start, end, tz);
+    let artists_first_plays = first_plays(&artists, tz);
+
+    let artists_evenness = evenness(&artists_in_range);
+    let tags_evenness = evenness(&plays_in_range(&tags, start, end, tz));
+
+    Ok(Discovery {
+        start,
+        end,
+        bucket,
+        new_artists: discoveries(&artists, start, end, tz),
+        new_tracks: discoveries(&tracks, start, end, tz),
+        new_tags: discoveries(&tags, start, end, tz),
+        series: series(&artists_in_range, &artists_first_plays, start, end, bucket),
+        eclecticism: Eclecticism {
+            artists: artists_evenness,
+            tags: tags_evenness,
+            score: (artists_evenness + tags_evenness) / 2,
+        },
+    })
+}
+
+/// The local time and the entity of the plays between two local days, both included.
+fn plays_in_range(
+    plays: &[EntityPlayTime],
+    start: NaiveDate,
+    end: NaiveDate,
+    tz: Tz,
+) -> Vec<(NaiveDateTime, &str)> {
+    plays
+        .iter()
+        .map(|play| {
+            (
+                play.timestamp.with_timezone(&tz).naive_local(),
+                play.id.as_str(),
+            )
+        })
+        .filter(|(local, _)| local.date() >= start && local.date() <= end)
+        .collect()
+}
+
+/// The local time of the first play of each entity. Plays must be sorted, oldest first.
+fn first_plays(plays: &[EntityPlayTime], tz: Tz) -> HashMap<&str, NaiveDateTime> {
+    let mut first = HashMap::new();
+    for play in plays.iter() {
+        first
+            .entry(play.id.as_str())
+            .or_insert_with(|| play.timestamp.with_timezone(&tz).naive_local());
+    }
+
+    first
+}
+
+/// The entities first played in the range, the most played in the range first.
+fn discoveries(plays: &[EntityPlayTime], start: NaiveDate, end: NaiveDate, tz: Tz) -> Discoveries {
+    let mut found = HashMap::<&str, Discovered>::new();
+    let mut seen = HashSet::<&str>::new();
+    for play in plays.iter() {
+        let day = play.timestamp.with_timezone(&tz).date_naive();
+        // the first play decides whether the entity is new in the range
+        if seen.insert(play.id.as_str()) && day >= start && day <= end {
+            found.insert(
+                play.id.as_str(),
+                Discovered {
+                    id: play.id.clone(),
+                    name: play.name.clone(),
+                    first_played: play.timestamp,
+                    scrobbles: 0,
+                },
+            );
+        }
+        if let Some(discovered) = found.get_mut(play.id.as_str()) {
+            discovered.scrobbles += 1;
+        }
+    }
+
+    let mut top: Vec<Discovered> = found.into_values().collect();
+    top.sort_by(|a, b| {
+        b.scrobbles
+            .cmp(&a.scrobbles)
+            .then_with(|| a.first_played.cmp(&b.first_played))
+    });
+    let total = top.len() as u32;
+    top.truncate(DISCOVERIES_TOP_SIZE);
+
+    Discoveries { total, top }
+}
+
+/// How many of the artists played in each bucket were played for the first time. Every
+/// bucket of the range is there, empty ones too.
+fn series(
+    plays: &[(NaiveDateTime, &str)],
+    first_plays: &HashMap<&str, NaiveDateTime>,
+    start: NaiveDate,
+    end: NaiveDate,
+    bucket: ActivityBucket,
+) -> Vec<DiscoveryPoint> {
+    let mut buckets = HashMap::<NaiveDateTime, (HashSet<&str>, HashSet<&str>)>::new();
+    for (local, id) in plays.iter() {
+        let key = bucket_start(*local, bucket);
+        let (artists, new_artists) = buckets.entry(key).or_default();
+        artists.insert(*id);
+        if first_plays
+            .get(id)
+            .is_some_and(|first| bucket_start(*first, bucket) == key)
+        {
+            new_artists.insert(*id);
+        }
+    }
+
+    let mut series = vec![];
+    let mut current = bucket_start(start.and_time(NaiveTime::MIN), bucket);
+    while current.date() <= end {
+        let (artists, new_artists) = buckets
+            .get(&current)
+            .map(|(artists, new_artists)| (artists.len() as u32, new_artists.len() as u32))
+            .unwrap_or_default();
+        series.push(DiscoveryPoint {
+            start: current,
+            artists,
+            new_artists,
+            ratio: match artists {
+                0 => 0,
+                _ => new_artists * 100 / artists,
+            },
+        });
+        current = match next_bucket(current, bucket) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+
+    series
+}
+
+/// The Shannon entropy of the plays of each entity, relative to the highest one possible
+/// with as many entities, from 0 to 100.
+fn evenness(plays: &[(NaiveDateTime, &str)]) -> u32 {
+    let mut counts = HashMap::<&str, u32>::new();
+    for (_, id) in plays.iter() {
+        *counts.entry(*id).or_default() += 1;
+    }
+    if counts.len() < 2 {
+        return 0;
+    }
+
+    let total = plays.len() as f64;
+    let entropy: f64 = counts
+        .values()
+        .map(|&count| {
+            let share = count as f64 / total;
+            -share * share.ln()
+        })
+        .sum();
+
+    (entropy / (counts.len() as f64).ln() * 100.0).round() as u32
+}
@@ -0,0 +1,130 @@
+use anyhow::{anyhow, Result};
+
+use scrobblify_domain::{
+    config::ScrobbleConfig,
+    models::{PlaybackContext, PlaybackDevice},
+};
+
+/// What to do with a play matching a rule.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FilterAction {
+    Allow,
+    Deny,
+}
+
+/// What a rule matches against. Names and types are compared case-insensitively,
+/// context URIs ending with `*` match by prefix.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FilterMatcher {
+    All,
+    DeviceName(String),
+    DeviceType(String),
+    PrivateSession,
+    ContextType(String),
+    ContextUri(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilterRule {
+    pub action: FilterAction,
+    pub matcher: FilterMatcher,
+}
+
+impl FilterRule {
+    /// Whether the play matches the rule, `None` for a device rule when the play doesn't
+    /// tell its device.
+    fn matches(
+        &self,
+        device: Option<&PlaybackDevice>,
+        context: Option<&PlaybackContext>,
+    ) -> Option<bool> {
+        match &self.matcher {
+            FilterMatcher::All => Some(true),
+            FilterMatcher::DeviceName(name) => device.map(|d| d.name.eq_ignore_ascii_case(name)),
+            FilterMatcher::DeviceType(kind) => device.map(|d| d.kind.eq_ignore_ascii_case(kind)),
+            FilterMatcher::PrivateSession => device.map(|d| d.is_private_session),
+            FilterMatcher::ContextType(kind) => {
+                Some(context.is_some_and(|c| c.kind.eq_ignore_ascii_case(kind)))
+            }
+            FilterMatcher::ContextUri(uri) => {
+                Some(context.is_some_and(|c| match uri.strip_suffix('*') {
+                    Some(prefix) => c.uri.starts_with(prefix),
+                    None => c.uri == *uri,
+                }))
+            }
+        }
+    }
+}
+
+/// Allow/deny rules evaluated before a play gets scrobbled.
+///
+/// Rules are checked in order and the first matching one wins, plays matching
+/// no rule are scrobbled. Plays fetched from the recently played history carry
+/// no device: reaching a device rule accepts them, as the ones denied while
+/// auto-scrobbling are left out of it.
+#[derive(Clone, Debug, Default)]
+pub struct ScrobbleFilter {
+    rules: Vec<FilterRule>,
+}
+
+impl ScrobbleFilter {
+    pub fn new(rules: Vec<FilterRule>) -> Self {
+        Self { rules }
+    }
+
+    /// Parses the configured rules, e.g.
+    /// `["deny device_name=Office Speaker", "deny private_session", "allow context_type=album"]`.
+    pub fn from_config(config: &ScrobbleConfig) -> Result<Self> {
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| rule.trim())
+            .filter(|rule| !rule.is_empty() && !rule.starts_with('#'))
+            .map(parse_rule)
+            .collect::<Result<Vec<FilterRule>>>()?;
+
+        Ok(Self::new(rules))
+    }
+
+    pub fn accepts(
+        &self,
+        device: Option<&PlaybackDevice>,
+        context: Option<&PlaybackContext>,
+    ) -> bool {
+        for rule in &self.rules {
+            match rule.matches(device, context) {
+                Some(true) => return rule.action == FilterAction::Allow,
+                Some(false) => continue,
+                None => return true,
+            }
+        }
+        true
+    }
+}
+
+fn parse_rule(rule: &str) -> Result<FilterRule> {
+    let (action, matcher) = rule.split_once(char::is_whitespace).unwrap_or((rule, ""));
+
+    let action = match action {
+        "allow" => FilterAction::Allow,
+        "deny" => FilterAction::Deny,
+        _ => return Err(anyhow!("invalid scrobble rule `{}`: unknown action", rule)),
+    };
+
+    let (key, value) = match matcher.trim().split_once('=') {
+        Some((key, value)) => (key.trim(), value.trim().to_string()),
+        None => (matcher.trim(), String::new()),
+    };
+
+    let matcher = match (key, value.is_empty()) {
+        ("all", true) => FilterMatcher::All,
+        ("private_session", true) => FilterMatcher::PrivateSession,
+        ("device_name", false) => FilterMatcher::DeviceName(value),
+        ("device_type", false) => FilterMatcher::DeviceType(value),
+        ("context_type", false) => FilterMatcher::ContextType(value),
+        ("context_uri", false) => FilterMatcher::ContextUri(value),
+        _ => return Err(anyhow!("invalid scrobble rule `{}`: unknown matcher", rule)),
+    };
+
+    Ok(FilterRule { action, matcher })
+}
@@ -1,5 +1,17 @@
+mod activity;
 mod app;
+mod auth;
+mod charts;
+mod comparison;
+mod discovery;
+mod filters;
+mod rewrite;
 mod scrobbler;
+mod sessions;
+mod streaks;
+mod wrapped;
 
 pub use app::App;
+pub use filters::{FilterAction, FilterMatcher, FilterRule, ScrobbleFilter};
+pub use rewrite::Rewriter;
 pub use scrobbler::*;
@@ -0,0 +1,52 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+
+use scrobblify_domain::models::{RewriteField, RewriteRule, TrackInfo};
+
+/// Applies rewrite rules to scrobbles metadata, in the order they have been defined.
+#[derive(Clone, Debug, Default)]
+pub struct Rewriter {
+    rules: Vec<(RewriteRule, Regex)>,
+}
+
+impl Rewriter {
+    pub fn new(rules: Vec<RewriteRule>) -> Result<Self> {
+        let rules = rules
+            .into_iter()
+            .map(|rule| compile(&rule).map(|regex| (rule, regex)))
+            .collect::<Result<Vec<(RewriteRule, Regex)>>>()?;
+
+        Ok(Self { rules })
+    }
+
+    pub fn rewrite_track(&self, track: &mut TrackInfo, origin: &str) {
+        track.title = self.rewrite(RewriteField::Title, &track.title, Some(origin));
+        track.album.title = self.rewrite(RewriteField::Album, &track.album.title, Some(origin));
+        for artist in track.artists.iter_mut() {
+            artist.name = self.rewrite(RewriteField::Artist, &artist.name, Some(origin));
+        }
+    }
+
+    /// Rewrites a single value. When no origin is given, rules are applied regardless of
+    /// the origin they're bound to.
+    pub fn rewrite(&self, field: RewriteField, value: &str, origin: Option<&str>) -> String {
+        self.rules
+            .iter()
+            .filter(|(rule, _)| rule.field == field)
+            .filter(|(rule, _)| match (&rule.origin, origin) {
+                (Some(rule_origin), Some(origin)) => rule_origin == origin,
+                _ => true,
+            })
+            .fold(value.to_string(), |value, (rule, regex)| {
+                regex
+                    .replace_all(&value, rule.replacement.as_str())
+                    .trim()
+                    .to_string()
+            })
+    }
+}
+
+/// Checks that a rule has a valid pattern.
+pub fn compile(rule: &RewriteRule) -> Result<Regex> {
+    Regex::new(&rule.pattern).with_context(|| format!("invalid pattern `{}`", rule.pattern))
+}
@@ -1,23 +1,26 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 use tokio::{
     sync::Mutex,
+    task::JoinHandle,
     time::{sleep, Duration},
 };
 
 use scrobblify_domain::{
     app::App as DomainApp,
-    models::{CurrentPlayingTrack, ScrobbleInfo},
+    config::SpotifySourceConfig,
+    models::{CurrentPlayingTrack, EpisodeScrobbleInfo, PlayingItem, ScrobbleInfo, User},
 };
 
 use super::App;
 
 const SCROBBLE_LISTENING_MIN_SECS: u64 = 180;
-const SPOTIFY_POLLING_SECS: u64 = 60;
+const SUPERVISOR_POLLING_SECS: u64 = 60;
 
 pub enum ScrobblerResult {
     Ok(ScrobbleInfo),
+    Episode(EpisodeScrobbleInfo),
     Cache,
     NotPlaying,
     AlreadyScrobbled,
@@ -27,66 +30,166 @@ pub enum ScrobblerResult {
 pub struct Scrobbler;
 
 impl Scrobbler {
-    pub async fn start_auto_scrobbling(app: Arc<Mutex<App>>) {
+    /// Keeps a scrobbling task running for every user linked to Spotify. Users linking their
+    /// account later are picked up at the next check, tasks that died get restarted and
+    /// the ones of users who unlinked their account get stopped.
+    pub async fn start_auto_scrobbling(app: Arc<Mutex<App>>, config: SpotifySourceConfig) {
+        if !config.enabled {
+            tracing::info!(msg = "spotify auto-scrobbling is disabled");
+            return;
+        }
+        let polling = Duration::from_secs(config.polling_secs);
+
         tokio::spawn(async move {
-            tracing::info!(msg = "start auto-scrobbling");
+            tracing::info!(msg = "start scrobblers supervisor");
+            let mut tasks: HashMap<i32, JoinHandle<()>> = HashMap::new();
 
             loop {
-                if let Err(err) = Self::auto_scrobble(app.clone()).await {
-                    tracing::error!(msg = "auto_scrobble", error = format!("{:?}", err));
+                let user_ids: Vec<i32> = tasks.keys().copied().collect();
+                for user_id in user_ids {
+                    if app.lock().await.is_spotify_authenticated(user_id).await {
+                        continue;
+                    }
+                    if let Some(task) = tasks.remove(&user_id) {
+                        tracing::info!(msg = "stop auto-scrobbling", user_id = user_id);
+                        task.abort();
+                    }
                 }
-                let duration = Duration::new(SPOTIFY_POLLING_SECS, 0);
+
+                tasks.retain(|user_id, task| {
+                    if task.is_finished() {
+                        tracing::error!(msg = "scrobbler stopped", user_id = *user_id);
+                    }
+                    !task.is_finished()
+                });
+
+                // the users are listed again on the next round
+                let users = match app.lock().await.list_users().await {
+                    Ok(users) => users,
+                    Err(err) => {
+                        tracing::error!(msg = "list_users", error = format!("{:?}", err));
+                        vec![]
+                    }
+                };
+                for user in users {
+                    if tasks.contains_key(&user.id)
+                        || !app.lock().await.is_spotify_authenticated(user.id).await
+                    {
+                        continue;
+                    }
+                    let user_id = user.id;
+                    let task = Self::start_user_scrobbling(app.clone(), user, polling);
+                    tasks.insert(user_id, task);
+                }
+
+                let duration = Duration::new(SUPERVISOR_POLLING_SECS, 0);
                 sleep(duration).await;
             }
         });
     }
 
-    pub async fn scrobble_recently_played(app: Arc<Mutex<App>>) {
-        tracing::info!(msg = "check recently played tracks");
-        let mut recently_played = match app.clone().lock().await.get_recently_played().await {
+    fn start_user_scrobbling(
+        app: Arc<Mutex<App>>,
+        user: User,
+        polling: Duration,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            tracing::info!(msg = "start auto-scrobbling", user = user.name);
+            Self::scrobble_recently_played(app.clone(), user.id).await;
+
+            loop {
+                if let Err(err) = Self::auto_scrobble(app.clone(), user.id).await {
+                    tracing::error!(
+                        msg = "auto_scrobble",
+                        user = user.name,
+                        error = format!("{:?}", err)
+                    );
+                }
+                sleep(polling).await;
+            }
+        })
+    }
+
+    pub async fn scrobble_recently_played(app: Arc<Mutex<App>>, user_id: i32) {
+        tracing::info!(msg = "check recently played tracks", user_id = user_id);
+        let recently_played = app.clone().lock().await.get_recently_played(user_id).await;
+        let mut recently_played = match recently_played {
             Ok(rp) => rp,
             Err(err) => {
                 tracing::error!(msg = "recently_played", error = format!("{:?}", err));
-                return ();
+                return;
             }
         };
 
         recently_played.reverse();
         for played in recently_played {
             let scrobble = ScrobbleInfo {
+                user_id,
                 timestamp: played.played_at,
                 duration_secs: played.track.duration_secs.as_secs_f64(),
+                origin: String::from("spotify"),
                 track: played.track,
+                device: None,
+                context: played.context,
             };
 
+            if !app
+                .clone()
+                .lock()
+                .await
+                .accepts_playback(None, scrobble.context.as_ref())
+            {
+                log_scrobbling(&scrobble.clone(), "skip: filtered by scrobble rules");
+                continue;
+            }
+
             log_scrobbling(&scrobble.clone(), "recently_played");
             let _ = app.clone().lock().await.scrobble(scrobble).await;
         }
     }
 
-    async fn auto_scrobble(app: Arc<Mutex<App>>) -> Result<()> {
+    /// Checks once what the user is playing, scrobbling it when it has been listened long
+    /// enough and keeping it for the next check otherwise.
+    pub async fn auto_scrobble(app: Arc<Mutex<App>>, user_id: i32) -> Result<()> {
         let mut app = app.lock().await;
-        let current = &app.get_currently_playing().await?;
-        let cache = app.get_current_track();
+        let current = &app.get_currently_playing(user_id).await?;
+        let cache = app.get_current_track(user_id);
 
-        match calculate_scrobble(current, cache) {
+        match calculate_scrobble(user_id, current, &cache, app.now()) {
             ScrobblerResult::Ok(scrobble) => {
                 let mut new_current = current.clone().unwrap();
                 new_current.scrobbled = true;
 
-                log_scrobbling(&scrobble.clone(), "scrobble");
-                app.scrobble(scrobble).await?;
-                app.set_current_track(Some(new_current.clone()));
+                if app.accepts_playback(scrobble.device.as_ref(), scrobble.context.as_ref()) {
+                    log_scrobbling(&scrobble.clone(), "scrobble");
+                    app.scrobble(scrobble).await?;
+                } else {
+                    log_scrobbling(&scrobble.clone(), "skip: filtered by scrobble rules");
+                    app.skip_play(&scrobble).await?;
+                }
+                app.set_current_track(user_id, Some(new_current.clone()));
+            }
+            ScrobblerResult::Episode(scrobble) => {
+                let mut new_current = current.clone().unwrap();
+                new_current.scrobbled = true;
+
+                if app.accepts_playback(scrobble.device.as_ref(), scrobble.context.as_ref()) {
+                    log_episode_scrobbling(&scrobble.clone(), "scrobble");
+                    app.scrobble_episode(scrobble).await?;
+                } else {
+                    log_episode_scrobbling(&scrobble.clone(), "skip: filtered by scrobble rules");
+                }
+                app.set_current_track(user_id, Some(new_current.clone()));
             }
             ScrobblerResult::Cache => {
                 let new_current = current.clone().unwrap();
-                app.set_current_track(Some(new_current.clone()));
+                app.set_current_track(user_id, Some(new_current.clone()));
 
-                let title = new_current.clone().track.title;
+                let title = new_current.item.title().to_string();
                 tracing::debug!(msg = "cache track", title = title,);
             }
             ScrobblerResult::NotPlaying => {
-                app.set_current_track(None);
+                app.set_current_track(user_id, None);
                 tracing::debug!(msg = "ignore: nothing is playing");
             }
             ScrobblerResult::AlreadyScrobbled => {
@@ -102,8 +205,10 @@ impl Scrobbler {
 }
 
 fn calculate_scrobble(
+    user_id: i32,
     current: &Option<CurrentPlayingTrack>,
     cache: &Option<CurrentPlayingTrack>,
+    now: DateTime<Utc>,
 ) -> ScrobblerResult {
     match (current, cache) {
         // track has been playing for enough time, so we scrobble it
@@ -119,13 +224,29 @@ fn calculate_scrobble(
             }
 
             let timestamp = get_timestamp(current, cache);
-            if let Some(duration) = calculate_duration(&current, timestamp) {
+            if let Some(duration) = calculate_duration(current, timestamp, now) {
                 // the track has been playing for enough, scrobble it
-                return ScrobblerResult::Ok(ScrobbleInfo {
-                    timestamp,
-                    duration_secs: duration as f64,
-                    track: current.clone().track,
-                });
+                return match current.clone().item {
+                    PlayingItem::Track(track) => ScrobblerResult::Ok(ScrobbleInfo {
+                        user_id,
+                        timestamp,
+                        duration_secs: duration as f64,
+                        origin: String::from("spotify"),
+                        track,
+                        device: current.device.clone(),
+                        context: current.context.clone(),
+                    }),
+                    PlayingItem::Episode(episode) => {
+                        ScrobblerResult::Episode(EpisodeScrobbleInfo {
+                            user_id,
+                            timestamp,
+                            duration_secs: duration as f64,
+                            episode,
+                            device: current.device.clone(),
+                            context: current.context.clone(),
+                        })
+                    }
+                };
             }
             // the track hasn't been playing for enough, skip for later
             ScrobblerResult::NotReadyForScrobble
@@ -144,8 +265,11 @@ fn get_timestamp(current: &CurrentPlayingTrack, cache: &CurrentPlayingTrack) ->
     current.clone().timestamp
 }
 
-fn calculate_duration(current: &CurrentPlayingTrack, timestamp: DateTime<Utc>) -> Option<u64> {
-    let now = Utc::now();
+fn calculate_duration(
+    current: &CurrentPlayingTrack,
+    timestamp: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Option<u64> {
     let listened_time = now
         .signed_duration_since(timestamp)
         .to_std()
@@ -156,11 +280,11 @@ fn calculate_duration(current: &CurrentPlayingTrack, timestamp: DateTime<Utc>) -
                 now = format!("{:?}", now),
                 error = format!("{:?}", err)
             );
-            return Duration::new(0, 0);
+            Duration::new(0, 0)
         })
         .as_secs();
 
-    let duration = current.track.clone().duration_secs.as_secs();
+    let duration = current.item.duration_secs().as_secs();
     if listened_time >= (duration / 2) || listened_time >= SCROBBLE_LISTENING_MIN_SECS {
         return Some(duration);
     }
@@ -182,3 +306,11 @@ fn log_scrobbling(scrobble: &ScrobbleInfo, msg: &str) {
 
     tracing::info!(msg, title = title, artists = artists, timestamp = timestamp,);
 }
+
+fn log_episode_scrobbling(scrobble: &EpisodeScrobbleInfo, msg: &str) {
+    let title = scrobble.clone().episode.title;
+    let show = scrobble.clone().episode.show.name;
+    let timestamp = format!("{}", scrobble.clone().timestamp.format("%Y/%m/%d %H:%M"));
+
+    tracing::info!(msg, title = title, show = show, timestamp = timestamp,);
+}
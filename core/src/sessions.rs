@@ -0,0 +1,79 @@
+use chrono::{Duration, NaiveDate, Timelike};
+
+use scrobblify_domain::{
+    models::{ListeningSession, Scrobble, SessionStartHour, Sessions},
+    time::Tz,
+};
+
+use super::activity::level;
+
+/// Groups the scrobbles into sessions, which end when nothing is played for longer than
+/// the gap, and describes when they start in the local hours of a time zone.
+pub fn build_sessions(
+    mut scrobbles: Vec<Scrobble>,
+    start: NaiveDate,
+    end: NaiveDate,
+    gap: Duration,
+    tz: Tz,
+) -> Sessions {
+    scrobbles.sort_by_key(|s| s.timestamp);
+
+    let mut sessions: Vec<ListeningSession> = vec![];
+    for scrobble in scrobbles.into_iter() {
+        let ended_at = scrobble.timestamp + played_for(&scrobble);
+        match sessions.last_mut() {
+            Some(session) if scrobble.timestamp - session.end <= gap => {
+                session.end = session.end.max(ended_at);
+                session.scrobbles.push(scrobble);
+            }
+            _ => sessions.push(ListeningSession {
+                start: scrobble.timestamp,
+                end: ended_at,
+                scrobbles: vec![scrobble],
+            }),
+        }
+    }
+
+    let mut hours = [0u32; 24];
+    for session in sessions.iter() {
+        hours[session.start.with_timezone(&tz).hour() as usize] += 1;
+    }
+    let max = hours.iter().copied().max().unwrap_or(0);
+
+    let average_minutes = match sessions.len() {
+        0 => 0,
+        count => sessions.iter().map(|s| s.minutes()).sum::<i64>() / count as i64,
+    };
+    // the earliest one on ties
+    let longest = sessions
+        .iter()
+        .rev()
+        .max_by_key(|s| s.end - s.start)
+        .cloned();
+    sessions.reverse();
+
+    Sessions {
+        start,
+        end,
+        sessions,
+        average_minutes,
+        longest,
+        start_hours: hours
+            .iter()
+            .enumerate()
+            .map(|(hour, &sessions)| SessionStartHour {
+                hour: hour as u32,
+                sessions,
+                level: level(sessions, max),
+            })
+            .collect(),
+        typical_start_hour: (max > 0)
+            .then(|| hours.iter().position(|&sessions| sessions == max))
+            .flatten()
+            .map(|hour| hour as u32),
+    }
+}
+
+fn played_for(scrobble: &Scrobble) -> Duration {
+    Duration::from_std(scrobble.duration_secs).unwrap_or_else(|_| Duration::zero())
+}
@@ -0,0 +1,170 @@
+use anyhow::Result;
+use chrono::{Datelike, NaiveDate};
+use std::collections::{BTreeSet, HashMap};
+
+use scrobblify_domain::{
+    db::{ParamsForStatsQuery, Repository},
+    models::{
+        EntityDay, EntityStreak, ListeningStreak, Milestones, OnThisDay, ScrobbleMilestone, Streaks,
+    },
+    time::Tz,
+};
+
+use super::wrapped::longest_streak;
+
+/// How many artists and tracks with the longest streaks are shown.
+const ENTITY_STREAKS_SIZE: usize = 5;
+/// How many artists played for the first time are shown.
+const FIRST_PLAYS_SIZE: u64 = 5;
+/// Scrobble counts worth celebrating.
+const MILESTONES: [u64; 12] = [
+    1, 100, 500, 1_000, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000, 1_000_000,
+];
+
+/// Builds the streaks of a user over their whole history up to today, with the days of a
+/// time zone.
+pub async fn build_streaks(
+    db: &dyn Repository,
+    user_id: i32,
+    today: NaiveDate,
+    tz: Tz,
+) -> Result<Streaks> {
+    let first = match db.get_first_scrobble_timestamp(user_id).await? {
+        Some(first) => first.with_timezone(&tz).date_naive(),
+        None => return Ok(Streaks::default()),
+    };
+    let opts = ParamsForStatsQuery::new(first, today, None)
+        .for_user(user_id)
+        .in_time_zone(tz);
+
+    let days: BTreeSet<NaiveDate> = db
+        .list_listening_days(opts.clone())
+        .await?
+        .into_iter()
+        .collect();
+
+    Ok(Streaks {
+        current: current_streak(&days, today),
+        longest: longest_streak(days.iter().copied()),
+        artists: entity_streaks(&db.list_artist_listening_days(opts.clone()).await?),
+        tracks: entity_streaks(&db.list_track_listening_days(opts).await?),
+    })
+}
+
+/// Finds the scrobbles that reached a milestone, and the next one to reach.
+pub async fn build_milestones(db: &dyn Repository, user_id: i32) -> Result<Milestones> {
+    let total_scrobbles = db.count_scrobbles(user_id).await?;
+
+    let mut reached = vec![];
+    for count in MILESTONES.iter().copied() {
+        if count > total_scrobbles {
+            break;
+        }
+        if let Some(scrobble) = db.get_nth_scrobble(user_id, count).await? {
+            reached.push(ScrobbleMilestone { count, scrobble });
+        }
+    }
+    // the latest milestone comes first
+    reached.reverse();
+
+    Ok(Milestones {
+        total_scrobbles,
+        reached,
+        next: MILESTONES
+            .iter()
+            .copied()
+            .find(|&count| count > total_scrobbles),
+        first_plays: db.list_first_plays(user_id, FIRST_PLAYS_SIZE).await?,
+    })
+}
+
+/// What was played today in the past years, the latest year first. Years without
+/// today's date, like Feb 29th, are skipped.
+pub async fn on_this_day(
+    db: &dyn Repository,
+    user_id: i32,
+    today: NaiveDate,
+    tz: Tz,
+) -> Result<Vec<OnThisDay>> {
+    let first_year = match db.get_first_scrobble_timestamp(user_id).await? {
+        Some(first) => first.with_timezone(&tz).year(),
+        None => return Ok(vec![]),
+    };
+
+    let mut years = vec![];
+    for year in (first_year..today.year()).rev() {
+        let day = match NaiveDate::from_ymd_opt(year, today.month(), today.day()) {
+            Some(day) => day,
+            None => continue,
+        };
+        let mut scrobbles = db
+            .list_scrobbles_by_date_range(
+                ParamsForStatsQuery::new(day, day, None)
+                    .for_user(user_id)
+                    .in_time_zone(tz),
+            )
+            .await?;
+        if scrobbles.is_empty() {
+            continue;
+        }
+
+        scrobbles.sort_by_key(|s| s.timestamp);
+        years.push(OnThisDay { year, scrobbles });
+    }
+
+    Ok(years)
+}
+
+/// The streak ending today or, when nothing has been played yet today, yesterday.
+fn current_streak(days: &BTreeSet<NaiveDate>, today: NaiveDate) -> Option<ListeningStreak> {
+    let end = [Some(today), today.pred_opt()]
+        .into_iter()
+        .flatten()
+        .find(|day| days.contains(day))?;
+
+    let mut start = end;
+    while let Some(previous) = start.pred_opt().filter(|day| days.contains(day)) {
+        start = previous;
+    }
+
+    Some(ListeningStreak {
+        start,
+        end,
+        days: (end - start).num_days() as u32 + 1,
+    })
+}
+
+/// The longest streak of each artist or track, the longest ones first. Single days
+/// aren't streaks, so they're left out.
+fn entity_streaks(plays: &[EntityDay]) -> Vec<EntityStreak> {
+    let mut days = HashMap::<&str, (&str, BTreeSet<NaiveDate>)>::new();
+    for play in plays.iter() {
+        days.entry(play.id.as_str())
+            .or_insert_with(|| (play.name.as_str(), BTreeSet::new()))
+            .1
+            .insert(play.day);
+    }
+
+    let mut streaks: Vec<EntityStreak> = days
+        .into_iter()
+        .filter_map(|(id, (name, days))| {
+            longest_streak(days.into_iter())
+                .filter(|streak| streak.days > 1)
+                .map(|streak| EntityStreak {
+                    id: id.to_string(),
+                    name: name.to_string(),
+                    streak,
+                })
+        })
+        .collect();
+    streaks.sort_by(|a, b| {
+        b.streak
+            .days
+            .cmp(&a.streak.days)
+            .then_with(|| b.streak.end.cmp(&a.streak.end))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    streaks.truncate(ENTITY_STREAKS_SIZE);
+
+    streaks
+}
@@ -0,0 +1,191 @@
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, Duration, NaiveDate};
+use std::collections::BTreeMap;
+
+use scrobblify_domain::{
+    db::{ParamsForStatsQuery, Repository},
+    models::{DayPlays, ListeningStreak, Wrapped, WrappedMonth},
+    time::Tz,
+};
+
+use super::charts::join_artists;
+
+/// How many tracks, artists, albums and genres a year in review shows.
+const WRAPPED_TOP_SIZE: u64 = 5;
+/// How many genres each month shows, to follow how taste changes.
+const MONTHLY_TAGS_SIZE: u64 = 3;
+const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Builds the year in review of a user, with the days of a time zone.
+pub async fn build_wrapped(
+    db: &dyn Repository,
+    user_id: i32,
+    year: i32,
+    tz: Tz,
+) -> Result<Wrapped> {
+    let (first_day, last_day) =
+        year_range(year).ok_or_else(|| anyhow!("{} is out of range", year))?;
+    let year_opts = |limit: u64| {
+        ParamsForStatsQuery::new(first_day, last_day, Some(limit))
+            .for_user(user_id)
+            .in_time_zone(tz)
+    };
+
+    let scrobbles = db
+        .list_scrobbles_by_date_range(
+            ParamsForStatsQuery::new(first_day, last_day, None)
+                .for_user(user_id)
+                .in_time_zone(tz),
+        )
+        .await?;
+    let listened_secs: f64 = scrobbles
+        .iter()
+        .map(|s| s.duration_secs.as_secs_f64())
+        .sum();
+
+    let mut plays_by_day = BTreeMap::<NaiveDate, u32>::new();
+    for scrobble in scrobbles.iter() {
+        let day = scrobble.timestamp.with_timezone(&tz).date_naive();
+        *plays_by_day.entry(day).or_default() += 1;
+    }
+    let mut plays_by_month = [0u32; 12];
+    for (day, plays) in plays_by_day.iter() {
+        plays_by_month[day.month0() as usize] += plays;
+    }
+
+    let mut top_tracks = db
+        .stats_for_popular_tracks(year_opts(WRAPPED_TOP_SIZE))
+        .await?;
+    for track in top_tracks.iter_mut() {
+        track.artists = join_artists(&track.artists);
+    }
+    let mut top_albums = db
+        .stats_for_popular_albums(year_opts(WRAPPED_TOP_SIZE))
+        .await?;
+    for album in top_albums.iter_mut() {
+        album.artists = join_artists(&album.artists);
+    }
+
+    let new_artists = db.stats_for_new_artists(year_opts(u32::MAX as u64)).await?;
+
+    let mut months = vec![];
+    for (month0, name) in MONTH_NAMES.iter().enumerate() {
+        let month_range = |month0: u32| first_day.with_month0(month0);
+        let (start, end) = match (month_range(month0 as u32), month_range(month0 as u32 + 1)) {
+            (Some(start), Some(next)) => (start, next - Duration::days(1)),
+            (Some(start), None) => (start, last_day),
+            _ => return Err(anyhow!("{} is out of range", year)),
+        };
+        let opts = |limit: u64| {
+            ParamsForStatsQuery::new(start, end, Some(limit))
+                .for_user(user_id)
+                .in_time_zone(tz)
+        };
+
+        let top_track = db
+            .stats_for_popular_tracks(opts(1))
+            .await?
+            .into_iter()
+            .next()
+            .map(|mut track| {
+                track.artists = join_artists(&track.artists);
+                track
+            });
+
+        months.push(WrappedMonth {
+            month: month0 as u32 + 1,
+            name: name.to_string(),
+            scrobbles: plays_by_month[month0],
+            top_track,
+            top_tags: db.stats_for_popular_tags(opts(MONTHLY_TAGS_SIZE)).await?,
+        });
+    }
+
+    Ok(Wrapped {
+        year,
+        scrobbles: scrobbles.len() as u32,
+        listened_minutes: (listened_secs / 60.0).round() as u64,
+        top_tracks,
+        top_artists: db
+            .stats_for_popular_artists(year_opts(WRAPPED_TOP_SIZE))
+            .await?,
+        top_albums,
+        top_tags: db
+            .stats_for_popular_tags(year_opts(WRAPPED_TOP_SIZE))
+            .await?,
+        most_played_day: most_played_day(&plays_by_day),
+        longest_streak: longest_streak(plays_by_day.keys().copied()),
+        new_artists: new_artists.len() as u32,
+        top_new_artists: new_artists
+            .into_iter()
+            .take(WRAPPED_TOP_SIZE as usize)
+            .collect(),
+        months,
+    })
+}
+
+/// The day with the most scrobbles, the earliest one on ties.
+/// The first and last day of a year, unless it's too far in the past or in the future
+/// to be queried: ranges end at the start of the day after their last one.
+fn year_range(year: i32) -> Option<(NaiveDate, NaiveDate)> {
+    let first_day = NaiveDate::from_ymd_opt(year, 1, 1)?;
+    let last_day = NaiveDate::from_ymd_opt(year, 12, 31)?;
+    last_day.succ_opt()?;
+
+    Some((first_day, last_day))
+}
+
+fn most_played_day(plays_by_day: &BTreeMap<NaiveDate, u32>) -> Option<DayPlays> {
+    plays_by_day.iter().fold(
+        None,
+        |best: Option<DayPlays>, (&day, &scrobbles)| match best {
+            Some(best) if best.scrobbles >= scrobbles => Some(best),
+            _ => Some(DayPlays { day, scrobbles }),
+        },
+    )
+}
+
+/// The longest run of consecutive days, the earliest one on ties. Days must be sorted.
+pub(crate) fn longest_streak(days: impl Iterator<Item = NaiveDate>) -> Option<ListeningStreak> {
+    let mut longest: Option<ListeningStreak> = None;
+    let mut current: Option<ListeningStreak> = None;
+
+    for day in days {
+        current = match current {
+            Some(streak) if streak.end.succ_opt() == Some(day) => Some(ListeningStreak {
+                end: day,
+                days: streak.days + 1,
+                ..streak
+            }),
+            _ => Some(ListeningStreak {
+                start: day,
+                end: day,
+                days: 1,
+            }),
+        };
+
+        if let Some(streak) = &current {
+            if longest
+                .as_ref()
+                .is_none_or(|longest| streak.days > longest.days)
+            {
+                longest = Some(streak.clone());
+            }
+        }
+    }
+
+    longest
+}
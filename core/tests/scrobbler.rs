@@ -0,0 +1,608 @@
+//! Tests for the scrobbler, driven one check at a time against an in-memory repository,
+//! a fake Spotify and a clock that only moves when told to:
+//!
+//! ```sh
+//! cargo test -p scrobblify-core
+//! ```
+
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use scrobblify_core::{App, FilterAction, FilterMatcher, FilterRule, ScrobbleFilter, Scrobbler};
+use scrobblify_domain::{
+    app::App as _,
+    db::{ParamsForStatsQuery, Repository as _},
+    identity,
+    models::{
+        ActivityBucket, Album, Artist, CurrentPlayingTrack, EpisodeInfo, HistoryPlayedTrack,
+        PlaybackDevice, PlayingItem, RewriteField, RewriteRule, ScrobbleInfo, Show, Tag, TrackInfo,
+    },
+    time::{Clock as _, Tz},
+};
+use scrobblify_testing::{FakeSpotify, ManualClock, MemoryRepository, MemoryTokenStore};
+
+#[tokio::test]
+async fn caches_then_scrobbles_after_listening_enough() {
+    let harness = Harness::new().await;
+    let track = track_info("track-1", "Song");
+    harness.spotify.set_tags("artist-1", vec![tag("rock")]);
+    harness.play(&track, start());
+
+    // the first check only remembers what is playing
+    harness.check().await;
+    assert_eq!(harness.scrobbles().await, 0);
+    assert!(harness.current_track().await.is_some());
+
+    // listened for less than half of the track
+    harness.clock.advance(Duration::seconds(60));
+    harness.check().await;
+    assert_eq!(harness.scrobbles().await, 0);
+
+    harness.clock.advance(Duration::seconds(30));
+    harness.check().await;
+    let last = harness.repo.get_last_scrobble(DEFAULT_USER).await.unwrap();
+    let last = last.expect("the track should be scrobbled");
+    assert_eq!(last.timestamp, start());
+    assert_eq!(last.track, "Song");
+    assert_eq!(last.artists, vec!["Artist"]);
+    assert_eq!(last.tags, vec!["rock"]);
+    assert!(harness.current_track().await.unwrap().scrobbled);
+
+    // still the same play, it's scrobbled only once
+    harness.clock.advance(Duration::seconds(60));
+    harness.check().await;
+    assert_eq!(harness.scrobbles().await, 1);
+}
+
+#[tokio::test]
+async fn scrobbles_long_tracks_after_three_minutes() {
+    let harness = Harness::new().await;
+    let mut track = track_info("track-1", "Suite");
+    track.duration_secs = std::time::Duration::from_secs(1200);
+    harness.play(&track, start());
+
+    harness.check().await;
+    harness.clock.advance(Duration::seconds(179));
+    harness.check().await;
+    assert_eq!(harness.scrobbles().await, 0);
+
+    harness.clock.advance(Duration::seconds(1));
+    harness.check().await;
+    assert_eq!(harness.scrobbles().await, 1);
+}
+
+#[tokio::test]
+async fn skipped_tracks_are_not_scrobbled() {
+    let harness = Harness::new().await;
+    harness.play(&track_info("track-1", "Skipped"), start());
+    harness.check().await;
+
+    harness.clock.advance(Duration::seconds(30));
+    harness.play(&track_info("track-2", "Played"), harness.clock.now());
+    harness.check().await;
+    assert_eq!(harness.scrobbles().await, 0);
+
+    harness.clock.advance(Duration::seconds(90));
+    harness.check().await;
+    let last = harness.repo.get_last_scrobble(DEFAULT_USER).await.unwrap();
+    assert_eq!(last.unwrap().track, "Played");
+    assert_eq!(harness.scrobbles().await, 1);
+}
+
+#[tokio::test]
+async fn stopping_forgets_the_current_track() {
+    let harness = Harness::new().await;
+    let track = track_info("track-1", "Song");
+    harness.play(&track, start());
+    harness.check().await;
+
+    harness.clock.advance(Duration::seconds(30));
+    harness.spotify.stop(DEFAULT_USER);
+    harness.check().await;
+    assert!(harness.current_track().await.is_none());
+
+    // playing it again starts over
+    harness.clock.advance(Duration::seconds(120));
+    harness.play(&track, harness.clock.now());
+    harness.check().await;
+    assert_eq!(harness.scrobbles().await, 0);
+}
+
+#[tokio::test]
+async fn scrobbles_podcast_episodes_apart() {
+    let harness = Harness::new().await;
+    harness.spotify.play(
+        DEFAULT_USER,
+        playing(PlayingItem::Episode(episode_info()), start()),
+    );
+
+    harness.check().await;
+    harness.clock.advance(Duration::seconds(180));
+    harness.check().await;
+
+    let shows = harness.repo.stats_for_popular_shows(today()).await.unwrap();
+    assert_eq!(shows.len(), 1);
+    assert_eq!(shows[0].name, "Show");
+    assert_eq!(shows[0].episodes, 1);
+    assert_eq!(harness.scrobbles().await, 0);
+}
+
+#[tokio::test]
+async fn scrobbles_recently_played_since_the_last_scrobble() {
+    let harness = Harness::new().await;
+    harness.play(&track_info("track-1", "Live"), start());
+    harness.check().await;
+    harness.clock.advance(Duration::seconds(90));
+    harness.check().await;
+
+    harness.spotify.set_recently_played(
+        DEFAULT_USER,
+        vec![
+            played(
+                &track_info("track-2", "Before"),
+                start() - Duration::hours(1),
+            ),
+            played(
+                &track_info("track-3", "After"),
+                start() + Duration::minutes(5),
+            ),
+            played(
+                &track_info("track-4", "Later"),
+                start() + Duration::minutes(10),
+            ),
+        ],
+    );
+    Scrobbler::scrobble_recently_played(harness.app.clone(), DEFAULT_USER).await;
+
+    let titles: Vec<String> = harness
+        .repo
+        .list_scrobbles_by_date_range(today())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|scrobble| scrobble.track)
+        .collect();
+    assert_eq!(titles, vec!["Later", "After", "Live"]);
+}
+
+#[tokio::test]
+async fn plays_denied_by_device_are_left_out_of_recently_played() {
+    let harness = Harness::with_filter(ScrobbleFilter::new(vec![FilterRule {
+        action: FilterAction::Deny,
+        matcher: FilterMatcher::DeviceName("Office Speaker".to_string()),
+    }]))
+    .await;
+    harness.play(&track_info("track-1", "Live"), start());
+    harness.check().await;
+    harness.clock.advance(Duration::seconds(90));
+    harness.check().await;
+
+    let denied = track_info("track-2", "Denied");
+    let mut playing_denied = playing(
+        PlayingItem::Track(denied.clone()),
+        start() + Duration::minutes(5),
+    );
+    playing_denied.device = Some(PlaybackDevice {
+        id: None,
+        name: "Office Speaker".to_string(),
+        kind: "Speaker".to_string(),
+        is_private_session: false,
+    });
+    harness.clock.set(start() + Duration::minutes(5));
+    harness.spotify.play(DEFAULT_USER, playing_denied);
+    harness.check().await;
+    harness.clock.advance(Duration::seconds(90));
+    harness.check().await;
+    assert_eq!(harness.scrobbles().await, 1);
+
+    // the history doesn't tell the device, the denied play is recognized by its time
+    harness.spotify.set_recently_played(
+        DEFAULT_USER,
+        vec![
+            played(&denied, start() + Duration::minutes(8)),
+            played(
+                &track_info("track-3", "After"),
+                start() + Duration::minutes(12),
+            ),
+        ],
+    );
+    Scrobbler::scrobble_recently_played(harness.app.clone(), DEFAULT_USER).await;
+
+    let titles: Vec<String> = harness
+        .repo
+        .list_scrobbles_by_date_range(today())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|scrobble| scrobble.track)
+        .collect();
+    assert_eq!(titles, vec!["After", "Live"]);
+}
+
+#[tokio::test]
+async fn device_rules_dont_drop_recently_played() {
+    let harness = Harness::with_filter(ScrobbleFilter::new(vec![
+        FilterRule {
+            action: FilterAction::Allow,
+            matcher: FilterMatcher::DeviceName("Phone".to_string()),
+        },
+        FilterRule {
+            action: FilterAction::Deny,
+            matcher: FilterMatcher::All,
+        },
+    ]))
+    .await;
+    let mut live = playing(PlayingItem::Track(track_info("track-1", "Live")), start());
+    live.device = Some(PlaybackDevice {
+        id: None,
+        name: "Phone".to_string(),
+        kind: "Smartphone".to_string(),
+        is_private_session: false,
+    });
+    harness.spotify.play(DEFAULT_USER, live);
+    harness.check().await;
+    harness.clock.advance(Duration::seconds(90));
+    harness.check().await;
+    assert_eq!(harness.scrobbles().await, 1);
+
+    // the history doesn't tell the device, so the device rule can't deny the play
+    harness.spotify.set_recently_played(
+        DEFAULT_USER,
+        vec![played(
+            &track_info("track-2", "After"),
+            start() + Duration::minutes(8),
+        )],
+    );
+    Scrobbler::scrobble_recently_played(harness.app.clone(), DEFAULT_USER).await;
+    assert_eq!(harness.scrobbles().await, 2);
+}
+
+#[tokio::test]
+async fn rewrites_local_files_before_deriving_their_ids() {
+    let harness = Harness::new().await;
+    let app = harness.app.lock().await;
+    let mut local = track_info("", "Song (Remastered)");
+    local.album.id = String::new();
+    local.artists[0].id = String::new();
+    identity::assign_missing_ids(&mut local);
+
+    app.scrobble(scrobble_info(&local, start())).await.unwrap();
+    let last = harness.repo.get_last_scrobble(DEFAULT_USER).await.unwrap();
+    assert_eq!(last.unwrap().track, "Song (Remastered)");
+
+    // a new rule is picked up by the next scrobble
+    app.add_rewrite_rule(RewriteRule {
+        id: None,
+        field: RewriteField::Title,
+        pattern: r" \(Remastered\)$".to_string(),
+        replacement: String::new(),
+        origin: None,
+    })
+    .await
+    .unwrap();
+    app.scrobble(scrobble_info(&local, start() + Duration::minutes(5)))
+        .await
+        .unwrap();
+    let last = harness.repo.get_last_scrobble(DEFAULT_USER).await.unwrap();
+    let last = last.unwrap();
+    assert_eq!(last.track, "Song");
+    assert_eq!(last.track_id, identity::track_id("Artist", "Song", "Album"));
+}
+
+#[tokio::test]
+async fn origin_rules_are_not_applied_to_stored_scrobbles() {
+    let harness = Harness::new().await;
+    let app = harness.app.lock().await;
+    let live = track_info("track-1", "Song (Live)");
+    app.scrobble(scrobble_info(&live, start())).await.unwrap();
+
+    let rule = RewriteRule {
+        id: None,
+        field: RewriteField::Title,
+        pattern: r" \(Live\)$".to_string(),
+        replacement: String::new(),
+        origin: Some("lastfm".to_string()),
+    };
+    assert!(app.apply_rewrite_rule(rule).await.is_err());
+    let last = harness.repo.get_last_scrobble(DEFAULT_USER).await.unwrap();
+    assert_eq!(last.unwrap().track, "Song (Live)");
+}
+
+#[tokio::test]
+async fn fails_when_spotify_is_offline() {
+    let harness = Harness::new().await;
+    harness.play(&track_info("track-1", "Song"), start());
+    harness.spotify.set_offline(true);
+
+    let result = Scrobbler::auto_scrobble(harness.app.clone(), DEFAULT_USER).await;
+    assert!(result.is_err());
+    assert!(harness.current_track().await.is_none());
+}
+
+#[tokio::test]
+async fn stats_follow_the_clock() {
+    let harness = Harness::new().await;
+    for day in 0..3 {
+        harness.clock.set(start() + Duration::days(day));
+        harness.play(&track_info("track-1", "Song"), harness.clock.now());
+        harness.check().await;
+        harness.clock.advance(Duration::seconds(90));
+        harness.check().await;
+    }
+
+    let artists = harness
+        .app
+        .lock()
+        .await
+        .stats_for_popular_artists(ParamsForStatsQuery::new(day(20), day(22), None))
+        .await
+        .unwrap();
+    assert_eq!(artists[0].name, "Artist");
+    assert_eq!(artists[0].score, 3);
+
+    let streaks = harness
+        .app
+        .lock()
+        .await
+        .get_streaks(DEFAULT_USER, Tz::UTC)
+        .await
+        .unwrap();
+    assert_eq!(streaks.current.unwrap().days, 3);
+
+    // two days without scrobbles break the streak
+    harness.clock.advance(Duration::days(2));
+    let streaks = harness
+        .app
+        .lock()
+        .await
+        .get_streaks(DEFAULT_USER, Tz::UTC)
+        .await
+        .unwrap();
+    assert!(streaks.current.is_none());
+    assert_eq!(streaks.longest.unwrap().days, 3);
+}
+
+#[tokio::test]
+async fn wrapped_rejects_years_out_of_range() {
+    let harness = Harness::new().await;
+    let app = harness.app.lock().await;
+
+    assert!(app.get_wrapped(DEFAULT_USER, 2022, Tz::UTC).await.is_ok());
+    assert!(app
+        .get_wrapped(DEFAULT_USER, -300000, Tz::UTC)
+        .await
+        .is_err());
+    assert!(app
+        .get_wrapped(DEFAULT_USER, i32::MIN, Tz::UTC)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn activity_ranges_are_capped() {
+    let harness = Harness::new().await;
+    let app = harness.app.lock().await;
+    let (start, end) = (day(20), NaiveDate::from_ymd_opt(9999, 12, 31).unwrap());
+
+    for bucket in [ActivityBucket::Day, ActivityBucket::Month] {
+        assert!(app
+            .get_activity(DEFAULT_USER, start, end, bucket, Tz::UTC)
+            .await
+            .is_err());
+        assert!(app
+            .get_discovery(DEFAULT_USER, start, end, bucket, Tz::UTC)
+            .await
+            .is_err());
+    }
+    assert!(app
+        .get_sessions(DEFAULT_USER, start, end, Tz::UTC)
+        .await
+        .is_err());
+    assert!(app
+        .get_activity(DEFAULT_USER, start, day(30), ActivityBucket::Day, Tz::UTC)
+        .await
+        .is_ok());
+    assert!(app
+        .get_sessions(DEFAULT_USER, start, day(30), Tz::UTC)
+        .await
+        .is_ok());
+}
+
+#[tokio::test]
+async fn changing_the_password_logs_out_the_other_sessions() {
+    let harness = Harness::new().await;
+    let mut app = harness.app.lock().await;
+    let user = app
+        .create_user("someone", Some("old password"))
+        .await
+        .unwrap();
+    let current = app.login("someone", "old password").await.unwrap().unwrap();
+    let other = app.login("someone", "old password").await.unwrap().unwrap();
+
+    assert!(app
+        .change_password(user.id, "wrong password", "new password", Some(&current))
+        .await
+        .is_err());
+    assert!(app.get_session_user(&other).await.unwrap().is_some());
+
+    app.change_password(user.id, "old password", "new password", Some(&current))
+        .await
+        .unwrap();
+    assert!(app.get_session_user(&current).await.unwrap().is_some());
+    assert!(app.get_session_user(&other).await.unwrap().is_none());
+    assert!(app
+        .login("someone", "old password")
+        .await
+        .unwrap()
+        .is_none());
+
+    // passwords set by admins log out everywhere
+    app.set_password(user.id, "admin password").await.unwrap();
+    assert!(app.get_session_user(&current).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn sessions_expire_after_thirty_days() {
+    let harness = Harness::new().await;
+    let mut app = harness.app.lock().await;
+    app.create_user("someone", Some("password")).await.unwrap();
+    let session = app.login("someone", "password").await.unwrap().unwrap();
+
+    harness.clock.advance(Duration::days(30));
+    assert!(app.get_session_user(&session).await.unwrap().is_some());
+    harness.clock.advance(Duration::seconds(1));
+    assert!(app.get_session_user(&session).await.unwrap().is_none());
+}
+
+// Harness
+const DEFAULT_USER: i32 = 1;
+
+struct Harness {
+    app: Arc<Mutex<App>>,
+    repo: MemoryRepository,
+    spotify: FakeSpotify,
+    clock: ManualClock,
+}
+
+impl Harness {
+    /// An app whose default user has linked their Spotify account.
+    async fn new() -> Harness {
+        Harness::with_filter(ScrobbleFilter::default()).await
+    }
+
+    /// Like `new`, checking plays against some scrobble rules.
+    async fn with_filter(filter: ScrobbleFilter) -> Harness {
+        let clock = ManualClock::new(start());
+        let repo = MemoryRepository::new().with_clock(Arc::new(clock.clone()));
+        let spotify = FakeSpotify::new();
+
+        let mut app = App::new(
+            Box::new(repo.clone()),
+            Arc::new(MemoryTokenStore::new()),
+            Box::new(spotify.clone()),
+            filter,
+            Tz::UTC,
+            Duration::minutes(30),
+        )
+        .with_clock(Arc::new(clock.clone()));
+        app.load_users().await.unwrap();
+        app.store_spotify_auth_token(DEFAULT_USER, "state-1", "code")
+            .await
+            .unwrap();
+        assert!(app.is_spotify_authenticated(DEFAULT_USER).await);
+
+        Harness {
+            app: Arc::new(Mutex::new(app)),
+            repo,
+            spotify,
+            clock,
+        }
+    }
+
+    fn play(&self, track: &TrackInfo, timestamp: DateTime<Utc>) {
+        self.spotify.play(
+            DEFAULT_USER,
+            playing(PlayingItem::Track(track.clone()), timestamp),
+        );
+    }
+
+    /// Runs a single check of the scrobbler, like the polling loop does.
+    async fn check(&self) {
+        Scrobbler::auto_scrobble(self.app.clone(), DEFAULT_USER)
+            .await
+            .unwrap();
+    }
+
+    async fn current_track(&self) -> Option<CurrentPlayingTrack> {
+        self.app.lock().await.get_current_track(DEFAULT_USER)
+    }
+
+    async fn scrobbles(&self) -> u64 {
+        self.repo.count_scrobbles(DEFAULT_USER).await.unwrap()
+    }
+}
+
+// Fixtures
+fn track_info(id: &str, title: &str) -> TrackInfo {
+    TrackInfo {
+        id: id.to_string(),
+        title: title.to_string(),
+        album: Album {
+            id: format!("album-{}", id),
+            title: "Album".to_string(),
+            cover: "cover.jpg".to_string(),
+        },
+        artists: vec![Artist {
+            id: "artist-1".to_string(),
+            name: "Artist".to_string(),
+        }],
+        duration_secs: std::time::Duration::from_secs(180),
+        tags: vec![],
+        isrc: format!("isrc-{}", id),
+        cover: "cover.jpg".to_string(),
+    }
+}
+
+fn episode_info() -> EpisodeInfo {
+    EpisodeInfo {
+        id: "episode-1".to_string(),
+        title: "Episode".to_string(),
+        show: Show {
+            id: "show-1".to_string(),
+            name: "Show".to_string(),
+            publisher: "Publisher".to_string(),
+            cover: "show.jpg".to_string(),
+        },
+        duration_secs: std::time::Duration::from_secs(3600),
+        release_date: "2022-11-01".to_string(),
+        cover: "episode.jpg".to_string(),
+    }
+}
+
+fn tag(id: &str) -> Tag {
+    Tag { id: id.to_string() }
+}
+
+fn playing(item: PlayingItem, timestamp: DateTime<Utc>) -> CurrentPlayingTrack {
+    CurrentPlayingTrack {
+        item,
+        timestamp,
+        progress_secs: std::time::Duration::from_secs(0),
+        scrobbled: false,
+        device: None,
+        context: None,
+    }
+}
+
+fn played(track: &TrackInfo, played_at: DateTime<Utc>) -> HistoryPlayedTrack {
+    HistoryPlayedTrack {
+        track: track.clone(),
+        played_at,
+        context: None,
+    }
+}
+
+fn scrobble_info(track: &TrackInfo, timestamp: DateTime<Utc>) -> ScrobbleInfo {
+    ScrobbleInfo {
+        user_id: DEFAULT_USER,
+        timestamp,
+        duration_secs: track.duration_secs.as_secs_f64(),
+        origin: "spotify".to_string(),
+        track: track.clone(),
+        device: None,
+        context: None,
+    }
+}
+
+fn start() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2022, 11, 20, 10, 0, 0).unwrap()
+}
+
+fn day(day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2022, 11, day).unwrap()
+}
+
+fn today() -> ParamsForStatsQuery {
+    ParamsForStatsQuery::new(day(20), day(20), None)
+}
@@ -0,0 +1,230 @@
+//! Compares the stats read from the daily rollups with the ones computed from the scrobbles,
+//! on a synthetic database of a million scrobbles:
+//!
+//! ```sh
+//! cargo bench -p scrobblify-db --bench rollups
+//! ```
+//!
+//! `SCROBBLIFY_BENCH_URL` picks another database, e.g. a Postgres one, and
+//! `SCROBBLIFY_BENCH_SCROBBLES` how many scrobbles to generate. The database is wiped first,
+//! so don't point it to one you care about.
+
+use chrono::{Duration, NaiveDate, TimeZone, Utc};
+use std::{
+    env,
+    future::Future,
+    time::{Duration as StdDuration, Instant},
+};
+
+use scrobblify_db::{
+    migrator::{sea_orm_migration::MigratorTrait, Migrator},
+    sea_orm::{ConnectionTrait, Statement, TransactionTrait},
+    Repository,
+};
+use scrobblify_domain::{
+    db::{ParamsForStatsQuery, Repository as _},
+    errors::DatabaseResult,
+};
+
+const DEFAULT_SCROBBLES: u64 = 1_000_000;
+const TRACKS: u64 = 20_000;
+const TRACKS_PER_ALBUM: u64 = 10;
+const ARTISTS: u64 = 1_500;
+const TAGS: u64 = 200;
+/// A scrobble every few minutes, a million of them span about ten years.
+const SCROBBLE_EVERY_MILLIS: i64 = 5 * 60 * 1000;
+const BATCH_SIZE: u64 = 500;
+const RUNS: u32 = 5;
+
+#[tokio::main]
+async fn main() {
+    let url = env::var("SCROBBLIFY_BENCH_URL").unwrap_or_else(|_| "sqlite::memory:".to_string());
+    let scrobbles = env::var("SCROBBLIFY_BENCH_SCROBBLES")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(DEFAULT_SCROBBLES);
+
+    let repo = Repository::new(url)
+        .await
+        .expect("failed to connect to the database");
+    Migrator::fresh(&repo.conn())
+        .await
+        .expect("failed to run migrations");
+
+    let started = Instant::now();
+    let last_day = generate(&repo, scrobbles).await;
+    println!(
+        "{} scrobbles generated in {:?}",
+        scrobbles,
+        started.elapsed()
+    );
+    let started = Instant::now();
+    repo.rebuild_rollups()
+        .await
+        .expect("failed to rebuild the rollups");
+    println!("rollups rebuilt in {:?}", started.elapsed());
+
+    let raw = repo.clone().without_rollups();
+    println!();
+    println!(
+        "{:<10} {:<8} {:>12} {:>12} {:>8}",
+        "range", "stats", "scrobbles", "rollups", "speedup"
+    );
+    for (range, days) in [("week", 7), ("month", 30), ("year", 365), ("all", 36_500)] {
+        let opts =
+            ParamsForStatsQuery::new(last_day - Duration::days(days - 1), last_day, Some(10));
+
+        let paths = [
+            (
+                "tracks",
+                time(|| raw.stats_for_popular_tracks(opts.clone())).await,
+                time(|| repo.stats_for_popular_tracks(opts.clone())).await,
+            ),
+            (
+                "artists",
+                time(|| raw.stats_for_popular_artists(opts.clone())).await,
+                time(|| repo.stats_for_popular_artists(opts.clone())).await,
+            ),
+            (
+                "albums",
+                time(|| raw.stats_for_popular_albums(opts.clone())).await,
+                time(|| repo.stats_for_popular_albums(opts.clone())).await,
+            ),
+            (
+                "tags",
+                time(|| raw.stats_for_popular_tags(opts.clone())).await,
+                time(|| repo.stats_for_popular_tags(opts.clone())).await,
+            ),
+        ];
+        for (stats, scrobbles, rollups) in paths {
+            println!(
+                "{:<10} {:<8} {:>12} {:>12} {:>7.1}x",
+                range,
+                stats,
+                format!("{:.2?}", scrobbles),
+                format!("{:.2?}", rollups),
+                scrobbles.as_secs_f64() / rollups.as_secs_f64()
+            );
+        }
+    }
+}
+
+/// The average time of a query, after a warm-up run.
+async fn time<F, Fut, T>(query: F) -> StdDuration
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = DatabaseResult<Vec<T>>>,
+{
+    assert!(
+        !query().await.expect("the query failed").is_empty(),
+        "the query found nothing"
+    );
+
+    let started = Instant::now();
+    for _ in 0..RUNS {
+        query().await.expect("the query failed");
+    }
+    started.elapsed() / RUNS
+}
+
+/// Fills the database with the catalog and the scrobbles of the default user, the popular
+/// tracks played much more than the others. Returns the day of the last scrobble.
+async fn generate(repo: &Repository, scrobbles: u64) -> NaiveDate {
+    let conn = repo.conn();
+    let txn = conn.begin().await.unwrap();
+
+    insert_rows(&txn, "artists (id, name)", ARTISTS, |a| {
+        format!("('artist-{a}', 'Artist {a}')", a = a)
+    })
+    .await;
+    insert_rows(&txn, "tags (id)", TAGS, |t| format!("('tag-{}')", t)).await;
+    insert_rows(
+        &txn,
+        "albums (id, title, cover)",
+        TRACKS / TRACKS_PER_ALBUM,
+        |l| format!("('album-{l}', 'Album {l}', 'cover-{l}.jpg')", l = l),
+    )
+    .await;
+    insert_rows(
+        &txn,
+        "albums_artists (album_id, artist_id)",
+        TRACKS / TRACKS_PER_ALBUM,
+        |l| format!("('album-{}', 'artist-{}')", l, l % ARTISTS),
+    )
+    .await;
+    insert_rows(
+        &txn,
+        "tracks (id, title, duration_secs, isrc)",
+        TRACKS,
+        |t| format!("('track-{t}', 'Track {t}', 200.0, 'isrc-{t}')", t = t),
+    )
+    .await;
+    insert_rows(&txn, "albums_tracks (album_id, track_id)", TRACKS, |t| {
+        format!("('album-{}', 'track-{}')", t / TRACKS_PER_ALBUM, t)
+    })
+    .await;
+    insert_rows(&txn, "artists_tracks (artist_id, track_id)", TRACKS, |t| {
+        format!(
+            "('artist-{}', 'track-{}')",
+            (t / TRACKS_PER_ALBUM) % ARTISTS,
+            t
+        )
+    })
+    .await;
+    insert_rows(&txn, "tags_tracks (tag_id, track_id)", TRACKS * 2, |i| {
+        let t = i / 2;
+        format!("('tag-{}', 'track-{}')", (t + i % 2) % TAGS, t)
+    })
+    .await;
+
+    let last = Utc
+        .with_ymd_and_hms(2022, 12, 1, 0, 0, 0)
+        .unwrap()
+        .timestamp_millis()
+        - 1;
+    let first = last - (scrobbles as i64 - 1) * SCROBBLE_EVERY_MILLIS;
+    let mut seed: u64 = 42;
+    insert_rows(
+        &txn,
+        "scrobbles (user_id, timestamp, origin, duration_secs, track_id)",
+        scrobbles,
+        |i| {
+            // a product of two uniform numbers favours the low ones
+            let (a, b) = (next(&mut seed) % TRACKS, next(&mut seed) % TRACKS);
+            format!(
+                "(1, {}, 'bench', 200.0, 'track-{}')",
+                first + i as i64 * SCROBBLE_EVERY_MILLIS,
+                a * b / TRACKS
+            )
+        },
+    )
+    .await;
+
+    txn.commit().await.unwrap();
+    Utc.timestamp_millis_opt(last).unwrap().date_naive()
+}
+
+async fn insert_rows<C, F>(conn: &C, into: &str, count: u64, mut row: F)
+where
+    C: ConnectionTrait,
+    F: FnMut(u64) -> String,
+{
+    let mut start = 0;
+    while start < count {
+        let end = (start + BATCH_SIZE).min(count);
+        let values: Vec<String> = (start..end).map(&mut row).collect();
+        let insert = format!("INSERT INTO {} VALUES {}", into, values.join(", "));
+        conn.execute(Statement::from_string(conn.get_database_backend(), insert))
+            .await
+            .unwrap();
+        start = end;
+    }
+}
+
+/// A linear congruential generator, good enough for a skewed catalog.
+fn next(seed: &mut u64) -> u64 {
+    *seed = seed
+        .wrapping_mul(6_364_136_223_846_793_005)
+        .wrapping_add(1_442_695_040_888_963_407);
+    *seed >> 33
+}
@@ -0,0 +1,28 @@
+use sea_orm::FromQueryResult;
+
+use scrobblify_domain::duplicates::DuplicateCandidate;
+
+#[derive(Debug, FromQueryResult)]
+pub(crate) struct DuplicateCandidateQueryResult {
+    pub id: String,
+    pub name: String,
+    pub isrc: Option<String>,
+    /// JSON array of names.
+    pub artists: Option<String>,
+    pub scrobbles: i64,
+}
+
+impl From<DuplicateCandidateQueryResult> for DuplicateCandidate {
+    fn from(c: DuplicateCandidateQueryResult) -> Self {
+        Self {
+            id: c.id,
+            name: c.name,
+            isrc: c.isrc,
+            artists: c
+                .artists
+                .and_then(|artists| serde_json::from_str(&artists).ok())
+                .unwrap_or_default(),
+            scrobbles: c.scrobbles as u32,
+        }
+    }
+}
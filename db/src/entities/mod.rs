@@ -7,7 +7,18 @@ pub mod albums_artists;
 pub mod albums_tracks;
 pub mod artists;
 pub mod artists_tracks;
+pub mod audit_log;
+pub mod episode_scrobbles;
+pub mod episodes;
+pub mod merged_ids;
+pub mod rewrite_rules;
 pub mod scrobbles;
+pub mod sessions;
+pub mod shows;
+pub mod skipped_plays;
 pub mod tags;
 pub mod tags_tracks;
 pub mod tracks;
+pub mod users;
+pub mod weekly_chart_entries;
+pub mod weekly_charts;
@@ -5,7 +5,18 @@ pub use super::albums_artists::Entity as AlbumsArtists;
 pub use super::albums_tracks::Entity as AlbumsTracks;
 pub use super::artists::Entity as Artists;
 pub use super::artists_tracks::Entity as ArtistsTracks;
+pub use super::audit_log::Entity as AuditLog;
+pub use super::episode_scrobbles::Entity as EpisodeScrobbles;
+pub use super::episodes::Entity as Episodes;
+pub use super::merged_ids::Entity as MergedIds;
+pub use super::rewrite_rules::Entity as RewriteRules;
 pub use super::scrobbles::Entity as Scrobbles;
+pub use super::sessions::Entity as Sessions;
+pub use super::shows::Entity as Shows;
+pub use super::skipped_plays::Entity as SkippedPlays;
 pub use super::tags::Entity as Tags;
 pub use super::tags_tracks::Entity as TagsTracks;
 pub use super::tracks::Entity as Tracks;
+pub use super::users::Entity as Users;
+pub use super::weekly_chart_entries::Entity as WeeklyChartEntries;
+pub use super::weekly_charts::Entity as WeeklyCharts;
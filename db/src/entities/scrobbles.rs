@@ -5,10 +5,16 @@ use sea_orm::entity::prelude::*;
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
 #[sea_orm(table_name = "scrobbles")]
 pub struct Model {
-    #[sea_orm(primary_key, auto_increment = false)]
-    pub timestamp: String,
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub user_id: i32,
+    pub timestamp: i64,
     pub origin: String,
     pub duration_secs: f64,
+    pub device_name: Option<String>,
+    pub device_type: Option<String>,
+    pub context_type: Option<String>,
+    pub context_uri: Option<String>,
     pub track_id: String,
 }
 
@@ -1,7 +1,11 @@
+mod duplicates;
 pub mod entities;
 pub mod migrator;
 mod repository;
+mod rollups;
 mod shims;
+mod token_store;
 
 pub use repository::Repository;
 pub use sea_orm;
+pub use token_store::DbTokenStore;
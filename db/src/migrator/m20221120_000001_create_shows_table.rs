@@ -0,0 +1,38 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the Shows table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Shows::Table)
+                    .col(ColumnDef::new(Shows::Id).string().not_null().primary_key())
+                    .col(ColumnDef::new(Shows::Name).string().not_null())
+                    .col(ColumnDef::new(Shows::Publisher).string().not_null())
+                    .col(ColumnDef::new(Shows::Cover).string().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the Shows table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Shows::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum Shows {
+    Table,
+    Id,
+    Name,
+    Publisher,
+    Cover,
+}
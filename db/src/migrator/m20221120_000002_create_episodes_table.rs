@@ -0,0 +1,53 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20221120_000001_create_shows_table::Shows;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the Episodes table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Episodes::Table)
+                    .col(
+                        ColumnDef::new(Episodes::Id)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Episodes::Title).string().not_null())
+                    .col(ColumnDef::new(Episodes::DurationSecs).float().not_null())
+                    .col(ColumnDef::new(Episodes::ReleaseDate).string().not_null())
+                    .col(ColumnDef::new(Episodes::ShowId).string().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-episodes-show_id")
+                            .from(Episodes::Table, Episodes::ShowId)
+                            .to(Shows::Table, Shows::Id),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the Episodes table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Episodes::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum Episodes {
+    Table,
+    Id,
+    Title,
+    DurationSecs,
+    ReleaseDate,
+    ShowId,
+}
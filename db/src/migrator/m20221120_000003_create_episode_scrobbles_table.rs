@@ -0,0 +1,59 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20221120_000002_create_episodes_table::Episodes;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the EpisodeScrobbles table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(EpisodeScrobbles::Table)
+                    .col(
+                        ColumnDef::new(EpisodeScrobbles::Timestamp)
+                            .timestamp()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(EpisodeScrobbles::Origin).string().not_null())
+                    .col(
+                        ColumnDef::new(EpisodeScrobbles::DurationSecs)
+                            .float()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(EpisodeScrobbles::EpisodeId)
+                            .string()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-episode_scrobbles-episode_id")
+                            .from(EpisodeScrobbles::Table, EpisodeScrobbles::EpisodeId)
+                            .to(Episodes::Table, Episodes::Id),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the EpisodeScrobbles table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(EpisodeScrobbles::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum EpisodeScrobbles {
+    Table,
+    Timestamp,
+    Origin,
+    DurationSecs,
+    EpisodeId,
+}
@@ -0,0 +1,82 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20221022_000002_create_scrobbles_table::Scrobbles;
+use super::m20221120_000003_create_episode_scrobbles_table::EpisodeScrobbles;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Add device and context columns to scrobbles.
+    // SQLite supports only one column per ALTER TABLE, so each one is added separately.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in PlaybackColumns::all() {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Scrobbles::Table)
+                        .add_column(ColumnDef::new(column).string().null())
+                        .to_owned(),
+                )
+                .await?;
+        }
+        for column in PlaybackColumns::all() {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(EpisodeScrobbles::Table)
+                        .add_column(ColumnDef::new(column).string().null())
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    // Define how to rollback this migration: Drop device and context columns from scrobbles.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in PlaybackColumns::all() {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Scrobbles::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        for column in PlaybackColumns::all() {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(EpisodeScrobbles::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Iden, Clone, Copy)]
+pub enum PlaybackColumns {
+    DeviceName,
+    DeviceType,
+    ContextType,
+    ContextUri,
+}
+
+impl PlaybackColumns {
+    fn all() -> [Self; 4] {
+        [
+            Self::DeviceName,
+            Self::DeviceType,
+            Self::ContextType,
+            Self::ContextUri,
+        ]
+    }
+}
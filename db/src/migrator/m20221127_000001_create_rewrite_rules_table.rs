@@ -0,0 +1,50 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the RewriteRules table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RewriteRules::Table)
+                    .col(
+                        ColumnDef::new(RewriteRules::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(RewriteRules::Field).string().not_null())
+                    .col(ColumnDef::new(RewriteRules::Pattern).string().not_null())
+                    .col(
+                        ColumnDef::new(RewriteRules::Replacement)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(RewriteRules::Origin).string().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the RewriteRules table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RewriteRules::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum RewriteRules {
+    Table,
+    Id,
+    Field,
+    Pattern,
+    Replacement,
+    Origin,
+}
@@ -0,0 +1,45 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the MergedIds table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(MergedIds::Table)
+                    .col(ColumnDef::new(MergedIds::Kind).string().not_null())
+                    .col(ColumnDef::new(MergedIds::Id).string().not_null())
+                    .col(ColumnDef::new(MergedIds::CanonicalId).string().not_null())
+                    .index(
+                        Index::create()
+                            .name("idx-merged-ids-kind-id")
+                            .table(MergedIds::Table)
+                            .col(MergedIds::Kind)
+                            .col(MergedIds::Id)
+                            .primary()
+                            .unique(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the MergedIds table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(MergedIds::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum MergedIds {
+    Table,
+    Kind,
+    Id,
+    CanonicalId,
+}
@@ -0,0 +1,87 @@
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{ConnectionTrait, DbBackend, Statement},
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Durations lose precision as single-precision floats.
+const DURATION_TABLES: [&str; 4] = ["tracks", "scrobbles", "episodes", "episode_scrobbles"];
+/// Timestamps are written as strings, which Postgres doesn't take for its timestamp type.
+const TIMESTAMP_TABLES: [&str; 2] = ["scrobbles", "episode_scrobbles"];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Store durations as double precision floats and
+    // scrobble timestamps as strings.
+    // Floats and doubles are both REAL in SQLite and timestamps are already stored as text,
+    // so only Postgres has something to change.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() != DbBackend::Postgres {
+            return Ok(());
+        }
+
+        for table in DURATION_TABLES {
+            execute(
+                manager,
+                format!(
+                    "ALTER TABLE {} ALTER COLUMN duration_secs TYPE double precision",
+                    table
+                ),
+            )
+            .await?;
+        }
+        for table in TIMESTAMP_TABLES {
+            execute(
+                manager,
+                format!(
+                    "ALTER TABLE {} ALTER COLUMN timestamp TYPE varchar \
+                     USING TO_CHAR(timestamp, 'YYYY-MM-DD HH24:MI:SS.US') || ' UTC'",
+                    table
+                ),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    // Define how to rollback this migration: Restore single-precision durations and
+    // timestamp columns.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.get_database_backend() != DbBackend::Postgres {
+            return Ok(());
+        }
+
+        for table in DURATION_TABLES {
+            execute(
+                manager,
+                format!("ALTER TABLE {} ALTER COLUMN duration_secs TYPE real", table),
+            )
+            .await?;
+        }
+        for table in TIMESTAMP_TABLES {
+            execute(
+                manager,
+                format!(
+                    "ALTER TABLE {} ALTER COLUMN timestamp TYPE timestamp \
+                     USING REPLACE(timestamp, ' UTC', '')::timestamp",
+                    table
+                ),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+}
+
+async fn execute(manager: &SchemaManager<'_>, sql: String) -> Result<(), DbErr> {
+    manager
+        .get_connection()
+        .execute(Statement::from_string(manager.get_database_backend(), sql))
+        .await?;
+
+    Ok(())
+}
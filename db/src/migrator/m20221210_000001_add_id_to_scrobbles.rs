@@ -0,0 +1,293 @@
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{ConnectionTrait, DbBackend, Statement},
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// A table of scrobbles and the column pointing to what has been played.
+struct ScrobblesTable {
+    name: &'static str,
+    item_column: &'static str,
+    item_table: &'static str,
+}
+
+const TABLES: [ScrobblesTable; 2] = [
+    ScrobblesTable {
+        name: "scrobbles",
+        item_column: "track_id",
+        item_table: "tracks",
+    },
+    ScrobblesTable {
+        name: "episode_scrobbles",
+        item_column: "episode_id",
+        item_table: "episodes",
+    },
+];
+
+const PLAYBACK_COLUMNS: [&str; 4] = ["device_name", "device_type", "context_type", "context_uri"];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Replace the timestamp primary key of scrobbles
+    // with a surrogate ID, store timestamps as milliseconds since epoch (UTC) and make
+    // scrobbles unique by user, timestamp and track.
+    // Tables can't change their primary key in SQLite, so they're rebuilt and data copied over.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let backend = manager.get_database_backend();
+
+        for table in TABLES.iter() {
+            let new_table = format!("{}_new", table.name);
+
+            manager
+                .create_table(
+                    Table::create()
+                        .table(Alias::new(&new_table))
+                        .col(
+                            ColumnDef::new(Alias::new("id"))
+                                .integer()
+                                .not_null()
+                                .auto_increment()
+                                .primary_key(),
+                        )
+                        .col(
+                            ColumnDef::new(Alias::new("user_id"))
+                                .integer()
+                                .not_null()
+                                .default(1),
+                        )
+                        .col(
+                            ColumnDef::new(Alias::new("timestamp"))
+                                .big_integer()
+                                .not_null(),
+                        )
+                        .col(ColumnDef::new(Alias::new("origin")).string().not_null())
+                        .col(
+                            ColumnDef::new(Alias::new("duration_secs"))
+                                .double()
+                                .not_null(),
+                        )
+                        .col(
+                            ColumnDef::new(Alias::new(table.item_column))
+                                .string()
+                                .not_null(),
+                        )
+                        .col(
+                            ColumnDef::new(Alias::new(PLAYBACK_COLUMNS[0]))
+                                .string()
+                                .null(),
+                        )
+                        .col(
+                            ColumnDef::new(Alias::new(PLAYBACK_COLUMNS[1]))
+                                .string()
+                                .null(),
+                        )
+                        .col(
+                            ColumnDef::new(Alias::new(PLAYBACK_COLUMNS[2]))
+                                .string()
+                                .null(),
+                        )
+                        .col(
+                            ColumnDef::new(Alias::new(PLAYBACK_COLUMNS[3]))
+                                .string()
+                                .null(),
+                        )
+                        .foreign_key(
+                            ForeignKey::create()
+                                .name(&format!("fk-{}-{}", table.name, table.item_column))
+                                .from(Alias::new(&new_table), Alias::new(table.item_column))
+                                .to(Alias::new(table.item_table), Alias::new("id")),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+
+            let timestamp = match backend {
+                DbBackend::Postgres => {
+                    "(EXTRACT(EPOCH FROM REPLACE(timestamp, ' UTC', '')::timestamp) * 1000)::bigint"
+                }
+                _ => {
+                    "CAST(ROUND((julianday(REPLACE(timestamp, ' UTC', '')) - 2440587.5) * 86400000) AS INTEGER)"
+                }
+            };
+            copy_rows(
+                manager,
+                table,
+                &new_table,
+                table.name,
+                timestamp,
+                "ORDER BY timestamp",
+            )
+            .await?;
+
+            manager
+                .drop_table(Table::drop().table(Alias::new(table.name)).to_owned())
+                .await?;
+            manager
+                .rename_table(
+                    Table::rename()
+                        .table(Alias::new(&new_table), Alias::new(table.name))
+                        .to_owned(),
+                )
+                .await?;
+
+            manager
+                .create_index(
+                    Index::create()
+                        .name(&format!("idx-{}-timestamp", table.name))
+                        .table(Alias::new(table.name))
+                        .col(Alias::new("timestamp"))
+                        .to_owned(),
+                )
+                .await?;
+            manager
+                .create_index(
+                    Index::create()
+                        .name(&format!(
+                            "idx-{}-user_id-timestamp-{}",
+                            table.name, table.item_column
+                        ))
+                        .table(Alias::new(table.name))
+                        .col(Alias::new("user_id"))
+                        .col(Alias::new("timestamp"))
+                        .col(Alias::new(table.item_column))
+                        .unique()
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    // Define how to rollback this migration: Restore the timestamp as primary key, formatted
+    // as a string. Scrobbles sharing the same timestamp can't be restored, so only the
+    // first one is kept.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let backend = manager.get_database_backend();
+
+        for table in TABLES.iter() {
+            let old_table = format!("{}_old", table.name);
+
+            manager
+                .create_table(
+                    Table::create()
+                        .table(Alias::new(&old_table))
+                        .col(
+                            ColumnDef::new(Alias::new("timestamp"))
+                                .string()
+                                .not_null()
+                                .primary_key(),
+                        )
+                        .col(ColumnDef::new(Alias::new("origin")).string().not_null())
+                        .col(
+                            ColumnDef::new(Alias::new("duration_secs"))
+                                .double()
+                                .not_null(),
+                        )
+                        .col(
+                            ColumnDef::new(Alias::new(table.item_column))
+                                .string()
+                                .not_null(),
+                        )
+                        .col(
+                            ColumnDef::new(Alias::new(PLAYBACK_COLUMNS[0]))
+                                .string()
+                                .null(),
+                        )
+                        .col(
+                            ColumnDef::new(Alias::new(PLAYBACK_COLUMNS[1]))
+                                .string()
+                                .null(),
+                        )
+                        .col(
+                            ColumnDef::new(Alias::new(PLAYBACK_COLUMNS[2]))
+                                .string()
+                                .null(),
+                        )
+                        .col(
+                            ColumnDef::new(Alias::new(PLAYBACK_COLUMNS[3]))
+                                .string()
+                                .null(),
+                        )
+                        .foreign_key(
+                            ForeignKey::create()
+                                .name(&format!("fk-{}-{}", table.name, table.item_column))
+                                .from(Alias::new(&old_table), Alias::new(table.item_column))
+                                .to(Alias::new(table.item_table), Alias::new("id")),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+
+            let timestamp = match backend {
+                DbBackend::Postgres => {
+                    "TO_CHAR(TO_TIMESTAMP(timestamp / 1000.0) AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS.MS') || ' UTC'"
+                }
+                _ => "STRFTIME('%Y-%m-%d %H:%M:%f', timestamp / 1000.0, 'unixepoch') || ' UTC'",
+            };
+            copy_rows(
+                manager,
+                table,
+                &old_table,
+                table.name,
+                timestamp,
+                "ORDER BY id ON CONFLICT DO NOTHING",
+            )
+            .await?;
+
+            manager
+                .drop_table(Table::drop().table(Alias::new(table.name)).to_owned())
+                .await?;
+            manager
+                .rename_table(
+                    Table::rename()
+                        .table(Alias::new(&old_table), Alias::new(table.name))
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Copies all the scrobbles between two versions of the same table, converting the
+/// timestamp with the given SQL expression.
+async fn copy_rows(
+    manager: &SchemaManager<'_>,
+    table: &ScrobblesTable,
+    to: &str,
+    from: &str,
+    timestamp: &str,
+    suffix: &str,
+) -> Result<(), DbErr> {
+    let columns = [
+        "origin",
+        "duration_secs",
+        table.item_column,
+        PLAYBACK_COLUMNS[0],
+        PLAYBACK_COLUMNS[1],
+        PLAYBACK_COLUMNS[2],
+        PLAYBACK_COLUMNS[3],
+    ]
+    .join(", ");
+
+    let sql = format!(
+        "INSERT INTO {to} (timestamp, {columns}) \
+         SELECT {timestamp}, {columns} FROM {from} WHERE true {suffix}",
+        to = to,
+        from = from,
+        columns = columns,
+        timestamp = timestamp,
+        suffix = suffix
+    );
+
+    manager
+        .get_connection()
+        .execute(Statement::from_string(manager.get_database_backend(), sql))
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{ConnectionTrait, DbBackend, Statement},
+};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Existing scrobbles belong to the user with this ID, created along with the table.
+const DEFAULT_USER_ID: i32 = 1;
+const DEFAULT_USER_NAME: &str = "default";
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the Users table, with the default user
+    // owning the scrobbles stored until now.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Users::Table)
+                    .col(
+                        ColumnDef::new(Users::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Users::Name).string().not_null().unique_key())
+                    .col(ColumnDef::new(Users::SpotifyToken).text().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        let insert = Query::insert()
+            .into_table(Users::Table)
+            .columns(vec![Users::Id, Users::Name])
+            .values_panic(vec![DEFAULT_USER_ID.into(), DEFAULT_USER_NAME.into()])
+            .to_owned();
+        manager.exec_stmt(insert).await?;
+
+        // Postgres sequences don't move forward when IDs are given explicitly
+        if manager.get_database_backend() == DbBackend::Postgres {
+            manager
+                .get_connection()
+                .execute(Statement::from_string(
+                    DbBackend::Postgres,
+                    "SELECT setval(pg_get_serial_sequence('users', 'id'), MAX(id)) FROM users"
+                        .to_string(),
+                ))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    // Define how to rollback this migration: Drop the Users table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Users::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum Users {
+    Table,
+    Id,
+    Name,
+    SpotifyToken,
+}
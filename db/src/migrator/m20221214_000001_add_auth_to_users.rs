@@ -0,0 +1,108 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20221212_000001_create_users_table::Users;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Add password, role and stats visibility to users,
+    // the default user becomes an admin. Create the Sessions table.
+    // SQLite supports only one column per ALTER TABLE, so each one is added separately.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for mut column in [
+            ColumnDef::new(AuthColumns::PasswordHash)
+                .text()
+                .null()
+                .to_owned(),
+            ColumnDef::new(AuthColumns::IsAdmin)
+                .boolean()
+                .not_null()
+                .default(false)
+                .to_owned(),
+            ColumnDef::new(AuthColumns::PublicStats)
+                .boolean()
+                .not_null()
+                .default(false)
+                .to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Users::Table)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        let update = Query::update()
+            .table(Users::Table)
+            .value(AuthColumns::IsAdmin, true.into())
+            .and_where(Expr::col(Users::Id).eq(1))
+            .to_owned();
+        manager.exec_stmt(update).await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Sessions::Table)
+                    .col(
+                        ColumnDef::new(Sessions::Id)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Sessions::UserId).integer().not_null())
+                    .col(ColumnDef::new(Sessions::ExpiresAt).big_integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-sessions-user_id")
+                            .from(Sessions::Table, Sessions::UserId)
+                            .to(Users::Table, Users::Id),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the Sessions table and the auth columns.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Sessions::Table).to_owned())
+            .await?;
+
+        for column in [
+            AuthColumns::PasswordHash,
+            AuthColumns::IsAdmin,
+            AuthColumns::PublicStats,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Users::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+pub enum AuthColumns {
+    PasswordHash,
+    IsAdmin,
+    PublicStats,
+}
+
+#[derive(Iden)]
+pub enum Sessions {
+    Table,
+    Id,
+    UserId,
+    ExpiresAt,
+}
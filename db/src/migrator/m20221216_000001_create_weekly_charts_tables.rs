@@ -0,0 +1,126 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20221212_000001_create_users_table::Users;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the WeeklyCharts table, with a row for every
+    // snapshotted week (even the empty ones), and the WeeklyChartEntries table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(WeeklyCharts::Table)
+                    .col(
+                        ColumnDef::new(WeeklyCharts::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(WeeklyCharts::UserId).integer().not_null())
+                    .col(ColumnDef::new(WeeklyCharts::Kind).string().not_null())
+                    .col(ColumnDef::new(WeeklyCharts::WeekStart).date().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-weekly_charts-user_id")
+                            .from(WeeklyCharts::Table, WeeklyCharts::UserId)
+                            .to(Users::Table, Users::Id),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-weekly-charts-user-kind-week")
+                    .table(WeeklyCharts::Table)
+                    .col(WeeklyCharts::UserId)
+                    .col(WeeklyCharts::Kind)
+                    .col(WeeklyCharts::WeekStart)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(WeeklyChartEntries::Table)
+                    .col(
+                        ColumnDef::new(WeeklyChartEntries::ChartId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WeeklyChartEntries::Rank)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WeeklyChartEntries::EntityId)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(WeeklyChartEntries::Name).string().not_null())
+                    .col(
+                        ColumnDef::new(WeeklyChartEntries::Artists)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(WeeklyChartEntries::Score)
+                            .integer()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(WeeklyChartEntries::ChartId)
+                            .col(WeeklyChartEntries::Rank),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-weekly_chart_entries-chart_id")
+                            .from(WeeklyChartEntries::Table, WeeklyChartEntries::ChartId)
+                            .to(WeeklyCharts::Table, WeeklyCharts::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the WeeklyChartEntries and WeeklyCharts tables.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(WeeklyChartEntries::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(WeeklyCharts::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum WeeklyCharts {
+    Table,
+    Id,
+    UserId,
+    Kind,
+    WeekStart,
+}
+
+#[derive(Iden)]
+pub enum WeeklyChartEntries {
+    Table,
+    ChartId,
+    Rank,
+    EntityId,
+    Name,
+    Artists,
+    Score,
+}
@@ -0,0 +1,101 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20221212_000001_create_users_table::Users;
+use crate::rollups;
+use scrobblify_domain::time::Tz;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// A table of daily plays and the column of the entity played.
+struct RollupTable {
+    name: &'static str,
+    entity_column: &'static str,
+}
+
+const TABLES: [RollupTable; 4] = [
+    RollupTable {
+        name: "daily_track_plays",
+        entity_column: "track_id",
+    },
+    RollupTable {
+        name: "daily_artist_plays",
+        entity_column: "artist_id",
+    },
+    RollupTable {
+        name: "daily_album_plays",
+        entity_column: "album_id",
+    },
+    RollupTable {
+        name: "daily_tag_plays",
+        entity_column: "tag_id",
+    },
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create a table of daily plays for tracks, artists,
+    // albums and tags, keyed by user, UTC day (milliseconds since epoch) and entity, and
+    // fill them with the scrobbles stored until now.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for table in TABLES.iter() {
+            manager
+                .create_table(
+                    Table::create()
+                        .table(Alias::new(table.name))
+                        .col(ColumnDef::new(Alias::new("user_id")).integer().not_null())
+                        .col(ColumnDef::new(Alias::new("day")).big_integer().not_null())
+                        .col(
+                            ColumnDef::new(Alias::new(table.entity_column))
+                                .string()
+                                .not_null(),
+                        )
+                        .col(ColumnDef::new(Alias::new("scrobbles")).integer().not_null())
+                        .col(
+                            ColumnDef::new(Alias::new("listened_secs"))
+                                .double()
+                                .not_null(),
+                        )
+                        .primary_key(
+                            Index::create()
+                                .col(Alias::new("user_id"))
+                                .col(Alias::new("day"))
+                                .col(Alias::new(table.entity_column)),
+                        )
+                        .foreign_key(
+                            ForeignKey::create()
+                                .name(&format!("fk-{}-user_id", table.name))
+                                .from(Alias::new(table.name), Alias::new("user_id"))
+                                .to(Users::Table, Users::Id),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+
+            // stats of every user only filter by day
+            manager
+                .create_index(
+                    Index::create()
+                        .name(&format!("idx-{}-day", table.name))
+                        .table(Alias::new(table.name))
+                        .col(Alias::new("day"))
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        // the days are UTC ones until the app picks its time zone
+        rollups::rebuild(manager.get_connection(), Tz::UTC).await
+    }
+
+    // Define how to rollback this migration: Drop the tables of daily plays.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for table in TABLES.iter() {
+            manager
+                .drop_table(Table::drop().table(Alias::new(table.name)).to_owned())
+                .await?;
+        }
+
+        Ok(())
+    }
+}
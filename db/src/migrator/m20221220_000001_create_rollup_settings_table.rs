@@ -0,0 +1,38 @@
+use sea_orm_migration::prelude::*;
+
+use crate::rollups;
+use scrobblify_domain::time::Tz;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the RollupSettings table, which records the
+    // time zone of the days of the rollups. These have been built with UTC days until now.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RollupSettings::Table)
+                    .col(ColumnDef::new(RollupSettings::TimeZone).string().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        rollups::store_time_zone(manager.get_connection(), Tz::UTC).await
+    }
+
+    // Define how to rollback this migration: Drop the RollupSettings table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RollupSettings::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum RollupSettings {
+    Table,
+    TimeZone,
+}
@@ -0,0 +1,60 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20221212_000001_create_users_table::Users;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the SkippedPlays table.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SkippedPlays::Table)
+                    .col(ColumnDef::new(SkippedPlays::UserId).integer().not_null())
+                    .col(
+                        ColumnDef::new(SkippedPlays::Timestamp)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(SkippedPlays::TrackId).string().not_null())
+                    .col(
+                        ColumnDef::new(SkippedPlays::DurationSecs)
+                            .double()
+                            .not_null(),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(SkippedPlays::UserId)
+                            .col(SkippedPlays::Timestamp)
+                            .col(SkippedPlays::TrackId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-skipped_plays-user_id")
+                            .from(SkippedPlays::Table, SkippedPlays::UserId)
+                            .to(Users::Table, Users::Id),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the SkippedPlays table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SkippedPlays::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum SkippedPlays {
+    Table,
+    UserId,
+    Timestamp,
+    TrackId,
+    DurationSecs,
+}
@@ -10,6 +10,21 @@ mod m20221027_000002_create_albums_artists_tracks_table;
 mod m20221027_000003_create_albums_tracks_table;
 mod m20221101_000001_create_tags_table;
 mod m20221101_000002_create_tags_tracks_table;
+mod m20221120_000001_create_shows_table;
+mod m20221120_000002_create_episodes_table;
+mod m20221120_000003_create_episode_scrobbles_table;
+mod m20221125_000001_add_playback_to_scrobbles;
+mod m20221127_000001_create_rewrite_rules_table;
+mod m20221203_000001_create_audit_log_table;
+mod m20221206_000001_create_merged_ids_table;
+mod m20221207_000001_change_column_types;
+mod m20221210_000001_add_id_to_scrobbles;
+mod m20221212_000001_create_users_table;
+mod m20221214_000001_add_auth_to_users;
+mod m20221216_000001_create_weekly_charts_tables;
+mod m20221218_000001_create_daily_rollup_tables;
+mod m20221220_000001_create_rollup_settings_table;
+mod m20221222_000001_create_skipped_plays_table;
 
 pub struct Migrator;
 
@@ -26,6 +41,21 @@ impl MigratorTrait for Migrator {
             Box::new(m20221027_000003_create_albums_tracks_table::Migration),
             Box::new(m20221101_000001_create_tags_table::Migration),
             Box::new(m20221101_000002_create_tags_tracks_table::Migration),
+            Box::new(m20221120_000001_create_shows_table::Migration),
+            Box::new(m20221120_000002_create_episodes_table::Migration),
+            Box::new(m20221120_000003_create_episode_scrobbles_table::Migration),
+            Box::new(m20221125_000001_add_playback_to_scrobbles::Migration),
+            Box::new(m20221127_000001_create_rewrite_rules_table::Migration),
+            Box::new(m20221203_000001_create_audit_log_table::Migration),
+            Box::new(m20221206_000001_create_merged_ids_table::Migration),
+            Box::new(m20221207_000001_change_column_types::Migration),
+            Box::new(m20221210_000001_add_id_to_scrobbles::Migration),
+            Box::new(m20221212_000001_create_users_table::Migration),
+            Box::new(m20221214_000001_add_auth_to_users::Migration),
+            Box::new(m20221216_000001_create_weekly_charts_tables::Migration),
+            Box::new(m20221218_000001_create_daily_rollup_tables::Migration),
+            Box::new(m20221220_000001_create_rollup_settings_table::Migration),
+            Box::new(m20221222_000001_create_skipped_plays_table::Migration),
         ]
     }
 }
@@ -0,0 +1,17 @@
+SELECT
+  l.id,
+  l.title AS name,
+  NULL AS isrc,
+  (
+    SELECT json_agg(a.name)::text
+    FROM albums_artists AS la
+      JOIN artists AS a ON a.id = la.artist_id
+    WHERE la.album_id = l.id
+  ) AS artists,
+  (
+    SELECT COUNT(*)
+    FROM albums_tracks AS ll
+      JOIN scrobbles AS s ON s.track_id = ll.track_id
+    WHERE ll.album_id = l.id
+  ) AS scrobbles
+FROM albums AS l;
@@ -0,0 +1,12 @@
+SELECT
+  a.id,
+  a.name,
+  NULL AS isrc,
+  NULL AS artists,
+  (
+    SELECT COUNT(*)
+    FROM artists_tracks AS aa
+      JOIN scrobbles AS s ON s.track_id = aa.track_id
+    WHERE aa.artist_id = a.id
+  ) AS scrobbles
+FROM artists AS a;
@@ -0,0 +1,10 @@
+SELECT DISTINCT
+  a.id AS id,
+  a.name AS name,
+  d.day AS day
+FROM daily_artist_plays AS d
+  JOIN artists AS a ON a.id = d.artist_id
+WHERE d.day >= $1
+  AND d.day <= $2
+  AND ($3::integer IS NULL OR d.user_id = $3)
+ORDER BY d.day, a.id;
@@ -0,0 +1,11 @@
+SELECT
+  a.id,
+  a.name,
+  s.timestamp
+FROM scrobbles AS s
+  JOIN artists_tracks AS tt ON s.track_id = tt.track_id
+  JOIN artists AS a ON a.id = tt.artist_id
+WHERE s.timestamp >= $1
+  AND s.timestamp <= $2
+  AND ($3::integer IS NULL OR s.user_id = $3)
+ORDER BY s.timestamp ASC
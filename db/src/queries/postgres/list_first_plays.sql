@@ -0,0 +1,11 @@
+SELECT
+  a.id,
+  a.name,
+  MIN(s.timestamp) AS timestamp
+FROM scrobbles AS s
+  JOIN artists_tracks AS tt ON s.track_id = tt.track_id
+  JOIN artists AS a ON a.id = tt.artist_id
+WHERE s.user_id = $1
+GROUP BY a.id
+ORDER BY timestamp DESC
+LIMIT $2
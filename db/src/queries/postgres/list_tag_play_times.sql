@@ -0,0 +1,10 @@
+SELECT
+  tt.tag_id AS id,
+  tt.tag_id AS name,
+  s.timestamp
+FROM scrobbles AS s
+  JOIN tags_tracks AS tt ON s.track_id = tt.track_id
+WHERE s.timestamp >= $1
+  AND s.timestamp <= $2
+  AND ($3::integer IS NULL OR s.user_id = $3)
+ORDER BY s.timestamp ASC
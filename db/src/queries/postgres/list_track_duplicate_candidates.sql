@@ -0,0 +1,16 @@
+SELECT
+  t.id,
+  t.title AS name,
+  t.isrc,
+  (
+    SELECT json_agg(a.name)::text
+    FROM artists_tracks AS aa
+      JOIN artists AS a ON a.id = aa.artist_id
+    WHERE aa.track_id = t.id
+  ) AS artists,
+  (
+    SELECT COUNT(*)
+    FROM scrobbles AS s
+    WHERE s.track_id = t.id
+  ) AS scrobbles
+FROM tracks AS t;
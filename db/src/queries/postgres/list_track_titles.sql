@@ -0,0 +1,10 @@
+SELECT
+  t.id,
+  t.title AS name,
+  COUNT(s.id) AS scrobbles
+FROM tracks AS t
+  JOIN scrobbles AS s ON s.track_id = t.id
+WHERE $1 IS NULL
+  OR s.origin = $1
+GROUP BY t.id
+ORDER BY scrobbles DESC;
@@ -0,0 +1,33 @@
+WITH
+  track_albums AS (
+    SELECT
+      tt.track_id as track_id,
+      MIN(tt.album_id) AS album_id
+    FROM albums_tracks AS tt
+    GROUP BY tt.track_id
+  ),
+  album_artists AS (
+    SELECT
+      aa.album_id as album_id,
+      json_agg(DISTINCT a.name)::text as artists
+    FROM albums_artists AS aa
+      JOIN artists AS a ON a.id = aa.artist_id
+    GROUP BY aa.album_id
+  )
+SELECT
+  l.id,
+  l.title,
+  l.cover,
+  COALESCE(aa.artists, '[]') AS artists,
+  COUNT(DISTINCT(s.track_id)) AS tracks,
+  COUNT(DISTINCT(s.id)) AS score
+FROM scrobbles AS s
+  JOIN track_albums AS tt ON tt.track_id = s.track_id
+  JOIN albums AS l ON l.id = tt.album_id
+  LEFT JOIN album_artists AS aa ON aa.album_id = l.id
+WHERE s.timestamp >= $1
+  AND s.timestamp <= $2
+  AND ($4::integer IS NULL OR s.user_id = $4)
+GROUP BY l.id, l.title, l.cover, aa.artists
+ORDER BY score DESC, tracks DESC
+LIMIT $3;
@@ -0,0 +1,50 @@
+WITH
+  track_albums AS (
+    SELECT
+      tt.track_id as track_id,
+      MIN(tt.album_id) AS album_id
+    FROM albums_tracks AS tt
+    GROUP BY tt.track_id
+  ),
+  album_scores AS (
+    SELECT
+      d.album_id as album_id,
+      SUM(d.scrobbles) AS score
+    FROM daily_album_plays AS d
+    WHERE d.day >= $1
+      AND d.day <= $2
+      AND ($4::integer IS NULL OR d.user_id = $4)
+    GROUP BY d.album_id
+  ),
+  album_tracks AS (
+    SELECT
+      tt.album_id as album_id,
+      COUNT(DISTINCT(d.track_id)) AS tracks
+    FROM daily_track_plays AS d
+      JOIN track_albums AS tt ON tt.track_id = d.track_id
+    WHERE d.day >= $1
+      AND d.day <= $2
+      AND ($4::integer IS NULL OR d.user_id = $4)
+    GROUP BY tt.album_id
+  ),
+  album_artists AS (
+    SELECT
+      aa.album_id as album_id,
+      json_agg(DISTINCT a.name)::text as artists
+    FROM albums_artists AS aa
+      JOIN artists AS a ON a.id = aa.artist_id
+    GROUP BY aa.album_id
+  )
+SELECT
+  l.id,
+  l.title,
+  l.cover,
+  COALESCE(aa.artists, '[]') AS artists,
+  tr.tracks,
+  sc.score
+FROM album_scores AS sc
+  JOIN album_tracks AS tr ON tr.album_id = sc.album_id
+  JOIN albums AS l ON l.id = sc.album_id
+  LEFT JOIN album_artists AS aa ON aa.album_id = l.id
+ORDER BY score DESC, tracks DESC
+LIMIT $3;
@@ -0,0 +1,17 @@
+SELECT
+  sh.id,
+  sh.name,
+  sh.publisher,
+  sh.cover,
+  COUNT(DISTINCT(e.id)) AS episodes,
+  COUNT(*) AS score,
+  SUM(s.duration_secs) AS listened_secs
+FROM episode_scrobbles AS s
+  JOIN episodes AS e ON e.id = s.episode_id
+  JOIN shows AS sh ON sh.id = e.show_id
+WHERE s.timestamp >= $1
+  AND s.timestamp <= $2
+  AND ($4::integer IS NULL OR s.user_id = $4)
+GROUP BY sh.id
+ORDER BY score DESC, listened_secs DESC
+LIMIT $3;
@@ -0,0 +1,26 @@
+WITH
+  all_tags AS (
+    SELECT
+      tt.tag_id as tag_id,
+      COUNT(*) AS tot
+    FROM scrobbles AS s
+      JOIN tags_tracks AS tt ON s.track_id = tt.track_id
+    WHERE s.timestamp >= $1
+      AND s.timestamp <= $2
+      AND ($4::integer IS NULL OR s.user_id = $4)
+    GROUP BY tt.tag_id
+  ),
+  all_scrobbles AS (
+    SELECT COUNT(*) as tot
+    FROM scrobbles AS s
+    WHERE s.timestamp >= $1
+      AND s.timestamp <= $2
+      AND ($4::integer IS NULL OR s.user_id = $4)
+  )
+SELECT
+  ta.tag_id as tag,
+  (ta.tot * 100 / ss.tot) as score
+FROM all_tags AS ta
+  CROSS JOIN all_scrobbles AS ss
+ORDER BY ta.tot DESC
+LIMIT $3
@@ -0,0 +1,25 @@
+WITH
+  all_tags AS (
+    SELECT
+      d.tag_id as tag_id,
+      SUM(d.scrobbles) AS tot
+    FROM daily_tag_plays AS d
+    WHERE d.day >= $1
+      AND d.day <= $2
+      AND ($4::integer IS NULL OR d.user_id = $4)
+    GROUP BY d.tag_id
+  ),
+  all_scrobbles AS (
+    SELECT SUM(d.scrobbles) as tot
+    FROM daily_track_plays AS d
+    WHERE d.day >= $1
+      AND d.day <= $2
+      AND ($4::integer IS NULL OR d.user_id = $4)
+  )
+SELECT
+  ta.tag_id as tag,
+  (ta.tot * 100 / ss.tot) as score
+FROM all_tags AS ta
+  CROSS JOIN all_scrobbles AS ss
+ORDER BY ta.tot DESC
+LIMIT $3
@@ -0,0 +1,35 @@
+WITH
+  track_albums AS (
+    SELECT
+      tt.track_id as track_id,
+      MIN(tt.album_id) AS album_id
+    FROM albums_tracks AS tt
+    GROUP BY tt.track_id
+  ),
+  all_artists AS (
+    SELECT
+      s.track_id as track_id,
+      json_agg(DISTINCT a.name)::text as artists
+    FROM scrobbles AS s
+    LEFT JOIN artists_tracks AS aa ON s.track_id = aa.track_id
+    LEFT JOIN artists AS a ON aa.artist_id = a.id
+    GROUP BY s.track_id
+  )
+SELECT
+  t.id,
+  t.title,
+  a.cover as cover,
+  COUNT(*) AS score,
+  SUM(s.duration_secs) AS listened_secs,
+  aa.artists
+FROM scrobbles AS s
+  JOIN tracks AS t ON t.id = s.track_id
+  JOIN track_albums AS tt ON tt.track_id = t.id
+  JOIN albums AS a ON tt.album_id = a.id
+  JOIN all_artists AS aa ON aa.track_id = t.id
+WHERE s.timestamp >= $1
+  AND s.timestamp <= $2
+  AND ($4::integer IS NULL OR s.user_id = $4)
+GROUP BY t.id, a.cover, aa.artists
+ORDER BY score DESC, listened_secs DESC
+LIMIT $3;
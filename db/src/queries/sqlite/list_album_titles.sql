@@ -0,0 +1,11 @@
+SELECT
+  l.id,
+  l.title AS name,
+  COUNT(s.id) AS scrobbles
+FROM albums AS l
+  JOIN albums_tracks AS ll ON ll.album_id = l.id
+  JOIN scrobbles AS s ON s.track_id = ll.track_id
+WHERE ?1 IS NULL
+  OR s.origin = ?1
+GROUP BY l.id
+ORDER BY scrobbles DESC;
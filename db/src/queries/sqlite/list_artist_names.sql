@@ -0,0 +1,11 @@
+SELECT
+  a.id,
+  a.name AS name,
+  COUNT(s.id) AS scrobbles
+FROM artists AS a
+  JOIN artists_tracks AS aa ON aa.artist_id = a.id
+  JOIN scrobbles AS s ON s.track_id = aa.track_id
+WHERE ?1 IS NULL
+  OR s.origin = ?1
+GROUP BY a.id
+ORDER BY scrobbles DESC;
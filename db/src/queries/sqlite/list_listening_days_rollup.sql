@@ -0,0 +1,7 @@
+SELECT DISTINCT
+  d.day AS day
+FROM daily_track_plays AS d
+WHERE d.day >= ?1
+  AND d.day <= ?2
+  AND (?3 IS NULL OR d.user_id = ?3)
+ORDER BY d.day;
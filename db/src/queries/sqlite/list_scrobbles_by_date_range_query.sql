@@ -2,23 +2,32 @@ WITH
   all_tags AS (
     SELECT
       s.track_id as track_id,
-      GROUP_CONCAT(DISTINCT(t.id)) AS tags
+      json_group_array(DISTINCT(t.id)) AS tags
     FROM scrobbles AS s
     LEFT JOIN tags_tracks AS tt ON s.track_id = tt.track_id
     LEFT JOIN tags AS t ON tt.tag_id = t.id
     GROUP BY s.track_id
   ),
+  track_albums AS (
+    SELECT
+      ll.track_id as track_id,
+      MIN(ll.album_id) AS album_id
+    FROM albums_tracks AS ll
+    GROUP BY ll.track_id
+  ),
   all_artists AS (
     SELECT
       s.track_id as track_id,
-      GROUP_CONCAT(DISTINCT(a.name)) AS artists
+      json_group_array(DISTINCT(a.name)) AS artists
     FROM scrobbles AS s
     LEFT JOIN artists_tracks AS aa ON s.track_id = aa.track_id
     LEFT JOIN artists AS a ON aa.artist_id = a.id
     GROUP BY s.track_id
   )
 SELECT
+  s.id,
   s.timestamp,
+  s.track_id,
   t.title AS track,
   l.title AS album,
   a.artists AS artists,
@@ -29,8 +38,9 @@ FROM scrobbles AS s
 JOIN tracks AS t ON s.track_id = t.id
 JOIN all_tags AS g ON t.id = g.track_id
 JOIN all_artists AS a ON t.id = a.track_id
-JOIN albums_tracks AS ll ON t.id = ll.track_id
+JOIN track_albums AS ll ON t.id = ll.track_id
 JOIN albums AS l ON l.id = ll.album_id
-WHERE s.timestamp >= ?
-  AND s.timestamp <= ?
+WHERE s.timestamp >= ?1
+  AND s.timestamp <= ?2
+  AND (?3 IS NULL OR s.user_id = ?3)
 ORDER BY s.timestamp DESC;
\ No newline at end of file
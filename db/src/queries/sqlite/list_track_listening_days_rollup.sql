@@ -0,0 +1,10 @@
+SELECT DISTINCT
+  t.id AS id,
+  t.title AS name,
+  d.day AS day
+FROM daily_track_plays AS d
+  JOIN tracks AS t ON t.id = d.track_id
+WHERE d.day >= ?1
+  AND d.day <= ?2
+  AND (?3 IS NULL OR d.user_id = ?3)
+ORDER BY d.day, t.id;
@@ -0,0 +1,10 @@
+SELECT
+  t.id,
+  t.title AS name,
+  s.timestamp
+FROM scrobbles AS s
+  JOIN tracks AS t ON t.id = s.track_id
+WHERE s.timestamp >= ?1
+  AND s.timestamp <= ?2
+  AND (?3 IS NULL OR s.user_id = ?3)
+ORDER BY s.timestamp ASC
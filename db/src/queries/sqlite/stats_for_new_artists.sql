@@ -0,0 +1,27 @@
+WITH
+  first_plays AS (
+    SELECT
+      tt.artist_id AS artist_id,
+      MIN(s.timestamp) AS first_timestamp
+    FROM scrobbles AS s
+      JOIN artists_tracks AS tt ON s.track_id = tt.track_id
+    WHERE (?4 IS NULL OR s.user_id = ?4)
+    GROUP BY tt.artist_id
+  )
+SELECT
+  a.id,
+  a.name,
+  COUNT(DISTINCT(s.track_id)) AS tracks,
+  COUNT(DISTINCT(s.id)) AS score
+FROM first_plays AS fp
+  JOIN artists AS a ON a.id = fp.artist_id
+  JOIN artists_tracks AS tt ON tt.artist_id = a.id
+  JOIN scrobbles AS s ON s.track_id = tt.track_id
+WHERE fp.first_timestamp >= ?1
+  AND fp.first_timestamp <= ?2
+  AND s.timestamp >= ?1
+  AND s.timestamp <= ?2
+  AND (?4 IS NULL OR s.user_id = ?4)
+GROUP BY a.id
+ORDER BY score DESC, tracks DESC
+LIMIT ?3
@@ -0,0 +1,32 @@
+WITH
+  artist_scores AS (
+    SELECT
+      d.artist_id as artist_id,
+      SUM(d.scrobbles) AS score
+    FROM daily_artist_plays AS d
+    WHERE d.day >= ?1
+      AND d.day <= ?2
+      AND (?4 IS NULL OR d.user_id = ?4)
+    GROUP BY d.artist_id
+  ),
+  artist_tracks AS (
+    SELECT
+      tt.artist_id as artist_id,
+      COUNT(DISTINCT(d.track_id)) AS tracks
+    FROM daily_track_plays AS d
+      JOIN artists_tracks AS tt ON tt.track_id = d.track_id
+    WHERE d.day >= ?1
+      AND d.day <= ?2
+      AND (?4 IS NULL OR d.user_id = ?4)
+    GROUP BY tt.artist_id
+  )
+SELECT
+  a.id,
+  a.name,
+  tr.tracks,
+  sc.score
+FROM artist_scores AS sc
+  JOIN artist_tracks AS tr ON tr.artist_id = sc.artist_id
+  JOIN artists AS a ON a.id = sc.artist_id
+ORDER BY score DESC, tracks DESC
+LIMIT ?3
@@ -9,6 +9,7 @@ WITH
       LEFT JOIN tags AS t ON tt.tag_id = t.id
 		    WHERE s.timestamp >= ?1
           AND s.timestamp <= ?2
+          AND (?4 IS NULL OR s.user_id = ?4)
     GROUP BY t.id
   ),
   all_scrobbles AS (
@@ -16,6 +17,7 @@ WITH
     FROM scrobbles AS s
     WHERE s.timestamp >= ?1
       AND s.timestamp <= ?2
+      AND (?4 IS NULL OR s.user_id = ?4)
   )
 SELECT
   ta.tag_id as tag,
@@ -1,4 +1,12 @@
-WITH all_artists AS (
+WITH
+  track_albums AS (
+    SELECT
+      tt.track_id as track_id,
+      MIN(tt.album_id) AS album_id
+    FROM albums_tracks AS tt
+    GROUP BY tt.track_id
+  ),
+  all_artists AS (
     SELECT
       s.track_id as track_id,
       -- GROUP_CONCAT(DISTINCT(a.name)) AS artists
@@ -17,11 +25,12 @@ SELECT
 	aa.artists
 FROM scrobbles AS s
   JOIN tracks AS t ON t.id = s.track_id
-  JOIN albums_tracks AS tt ON tt.track_id = t.id
+  JOIN track_albums AS tt ON tt.track_id = t.id
   JOIN albums AS a ON tt.album_id = a.id
 	JOIN all_artists AS aa ON aa.track_id = t.id
 WHERE s.timestamp >= ?1
 	AND s.timestamp <= ?2
+	AND (?4 IS NULL OR s.user_id = ?4)
 GROUP BY t.id
 ORDER BY score DESC, listened_secs DESC
 LIMIT ?3;
\ No newline at end of file
@@ -0,0 +1,45 @@
+WITH
+  top_tracks AS (
+    SELECT
+      d.track_id as track_id,
+      SUM(d.scrobbles) AS score,
+      SUM(d.listened_secs) AS listened_secs
+    FROM daily_track_plays AS d
+    WHERE d.day >= ?1
+      AND d.day <= ?2
+      AND (?4 IS NULL OR d.user_id = ?4)
+      AND d.track_id IN (SELECT track_id FROM albums_tracks)
+    GROUP BY d.track_id
+    ORDER BY score DESC, listened_secs DESC
+    LIMIT ?3
+  ),
+  track_albums AS (
+    SELECT
+      tt.track_id as track_id,
+      MIN(tt.album_id) AS album_id
+    FROM albums_tracks AS tt
+      JOIN top_tracks AS tp ON tp.track_id = tt.track_id
+    GROUP BY tt.track_id
+  ),
+  all_artists AS (
+    SELECT
+      tp.track_id as track_id,
+      json_group_array(DISTINCT(a.name)) as artists
+    FROM top_tracks AS tp
+    LEFT JOIN artists_tracks AS aa ON tp.track_id = aa.track_id
+    LEFT JOIN artists AS a ON aa.artist_id = a.id
+    GROUP BY tp.track_id
+  )
+SELECT
+  t.id,
+  t.title,
+  a.cover as cover,
+  tp.score,
+  tp.listened_secs,
+  aa.artists
+FROM top_tracks AS tp
+  JOIN tracks AS t ON t.id = tp.track_id
+  JOIN track_albums AS tt ON tt.track_id = t.id
+  JOIN albums AS a ON tt.album_id = a.id
+  JOIN all_artists AS aa ON aa.track_id = t.id
+ORDER BY tp.score DESC, tp.listened_secs DESC;
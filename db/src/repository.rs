@@ -1,80 +1,171 @@
-use anyhow::Result;
-use chrono::{DateTime, NaiveDateTime};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
 use sea_orm::{
-    sea_query::OnConflict, ActiveModelTrait, ActiveValue, Database, DatabaseConnection, DbBackend,
-    EntityTrait, FromQueryResult, Statement,
+    sea_query::{Expr, OnConflict, Query},
+    ActiveModelTrait, ActiveValue, ColumnTrait, Condition, ConnectionTrait, Database,
+    DatabaseConnection, DbBackend, EntityTrait, FromQueryResult, Insert, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect, QueryTrait, Statement, TransactionTrait,
 };
-use std::{env, str::FromStr, time::Duration};
+use std::time::Duration;
 
 use scrobblify_domain::{
     self,
     db::ParamsForStatsQuery,
+    duplicates::group_duplicates,
+    errors::{DatabaseError, DatabaseResult},
     models::{
-        Album, Artist, Scrobble, ScrobbleInfo, StatsArtist, StatsTag, StatsTrack, Tag, Track,
-        TrackInfo,
+        Album, Artist, AuditEntry, ChartItem, ChartKind, ChartSnapshot, DuplicateGroup, EntityDay,
+        EntityName, EntityPlayTime, Episode, EpisodeScrobbleInfo, PlayTime, RewriteField,
+        RewriteRule, Scrobble, ScrobbleEdit, ScrobbleInfo, ScrobbleSelection, Show, SkippedPlay,
+        StatsAlbum, StatsArtist, StatsShow, StatsTag, StatsTrack, Tag, Track, TrackInfo, User,
     },
+    time::{start_of_day, start_of_next_day, Tz},
 };
 
+use crate::duplicates::DuplicateCandidateQueryResult;
 use crate::entities::{
     albums::{self, ActiveModel as AlbumsModel, Entity as AlbumEntity},
     albums_artists::{self, ActiveModel as AlbumsArtistsModel, Entity as AlbumsArtistsEntity},
     albums_tracks::{self, ActiveModel as AlbumsTracksModel, Entity as AlbumsTracksEntity},
     artists::{self, ActiveModel as ArtistsModel, Entity as ArtistEntity},
     artists_tracks::{self, ActiveModel as ArtistsTracksModel, Entity as ArtistsTracksEntity},
-    scrobbles::ActiveModel as ScrobblesModel,
+    audit_log::{self, ActiveModel as AuditLogModel, Entity as AuditLogEntity},
+    episode_scrobbles::ActiveModel as EpisodeScrobblesModel,
+    episodes::{self, ActiveModel as EpisodesModel, Entity as EpisodeEntity},
+    merged_ids::{self, ActiveModel as MergedIdsModel, Entity as MergedIdsEntity},
+    rewrite_rules::{self, ActiveModel as RewriteRulesModel, Entity as RewriteRuleEntity},
+    scrobbles::{self, ActiveModel as ScrobblesModel, Entity as ScrobbleEntity},
+    sessions::{self, ActiveModel as SessionsModel, Entity as SessionEntity},
+    shows::{self, ActiveModel as ShowsModel, Entity as ShowEntity},
+    skipped_plays::{self, ActiveModel as SkippedPlaysModel, Entity as SkippedPlayEntity},
     tags::{self, ActiveModel as TagsModel, Entity as TagEntity},
     tags_tracks::{self, ActiveModel as TagsTracksModel, Entity as TagsTracksEntity},
     tracks::{self, ActiveModel as TracksModel, Entity as TrackEntity},
+    users::{self, ActiveModel as UsersModel, Entity as UserEntity},
+    weekly_chart_entries::{
+        self, ActiveModel as WeeklyChartEntriesModel, Entity as WeeklyChartEntryEntity,
+    },
+    weekly_charts::{self, ActiveModel as WeeklyChartsModel, Entity as WeeklyChartEntity},
 };
+use crate::rollups::{self, PlayedDays, Tracks};
+
+/// Picks the SQL of a raw query for the given backend, from `queries/sqlite`
+/// or `queries/postgres`.
+macro_rules! query {
+    ($backend:expr, $name:literal) => {
+        match $backend {
+            DbBackend::Postgres => include_str!(concat!("queries/postgres/", $name, ".sql")),
+            _ => include_str!(concat!("queries/sqlite/", $name, ".sql")),
+        }
+    };
+}
 
+/// Stats of tracks, artists, albums and tags are read from daily rollups of the scrobbles
+/// when their days are the ones of the rollups, and from the scrobbles otherwise.
 #[derive(Clone)]
 pub struct Repository {
     conn: DatabaseConnection,
+    rollups: bool,
+    /// The time zone of the days of the rollups.
+    time_zone: Tz,
 }
 
 impl Repository {
-    pub async fn new_from_env() -> Result<Repository> {
-        let url = env::var("DATABASE_URL").expect("DATABASE_URL is not set");
-        let conn = Database::connect(url).await?;
-        Ok(Repository { conn })
-    }
-
     pub async fn new(url: String) -> Result<Repository> {
         let conn = Database::connect(url).await?;
-        Ok(Repository { conn })
+        Ok(Repository::with_connection(conn))
     }
 
     pub fn with_connection(conn: DatabaseConnection) -> Repository {
-        Repository { conn }
+        Repository {
+            conn,
+            rollups: true,
+            time_zone: Tz::UTC,
+        }
+    }
+
+    /// Buckets the rollups by the days of a time zone, usually the configured one.
+    /// `sync_rollups` rebuilds them when they have the days of another one.
+    pub fn with_time_zone(self, time_zone: Tz) -> Repository {
+        Repository { time_zone, ..self }
+    }
+
+    /// Always reads stats from the scrobbles, e.g. to compare them with the rollups. These
+    /// are still kept up to date.
+    pub fn without_rollups(self) -> Repository {
+        Repository {
+            rollups: false,
+            ..self
+        }
     }
 
     pub fn conn(&self) -> DatabaseConnection {
         self.conn.clone()
     }
+
+    fn backend(&self) -> DbBackend {
+        self.conn.get_database_backend()
+    }
+
+    /// Recomputes the daily rollups from the scrobbles, e.g. after changing the database
+    /// by hand.
+    pub async fn rebuild_rollups(&self) -> Result<()> {
+        let txn = self.conn.begin().await?;
+        rollups::rebuild(&txn, self.time_zone)
+            .await
+            .map_err(to_db_error)?;
+        rollups::store_time_zone(&txn, self.time_zone)
+            .await
+            .map_err(to_db_error)?;
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    /// Rebuilds the rollups if they have been built in another time zone, e.g. after the
+    /// configured one changed.
+    pub async fn sync_rollups(&self) -> Result<()> {
+        let stored = rollups::stored_time_zone(&self.conn)
+            .await
+            .map_err(to_db_error)?;
+        if stored != Some(self.time_zone) {
+            self.rebuild_rollups().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Rollups have the days of the configured time zone, so they give the stats of whole
+    /// days of that time zone only.
+    fn reads_rollups(&self, opts: &ParamsForStatsQuery) -> bool {
+        self.rollups && opts.time_zone == self.time_zone
+    }
 }
 
 #[derive(Debug, FromQueryResult)]
 struct ScrobbleQueryResult {
+    id: i32,
+    track_id: String,
     track: String,
     duration_secs: f64,
     cover: String,
     artists: String,
     album: String,
     tags: Option<String>,
-    timestamp: String,
+    timestamp: i64,
 }
 
 #[derive(Debug, FromQueryResult)]
 struct PopularTagQueryResult {
     tag: String,
-    score: u32,
+    score: i64,
 }
 
 #[derive(Debug, FromQueryResult)]
 struct PopularTrackQueryResult {
     id: String,
     title: String,
-    score: u32,
+    score: i64,
     listened_secs: f64,
     cover: String,
     artists: String,
@@ -84,13 +175,66 @@ struct PopularTrackQueryResult {
 struct PopularArtistQueryResult {
     id: String,
     name: String,
-    score: u32,
-    tracks: u32,
+    score: i64,
+    tracks: i64,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct EntityNameQueryResult {
+    id: String,
+    name: String,
+    scrobbles: i64,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct PopularShowQueryResult {
+    id: String,
+    name: String,
+    publisher: String,
+    cover: String,
+    score: i64,
+    episodes: i64,
+    listened_secs: f64,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct EntityPlayTimeQueryResult {
+    id: String,
+    name: String,
+    timestamp: i64,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct ListeningDayQueryResult {
+    day: i64,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct EntityDayQueryResult {
+    id: String,
+    name: String,
+    day: i64,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct PlayTimeQueryResult {
+    timestamp: i64,
+    duration_secs: f64,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct PopularAlbumQueryResult {
+    id: String,
+    title: String,
+    cover: String,
+    artists: String,
+    score: i64,
+    tracks: i64,
 }
 
 #[async_trait::async_trait]
 impl scrobblify_domain::db::Repository for Repository {
-    async fn insert_track(&self, track: Track) -> Result<()> {
+    async fn insert_track(&self, track: Track) -> DatabaseResult<()> {
         let new_track = TracksModel {
             id: ActiveValue::Set(track.id),
             title: ActiveValue::Set(track.title),
@@ -98,262 +242,1928 @@ impl scrobblify_domain::db::Repository for Repository {
             isrc: ActiveValue::Set(track.isrc),
         };
 
-        TrackEntity::insert(new_track.clone())
-            .on_conflict(
+        exec_without_returning(
+            &self.conn,
+            TrackEntity::insert(new_track.clone()).on_conflict(
                 OnConflict::column(tracks::Column::Id)
                     .do_nothing()
                     .to_owned(),
-            )
-            .exec(&self.conn)
-            .await
-            .map_err(to_db_error)?;
+            ),
+        )
+        .await
+        .map_err(to_db_error)?;
 
         Ok(())
     }
 
-    async fn get_track_by_id(&self, id: String) -> Result<Option<Track>> {
-        match TrackEntity::find_by_id(id).one(&self.conn).await? {
+    async fn get_track_by_id(&self, id: String) -> DatabaseResult<Option<Track>> {
+        match TrackEntity::find_by_id(id)
+            .one(&self.conn)
+            .await
+            .map_err(to_db_error)?
+        {
             Some(track) => Ok(Some(track.into())),
             None => Ok(None),
         }
     }
 
-    async fn insert_album(&self, album: Album) -> Result<()> {
+    async fn update_track(&self, track: Track) -> DatabaseResult<()> {
+        let txn = self.conn.begin().await.map_err(to_db_error)?;
+        let before = TrackEntity::find_by_id(track.id.clone())
+            .one(&txn)
+            .await
+            .map_err(to_db_error)?
+            .ok_or_else(|| anyhow!("track `{}` not found", track.id))?;
+
+        let updated = TracksModel {
+            id: ActiveValue::Unchanged(track.id.clone()),
+            title: ActiveValue::Set(track.title.clone()),
+            duration_secs: ActiveValue::Set(track.duration_secs.as_secs_f64()),
+            isrc: ActiveValue::Set(track.isrc.clone()),
+        };
+        updated.update(&txn).await.map_err(to_db_error)?;
+
+        let details = describe_changes(vec![
+            ("title", before.title, track.title),
+            (
+                "duration_secs",
+                before.duration_secs.to_string(),
+                track.duration_secs.as_secs_f64().to_string(),
+            ),
+            ("isrc", before.isrc, track.isrc),
+        ]);
+        insert_audit_entry(&txn, "track", &track.id, "update", details).await?;
+        txn.commit().await.map_err(to_db_error)?;
+
+        Ok(())
+    }
+
+    async fn delete_track(&self, id: &str) -> DatabaseResult<()> {
+        let txn = self.conn.begin().await.map_err(to_db_error)?;
+        let before = TrackEntity::find_by_id(id.to_string())
+            .one(&txn)
+            .await
+            .map_err(to_db_error)?
+            .ok_or_else(|| anyhow!("track `{}` not found", id))?;
+        let played = rollups::played_days(&txn, Tracks::Id(id))
+            .await
+            .map_err(to_db_error)?;
+
+        let deleted = ScrobbleEntity::delete_many()
+            .filter(scrobbles::Column::TrackId.eq(id))
+            .exec(&txn)
+            .await
+            .map_err(to_db_error)?;
+        ArtistsTracksEntity::delete_many()
+            .filter(artists_tracks::Column::TrackId.eq(id))
+            .exec(&txn)
+            .await
+            .map_err(to_db_error)?;
+        AlbumsTracksEntity::delete_many()
+            .filter(albums_tracks::Column::TrackId.eq(id))
+            .exec(&txn)
+            .await
+            .map_err(to_db_error)?;
+        TagsTracksEntity::delete_many()
+            .filter(tags_tracks::Column::TrackId.eq(id))
+            .exec(&txn)
+            .await
+            .map_err(to_db_error)?;
+        TrackEntity::delete_by_id(id.to_string())
+            .exec(&txn)
+            .await
+            .map_err(to_db_error)?;
+
+        let details = format!(
+            "deleted {:?} with {} scrobbles",
+            before.title, deleted.rows_affected
+        );
+        insert_audit_entry(&txn, "track", id, "delete", details).await?;
+        rollups::refresh_played_days(&txn, self.time_zone, &played)
+            .await
+            .map_err(to_db_error)?;
+        txn.commit().await.map_err(to_db_error)?;
+
+        Ok(())
+    }
+
+    async fn insert_album(&self, album: Album) -> DatabaseResult<()> {
         let new_album = AlbumsModel {
             id: ActiveValue::Set(album.id),
             title: ActiveValue::Set(album.title),
             cover: ActiveValue::Set(album.cover),
         };
 
-        AlbumEntity::insert(new_album.clone())
-            .on_conflict(
+        exec_without_returning(
+            &self.conn,
+            AlbumEntity::insert(new_album.clone()).on_conflict(
                 OnConflict::column(albums::Column::Id)
                     .do_nothing()
                     .to_owned(),
-            )
-            .exec(&self.conn)
+            ),
+        )
+        .await
+        .map_err(to_db_error)?;
+
+        Ok(())
+    }
+
+    async fn get_album_by_id(&self, id: String) -> DatabaseResult<Option<Album>> {
+        match AlbumEntity::find_by_id(id)
+            .one(&self.conn)
+            .await
+            .map_err(to_db_error)?
+        {
+            Some(album) => Ok(Some(album.into())),
+            None => Ok(None),
+        }
+    }
+
+    async fn update_album(&self, album: Album) -> DatabaseResult<()> {
+        let txn = self.conn.begin().await.map_err(to_db_error)?;
+        let before = AlbumEntity::find_by_id(album.id.clone())
+            .one(&txn)
+            .await
+            .map_err(to_db_error)?
+            .ok_or_else(|| anyhow!("album `{}` not found", album.id))?;
+
+        let updated = AlbumsModel {
+            id: ActiveValue::Unchanged(album.id.clone()),
+            title: ActiveValue::Set(album.title.clone()),
+            cover: ActiveValue::Set(album.cover.clone()),
+        };
+        updated.update(&txn).await.map_err(to_db_error)?;
+
+        let details = describe_changes(vec![
+            ("title", before.title, album.title),
+            ("cover", before.cover, album.cover),
+        ]);
+        insert_audit_entry(&txn, "album", &album.id, "update", details).await?;
+        txn.commit().await.map_err(to_db_error)?;
+
+        Ok(())
+    }
+
+    async fn delete_album(&self, id: &str) -> DatabaseResult<()> {
+        let txn = self.conn.begin().await.map_err(to_db_error)?;
+        let before = AlbumEntity::find_by_id(id.to_string())
+            .one(&txn)
+            .await
+            .map_err(to_db_error)?
+            .ok_or_else(|| anyhow!("album `{}` not found", id))?;
+        let played = rollups::played_days(
+            &txn,
+            Tracks::LinkedTo {
+                table: "albums_tracks",
+                column: "album_id",
+                id,
+            },
+        )
+        .await
+        .map_err(to_db_error)?;
+
+        AlbumsTracksEntity::delete_many()
+            .filter(albums_tracks::Column::AlbumId.eq(id))
+            .exec(&txn)
+            .await
+            .map_err(to_db_error)?;
+        AlbumsArtistsEntity::delete_many()
+            .filter(albums_artists::Column::AlbumId.eq(id))
+            .exec(&txn)
+            .await
+            .map_err(to_db_error)?;
+        AlbumEntity::delete_by_id(id.to_string())
+            .exec(&txn)
+            .await
+            .map_err(to_db_error)?;
+
+        let details = format!("deleted {:?}", before.title);
+        insert_audit_entry(&txn, "album", id, "delete", details).await?;
+        rollups::refresh_played_days(&txn, self.time_zone, &played)
             .await
             .map_err(to_db_error)?;
+        txn.commit().await.map_err(to_db_error)?;
 
         Ok(())
     }
 
-    async fn insert_artist(&self, artist: Artist) -> Result<()> {
+    async fn insert_artist(&self, artist: Artist) -> DatabaseResult<()> {
         let new_artist = ArtistsModel {
             id: ActiveValue::Set(artist.id),
             name: ActiveValue::Set(artist.name),
         };
 
-        ArtistEntity::insert(new_artist.clone())
-            .on_conflict(
-                OnConflict::column(artists::Column::Id)
-                    .do_nothing()
-                    .to_owned(),
-            )
+        exec_without_returning(
+            &self.conn,
+            ArtistEntity::insert(new_artist.clone()).on_conflict(
+                OnConflict::column(artists::Column::Id)
+                    .do_nothing()
+                    .to_owned(),
+            ),
+        )
+        .await
+        .map_err(to_db_error)?;
+        Ok(())
+    }
+
+    async fn get_artist_by_id(&self, id: String) -> DatabaseResult<Option<Artist>> {
+        match ArtistEntity::find_by_id(id)
+            .one(&self.conn)
+            .await
+            .map_err(to_db_error)?
+        {
+            Some(artist) => Ok(Some(artist.into())),
+            None => Ok(None),
+        }
+    }
+
+    async fn update_artist(&self, artist: Artist) -> DatabaseResult<()> {
+        let txn = self.conn.begin().await.map_err(to_db_error)?;
+        let before = ArtistEntity::find_by_id(artist.id.clone())
+            .one(&txn)
+            .await
+            .map_err(to_db_error)?
+            .ok_or_else(|| anyhow!("artist `{}` not found", artist.id))?;
+
+        let updated = ArtistsModel {
+            id: ActiveValue::Unchanged(artist.id.clone()),
+            name: ActiveValue::Set(artist.name.clone()),
+        };
+        updated.update(&txn).await.map_err(to_db_error)?;
+
+        let details = describe_changes(vec![("name", before.name, artist.name)]);
+        insert_audit_entry(&txn, "artist", &artist.id, "update", details).await?;
+        txn.commit().await.map_err(to_db_error)?;
+
+        Ok(())
+    }
+
+    async fn delete_artist(&self, id: &str) -> DatabaseResult<()> {
+        let txn = self.conn.begin().await.map_err(to_db_error)?;
+        let before = ArtistEntity::find_by_id(id.to_string())
+            .one(&txn)
+            .await
+            .map_err(to_db_error)?
+            .ok_or_else(|| anyhow!("artist `{}` not found", id))?;
+        let played = rollups::played_days(
+            &txn,
+            Tracks::LinkedTo {
+                table: "artists_tracks",
+                column: "artist_id",
+                id,
+            },
+        )
+        .await
+        .map_err(to_db_error)?;
+
+        ArtistsTracksEntity::delete_many()
+            .filter(artists_tracks::Column::ArtistId.eq(id))
+            .exec(&txn)
+            .await
+            .map_err(to_db_error)?;
+        AlbumsArtistsEntity::delete_many()
+            .filter(albums_artists::Column::ArtistId.eq(id))
+            .exec(&txn)
+            .await
+            .map_err(to_db_error)?;
+        ArtistEntity::delete_by_id(id.to_string())
+            .exec(&txn)
+            .await
+            .map_err(to_db_error)?;
+
+        let details = format!("deleted {:?}", before.name);
+        insert_audit_entry(&txn, "artist", id, "delete", details).await?;
+        rollups::refresh_played_days(&txn, self.time_zone, &played)
+            .await
+            .map_err(to_db_error)?;
+        txn.commit().await.map_err(to_db_error)?;
+
+        Ok(())
+    }
+
+    async fn insert_tag(&self, tag: Tag) -> DatabaseResult<()> {
+        let new_tag = TagsModel {
+            id: ActiveValue::Set(tag.id),
+        };
+
+        exec_without_returning(
+            &self.conn,
+            TagEntity::insert(new_tag.clone())
+                .on_conflict(OnConflict::column(tags::Column::Id).do_nothing().to_owned()),
+        )
+        .await
+        .map_err(to_db_error)?;
+        Ok(())
+    }
+
+    async fn insert_show(&self, show: Show) -> DatabaseResult<()> {
+        let new_show = ShowsModel {
+            id: ActiveValue::Set(show.id),
+            name: ActiveValue::Set(show.name),
+            publisher: ActiveValue::Set(show.publisher),
+            cover: ActiveValue::Set(show.cover),
+        };
+
+        exec_without_returning(
+            &self.conn,
+            ShowEntity::insert(new_show.clone()).on_conflict(
+                OnConflict::column(shows::Column::Id)
+                    .do_nothing()
+                    .to_owned(),
+            ),
+        )
+        .await
+        .map_err(to_db_error)?;
+        Ok(())
+    }
+
+    async fn insert_episode(&self, episode: Episode) -> DatabaseResult<()> {
+        let new_episode = EpisodesModel {
+            id: ActiveValue::Set(episode.id),
+            title: ActiveValue::Set(episode.title),
+            duration_secs: ActiveValue::Set(episode.duration_secs.as_secs_f64()),
+            release_date: ActiveValue::Set(episode.release_date),
+            show_id: ActiveValue::Set(episode.show_id),
+        };
+
+        exec_without_returning(
+            &self.conn,
+            EpisodeEntity::insert(new_episode.clone()).on_conflict(
+                OnConflict::column(episodes::Column::Id)
+                    .do_nothing()
+                    .to_owned(),
+            ),
+        )
+        .await
+        .map_err(to_db_error)?;
+        Ok(())
+    }
+
+    async fn get_episode_by_id(&self, id: String) -> DatabaseResult<Option<Episode>> {
+        match EpisodeEntity::find_by_id(id)
+            .one(&self.conn)
+            .await
+            .map_err(to_db_error)?
+        {
+            Some(episode) => Ok(Some(episode.into())),
+            None => Ok(None),
+        }
+    }
+
+    async fn insert_user(&self, name: &str) -> DatabaseResult<User> {
+        let new_user = UsersModel {
+            id: ActiveValue::NotSet,
+            name: ActiveValue::Set(name.to_string()),
+            spotify_token: ActiveValue::Set(None),
+            password_hash: ActiveValue::Set(None),
+            is_admin: ActiveValue::Set(false),
+            public_stats: ActiveValue::Set(false),
+        };
+
+        let user = new_user.insert(&self.conn).await.map_err(to_db_error)?;
+        Ok(user.into())
+    }
+
+    async fn get_user_by_name(&self, name: &str) -> DatabaseResult<Option<User>> {
+        match UserEntity::find()
+            .filter(users::Column::Name.eq(name))
+            .one(&self.conn)
+            .await
+            .map_err(to_db_error)?
+        {
+            Some(user) => Ok(Some(user.into())),
+            None => Ok(None),
+        }
+    }
+
+    async fn list_users(&self) -> DatabaseResult<Vec<User>> {
+        let users = UserEntity::find()
+            .order_by_asc(users::Column::Id)
+            .all(&self.conn)
+            .await
+            .map_err(|err| DatabaseError::query("users", err))?;
+
+        Ok(users.into_iter().map(|u| u.into()).collect())
+    }
+
+    async fn get_password_hash(&self, user_id: i32) -> DatabaseResult<Option<String>> {
+        match UserEntity::find_by_id(user_id)
+            .one(&self.conn)
+            .await
+            .map_err(to_db_error)?
+        {
+            Some(user) => Ok(user.password_hash),
+            None => Err(anyhow!("user `{}` not found", user_id).into()),
+        }
+    }
+
+    async fn set_password_hash(&self, user_id: i32, hash: &str) -> DatabaseResult<()> {
+        let updated = UsersModel {
+            id: ActiveValue::Unchanged(user_id),
+            password_hash: ActiveValue::Set(Some(hash.to_string())),
+            ..Default::default()
+        };
+        updated.update(&self.conn).await.map_err(to_db_error)?;
+
+        Ok(())
+    }
+
+    async fn set_public_stats(&self, user_id: i32, public_stats: bool) -> DatabaseResult<()> {
+        let updated = UsersModel {
+            id: ActiveValue::Unchanged(user_id),
+            public_stats: ActiveValue::Set(public_stats),
+            ..Default::default()
+        };
+        updated.update(&self.conn).await.map_err(to_db_error)?;
+
+        Ok(())
+    }
+
+    async fn insert_session(
+        &self,
+        id: &str,
+        user_id: i32,
+        expires_at: DateTime<Utc>,
+    ) -> DatabaseResult<()> {
+        let session = SessionsModel {
+            id: ActiveValue::Set(id.to_string()),
+            user_id: ActiveValue::Set(user_id),
+            expires_at: ActiveValue::Set(expires_at.timestamp_millis()),
+        };
+
+        exec_without_returning(&self.conn, SessionEntity::insert(session))
+            .await
+            .map_err(to_db_error)?;
+
+        Ok(())
+    }
+
+    async fn get_session_user(&self, id: &str, now: DateTime<Utc>) -> DatabaseResult<Option<User>> {
+        let session = match SessionEntity::find_by_id(id.to_string())
+            .one(&self.conn)
+            .await
+            .map_err(to_db_error)?
+        {
+            Some(session) => session,
+            None => return Ok(None),
+        };
+
+        if session.expires_at < now.timestamp_millis() {
+            SessionEntity::delete_by_id(session.id)
+                .exec(&self.conn)
+                .await
+                .map_err(to_db_error)?;
+            return Ok(None);
+        }
+
+        match UserEntity::find_by_id(session.user_id)
+            .one(&self.conn)
+            .await
+            .map_err(to_db_error)?
+        {
+            Some(user) => Ok(Some(user.into())),
+            None => Ok(None),
+        }
+    }
+
+    async fn delete_session(&self, id: &str) -> DatabaseResult<()> {
+        SessionEntity::delete_by_id(id.to_string())
+            .exec(&self.conn)
+            .await
+            .map_err(to_db_error)?;
+
+        Ok(())
+    }
+
+    async fn delete_user_sessions(&self, user_id: i32, except: Option<&str>) -> DatabaseResult<()> {
+        let mut delete = SessionEntity::delete_many().filter(sessions::Column::UserId.eq(user_id));
+        if let Some(except) = except {
+            delete = delete.filter(sessions::Column::Id.ne(except));
+        }
+        delete.exec(&self.conn).await.map_err(to_db_error)?;
+
+        Ok(())
+    }
+
+    async fn insert_scrobble(&self, scrobble: ScrobbleInfo) -> DatabaseResult<()> {
+        let track_info = scrobble.clone().track;
+        let device = scrobble.device.clone();
+        let context = scrobble.context.clone();
+        let (user_id, timestamp) = (scrobble.user_id, scrobble.timestamp.timestamp_millis());
+        let scrobble = ScrobblesModel {
+            id: ActiveValue::NotSet,
+            user_id: ActiveValue::Set(user_id),
+            timestamp: ActiveValue::Set(timestamp),
+            origin: ActiveValue::Set(scrobble.origin),
+            duration_secs: ActiveValue::Set(track_info.duration_secs.as_secs_f64()),
+            track_id: ActiveValue::Set(track_info.clone().id),
+            device_name: ActiveValue::Set(device.clone().map(|d| d.name)),
+            device_type: ActiveValue::Set(device.map(|d| d.kind)),
+            context_type: ActiveValue::Set(context.clone().map(|c| c.kind)),
+            context_uri: ActiveValue::Set(context.map(|c| c.uri)),
+        };
+
+        // the scrobble, its links and the rollups they change are stored all together
+        let txn = self.conn.begin().await.map_err(to_db_error)?;
+
+        // the same play can be reported more than once, e.g. by recently played tracks
+        let inserted = exec_without_returning(
+            &txn,
+            ScrobbleEntity::insert(scrobble).on_conflict(
+                OnConflict::columns(vec![
+                    scrobbles::Column::UserId,
+                    scrobbles::Column::Timestamp,
+                    scrobbles::Column::TrackId,
+                ])
+                .do_nothing()
+                .to_owned(),
+            ),
+        )
+        .await
+        .map_err(to_db_error)?;
+
+        let linked = insert_entity_links(&txn, track_info.clone()).await?;
+
+        // new links change the artists, album or tags of every day the track was played
+        if linked {
+            for day in rollups::track_days(&txn, &track_info.id)
+                .await
+                .map_err(to_db_error)?
+            {
+                rollups::refresh_days(&txn, self.time_zone, None, day, day)
+                    .await
+                    .map_err(to_db_error)?;
+            }
+        }
+        if inserted > 0 {
+            rollups::refresh_days(&txn, self.time_zone, Some(user_id), timestamp, timestamp)
+                .await
+                .map_err(to_db_error)?;
+        }
+        txn.commit().await.map_err(to_db_error)?;
+
+        Ok(())
+    }
+
+    async fn get_last_scrobble(&self, user_id: i32) -> DatabaseResult<Option<Scrobble>> {
+        // match ScrobbleEntity::find()
+        //     .join(JoinType::LeftJoin, scrobbles::Relation::Tracks.def())
+        //     .into_model::<ScrobbleQueryResult>()
+        //     .one(&self.conn)
+        //     .await?
+        // {
+        //     Some(scrobble) => Ok(Some(scrobble.into())),
+        //     None => Ok(None),
+        // }
+        match ScrobbleQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            query!(self.backend(), "get_last_scrobble_query"),
+            vec![sea_orm::Value::from(user_id)],
+        ))
+        .one(&self.conn)
+        .await
+        .map_err(to_db_error)?
+        {
+            Some(scrobble) => Ok(Some(scrobble.try_into()?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_first_scrobble_timestamp(
+        &self,
+        user_id: i32,
+    ) -> DatabaseResult<Option<DateTime<Utc>>> {
+        let first = ScrobbleEntity::find()
+            .filter(scrobbles::Column::UserId.eq(user_id))
+            .order_by_asc(scrobbles::Column::Timestamp)
+            .one(&self.conn)
+            .await
+            .map_err(to_db_error)?;
+
+        Ok(first
+            .map(|scrobble| millis_to_datetime(scrobble.timestamp))
+            .transpose()?)
+    }
+
+    async fn count_scrobbles(&self, user_id: i32) -> DatabaseResult<u64> {
+        let count = ScrobbleEntity::find()
+            .filter(scrobbles::Column::UserId.eq(user_id))
+            .count(&self.conn)
+            .await
+            .map_err(to_db_error)?;
+
+        Ok(count as u64)
+    }
+
+    async fn get_nth_scrobble(&self, user_id: i32, n: u64) -> DatabaseResult<Option<Scrobble>> {
+        if n == 0 {
+            return Ok(None);
+        }
+
+        let nth = ScrobbleEntity::find()
+            .filter(scrobbles::Column::UserId.eq(user_id))
+            .order_by_asc(scrobbles::Column::Timestamp)
+            .order_by_asc(scrobbles::Column::Id)
+            .offset(n - 1)
+            .one(&self.conn)
+            .await
+            .map_err(to_db_error)?;
+
+        match nth {
+            Some(scrobble) => self.get_scrobble(scrobble.id).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn get_scrobble(&self, id: i32) -> DatabaseResult<Option<Scrobble>> {
+        match ScrobbleQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            query!(self.backend(), "get_scrobble_query"),
+            vec![sea_orm::Value::from(id)],
+        ))
+        .one(&self.conn)
+        .await
+        .map_err(to_db_error)?
+        {
+            Some(scrobble) => Ok(Some(scrobble.try_into()?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn update_scrobble(&self, id: i32, scrobble: ScrobbleEdit) -> DatabaseResult<()> {
+        let txn = self.conn.begin().await.map_err(to_db_error)?;
+        let before = ScrobbleEntity::find_by_id(id)
+            .one(&txn)
+            .await
+            .map_err(to_db_error)?
+            .ok_or_else(|| anyhow!("scrobble `{}` not found", id))?;
+        if TrackEntity::find_by_id(scrobble.track_id.clone())
+            .one(&txn)
+            .await
+            .map_err(to_db_error)?
+            .is_none()
+        {
+            return Err(anyhow!("track `{}` not found", scrobble.track_id).into());
+        }
+
+        let updated = ScrobblesModel {
+            id: ActiveValue::Unchanged(id),
+            timestamp: ActiveValue::Set(scrobble.timestamp.timestamp_millis()),
+            track_id: ActiveValue::Set(scrobble.track_id.clone()),
+            duration_secs: ActiveValue::Set(scrobble.duration_secs),
+            ..Default::default()
+        };
+        updated.update(&txn).await.map_err(to_db_error)?;
+
+        let details = describe_changes(vec![
+            (
+                "timestamp",
+                millis_to_datetime(before.timestamp)?.to_string(),
+                scrobble.timestamp.to_string(),
+            ),
+            ("track_id", before.track_id, scrobble.track_id),
+            (
+                "duration_secs",
+                before.duration_secs.to_string(),
+                scrobble.duration_secs.to_string(),
+            ),
+        ]);
+        insert_audit_entry(&txn, "scrobble", &id.to_string(), "update", details).await?;
+        for day in [before.timestamp, scrobble.timestamp.timestamp_millis()] {
+            rollups::refresh_days(&txn, self.time_zone, Some(before.user_id), day, day)
+                .await
+                .map_err(to_db_error)?;
+        }
+        txn.commit().await.map_err(to_db_error)?;
+
+        Ok(())
+    }
+
+    async fn delete_scrobble(&self, id: i32) -> DatabaseResult<()> {
+        let txn = self.conn.begin().await.map_err(to_db_error)?;
+        let before = ScrobbleEntity::find_by_id(id)
+            .one(&txn)
+            .await
+            .map_err(to_db_error)?
+            .ok_or_else(|| anyhow!("scrobble `{}` not found", id))?;
+
+        ScrobbleEntity::delete_by_id(id)
+            .exec(&txn)
+            .await
+            .map_err(to_db_error)?;
+
+        let details = format!(
+            "deleted scrobble of track `{}` at {}",
+            before.track_id,
+            millis_to_datetime(before.timestamp)?
+        );
+        insert_audit_entry(&txn, "scrobble", &id.to_string(), "delete", details).await?;
+        rollups::refresh_days(
+            &txn,
+            self.time_zone,
+            Some(before.user_id),
+            before.timestamp,
+            before.timestamp,
+        )
+        .await
+        .map_err(to_db_error)?;
+        txn.commit().await.map_err(to_db_error)?;
+
+        Ok(())
+    }
+
+    async fn delete_scrobbles(&self, selection: ScrobbleSelection) -> DatabaseResult<u64> {
+        let txn = self.conn.begin().await.map_err(to_db_error)?;
+        let deleted = ScrobbleEntity::delete_many()
+            .filter(selection_condition(&selection))
+            .exec(&txn)
+            .await
+            .map_err(to_db_error)?;
+
+        let details = format!(
+            "deleted {} scrobbles {}",
+            deleted.rows_affected,
+            describe_selection(&selection)
+        );
+        insert_audit_entry(&txn, "scrobbles", &selection.track_id, "delete", details).await?;
+        refresh_selection(&txn, self.time_zone, &selection).await?;
+        txn.commit().await.map_err(to_db_error)?;
+
+        Ok(deleted.rows_affected)
+    }
+
+    async fn reassign_scrobbles(
+        &self,
+        selection: ScrobbleSelection,
+        track_id: &str,
+    ) -> DatabaseResult<u64> {
+        let txn = self.conn.begin().await.map_err(to_db_error)?;
+        if TrackEntity::find_by_id(track_id.to_string())
+            .one(&txn)
+            .await
+            .map_err(to_db_error)?
+            .is_none()
+        {
+            return Err(anyhow!("track `{}` not found", track_id).into());
+        }
+
+        let updated = ScrobbleEntity::update_many()
+            .col_expr(scrobbles::Column::TrackId, Expr::value(track_id))
+            .filter(selection_condition(&selection))
+            .exec(&txn)
+            .await
+            .map_err(to_db_error)?;
+
+        let details = format!(
+            "moved {} scrobbles {} to track `{}`",
+            updated.rows_affected,
+            describe_selection(&selection),
+            track_id
+        );
+        insert_audit_entry(&txn, "scrobbles", &selection.track_id, "reassign", details).await?;
+        refresh_selection(&txn, self.time_zone, &selection).await?;
+        txn.commit().await.map_err(to_db_error)?;
+
+        Ok(updated.rows_affected)
+    }
+
+    async fn list_scrobbles_by_date_range(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<Scrobble>> {
+        let (start, end) = build_dates_range(opts.clone());
+
+        let scrobbles = ScrobbleQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            query!(self.backend(), "list_scrobbles_by_date_range_query"),
+            vec![
+                sea_orm::Value::from(start),
+                sea_orm::Value::from(end),
+                sea_orm::Value::from(opts.user_id),
+            ],
+        ))
+        .all(&self.conn)
+        .await
+        .map_err(|err| DatabaseError::query("scrobbles of the range", err))?;
+
+        scrobbles.into_iter().map(Scrobble::try_from).collect()
+    }
+
+    async fn list_scrobbles_by_tag(&self, tag: &str) -> DatabaseResult<Vec<Scrobble>> {
+        let scrobbles = ScrobbleQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            query!(self.backend(), "list_scrobbles_by_tag_query"),
+            vec![tag.into()],
+        ))
+        .all(&self.conn)
+        .await
+        .map_err(|err| DatabaseError::query("scrobbles of the genre", err))?;
+
+        scrobbles.into_iter().map(Scrobble::try_from).collect()
+    }
+
+    async fn list_play_times(&self, opts: ParamsForStatsQuery) -> DatabaseResult<Vec<PlayTime>> {
+        let (start, end) = build_dates_range(opts.clone());
+        let mut query = ScrobbleEntity::find()
+            .select_only()
+            .column(scrobbles::Column::Timestamp)
+            .column(scrobbles::Column::DurationSecs)
+            .filter(scrobbles::Column::Timestamp.between(start, end))
+            .order_by_asc(scrobbles::Column::Timestamp);
+        if let Some(user_id) = opts.user_id {
+            query = query.filter(scrobbles::Column::UserId.eq(user_id));
+        }
+
+        let times = query
+            .into_model::<PlayTimeQueryResult>()
+            .all(&self.conn)
+            .await
+            .map_err(|err| DatabaseError::query("play times", err))?;
+
+        times.into_iter().map(PlayTime::try_from).collect()
+    }
+
+    async fn list_artist_play_times(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<EntityPlayTime>> {
+        let (start, end) = build_dates_range(opts.clone());
+
+        let times = EntityPlayTimeQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            query!(self.backend(), "list_artist_play_times"),
+            vec![
+                sea_orm::Value::from(start),
+                sea_orm::Value::from(end),
+                sea_orm::Value::from(opts.user_id),
+            ],
+        ))
+        .all(&self.conn)
+        .await
+        .map_err(|err| DatabaseError::query("artist play times", err))?;
+
+        times.into_iter().map(EntityPlayTime::try_from).collect()
+    }
+
+    async fn list_track_play_times(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<EntityPlayTime>> {
+        let (start, end) = build_dates_range(opts.clone());
+
+        let times = EntityPlayTimeQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            query!(self.backend(), "list_track_play_times"),
+            vec![
+                sea_orm::Value::from(start),
+                sea_orm::Value::from(end),
+                sea_orm::Value::from(opts.user_id),
+            ],
+        ))
+        .all(&self.conn)
+        .await
+        .map_err(|err| DatabaseError::query("track play times", err))?;
+
+        times.into_iter().map(EntityPlayTime::try_from).collect()
+    }
+
+    async fn list_listening_days(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<NaiveDate>> {
+        let tz = opts.time_zone;
+        if !self.reads_rollups(&opts) {
+            let mut days: Vec<NaiveDate> = self
+                .list_play_times(opts)
+                .await?
+                .iter()
+                .map(|t| t.timestamp.with_timezone(&tz).date_naive())
+                .collect();
+            days.dedup();
+            return Ok(days);
+        }
+
+        let (start, end) = build_dates_range(opts.clone());
+        let days = ListeningDayQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            query!(self.backend(), "list_listening_days_rollup"),
+            vec![
+                sea_orm::Value::from(start),
+                sea_orm::Value::from(end),
+                sea_orm::Value::from(opts.user_id),
+            ],
+        ))
+        .all(&self.conn)
+        .await
+        .map_err(|err| DatabaseError::query("listening days", err))?;
+
+        days.into_iter().map(|d| local_day(d.day, tz)).collect()
+    }
+
+    async fn list_artist_listening_days(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<EntityDay>> {
+        if !self.reads_rollups(&opts) {
+            let tz = opts.time_zone;
+            return Ok(entity_days(self.list_artist_play_times(opts).await?, tz));
+        }
+
+        let (start, end) = build_dates_range(opts.clone());
+        let days = EntityDayQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            query!(self.backend(), "list_artist_listening_days_rollup"),
+            vec![
+                sea_orm::Value::from(start),
+                sea_orm::Value::from(end),
+                sea_orm::Value::from(opts.user_id),
+            ],
+        ))
+        .all(&self.conn)
+        .await
+        .map_err(|err| DatabaseError::query("artist listening days", err))?;
+
+        days.into_iter()
+            .map(|d| {
+                Ok(EntityDay {
+                    id: d.id,
+                    name: d.name,
+                    day: local_day(d.day, opts.time_zone)?,
+                })
+            })
+            .collect()
+    }
+
+    async fn list_track_listening_days(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<EntityDay>> {
+        if !self.reads_rollups(&opts) {
+            let tz = opts.time_zone;
+            return Ok(entity_days(self.list_track_play_times(opts).await?, tz));
+        }
+
+        let (start, end) = build_dates_range(opts.clone());
+        let days = EntityDayQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            query!(self.backend(), "list_track_listening_days_rollup"),
+            vec![
+                sea_orm::Value::from(start),
+                sea_orm::Value::from(end),
+                sea_orm::Value::from(opts.user_id),
+            ],
+        ))
+        .all(&self.conn)
+        .await
+        .map_err(|err| DatabaseError::query("track listening days", err))?;
+
+        days.into_iter()
+            .map(|d| {
+                Ok(EntityDay {
+                    id: d.id,
+                    name: d.name,
+                    day: local_day(d.day, opts.time_zone)?,
+                })
+            })
+            .collect()
+    }
+
+    async fn list_tag_play_times(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<EntityPlayTime>> {
+        let (start, end) = build_dates_range(opts.clone());
+
+        let times = EntityPlayTimeQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            query!(self.backend(), "list_tag_play_times"),
+            vec![
+                sea_orm::Value::from(start),
+                sea_orm::Value::from(end),
+                sea_orm::Value::from(opts.user_id),
+            ],
+        ))
+        .all(&self.conn)
+        .await
+        .map_err(|err| DatabaseError::query("genre play times", err))?;
+
+        times.into_iter().map(EntityPlayTime::try_from).collect()
+    }
+
+    async fn list_first_plays(
+        &self,
+        user_id: i32,
+        limit: u64,
+    ) -> DatabaseResult<Vec<EntityPlayTime>> {
+        let plays = EntityPlayTimeQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            query!(self.backend(), "list_first_plays"),
+            vec![
+                sea_orm::Value::from(user_id),
+                sea_orm::Value::from(limit as i64),
+            ],
+        ))
+        .all(&self.conn)
+        .await
+        .map_err(|err| DatabaseError::query("first plays", err))?;
+
+        plays.into_iter().map(EntityPlayTime::try_from).collect()
+    }
+
+    async fn list_scrobbles_by_artist(&self, artist_id: &str) -> DatabaseResult<Vec<Scrobble>> {
+        let scrobbles = ScrobbleQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            query!(self.backend(), "list_scrobbles_by_artist_query"),
+            vec![artist_id.into()],
+        ))
+        .all(&self.conn)
+        .await
+        .map_err(|err| DatabaseError::query("scrobbles of the artist", err))?;
+
+        scrobbles.into_iter().map(Scrobble::try_from).collect()
+    }
+
+    async fn insert_episode_scrobble(&self, scrobble: EpisodeScrobbleInfo) -> DatabaseResult<()> {
+        let device = scrobble.device.clone();
+        let context = scrobble.context.clone();
+        let scrobble = EpisodeScrobblesModel {
+            id: ActiveValue::NotSet,
+            user_id: ActiveValue::Set(scrobble.user_id),
+            timestamp: ActiveValue::Set(scrobble.timestamp.timestamp_millis()),
+            origin: ActiveValue::Set(String::from("spotify")),
+            duration_secs: ActiveValue::Set(scrobble.duration_secs),
+            episode_id: ActiveValue::Set(scrobble.episode.id),
+            device_name: ActiveValue::Set(device.clone().map(|d| d.name)),
+            device_type: ActiveValue::Set(device.map(|d| d.kind)),
+            context_type: ActiveValue::Set(context.clone().map(|c| c.kind)),
+            context_uri: ActiveValue::Set(context.map(|c| c.uri)),
+        };
+
+        scrobble.insert(&self.conn).await.map_err(to_db_error)?;
+
+        Ok(())
+    }
+
+    async fn insert_skipped_play(&self, play: SkippedPlay) -> DatabaseResult<()> {
+        let play = SkippedPlaysModel {
+            user_id: ActiveValue::Set(play.user_id),
+            timestamp: ActiveValue::Set(play.timestamp.timestamp_millis()),
+            track_id: ActiveValue::Set(play.track_id),
+            duration_secs: ActiveValue::Set(play.duration_secs),
+        };
+
+        // the same play is denied again when checked after a restart
+        exec_without_returning(
+            &self.conn,
+            SkippedPlayEntity::insert(play).on_conflict(
+                OnConflict::columns(vec![
+                    skipped_plays::Column::UserId,
+                    skipped_plays::Column::Timestamp,
+                    skipped_plays::Column::TrackId,
+                ])
+                .do_nothing()
+                .to_owned(),
+            ),
+        )
+        .await
+        .map_err(to_db_error)?;
+
+        Ok(())
+    }
+
+    async fn list_skipped_plays(
+        &self,
+        user_id: i32,
+        since: DateTime<Utc>,
+    ) -> DatabaseResult<Vec<SkippedPlay>> {
+        let plays = SkippedPlayEntity::find()
+            .filter(skipped_plays::Column::UserId.eq(user_id))
+            .filter(skipped_plays::Column::Timestamp.gte(since.timestamp_millis()))
+            .order_by_asc(skipped_plays::Column::Timestamp)
+            .all(&self.conn)
+            .await
+            .map_err(|err| DatabaseError::query("skipped plays", err))?;
+
+        plays.into_iter().map(SkippedPlay::try_from).collect()
+    }
+
+    async fn delete_skipped_plays(
+        &self,
+        user_id: i32,
+        before: DateTime<Utc>,
+    ) -> DatabaseResult<()> {
+        SkippedPlayEntity::delete_many()
+            .filter(skipped_plays::Column::UserId.eq(user_id))
+            .filter(skipped_plays::Column::Timestamp.lt(before.timestamp_millis()))
             .exec(&self.conn)
             .await
             .map_err(to_db_error)?;
+
         Ok(())
     }
 
-    async fn insert_tag(&self, tag: Tag) -> Result<()> {
-        let new_tag = TagsModel {
-            id: ActiveValue::Set(tag.id),
+    async fn insert_rewrite_rule(&self, rule: RewriteRule) -> DatabaseResult<()> {
+        let new_rule = RewriteRulesModel {
+            id: ActiveValue::NotSet,
+            field: ActiveValue::Set(rule.field.as_str().to_string()),
+            pattern: ActiveValue::Set(rule.pattern),
+            replacement: ActiveValue::Set(rule.replacement),
+            origin: ActiveValue::Set(rule.origin),
         };
 
-        TagEntity::insert(new_tag.clone())
-            .on_conflict(OnConflict::column(tags::Column::Id).do_nothing().to_owned())
+        new_rule.insert(&self.conn).await.map_err(to_db_error)?;
+        Ok(())
+    }
+
+    async fn delete_rewrite_rule(&self, id: i32) -> DatabaseResult<()> {
+        RewriteRuleEntity::delete_by_id(id)
             .exec(&self.conn)
             .await
             .map_err(to_db_error)?;
         Ok(())
     }
 
-    async fn insert_scrobble(&self, scrobble: ScrobbleInfo) -> Result<()> {
-        let track_info = scrobble.clone().track;
-        let scrobble = ScrobblesModel {
-            timestamp: ActiveValue::Set(scrobble.timestamp.to_string()),
-            origin: ActiveValue::Set(String::from("spotify")),
-            duration_secs: ActiveValue::Set(track_info.duration_secs.as_secs_f64()),
-            track_id: ActiveValue::Set(track_info.clone().id),
+    async fn list_rewrite_rules(&self) -> DatabaseResult<Vec<RewriteRule>> {
+        let rules = RewriteRuleEntity::find()
+            .order_by_asc(rewrite_rules::Column::Id)
+            .all(&self.conn)
+            .await
+            .map_err(|err| DatabaseError::query("rewrite rules", err))?;
+
+        rules.into_iter().map(RewriteRule::try_from).collect()
+    }
+
+    async fn list_entity_names(
+        &self,
+        field: RewriteField,
+        origin: Option<String>,
+    ) -> DatabaseResult<Vec<EntityName>> {
+        let query = match field {
+            RewriteField::Title => query!(self.backend(), "list_track_titles"),
+            RewriteField::Artist => query!(self.backend(), "list_artist_names"),
+            RewriteField::Album => query!(self.backend(), "list_album_titles"),
         };
 
-        scrobble.insert(&self.conn).await.map_err(to_db_error)?;
+        let names = EntityNameQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            query,
+            vec![sea_orm::Value::from(origin)],
+        ))
+        .all(&self.conn)
+        .await
+        .map_err(|err| DatabaseError::query("entity names", err))?;
+
+        Ok(names.into_iter().map(|n| n.into()).collect())
+    }
+
+    async fn rename_entity(&self, field: RewriteField, id: &str, name: &str) -> DatabaseResult<()> {
+        let txn = self.conn.begin().await.map_err(to_db_error)?;
+        let entity = match field {
+            RewriteField::Title => {
+                TrackEntity::update_many()
+                    .col_expr(tracks::Column::Title, Expr::value(name))
+                    .filter(tracks::Column::Id.eq(id))
+                    .exec(&txn)
+                    .await
+                    .map_err(to_db_error)?;
+                "track"
+            }
+            RewriteField::Artist => {
+                ArtistEntity::update_many()
+                    .col_expr(artists::Column::Name, Expr::value(name))
+                    .filter(artists::Column::Id.eq(id))
+                    .exec(&txn)
+                    .await
+                    .map_err(to_db_error)?;
+                "artist"
+            }
+            RewriteField::Album => {
+                AlbumEntity::update_many()
+                    .col_expr(albums::Column::Title, Expr::value(name))
+                    .filter(albums::Column::Id.eq(id))
+                    .exec(&txn)
+                    .await
+                    .map_err(to_db_error)?;
+                "album"
+            }
+        };
 
-        insert_entity_links(&self.conn, track_info.clone()).await?;
+        let details = format!("{} renamed to {:?} by a rewrite rule", field.as_str(), name);
+        insert_audit_entry(&txn, entity, id, "rewrite", details).await?;
+        txn.commit().await.map_err(to_db_error)?;
 
         Ok(())
     }
 
-    async fn get_last_scrobble(&self) -> Result<Option<Scrobble>> {
-        // match ScrobbleEntity::find()
-        //     .join(JoinType::LeftJoin, scrobbles::Relation::Tracks.def())
-        //     .into_model::<ScrobbleQueryResult>()
-        //     .one(&self.conn)
-        //     .await?
-        // {
-        //     Some(scrobble) => Ok(Some(scrobble.into())),
-        //     None => Ok(None),
-        // }
-        match ScrobbleQueryResult::find_by_statement(Statement::from_sql_and_values(
-            DbBackend::Sqlite,
-            include_str!("queries/get_last_scrobble_query.sql"),
-            vec![1.into()],
+    async fn list_duplicates(&self, field: RewriteField) -> DatabaseResult<Vec<DuplicateGroup>> {
+        let query = match field {
+            RewriteField::Title => query!(self.backend(), "list_track_duplicate_candidates"),
+            RewriteField::Artist => query!(self.backend(), "list_artist_duplicate_candidates"),
+            RewriteField::Album => query!(self.backend(), "list_album_duplicate_candidates"),
+        };
+
+        let candidates = DuplicateCandidateQueryResult::find_by_statement(Statement::from_string(
+            self.backend(),
+            query.to_string(),
         ))
-        .one(&self.conn)
-        .await?
-        {
-            Some(scrobble) => Ok(Some(scrobble.into())),
-            None => Ok(None),
+        .all(&self.conn)
+        .await
+        .map_err(|err| DatabaseError::query("duplicates", err))?;
+
+        Ok(group_duplicates(
+            field,
+            candidates.into_iter().map(|c| c.into()).collect(),
+        ))
+    }
+
+    async fn merge_entities(
+        &self,
+        field: RewriteField,
+        canonical_id: &str,
+        duplicate_ids: Vec<String>,
+    ) -> DatabaseResult<()> {
+        let duplicate_ids: Vec<String> = duplicate_ids
+            .into_iter()
+            .filter(|id| id != canonical_id)
+            .collect();
+        if duplicate_ids.is_empty() {
+            return Err(anyhow!("nothing to merge into `{}`", canonical_id).into());
+        }
+
+        let entity = entity_kind(field);
+        let txn = self.conn.begin().await.map_err(to_db_error)?;
+        for id in std::iter::once(canonical_id).chain(duplicate_ids.iter().map(|id| id.as_str())) {
+            if !entity_exists(&txn, field, id).await? {
+                return Err(anyhow!("{} `{}` not found", entity, id).into());
+            }
+        }
+
+        // the days the duplicates were played in are the only ones whose rollups change
+        let mut played = PlayedDays::new();
+        for id in duplicate_ids.iter() {
+            let tracks = match field {
+                RewriteField::Title => Tracks::Id(id),
+                RewriteField::Artist => Tracks::LinkedTo {
+                    table: "artists_tracks",
+                    column: "artist_id",
+                    id,
+                },
+                RewriteField::Album => Tracks::LinkedTo {
+                    table: "albums_tracks",
+                    column: "album_id",
+                    id,
+                },
+            };
+            for (user_id, days) in rollups::played_days(&txn, tracks)
+                .await
+                .map_err(to_db_error)?
+            {
+                played.entry(user_id).or_default().extend(days);
+            }
+
+            let details = match field {
+                RewriteField::Title => {
+                    let moved = ScrobbleEntity::update_many()
+                        .col_expr(scrobbles::Column::TrackId, Expr::value(canonical_id))
+                        .filter(scrobbles::Column::TrackId.eq(id.as_str()))
+                        .exec(&txn)
+                        .await
+                        .map_err(to_db_error)?;
+                    repoint_links(
+                        &txn,
+                        "artists_tracks",
+                        "track_id",
+                        "artist_id",
+                        id,
+                        canonical_id,
+                    )
+                    .await?;
+                    repoint_links(
+                        &txn,
+                        "albums_tracks",
+                        "track_id",
+                        "album_id",
+                        id,
+                        canonical_id,
+                    )
+                    .await?;
+                    repoint_links(&txn, "tags_tracks", "track_id", "tag_id", id, canonical_id)
+                        .await?;
+                    TrackEntity::delete_by_id(id.clone())
+                        .exec(&txn)
+                        .await
+                        .map_err(to_db_error)?;
+
+                    format!("{} scrobbles moved", moved.rows_affected)
+                }
+                RewriteField::Artist => {
+                    let moved = repoint_links(
+                        &txn,
+                        "artists_tracks",
+                        "artist_id",
+                        "track_id",
+                        id,
+                        canonical_id,
+                    )
+                    .await?;
+                    repoint_links(
+                        &txn,
+                        "albums_artists",
+                        "artist_id",
+                        "album_id",
+                        id,
+                        canonical_id,
+                    )
+                    .await?;
+                    ArtistEntity::delete_by_id(id.clone())
+                        .exec(&txn)
+                        .await
+                        .map_err(to_db_error)?;
+
+                    format!("{} tracks moved", moved)
+                }
+                RewriteField::Album => {
+                    let moved = repoint_links(
+                        &txn,
+                        "albums_tracks",
+                        "album_id",
+                        "track_id",
+                        id,
+                        canonical_id,
+                    )
+                    .await?;
+                    repoint_links(
+                        &txn,
+                        "albums_artists",
+                        "album_id",
+                        "artist_id",
+                        id,
+                        canonical_id,
+                    )
+                    .await?;
+                    AlbumEntity::delete_by_id(id.clone())
+                        .exec(&txn)
+                        .await
+                        .map_err(to_db_error)?;
+
+                    format!("{} tracks moved", moved)
+                }
+            };
+
+            // past weekly charts rank the canonical entity in place of the duplicate
+            WeeklyChartEntryEntity::update_many()
+                .col_expr(
+                    weekly_chart_entries::Column::EntityId,
+                    Expr::value(canonical_id),
+                )
+                .filter(weekly_chart_entries::Column::EntityId.eq(id.as_str()))
+                .filter(
+                    weekly_chart_entries::Column::ChartId.in_subquery(
+                        Query::select()
+                            .column(weekly_charts::Column::Id)
+                            .from(WeeklyChartEntity)
+                            .and_where(weekly_charts::Column::Kind.eq(chart_kind(field).as_str()))
+                            .to_owned(),
+                    ),
+                )
+                .exec(&txn)
+                .await
+                .map_err(to_db_error)?;
+
+            // remember the merge, so new scrobbles of the duplicate go to the canonical
+            // entity too, including the ones of entities previously merged into it
+            MergedIdsEntity::update_many()
+                .col_expr(merged_ids::Column::CanonicalId, Expr::value(canonical_id))
+                .filter(merged_ids::Column::Kind.eq(entity))
+                .filter(merged_ids::Column::CanonicalId.eq(id.as_str()))
+                .exec(&txn)
+                .await
+                .map_err(to_db_error)?;
+            let merged = MergedIdsModel {
+                kind: ActiveValue::Set(entity.to_string()),
+                id: ActiveValue::Set(id.clone()),
+                canonical_id: ActiveValue::Set(canonical_id.to_string()),
+            };
+            exec_without_returning(
+                &txn,
+                MergedIdsEntity::insert(merged).on_conflict(
+                    OnConflict::columns(vec![merged_ids::Column::Kind, merged_ids::Column::Id])
+                        .update_column(merged_ids::Column::CanonicalId)
+                        .to_owned(),
+                ),
+            )
+            .await
+            .map_err(to_db_error)?;
+
+            let details = format!("merged into `{}`, {}", canonical_id, details);
+            insert_audit_entry(&txn, entity, id, "merge", details).await?;
         }
+        rollups::refresh_played_days(&txn, self.time_zone, &played)
+            .await
+            .map_err(to_db_error)?;
+        txn.commit().await.map_err(to_db_error)?;
+
+        Ok(())
     }
 
-    async fn list_scrobbles_by_date_range(&self, opts: ParamsForStatsQuery) -> Vec<Scrobble> {
-        let (start, end) = build_dates_range(opts);
+    async fn resolve_merged_id(&self, field: RewriteField, id: &str) -> DatabaseResult<String> {
+        let merged = MergedIdsEntity::find_by_id((entity_kind(field).to_string(), id.to_string()))
+            .one(&self.conn)
+            .await
+            .map_err(to_db_error)?;
 
-        match ScrobbleQueryResult::find_by_statement(Statement::from_sql_and_values(
-            DbBackend::Sqlite,
-            include_str!("queries/list_scrobbles_by_date_range_query.sql"),
+        Ok(merged.map_or_else(|| id.to_string(), |m| m.canonical_id))
+    }
+
+    async fn list_audit_log(&self, limit: u64) -> DatabaseResult<Vec<AuditEntry>> {
+        let entries = AuditLogEntity::find()
+            .order_by_desc(audit_log::Column::Id)
+            .limit(limit)
+            .all(&self.conn)
+            .await
+            .map_err(|err| DatabaseError::query("audit log", err))?;
+
+        entries.into_iter().map(AuditEntry::try_from).collect()
+    }
+
+    async fn stats_for_popular_tags(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsTag>> {
+        let (start, end) = build_dates_range(opts.clone());
+        let limit = opts.limit.unwrap_or(10);
+
+        let tracks = PopularTagQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            if self.reads_rollups(&opts) {
+                query!(self.backend(), "stats_for_popular_tags_rollup")
+            } else {
+                query!(self.backend(), "stats_for_popular_tags")
+            },
             vec![
-                sea_orm::Value::from(start.to_string()),
-                sea_orm::Value::from(end.to_string()),
+                sea_orm::Value::from(start),
+                sea_orm::Value::from(end),
+                sea_orm::Value::from(limit as i64),
+                sea_orm::Value::from(opts.user_id),
             ],
         ))
         .all(&self.conn)
         .await
-        {
-            Ok(scrobbles) => scrobbles.into_iter().map(|s| s.into()).collect(),
-            Err(_) => vec![],
-        }
+        .map_err(|err| DatabaseError::query("popular genres", err))?;
+
+        Ok(tracks.into_iter().map(|t| t.into()).collect())
     }
 
-    async fn list_scrobbles_by_tag(&self, tag: &str) -> Vec<Scrobble> {
-        match ScrobbleQueryResult::find_by_statement(Statement::from_sql_and_values(
-            DbBackend::Sqlite,
-            include_str!("queries/list_scrobbles_by_tag_query.sql"),
-            vec![tag.into()],
+    async fn stats_for_popular_tracks(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsTrack>> {
+        let (start, end) = build_dates_range(opts.clone());
+        let limit = opts.limit.unwrap_or(10);
+
+        let tracks = PopularTrackQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            if self.reads_rollups(&opts) {
+                query!(self.backend(), "stats_for_popular_tracks_rollup")
+            } else {
+                query!(self.backend(), "stats_for_popular_tracks")
+            },
+            vec![
+                sea_orm::Value::from(start),
+                sea_orm::Value::from(end),
+                sea_orm::Value::from(limit as i64),
+                sea_orm::Value::from(opts.user_id),
+            ],
         ))
         .all(&self.conn)
         .await
-        {
-            Ok(scrobbles) => scrobbles.into_iter().map(|s| s.into()).collect(),
-            Err(_) => vec![],
-        }
+        .map_err(|err| DatabaseError::query("popular tracks", err))?;
+
+        Ok(tracks.into_iter().map(|t| t.into()).collect())
     }
 
-    async fn list_scrobbles_by_artist(&self, artist_id: &str) -> Vec<Scrobble> {
-        match ScrobbleQueryResult::find_by_statement(Statement::from_sql_and_values(
-            DbBackend::Sqlite,
-            include_str!("queries/list_scrobbles_by_artist_query.sql"),
-            vec![artist_id.into()],
+    async fn stats_for_popular_artists(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsArtist>> {
+        let (start, end) = build_dates_range(opts.clone());
+        let limit = opts.limit.unwrap_or(10);
+
+        let tracks = PopularArtistQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            if self.reads_rollups(&opts) {
+                query!(self.backend(), "stats_for_popular_artists_rollup")
+            } else {
+                query!(self.backend(), "stats_for_popular_artists")
+            },
+            vec![
+                sea_orm::Value::from(start),
+                sea_orm::Value::from(end),
+                sea_orm::Value::from(limit as i64),
+                sea_orm::Value::from(opts.user_id),
+            ],
         ))
         .all(&self.conn)
         .await
-        {
-            Ok(scrobbles) => scrobbles.into_iter().map(|s| s.into()).collect(),
-            Err(err) => {
-                tracing::error!(
-                    msg = "list_scrobbles_by_artist_query",
-                    error = format!("{:?}", err)
-                );
-                vec![]
-            }
-        }
+        .map_err(|err| DatabaseError::query("popular artists", err))?;
+
+        Ok(tracks.into_iter().map(|t| t.into()).collect())
     }
 
-    async fn stats_for_popular_tags(&self, opts: ParamsForStatsQuery) -> Vec<StatsTag> {
+    async fn stats_for_popular_shows(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsShow>> {
         let (start, end) = build_dates_range(opts.clone());
         let limit = opts.limit.unwrap_or(10);
 
-        match PopularTagQueryResult::find_by_statement(Statement::from_sql_and_values(
-            DbBackend::Sqlite,
-            include_str!("queries/stats_for_popular_tags.sql"),
+        let shows = PopularShowQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            query!(self.backend(), "stats_for_popular_shows"),
             vec![
-                sea_orm::Value::from(start.to_string()),
-                sea_orm::Value::from(end.to_string()),
-                sea_orm::Value::from(limit),
+                sea_orm::Value::from(start),
+                sea_orm::Value::from(end),
+                sea_orm::Value::from(limit as i64),
+                sea_orm::Value::from(opts.user_id),
             ],
         ))
         .all(&self.conn)
         .await
-        {
-            Ok(tracks) => tracks.into_iter().map(|t| t.into()).collect(),
-            Err(err) => {
-                tracing::error!(msg = "popular_tags_query", error = format!("{:?}", err));
-                vec![]
-            }
-        }
+        .map_err(|err| DatabaseError::query("popular shows", err))?;
+
+        Ok(shows.into_iter().map(|s| s.into()).collect())
     }
 
-    async fn stats_for_popular_tracks(&self, opts: ParamsForStatsQuery) -> Vec<StatsTrack> {
+    async fn stats_for_popular_albums(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsAlbum>> {
         let (start, end) = build_dates_range(opts.clone());
         let limit = opts.limit.unwrap_or(10);
 
-        match PopularTrackQueryResult::find_by_statement(Statement::from_sql_and_values(
-            DbBackend::Sqlite,
-            include_str!("queries/stats_for_popular_tracks.sql"),
+        let albums = PopularAlbumQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            if self.reads_rollups(&opts) {
+                query!(self.backend(), "stats_for_popular_albums_rollup")
+            } else {
+                query!(self.backend(), "stats_for_popular_albums")
+            },
             vec![
-                sea_orm::Value::from(start.to_string()),
-                sea_orm::Value::from(end.to_string()),
-                sea_orm::Value::from(limit),
+                sea_orm::Value::from(start),
+                sea_orm::Value::from(end),
+                sea_orm::Value::from(limit as i64),
+                sea_orm::Value::from(opts.user_id),
             ],
         ))
         .all(&self.conn)
         .await
-        {
-            Ok(tracks) => tracks.into_iter().map(|t| t.into()).collect(),
-            Err(err) => {
-                tracing::error!(msg = "popular_tracks_query", error = format!("{:?}", err));
-                vec![]
-            }
-        }
+        .map_err(|err| DatabaseError::query("popular albums", err))?;
+
+        Ok(albums.into_iter().map(|a| a.into()).collect())
     }
 
-    async fn stats_for_popular_artists(&self, opts: ParamsForStatsQuery) -> Vec<StatsArtist> {
+    async fn stats_for_new_artists(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsArtist>> {
         let (start, end) = build_dates_range(opts.clone());
         let limit = opts.limit.unwrap_or(10);
 
-        match PopularArtistQueryResult::find_by_statement(Statement::from_sql_and_values(
-            DbBackend::Sqlite,
-            include_str!("queries/stats_for_popular_artists.sql"),
+        let artists = PopularArtistQueryResult::find_by_statement(Statement::from_sql_and_values(
+            self.backend(),
+            query!(self.backend(), "stats_for_new_artists"),
             vec![
-                sea_orm::Value::from(start.to_string()),
-                sea_orm::Value::from(end.to_string()),
-                sea_orm::Value::from(limit),
+                sea_orm::Value::from(start),
+                sea_orm::Value::from(end),
+                sea_orm::Value::from(limit as i64),
+                sea_orm::Value::from(opts.user_id),
             ],
         ))
         .all(&self.conn)
         .await
-        {
-            Ok(tracks) => tracks.into_iter().map(|t| t.into()).collect(),
-            Err(err) => {
-                tracing::error!(msg = "popular_artists_query", error = format!("{:?}", err));
-                vec![]
-            }
+        .map_err(|err| DatabaseError::query("new artists", err))?;
+
+        Ok(artists.into_iter().map(|a| a.into()).collect())
+    }
+
+    async fn list_chart_snapshots(
+        &self,
+        user_id: i32,
+        kind: ChartKind,
+        until: NaiveDate,
+    ) -> DatabaseResult<Vec<ChartSnapshot>> {
+        let charts = WeeklyChartEntity::find()
+            .filter(weekly_charts::Column::UserId.eq(user_id))
+            .filter(weekly_charts::Column::Kind.eq(kind.as_str()))
+            .filter(weekly_charts::Column::WeekStart.lte(until))
+            .order_by_asc(weekly_charts::Column::WeekStart)
+            .find_with_related(WeeklyChartEntryEntity)
+            .all(&self.conn)
+            .await
+            .map_err(to_db_error)?;
+
+        Ok(charts
+            .into_iter()
+            .map(|(chart, mut entries)| {
+                entries.sort_by_key(|entry| entry.rank);
+                ChartSnapshot {
+                    week_start: chart.week_start,
+                    items: entries.into_iter().map(|entry| entry.into()).collect(),
+                }
+            })
+            .collect())
+    }
+
+    async fn insert_chart_snapshot(
+        &self,
+        user_id: i32,
+        kind: ChartKind,
+        snapshot: ChartSnapshot,
+    ) -> DatabaseResult<()> {
+        let txn = self.conn.begin().await.map_err(to_db_error)?;
+
+        let chart = WeeklyChartsModel {
+            user_id: ActiveValue::Set(user_id),
+            kind: ActiveValue::Set(kind.as_str().to_string()),
+            week_start: ActiveValue::Set(snapshot.week_start),
+            ..Default::default()
+        };
+        let chart_id = WeeklyChartEntity::insert(chart)
+            .exec(&txn)
+            .await
+            .map_err(to_db_error)?
+            .last_insert_id;
+
+        // an empty week keeps its header row, so it isn't computed again
+        if !snapshot.items.is_empty() {
+            let entries = snapshot
+                .items
+                .into_iter()
+                .map(|item| WeeklyChartEntriesModel {
+                    chart_id: ActiveValue::Set(chart_id),
+                    rank: ActiveValue::Set(item.rank as i32),
+                    entity_id: ActiveValue::Set(item.id),
+                    name: ActiveValue::Set(item.name),
+                    artists: ActiveValue::Set(item.artists),
+                    score: ActiveValue::Set(item.score as i32),
+                });
+            exec_without_returning(&txn, WeeklyChartEntryEntity::insert_many(entries))
+                .await
+                .map_err(to_db_error)?;
         }
+
+        txn.commit().await.map_err(to_db_error)?;
+        Ok(())
     }
 }
 
 /// Helper function to cast a sea_orm::DbErr into a domain Database Error.
 /// This requires casting the sea_orm::DbErr into anyhow::Error first.
-fn to_db_error(e: sea_orm::DbErr) -> scrobblify_domain::errors::DatabaseError {
-    scrobblify_domain::errors::DatabaseError::from(anyhow::Error::from(e))
+fn to_db_error(e: sea_orm::DbErr) -> DatabaseError {
+    DatabaseError::from(anyhow::Error::from(e))
+}
+
+/// Runs an insert without reading the inserted rows back, which fails on Postgres when a
+/// conflict skips them all. Returns the number of inserted rows.
+async fn exec_without_returning<C, A>(conn: &C, insert: Insert<A>) -> Result<u64, sea_orm::DbErr>
+where
+    C: ConnectionTrait,
+    A: ActiveModelTrait,
+{
+    let statement = insert.build(conn.get_database_backend());
+    Ok(conn.execute(statement).await?.rows_affected())
+}
+
+async fn insert_audit_entry<C: ConnectionTrait>(
+    conn: &C,
+    entity: &str,
+    entity_id: &str,
+    action: &str,
+    details: String,
+) -> Result<()> {
+    let entry = AuditLogModel {
+        id: ActiveValue::NotSet,
+        timestamp: ActiveValue::Set(Utc::now().to_rfc3339()),
+        entity: ActiveValue::Set(entity.to_string()),
+        entity_id: ActiveValue::Set(entity_id.to_string()),
+        action: ActiveValue::Set(action.to_string()),
+        details: ActiveValue::Set(details),
+    };
+
+    entry.insert(conn).await.map_err(to_db_error)?;
+    Ok(())
+}
+
+fn entity_kind(field: RewriteField) -> &'static str {
+    match field {
+        RewriteField::Title => "track",
+        RewriteField::Artist => "artist",
+        RewriteField::Album => "album",
+    }
+}
+
+fn chart_kind(field: RewriteField) -> ChartKind {
+    match field {
+        RewriteField::Title => ChartKind::Tracks,
+        RewriteField::Artist => ChartKind::Artists,
+        RewriteField::Album => ChartKind::Albums,
+    }
+}
+
+async fn entity_exists<C: ConnectionTrait>(
+    conn: &C,
+    field: RewriteField,
+    id: &str,
+) -> Result<bool> {
+    let exists = match field {
+        RewriteField::Title => TrackEntity::find_by_id(id.to_string())
+            .one(conn)
+            .await?
+            .is_some(),
+        RewriteField::Artist => ArtistEntity::find_by_id(id.to_string())
+            .one(conn)
+            .await?
+            .is_some(),
+        RewriteField::Album => AlbumEntity::find_by_id(id.to_string())
+            .one(conn)
+            .await?
+            .is_some(),
+    };
+
+    Ok(exists)
+}
+
+/// Moves the rows of a join table from a duplicate entity to the canonical one, skipping
+/// the ones the canonical entity already has. Returns the number of rows moved.
+async fn repoint_links<C: ConnectionTrait>(
+    conn: &C,
+    table: &str,
+    column: &str,
+    other_column: &str,
+    id: &str,
+    canonical_id: &str,
+) -> Result<u64> {
+    let backend = conn.get_database_backend();
+    let (first, second) = match backend {
+        DbBackend::Postgres => ("$1", "$2"),
+        _ => ("?1", "?2"),
+    };
+
+    let insert = format!(
+        "INSERT INTO {table} ({column}, {other_column}) \
+         SELECT {first}, {other_column} FROM {table} WHERE {column} = {second} \
+         ON CONFLICT DO NOTHING",
+        table = table,
+        column = column,
+        other_column = other_column,
+        first = first,
+        second = second
+    );
+    conn.execute(Statement::from_sql_and_values(
+        backend,
+        &insert,
+        vec![canonical_id.into(), id.into()],
+    ))
+    .await
+    .map_err(to_db_error)?;
+
+    let delete = format!(
+        "DELETE FROM {table} WHERE {column} = {first}",
+        table = table,
+        column = column,
+        first = first
+    );
+    let deleted = conn
+        .execute(Statement::from_sql_and_values(
+            backend,
+            &delete,
+            vec![id.into()],
+        ))
+        .await
+        .map_err(to_db_error)?;
+
+    Ok(deleted.rows_affected())
+}
+
+/// Lists the fields whose values differ, e.g. `title: "A" -> "B"`.
+fn describe_changes(changes: Vec<(&str, String, String)>) -> String {
+    changes
+        .into_iter()
+        .filter(|(_, before, after)| before != after)
+        .map(|(field, before, after)| format!("{}: {:?} -> {:?}", field, before, after))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn describe_selection(selection: &ScrobbleSelection) -> String {
+    let mut description = format!("of track `{}`", selection.track_id);
+    if let Some(start) = selection.start {
+        description.push_str(&format!(" from {}", start));
+    }
+    if let Some(end) = selection.end {
+        description.push_str(&format!(" to {}", end));
+    }
+    description
+}
+
+fn selection_condition(selection: &ScrobbleSelection) -> Condition {
+    let mut condition =
+        Condition::all().add(scrobbles::Column::TrackId.eq(selection.track_id.clone()));
+    if let Some(start) = selection.start {
+        condition = condition.add(scrobbles::Column::Timestamp.gte(start.timestamp_millis()));
+    }
+    if let Some(end) = selection.end {
+        condition = condition.add(scrobbles::Column::Timestamp.lte(end.timestamp_millis()));
+    }
+    condition
+}
+
+/// Refreshes the rollups of the days of a selection or, when it's open-ended, of the days
+/// its track was played in: the rollups still list them until they're refreshed.
+async fn refresh_selection<C: ConnectionTrait>(
+    conn: &C,
+    tz: Tz,
+    selection: &ScrobbleSelection,
+) -> Result<()> {
+    match (selection.start, selection.end) {
+        (Some(start), Some(end)) => {
+            rollups::refresh_days(
+                conn,
+                tz,
+                None,
+                start.timestamp_millis(),
+                end.timestamp_millis(),
+            )
+            .await
+        }
+        _ => {
+            let played = rollups::played_days(conn, Tracks::Id(&selection.track_id))
+                .await
+                .map_err(to_db_error)?;
+            rollups::refresh_played_days(conn, tz, &played).await
+        }
+    }
+    .map_err(to_db_error)?;
+
+    Ok(())
 }
 
-fn build_dates_range(opts: ParamsForStatsQuery) -> (NaiveDateTime, NaiveDateTime) {
-    let time_start = chrono::NaiveTime::from_hms(0, 0, 0);
-    let start = chrono::NaiveDateTime::new(opts.start, time_start);
-    let time_end = chrono::NaiveTime::from_hms(23, 59, 59);
-    let end = chrono::NaiveDateTime::new(opts.end, time_end);
+/// Timestamps are stored as milliseconds since epoch, the range covers both days entirely,
+/// in the time zone of the query.
+fn build_dates_range(opts: ParamsForStatsQuery) -> (i64, i64) {
+    let start = start_of_day(opts.start, opts.time_zone).timestamp_millis();
+    let end = start_of_next_day(opts.end, opts.time_zone).timestamp_millis() - 1;
 
     (start, end)
 }
 
-async fn insert_entity_links(conn: &DatabaseConnection, track_info: TrackInfo) -> Result<()> {
+fn millis_to_datetime(millis: i64) -> DatabaseResult<DateTime<Utc>> {
+    Utc.timestamp_millis_opt(millis)
+        .single()
+        .ok_or_else(|| DatabaseError::InvalidData {
+            field: "timestamp",
+            value: millis.to_string(),
+        })
+}
+
+/// Names aggregated as a JSON array by the scrobble queries, without the `null` a track
+/// with no artists or tags yields.
+fn json_names(field: &'static str, json: &str) -> DatabaseResult<Vec<String>> {
+    let names: Vec<Option<String>> =
+        serde_json::from_str(json).map_err(|_| DatabaseError::InvalidData {
+            field,
+            value: json.to_string(),
+        })?;
+    Ok(names.into_iter().flatten().collect())
+}
+
+/// The local day a rollup day starts, in milliseconds since epoch, belongs to.
+fn local_day(millis: i64, tz: Tz) -> DatabaseResult<NaiveDate> {
+    Ok(millis_to_datetime(millis)?.with_timezone(&tz).date_naive())
+}
+
+/// The local days of plays, oldest first, each day of an entity once.
+fn entity_days(plays: Vec<EntityPlayTime>, tz: Tz) -> Vec<EntityDay> {
+    let mut days: Vec<EntityDay> = plays
+        .into_iter()
+        .map(|play| EntityDay {
+            day: play.timestamp.with_timezone(&tz).date_naive(),
+            id: play.id,
+            name: play.name,
+        })
+        .collect();
+    days.sort_by(|a, b| a.day.cmp(&b.day).then_with(|| a.id.cmp(&b.id)));
+    days.dedup();
+    days
+}
+
+/// Links a track to its artists, album and tags. Returns whether any link is new.
+async fn insert_entity_links<C: ConnectionTrait>(conn: &C, track_info: TrackInfo) -> Result<bool> {
     let track: Track = track_info.clone().into();
     let artists = track_info.clone().artists;
     let album = track_info.clone().album;
     let tags = track_info.clone().tags;
+    let mut linked = 0;
 
     for tag in tags.iter() {
         let tags_tracks = TagsTracksModel {
@@ -361,18 +2171,19 @@ async fn insert_entity_links(conn: &DatabaseConnection, track_info: TrackInfo) -
             track_id: ActiveValue::Set(track.id.clone()),
         };
 
-        TagsTracksEntity::insert(tags_tracks.clone())
-            .on_conflict(
+        linked += exec_without_returning(
+            conn,
+            TagsTracksEntity::insert(tags_tracks.clone()).on_conflict(
                 OnConflict::columns(vec![
                     tags_tracks::Column::TagId,
                     tags_tracks::Column::TrackId,
                 ])
                 .do_nothing()
                 .to_owned(),
-            )
-            .exec(conn)
-            .await
-            .map_err(to_db_error)?;
+            ),
+        )
+        .await
+        .map_err(to_db_error)?;
     }
 
     for artist in artists.iter() {
@@ -381,36 +2192,38 @@ async fn insert_entity_links(conn: &DatabaseConnection, track_info: TrackInfo) -
             track_id: ActiveValue::Set(track.id.clone()),
         };
 
-        ArtistsTracksEntity::insert(artists_tracks.clone())
-            .on_conflict(
+        linked += exec_without_returning(
+            conn,
+            ArtistsTracksEntity::insert(artists_tracks.clone()).on_conflict(
                 OnConflict::columns(vec![
                     artists_tracks::Column::ArtistId,
                     artists_tracks::Column::TrackId,
                 ])
                 .do_nothing()
                 .to_owned(),
-            )
-            .exec(conn)
-            .await
-            .map_err(to_db_error)?;
+            ),
+        )
+        .await
+        .map_err(to_db_error)?;
 
         let albums_artists = AlbumsArtistsModel {
             album_id: ActiveValue::Set(album.id.clone()),
             artist_id: ActiveValue::Set(artist.id.clone()),
         };
 
-        AlbumsArtistsEntity::insert(albums_artists.clone())
-            .on_conflict(
+        exec_without_returning(
+            conn,
+            AlbumsArtistsEntity::insert(albums_artists.clone()).on_conflict(
                 OnConflict::columns(vec![
                     albums_artists::Column::ArtistId,
                     albums_artists::Column::AlbumId,
                 ])
                 .do_nothing()
                 .to_owned(),
-            )
-            .exec(conn)
-            .await
-            .map_err(to_db_error)?;
+            ),
+        )
+        .await
+        .map_err(to_db_error)?;
     }
 
     let albums_tracks = AlbumsTracksModel {
@@ -418,48 +2231,47 @@ async fn insert_entity_links(conn: &DatabaseConnection, track_info: TrackInfo) -
         track_id: ActiveValue::Set(track.id),
     };
 
-    AlbumsTracksEntity::insert(albums_tracks.clone())
-        .on_conflict(
+    linked += exec_without_returning(
+        conn,
+        AlbumsTracksEntity::insert(albums_tracks.clone()).on_conflict(
             OnConflict::columns(vec![
                 albums_tracks::Column::TrackId,
                 albums_tracks::Column::AlbumId,
             ])
             .do_nothing()
             .to_owned(),
-        )
-        .exec(conn)
-        .await
-        .map_err(to_db_error)?;
+        ),
+    )
+    .await
+    .map_err(to_db_error)?;
 
-    Ok(())
+    Ok(linked > 0)
 }
 
-impl From<ScrobbleQueryResult> for Scrobble {
-    fn from(s: ScrobbleQueryResult) -> Self {
-        Self {
-            timestamp: DateTime::from_str(s.timestamp.as_str()).unwrap(),
-            duration_secs: Duration::from_secs_f64(s.duration_secs),
+impl TryFrom<ScrobbleQueryResult> for Scrobble {
+    type Error = DatabaseError;
+
+    fn try_from(s: ScrobbleQueryResult) -> DatabaseResult<Self> {
+        Ok(Self {
+            id: s.id,
+            timestamp: millis_to_datetime(s.timestamp)?,
+            duration_secs: Duration::try_from_secs_f64(s.duration_secs).map_err(|_| {
+                DatabaseError::InvalidData {
+                    field: "duration_secs",
+                    value: s.duration_secs.to_string(),
+                }
+            })?,
+            track_id: s.track_id,
             track: s.track,
             cover: s.cover,
             album: s.album,
-            artists: s
-                .artists
-                .as_str()
-                .split(',')
-                .into_iter()
-                .map(|t| t.to_string())
-                .collect(),
-            tags: s.tags.map_or_else(
-                || vec![],
-                |t| {
-                    t.as_str()
-                        .split(',')
-                        .into_iter()
-                        .map(|t| t.to_string())
-                        .collect()
-                },
-            ),
-        }
+            artists: json_names("artists", &s.artists)?,
+            tags: s
+                .tags
+                .map(|tags| json_names("tags", &tags))
+                .transpose()?
+                .unwrap_or_default(),
+        })
     }
 }
 
@@ -467,7 +2279,7 @@ impl From<PopularTagQueryResult> for StatsTag {
     fn from(t: PopularTagQueryResult) -> Self {
         Self {
             name: t.tag,
-            score: t.score,
+            score: t.score as u32,
         }
     }
 }
@@ -477,7 +2289,7 @@ impl From<PopularTrackQueryResult> for StatsTrack {
         Self {
             id: t.id,
             title: t.title,
-            score: t.score,
+            score: t.score as u32,
             listened_secs: t.listened_secs,
             cover: t.cover,
             artists: t.artists,
@@ -490,8 +2302,93 @@ impl From<PopularArtistQueryResult> for StatsArtist {
         Self {
             id: t.id,
             name: t.name,
-            score: t.score,
-            tracks: t.tracks,
+            score: t.score as u32,
+            tracks: t.tracks as u32,
+        }
+    }
+}
+
+impl From<PopularShowQueryResult> for StatsShow {
+    fn from(s: PopularShowQueryResult) -> Self {
+        Self {
+            id: s.id,
+            name: s.name,
+            publisher: s.publisher,
+            cover: s.cover,
+            score: s.score as u32,
+            episodes: s.episodes as u32,
+            listened_secs: s.listened_secs,
+        }
+    }
+}
+
+impl TryFrom<EntityPlayTimeQueryResult> for EntityPlayTime {
+    type Error = DatabaseError;
+
+    fn try_from(t: EntityPlayTimeQueryResult) -> DatabaseResult<Self> {
+        Ok(Self {
+            id: t.id,
+            name: t.name,
+            timestamp: millis_to_datetime(t.timestamp)?,
+        })
+    }
+}
+
+impl TryFrom<skipped_plays::Model> for SkippedPlay {
+    type Error = DatabaseError;
+
+    fn try_from(p: skipped_plays::Model) -> DatabaseResult<Self> {
+        Ok(Self {
+            user_id: p.user_id,
+            track_id: p.track_id,
+            timestamp: millis_to_datetime(p.timestamp)?,
+            duration_secs: p.duration_secs,
+        })
+    }
+}
+
+impl TryFrom<PlayTimeQueryResult> for PlayTime {
+    type Error = DatabaseError;
+
+    fn try_from(t: PlayTimeQueryResult) -> DatabaseResult<Self> {
+        Ok(Self {
+            timestamp: millis_to_datetime(t.timestamp)?,
+            duration_secs: t.duration_secs,
+        })
+    }
+}
+
+impl From<PopularAlbumQueryResult> for StatsAlbum {
+    fn from(a: PopularAlbumQueryResult) -> Self {
+        Self {
+            id: a.id,
+            title: a.title,
+            artists: a.artists,
+            cover: a.cover,
+            score: a.score as u32,
+            tracks: a.tracks as u32,
+        }
+    }
+}
+
+impl From<weekly_chart_entries::Model> for ChartItem {
+    fn from(e: weekly_chart_entries::Model) -> Self {
+        Self {
+            rank: e.rank as u32,
+            id: e.entity_id,
+            name: e.name,
+            artists: e.artists,
+            score: e.score as u32,
+        }
+    }
+}
+
+impl From<EntityNameQueryResult> for EntityName {
+    fn from(n: EntityNameQueryResult) -> Self {
+        Self {
+            id: n.id,
+            name: n.name,
+            scrobbles: n.scrobbles as u32,
         }
     }
 }
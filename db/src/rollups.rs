@@ -0,0 +1,367 @@
+use chrono::{NaiveDate, TimeZone, Utc};
+use sea_orm::{ConnectionTrait, DbBackend, DbErr, Statement};
+use std::collections::{BTreeMap, BTreeSet};
+
+use scrobblify_domain::time::{start_of_day, Tz};
+
+/// How many days are computed by a single statement, so that their list stays short.
+const DAYS_PER_STATEMENT: usize = 366;
+
+/// A table of daily plays, one row per user, day and entity, with the scrobbles of the day
+/// and how long they lasted. Days are the ones of the time zone the rollups are built in,
+/// and start at the millisecond since epoch of their local midnight.
+struct Rollup {
+    table: &'static str,
+    column: &'static str,
+    /// The entity of a scrobble `s`, found in `source`.
+    key: &'static str,
+    source: &'static str,
+}
+
+const ROLLUPS: [Rollup; 4] = [
+    Rollup {
+        table: "daily_track_plays",
+        column: "track_id",
+        key: "s.track_id",
+        source: "scrobbles AS s",
+    },
+    Rollup {
+        table: "daily_artist_plays",
+        column: "artist_id",
+        key: "k.artist_id",
+        source: "scrobbles AS s JOIN artists_tracks AS k ON k.track_id = s.track_id",
+    },
+    // stats count a track for the first of its albums only, like the raw queries
+    Rollup {
+        table: "daily_album_plays",
+        column: "album_id",
+        key: "k.album_id",
+        source: "scrobbles AS s \
+                 JOIN (SELECT track_id, MIN(album_id) AS album_id FROM albums_tracks \
+                 GROUP BY track_id) AS k ON k.track_id = s.track_id",
+    },
+    Rollup {
+        table: "daily_tag_plays",
+        column: "tag_id",
+        key: "k.tag_id",
+        source: "scrobbles AS s JOIN tags_tracks AS k ON k.track_id = s.track_id",
+    },
+];
+
+/// The local day of a timestamp in milliseconds, if chrono can represent it.
+fn day_of(millis: i64, tz: Tz) -> Option<NaiveDate> {
+    Utc.timestamp_millis_opt(millis)
+        .single()
+        .map(|time| time.with_timezone(&tz).date_naive())
+}
+
+/// The local days from the one of `start` to the one of `end`, each as the millisecond it
+/// starts at and the one the next day starts at.
+fn days_between(tz: Tz, start: i64, end: i64) -> Vec<(i64, i64)> {
+    let (mut day, last) = match (day_of(start, tz), day_of(end, tz)) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return vec![],
+    };
+
+    let mut days = vec![];
+    let mut starts_at = start_of_day(day, tz).timestamp_millis();
+    while day <= last {
+        let next = match day.succ_opt() {
+            Some(next) => next,
+            None => break,
+        };
+        let next_starts_at = start_of_day(next, tz).timestamp_millis();
+        days.push((starts_at, next_starts_at));
+        day = next;
+        starts_at = next_starts_at;
+    }
+    days
+}
+
+/// Recomputes the rollups of the local days from the one of `start` to the one of `end`,
+/// both timestamps in milliseconds. Without a user, the days of every user are.
+pub(crate) async fn refresh_days<C: ConnectionTrait>(
+    conn: &C,
+    tz: Tz,
+    user_id: Option<i32>,
+    start: i64,
+    end: i64,
+) -> Result<(), DbErr> {
+    let days = days_between(tz, start, end);
+    let (first, until) = match (days.first(), days.last()) {
+        (Some(first), Some(last)) => (first.0, last.1),
+        _ => return Ok(()),
+    };
+
+    let backend = conn.get_database_backend();
+    let (first_param, until_param, user) = match backend {
+        DbBackend::Postgres => ("$1", "$2", "$3::integer"),
+        _ => ("?1", "?2", "?3"),
+    };
+    for rollup in ROLLUPS.iter() {
+        let delete = format!(
+            "DELETE FROM {table} WHERE day >= {first} AND day < {until} \
+             AND ({user} IS NULL OR user_id = {user})",
+            table = rollup.table,
+            first = first_param,
+            until = until_param,
+            user = user
+        );
+        conn.execute(Statement::from_sql_and_values(
+            backend,
+            &delete,
+            vec![
+                sea_orm::Value::from(first),
+                sea_orm::Value::from(until),
+                sea_orm::Value::from(user_id),
+            ],
+        ))
+        .await?;
+    }
+
+    let filter = match backend {
+        DbBackend::Postgres => "($1::integer IS NULL OR s.user_id = $1)",
+        _ => "(?1 IS NULL OR s.user_id = ?1)",
+    };
+    insert_days(conn, &days, filter, vec![sea_orm::Value::from(user_id)]).await
+}
+
+/// Recomputes every rollup from scratch, e.g. when the time zone of the days changes.
+pub(crate) async fn rebuild<C: ConnectionTrait>(conn: &C, tz: Tz) -> Result<(), DbErr> {
+    let backend = conn.get_database_backend();
+    for rollup in ROLLUPS.iter() {
+        conn.execute(Statement::from_string(
+            backend,
+            format!("DELETE FROM {}", rollup.table),
+        ))
+        .await?;
+    }
+
+    let range = conn
+        .query_one(Statement::from_string(
+            backend,
+            "SELECT MIN(timestamp) AS first, MAX(timestamp) AS last FROM scrobbles".to_string(),
+        ))
+        .await?;
+    let (first, last) = match range {
+        Some(row) => (
+            row.try_get::<Option<i64>>("", "first")?,
+            row.try_get::<Option<i64>>("", "last")?,
+        ),
+        None => (None, None),
+    };
+    match (first, last) {
+        (Some(first), Some(last)) => {
+            insert_days(conn, &days_between(tz, first, last), "1 = 1", vec![]).await
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The local days in which some tracks have been played, by user, each as the millisecond
+/// it starts at.
+pub(crate) type PlayedDays = BTreeMap<i32, BTreeSet<i64>>;
+
+/// Tracks whose plays change when they, or an entity they're linked to, change.
+pub(crate) enum Tracks<'a> {
+    Id(&'a str),
+    /// The ones linked to an entity in a links table, like `artists_tracks`.
+    LinkedTo {
+        table: &'static str,
+        column: &'static str,
+        id: &'a str,
+    },
+}
+
+/// Lists the users and local days in which some tracks have been played, out of the rollups
+/// as they are, so before refreshing them.
+pub(crate) async fn played_days<C: ConnectionTrait>(
+    conn: &C,
+    tracks: Tracks<'_>,
+) -> Result<PlayedDays, DbErr> {
+    let backend = conn.get_database_backend();
+    let param = match backend {
+        DbBackend::Postgres => "$1",
+        _ => "?1",
+    };
+    let (condition, id) = match tracks {
+        Tracks::Id(id) => (format!("track_id = {}", param), id),
+        Tracks::LinkedTo { table, column, id } => (
+            format!(
+                "track_id IN (SELECT track_id FROM {} WHERE {} = {})",
+                table, column, param
+            ),
+            id,
+        ),
+    };
+    let select = format!(
+        "SELECT DISTINCT user_id, day FROM daily_track_plays WHERE {}",
+        condition
+    );
+
+    let mut days = PlayedDays::new();
+    for row in conn
+        .query_all(Statement::from_sql_and_values(
+            backend,
+            &select,
+            vec![id.into()],
+        ))
+        .await?
+    {
+        days.entry(row.try_get::<i32>("", "user_id")?)
+            .or_default()
+            .insert(row.try_get::<i64>("", "day")?);
+    }
+    Ok(days)
+}
+
+/// Recomputes the rollups of the given days of each user.
+pub(crate) async fn refresh_played_days<C: ConnectionTrait>(
+    conn: &C,
+    tz: Tz,
+    days: &PlayedDays,
+) -> Result<(), DbErr> {
+    let backend = conn.get_database_backend();
+    let (user, filter) = match backend {
+        DbBackend::Postgres => ("$1", "s.user_id = $1"),
+        _ => ("?1", "s.user_id = ?1"),
+    };
+
+    for (user_id, days) in days.iter() {
+        let days: Vec<(i64, i64)> = days
+            .iter()
+            .filter_map(|day| days_between(tz, *day, *day).first().copied())
+            .collect();
+        for chunk in days.chunks(DAYS_PER_STATEMENT) {
+            let starts = chunk
+                .iter()
+                .map(|(start, _)| start.to_string())
+                .collect::<Vec<String>>()
+                .join(", ");
+            for rollup in ROLLUPS.iter() {
+                let delete = format!(
+                    "DELETE FROM {} WHERE user_id = {} AND day IN ({})",
+                    rollup.table, user, starts
+                );
+                conn.execute(Statement::from_sql_and_values(
+                    backend,
+                    &delete,
+                    vec![sea_orm::Value::from(*user_id)],
+                ))
+                .await?;
+            }
+        }
+        insert_days(conn, &days, filter, vec![sea_orm::Value::from(*user_id)]).await?;
+    }
+
+    Ok(())
+}
+
+/// Lists the local days in which a track has been played, by anyone.
+pub(crate) async fn track_days<C: ConnectionTrait>(
+    conn: &C,
+    track_id: &str,
+) -> Result<Vec<i64>, DbErr> {
+    let backend = conn.get_database_backend();
+    let param = match backend {
+        DbBackend::Postgres => "$1",
+        _ => "?1",
+    };
+    let select = format!(
+        "SELECT DISTINCT day FROM daily_track_plays WHERE track_id = {}",
+        param
+    );
+
+    conn.query_all(Statement::from_sql_and_values(
+        backend,
+        &select,
+        vec![track_id.into()],
+    ))
+    .await?
+    .iter()
+    .map(|row| row.try_get::<i64>("", "day"))
+    .collect()
+}
+
+/// The time zone the rollups have been built in, if they have been.
+pub(crate) async fn stored_time_zone<C: ConnectionTrait>(conn: &C) -> Result<Option<Tz>, DbErr> {
+    let row = conn
+        .query_one(Statement::from_string(
+            conn.get_database_backend(),
+            "SELECT time_zone FROM rollup_settings".to_string(),
+        ))
+        .await?;
+
+    match row {
+        Some(row) => Ok(row.try_get::<String>("", "time_zone")?.parse().ok()),
+        None => Ok(None),
+    }
+}
+
+/// Records the time zone the rollups have been built in.
+pub(crate) async fn store_time_zone<C: ConnectionTrait>(conn: &C, tz: Tz) -> Result<(), DbErr> {
+    let backend = conn.get_database_backend();
+    let insert = match backend {
+        DbBackend::Postgres => "INSERT INTO rollup_settings (time_zone) VALUES ($1)",
+        _ => "INSERT INTO rollup_settings (time_zone) VALUES (?1)",
+    };
+    conn.execute(Statement::from_string(
+        backend,
+        "DELETE FROM rollup_settings".to_string(),
+    ))
+    .await?;
+    conn.execute(Statement::from_sql_and_values(
+        backend,
+        insert,
+        vec![tz.name().into()],
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Fills the rollups of some days, matching the scrobbles with the list of days.
+async fn insert_days<C: ConnectionTrait>(
+    conn: &C,
+    days: &[(i64, i64)],
+    filter: &str,
+    values: Vec<sea_orm::Value>,
+) -> Result<(), DbErr> {
+    let backend = conn.get_database_backend();
+    for chunk in days.chunks(DAYS_PER_STATEMENT) {
+        let days = chunk
+            .iter()
+            .map(|(start, until)| format!("({}, {})", start, until))
+            .collect::<Vec<String>>()
+            .join(", ");
+        for rollup in ROLLUPS.iter() {
+            conn.execute(Statement::from_sql_and_values(
+                backend,
+                &insert(rollup, &days, filter),
+                values.clone(),
+            ))
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Both SQLite and Postgres name the columns of a list of values `column1`, `column2` and
+/// so on: here the start of a day and the one of the next.
+fn insert(rollup: &Rollup, days: &str, filter: &str) -> String {
+    format!(
+        "INSERT INTO {table} (user_id, day, {column}, scrobbles, listened_secs) \
+         SELECT s.user_id, d.column1, {key}, COUNT(*), SUM(s.duration_secs) \
+         FROM {source} \
+         JOIN (VALUES {days}) AS d ON s.timestamp >= d.column1 AND s.timestamp < d.column2 \
+         WHERE {filter} \
+         GROUP BY s.user_id, d.column1, {key}",
+        table = rollup.table,
+        column = rollup.column,
+        key = rollup.key,
+        days = days,
+        source = rollup.source,
+        filter = filter
+    )
+}
@@ -1,9 +1,15 @@
-use scrobblify_domain::models::{Album, Artist, Tag, Track};
-use std::time::Duration;
+use chrono::{DateTime, Utc};
+use scrobblify_domain::{
+    errors::DatabaseError,
+    models::{Album, Artist, AuditEntry, Episode, RewriteRule, Show, Tag, Track, User},
+};
+use std::{str::FromStr, time::Duration};
 
 use crate::entities::{
-    albums::Model as AlbumsModel, artists::Model as ArtistsModel, tags::Model as TagsModel,
-    tracks::Model as TracksModel,
+    albums::Model as AlbumsModel, artists::Model as ArtistsModel,
+    audit_log::Model as AuditLogModel, episodes::Model as EpisodesModel,
+    rewrite_rules::Model as RewriteRulesModel, shows::Model as ShowsModel,
+    tags::Model as TagsModel, tracks::Model as TracksModel, users::Model as UsersModel,
 };
 
 impl From<TagsModel> for Tag {
@@ -43,3 +49,74 @@ impl From<ArtistsModel> for Artist {
         }
     }
 }
+
+impl From<UsersModel> for User {
+    fn from(u: UsersModel) -> Self {
+        Self {
+            id: u.id,
+            name: u.name,
+            is_admin: u.is_admin,
+            public_stats: u.public_stats,
+        }
+    }
+}
+
+impl From<ShowsModel> for Show {
+    fn from(s: ShowsModel) -> Self {
+        Self {
+            id: s.id,
+            name: s.name,
+            publisher: s.publisher,
+            cover: s.cover,
+        }
+    }
+}
+
+impl From<EpisodesModel> for Episode {
+    fn from(e: EpisodesModel) -> Self {
+        Self {
+            id: e.id,
+            title: e.title,
+            show_id: e.show_id,
+            duration_secs: Duration::from_secs_f64(e.duration_secs),
+            release_date: e.release_date,
+        }
+    }
+}
+
+impl TryFrom<RewriteRulesModel> for RewriteRule {
+    type Error = DatabaseError;
+
+    fn try_from(r: RewriteRulesModel) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: Some(r.id),
+            field: FromStr::from_str(r.field.as_str()).map_err(|_| DatabaseError::InvalidData {
+                field: "field",
+                value: r.field.clone(),
+            })?,
+            pattern: r.pattern,
+            replacement: r.replacement,
+            origin: r.origin,
+        })
+    }
+}
+
+impl TryFrom<AuditLogModel> for AuditEntry {
+    type Error = DatabaseError;
+
+    fn try_from(e: AuditLogModel) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: e.id,
+            timestamp: DateTime::parse_from_rfc3339(e.timestamp.as_str())
+                .map_err(|_| DatabaseError::InvalidData {
+                    field: "timestamp",
+                    value: e.timestamp.clone(),
+                })?
+                .with_timezone(&Utc),
+            entity: e.entity,
+            entity_id: e.entity_id,
+            action: e.action,
+            details: e.details,
+        })
+    }
+}
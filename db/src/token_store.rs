@@ -0,0 +1,113 @@
+use anyhow::{anyhow, Result};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use sea_orm::{ActiveModelTrait, ActiveValue, DatabaseConnection, EntityTrait};
+
+use scrobblify_domain::db::TokenStore;
+
+use crate::entities::users::{ActiveModel as UsersModel, Entity as UserEntity};
+
+/// Prefix of the tokens encrypted at rest, the ones without it are stored in plain text.
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const NONCE_LENGTH: usize = 12;
+
+/// Stores the Spotify tokens along with the users. When a key is configured, tokens are
+/// encrypted with ChaCha20-Poly1305 before hitting the database; plain tokens stored before
+/// the key was set keep working and get encrypted the next time they're saved.
+#[derive(Clone)]
+pub struct DbTokenStore {
+    conn: DatabaseConnection,
+    cipher: Option<ChaCha20Poly1305>,
+}
+
+impl DbTokenStore {
+    /// The key, if any, must be 32 bytes written as 64 hex digits. Without it tokens are
+    /// stored in plain text.
+    pub fn new(conn: DatabaseConnection, key: Option<&str>) -> Result<DbTokenStore> {
+        let cipher = match key {
+            Some(key) => {
+                let key = hex::decode(key.trim())
+                    .map_err(|_| anyhow!("the token key must be written in hex digits"))?;
+                if key.len() != 32 {
+                    return Err(anyhow!("the token key must be 32 bytes (64 hex digits)"));
+                }
+                Some(ChaCha20Poly1305::new(Key::from_slice(&key)))
+            }
+            None => None,
+        };
+
+        Ok(DbTokenStore { conn, cipher })
+    }
+
+    fn encrypt(&self, token: &str) -> Result<String> {
+        let cipher = match &self.cipher {
+            Some(cipher) => cipher,
+            None => return Ok(token.to_string()),
+        };
+
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, token.as_bytes())
+            .map_err(|_| anyhow!("failed to encrypt token"))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(format!("{}{}", ENCRYPTED_PREFIX, hex::encode(sealed)))
+    }
+
+    fn decrypt(&self, stored: &str) -> Result<String> {
+        let sealed = match stored.strip_prefix(ENCRYPTED_PREFIX) {
+            Some(sealed) => hex::decode(sealed).map_err(|_| anyhow!("malformed token"))?,
+            None => return Ok(stored.to_string()),
+        };
+        let cipher = self
+            .cipher
+            .as_ref()
+            .ok_or_else(|| anyhow!("tokens are encrypted, but no token key is configured"))?;
+        if sealed.len() < NONCE_LENGTH {
+            return Err(anyhow!("malformed token"));
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+        let token = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("failed to decrypt token, has the token key changed?"))?;
+
+        Ok(String::from_utf8(token)?)
+    }
+
+    async fn set_token(&self, user_id: i32, token: Option<String>) -> Result<()> {
+        let updated = UsersModel {
+            id: ActiveValue::Unchanged(user_id),
+            spotify_token: ActiveValue::Set(token),
+            ..Default::default()
+        };
+        updated.update(&self.conn).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl TokenStore for DbTokenStore {
+    async fn load_token(&self, user_id: i32) -> Result<Option<String>> {
+        match UserEntity::find_by_id(user_id).one(&self.conn).await? {
+            Some(user) => user
+                .spotify_token
+                .map(|token| self.decrypt(&token))
+                .transpose(),
+            None => Err(anyhow!("user `{}` not found", user_id)),
+        }
+    }
+
+    async fn save_token(&self, user_id: i32, token: &str) -> Result<()> {
+        let token = self.encrypt(token)?;
+        self.set_token(user_id, Some(token)).await
+    }
+
+    async fn delete_token(&self, user_id: i32) -> Result<()> {
+        self.set_token(user_id, None).await
+    }
+}
@@ -1,19 +1,142 @@
 use anyhow::Result;
+use chrono::NaiveDate;
 
-use super::{db::ParamsForStatsQuery, models::*};
+use super::{db::ParamsForStatsQuery, errors::DatabaseResult, models::*, time::Tz};
 
 #[async_trait::async_trait]
 pub trait App: Send + Sync {
-    fn get_current_track(&self) -> &Option<CurrentPlayingTrack>;
-    fn set_current_track(&mut self, current_track: Option<CurrentPlayingTrack>);
+    /// The configured time zone, used unless a request picks another.
+    fn time_zone(&self) -> Tz;
+    /// The current day in a time zone, by the clock of the app.
+    fn today(&self, tz: Tz) -> NaiveDate;
+    fn get_current_track(&self, user_id: i32) -> Option<CurrentPlayingTrack>;
+    fn set_current_track(&mut self, user_id: i32, current_track: Option<CurrentPlayingTrack>);
     async fn scrobble(&self, scrobble: ScrobbleInfo) -> Result<()>;
-    async fn get_recently_played(&self) -> Result<Vec<HistoryPlayedTrack>>;
-    async fn get_currently_playing(&self) -> Result<Option<CurrentPlayingTrack>>;
-    fn is_spotify_authenticated(&self) -> bool;
-    async fn get_spotify_auth_url(&self) -> Result<String>;
-    async fn store_spotify_auth_token(&self, code: &str) -> Result<()>;
-
-    async fn stats_for_popular_tracks(&self, opts: ParamsForStatsQuery) -> Vec<StatsTrack>;
-    async fn stats_for_popular_tags(&self, opts: ParamsForStatsQuery) -> Vec<StatsTag>;
-    async fn stats_for_popular_artists(&self, opts: ParamsForStatsQuery) -> Vec<StatsArtist>;
+    async fn scrobble_episode(&self, scrobble: EpisodeScrobbleInfo) -> Result<()>;
+    async fn get_recently_played(&self, user_id: i32) -> Result<Vec<HistoryPlayedTrack>>;
+    async fn get_currently_playing(&self, user_id: i32) -> Result<Option<CurrentPlayingTrack>>;
+    async fn is_spotify_authenticated(&self, user_id: i32) -> bool;
+    async fn get_spotify_auth_url(&self, user_id: i32) -> Result<String>;
+    async fn store_spotify_auth_token(
+        &mut self,
+        user_id: i32,
+        state: &str,
+        code: &str,
+    ) -> Result<()>;
+    async fn unlink_spotify(&mut self, user_id: i32) -> Result<()>;
+
+    async fn list_users(&self) -> DatabaseResult<Vec<User>>;
+    async fn get_user(&self, name: &str) -> Result<Option<User>>;
+    async fn create_user(&mut self, name: &str, password: Option<&str>) -> Result<User>;
+    /// Sets the password of a user, e.g. by an admin, logging them out everywhere.
+    async fn set_password(&self, user_id: i32, password: &str) -> Result<()>;
+    /// Changes the password of a user who knows the current one, logging them out of every
+    /// session but the given one.
+    async fn change_password(
+        &self,
+        user_id: i32,
+        current_password: &str,
+        new_password: &str,
+        session_token: Option<&str>,
+    ) -> Result<()>;
+    async fn set_public_stats(&self, user_id: i32, public_stats: bool) -> Result<()>;
+
+    async fn login(&self, name: &str, password: &str) -> Result<Option<String>>;
+    async fn logout(&self, session_token: &str) -> Result<()>;
+    async fn get_session_user(&self, session_token: &str) -> Result<Option<User>>;
+
+    async fn list_scrobbles(&self, opts: ParamsForStatsQuery) -> DatabaseResult<Vec<Scrobble>>;
+    async fn get_scrobble(&self, id: i32) -> Result<Option<Scrobble>>;
+    async fn update_scrobble(&self, id: i32, scrobble: ScrobbleEdit) -> Result<()>;
+    async fn delete_scrobble(&self, id: i32) -> Result<()>;
+    async fn delete_scrobbles(&self, selection: ScrobbleSelection) -> Result<u64>;
+    async fn reassign_scrobbles(&self, selection: ScrobbleSelection, track_id: &str)
+        -> Result<u64>;
+
+    async fn list_entity_names(&self, field: RewriteField) -> DatabaseResult<Vec<EntityName>>;
+    async fn get_track(&self, id: &str) -> Result<Option<Track>>;
+    async fn update_track(&self, track: Track) -> Result<()>;
+    async fn delete_track(&self, id: &str) -> Result<()>;
+    async fn get_artist(&self, id: &str) -> Result<Option<Artist>>;
+    async fn update_artist(&self, artist: Artist) -> Result<()>;
+    async fn delete_artist(&self, id: &str) -> Result<()>;
+    async fn get_album(&self, id: &str) -> Result<Option<Album>>;
+    async fn update_album(&self, album: Album) -> Result<()>;
+    async fn delete_album(&self, id: &str) -> Result<()>;
+    async fn list_audit_log(&self, limit: u64) -> DatabaseResult<Vec<AuditEntry>>;
+
+    async fn list_duplicates(&self, field: RewriteField) -> DatabaseResult<Vec<DuplicateGroup>>;
+    async fn merge_entities(
+        &self,
+        field: RewriteField,
+        canonical_id: &str,
+        duplicate_ids: Vec<String>,
+    ) -> Result<()>;
+
+    async fn list_rewrite_rules(&self) -> DatabaseResult<Vec<RewriteRule>>;
+    async fn add_rewrite_rule(&self, rule: RewriteRule) -> Result<()>;
+    async fn delete_rewrite_rule(&self, id: i32) -> Result<()>;
+    async fn preview_rewrite_rule(&self, rule: RewriteRule) -> Result<Vec<RewritePreview>>;
+    async fn apply_rewrite_rule(&self, rule: RewriteRule) -> Result<usize>;
+
+    async fn stats_for_popular_tracks(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsTrack>>;
+    async fn stats_for_popular_tags(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsTag>>;
+    async fn stats_for_popular_artists(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsArtist>>;
+    async fn stats_for_popular_shows(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsShow>>;
+    async fn stats_for_popular_albums(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsAlbum>>;
+    /// Compares the stats of a period against the ones of a previous period.
+    async fn compare_periods(
+        &self,
+        previous: ParamsForStatsQuery,
+        current: ParamsForStatsQuery,
+    ) -> Result<Comparison>;
+    async fn get_weekly_chart(
+        &self,
+        user_id: i32,
+        kind: ChartKind,
+        week: NaiveDate,
+    ) -> Result<WeeklyChart>;
+    async fn get_wrapped(&self, user_id: i32, year: i32, tz: Tz) -> Result<Wrapped>;
+    async fn get_activity(
+        &self,
+        user_id: i32,
+        start: NaiveDate,
+        end: NaiveDate,
+        bucket: ActivityBucket,
+        tz: Tz,
+    ) -> Result<Activity>;
+    async fn get_discovery(
+        &self,
+        user_id: i32,
+        start: NaiveDate,
+        end: NaiveDate,
+        bucket: ActivityBucket,
+        tz: Tz,
+    ) -> Result<Discovery>;
+    async fn get_sessions(
+        &self,
+        user_id: i32,
+        start: NaiveDate,
+        end: NaiveDate,
+        tz: Tz,
+    ) -> Result<Sessions>;
+    async fn get_streaks(&self, user_id: i32, tz: Tz) -> Result<Streaks>;
+    async fn get_milestones(&self, user_id: i32) -> Result<Milestones>;
+    /// What the user played on this day of the past years, the latest year first.
+    async fn get_on_this_day(&self, user_id: i32, tz: Tz) -> Result<Vec<OnThisDay>>;
 }
@@ -1,11 +1,29 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
+use std::sync::Arc;
 
+use crate::db::TokenStore;
 use crate::models::{CurrentPlayingTrack, HistoryPlayedTrack, Tag};
+
+/// Builds the Spotify clients acting on behalf of each user.
+#[async_trait::async_trait]
+pub trait SpotifyConnector: Send + Sync {
+    /// A client authenticated with the stored token of the user, if any.
+    async fn for_user(&self, user_id: i32, store: Arc<dyn TokenStore>)
+        -> Result<Box<dyn SpotifyApi>>;
+    /// The token cached on disk when Scrobblify had a single user, if any.
+    fn legacy_token(&self) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
+
 #[async_trait::async_trait]
-pub trait SpotifyApi {
-    fn has_auth(&self) -> bool;
+pub trait SpotifyApi: Send + Sync {
+    /// Whether there's a token that is valid or can be refreshed.
+    async fn has_auth(&self) -> bool;
+    fn get_auth_state(&self) -> &str;
     async fn get_auth_url(&self) -> Result<String>;
+    /// Requests an access token and stores it.
     async fn get_auth_token(&mut self, code: &str) -> Result<()>;
     async fn get_currently_playing(&self) -> Result<Option<CurrentPlayingTrack>>;
     async fn get_recently_played(
@@ -0,0 +1,387 @@
+//! Configuration of a Scrobblify instance.
+//!
+//! Settings are read from a TOML file (`scrobblify.toml` in the working directory, or the
+//! one pointed by `SCROBBLIFY_CONFIG`), then the environment variables listed in
+//! `.env.dist` override them, so existing env-only setups keep working. Everything is
+//! validated at once, to report every problem in a single run. The Spotify app credentials
+//! are checked apart, by the commands that talk to Spotify.
+
+use chrono::Duration;
+use serde::Deserialize;
+use std::{
+    env, fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    errors::ConfigError,
+    time::{parse_time_zone, Tz},
+};
+
+pub const DEFAULT_CONFIG_PATH: &str = "scrobblify.toml";
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub database: DatabaseConfig,
+    pub http: HttpConfig,
+    pub auth: AuthConfig,
+    pub spotify: SpotifyConfig,
+    pub scrobble: ScrobbleConfig,
+    pub sources: SourcesConfig,
+    pub stats: StatsConfig,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// A `sqlite://` or `postgres://` URL.
+    pub url: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub host: String,
+    pub port: u16,
+    /// Header with the user name set by an authenticating reverse proxy, which must strip
+    /// it from incoming requests.
+    pub auth_header: Option<String>,
+    /// Whether the session cookie is sent over HTTPS only. Turn it off only to log in over
+    /// plain HTTP, e.g. while developing.
+    pub secure_cookies: bool,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            host: "0.0.0.0".to_string(),
+            port: 8000,
+            auth_header: None,
+            secure_cookies: true,
+        }
+    }
+}
+
+impl HttpConfig {
+    pub fn address(&self) -> Result<SocketAddr, String> {
+        // IPv6 hosts come either bracketed, like `[::]`, or not
+        let host = self.host.trim_start_matches('[').trim_end_matches(']');
+        format!("[{}]:{}", host, self.port)
+            .parse::<SocketAddr>()
+            .or_else(|_| format!("{}:{}", host, self.port).parse::<SocketAddr>())
+            .map_err(|_| format!("`{}:{}` is not a valid address", self.host, self.port))
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Password given to the admins without one, so that they can log in the first time.
+    pub initial_admin_password: Option<String>,
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpotifyConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    /// Must be the same URL configured in the Spotify app.
+    pub auth_callback_uri: String,
+    /// 32 bytes as 64 hex digits, to encrypt the stored tokens.
+    pub token_key: Option<String>,
+}
+
+// secrets stay out of logs and error reports
+impl std::fmt::Debug for SpotifyConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpotifyConfig")
+            .field("client_id", &self.client_id)
+            .field("auth_callback_uri", &self.auth_callback_uri)
+            .field("token_key", &self.token_key.as_ref().map(|_| "<set>"))
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScrobbleConfig {
+    /// Allow/deny rules checked before scrobbling, e.g. `deny private_session`.
+    pub rules: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourcesConfig {
+    pub spotify: SpotifySourceConfig,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpotifySourceConfig {
+    /// Whether the linked Spotify accounts get auto-scrobbled.
+    pub enabled: bool,
+    pub polling_secs: u64,
+}
+
+impl Default for SpotifySourceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            polling_secs: 60,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatsConfig {
+    /// IANA name of the time zone of days and hours in stats, and of the times shown.
+    pub time_zone: String,
+    /// Minutes without scrobbles that end a listening session.
+    pub session_gap_mins: u64,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self {
+            time_zone: "UTC".to_string(),
+            session_gap_mins: 30,
+        }
+    }
+}
+
+impl StatsConfig {
+    /// The configured time zone, UTC if it isn't valid; `Config::load` reports that.
+    pub fn time_zone(&self) -> Tz {
+        parse_time_zone(&self.time_zone).unwrap_or(Tz::UTC)
+    }
+
+    pub fn session_gap(&self) -> Duration {
+        Duration::minutes(self.session_gap_mins as i64)
+    }
+}
+
+impl Config {
+    /// The file passed in `SCROBBLIFY_CONFIG` or, when it exists, `scrobblify.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        match env::var_os("SCROBBLIFY_CONFIG") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => Some(PathBuf::from(DEFAULT_CONFIG_PATH)).filter(|path| path.exists()),
+        }
+    }
+
+    /// Reads the file, if any, applies the environment overrides and validates the result.
+    pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+        let mut config = match path {
+            Some(path) => Self::read(path)?,
+            None => Config::default(),
+        };
+
+        let mut problems = config.apply_env(|name| env::var(name).ok());
+        problems.extend(config.problems());
+        if !problems.is_empty() {
+            return Err(ConfigError::Invalid(problems));
+        }
+
+        Ok(config)
+    }
+
+    fn read(path: &Path) -> Result<Config, ConfigError> {
+        let content = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        toml::from_str(&content).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Overrides settings with the environment variables that are set and not empty,
+    /// returning the ones that couldn't be parsed.
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Vec<String> {
+        let var = |name: &str| var(name).filter(|value| !value.trim().is_empty());
+        let mut problems = vec![];
+
+        if let Some(url) = var("DATABASE_URL") {
+            self.database.url = url;
+        }
+
+        // older setups spelled it `SCRUBBLIFY_HOST`
+        if let Some(host) = var("SCROBBLIFY_HOST").or_else(|| var("SCRUBBLIFY_HOST")) {
+            self.http.host = host;
+        }
+        if let Some(port) = var("PORT") {
+            match port.trim().parse() {
+                Ok(port) => self.http.port = port,
+                Err(_) => problems.push(format!("PORT: `{}` is not a valid port", port)),
+            }
+        }
+        if let Some(header) = var("SCROBBLIFY_AUTH_HEADER") {
+            self.http.auth_header = Some(header);
+        }
+        if let Some(secure) = var("SCROBBLIFY_SECURE_COOKIES") {
+            match secure.trim().parse() {
+                Ok(secure) => self.http.secure_cookies = secure,
+                Err(_) => problems.push(format!(
+                    "SCROBBLIFY_SECURE_COOKIES: `{}` is neither true nor false",
+                    secure
+                )),
+            }
+        }
+
+        if let Some(password) = var("SCROBBLIFY_ADMIN_PASSWORD") {
+            self.auth.initial_admin_password = Some(password);
+        }
+
+        if let Some(client_id) = var("SCROBBLIFY_SPOTIFY_CLIENT_ID") {
+            self.spotify.client_id = client_id;
+        }
+        if let Some(client_secret) = var("SCROBBLIFY_SPOTIFY_CLIENT_SECRET") {
+            self.spotify.client_secret = client_secret;
+        }
+        if let Some(uri) = var("SCROBBLIFY_SPOTIFY_AUTH_CALLBACK_URI") {
+            self.spotify.auth_callback_uri = uri;
+        }
+        if let Some(key) = var("SCROBBLIFY_TOKEN_KEY") {
+            self.spotify.token_key = Some(key);
+        }
+
+        // rules are one per line or separated by `;`
+        if let Some(rules) = var("SCROBBLIFY_SCROBBLE_RULES") {
+            self.scrobble.rules = rules
+                .split([';', '\n'])
+                .map(|rule| rule.trim().to_string())
+                .filter(|rule| !rule.is_empty() && !rule.starts_with('#'))
+                .collect();
+        }
+
+        if let Some(secs) = var("SCROBBLIFY_SPOTIFY_POLLING_SECS") {
+            match secs.trim().parse() {
+                Ok(secs) => self.sources.spotify.polling_secs = secs,
+                Err(_) => problems.push(format!(
+                    "SCROBBLIFY_SPOTIFY_POLLING_SECS: `{}` is not a number of seconds",
+                    secs
+                )),
+            }
+        }
+
+        if let Some(time_zone) = var("SCROBBLIFY_TIME_ZONE") {
+            self.stats.time_zone = time_zone;
+        }
+        if let Some(mins) = var("SCROBBLIFY_SESSION_GAP_MINS") {
+            match mins.trim().parse() {
+                Ok(mins) => self.stats.session_gap_mins = mins,
+                Err(_) => problems.push(format!(
+                    "SCROBBLIFY_SESSION_GAP_MINS: `{}` is not a number of minutes",
+                    mins
+                )),
+            }
+        }
+
+        problems
+    }
+
+    /// Checks that the Spotify app credentials are set, for the commands that need them.
+    pub fn check_spotify(&self) -> Result<(), ConfigError> {
+        let mut problems = vec![];
+        for (setting, value, var) in [
+            (
+                "spotify.client_id",
+                &self.spotify.client_id,
+                "SCROBBLIFY_SPOTIFY_CLIENT_ID",
+            ),
+            (
+                "spotify.client_secret",
+                &self.spotify.client_secret,
+                "SCROBBLIFY_SPOTIFY_CLIENT_SECRET",
+            ),
+            (
+                "spotify.auth_callback_uri",
+                &self.spotify.auth_callback_uri,
+                "SCROBBLIFY_SPOTIFY_AUTH_CALLBACK_URI",
+            ),
+        ] {
+            if value.trim().is_empty() {
+                problems.push(format!("{} ({}) is not set", setting, var));
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(ConfigError::Invalid(problems));
+        }
+        Ok(())
+    }
+
+    /// Checks the settings that can be checked without connecting anywhere. Scrobble rules
+    /// are parsed by the scrobbler, so they're checked there.
+    fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        let url = self.database.url.trim();
+        if url.is_empty() {
+            problems.push("database.url (DATABASE_URL) is not set".to_string());
+        } else if !["sqlite:", "postgres://", "postgresql://"]
+            .iter()
+            .any(|scheme| url.starts_with(scheme))
+        {
+            problems.push(format!(
+                "database.url: `{}` is neither a sqlite:// nor a postgres:// URL",
+                url
+            ));
+        }
+
+        if let Err(err) = self.http.address() {
+            problems.push(format!("http.host/http.port: {}", err));
+        }
+        if let Some(header) = &self.http.auth_header {
+            if header.is_empty()
+                || !header
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                problems.push(format!(
+                    "http.auth_header: `{}` is not a valid header name",
+                    header
+                ));
+            }
+        }
+
+        if let Some(password) = &self.auth.initial_admin_password {
+            if password.chars().count() < 8 {
+                problems.push(
+                    "auth.initial_admin_password must be at least 8 characters long".to_string(),
+                );
+            }
+        }
+
+        let uri = &self.spotify.auth_callback_uri;
+        if !(uri.is_empty() || uri.starts_with("http://") || uri.starts_with("https://")) {
+            problems.push(format!(
+                "spotify.auth_callback_uri: `{}` is not an http(s) URL",
+                uri
+            ));
+        }
+        if let Some(key) = &self.spotify.token_key {
+            if key.len() != 64 || !key.chars().all(|c| c.is_ascii_hexdigit()) {
+                problems.push("spotify.token_key must be 64 hex digits (32 bytes)".to_string());
+            }
+        }
+
+        if self.sources.spotify.polling_secs == 0 {
+            problems.push("sources.spotify.polling_secs must be greater than 0".to_string());
+        }
+
+        if let Err(err) = parse_time_zone(&self.stats.time_zone) {
+            problems.push(format!("stats.time_zone: {}", err));
+        }
+        if self.stats.session_gap_mins == 0 {
+            problems.push("stats.session_gap_mins must be greater than 0".to_string());
+        }
+
+        problems
+    }
+}
@@ -1,3 +1,5 @@
 mod repository;
+mod token_store;
 
 pub use repository::{ParamsForStatsQuery, Repository};
+pub use token_store::TokenStore;
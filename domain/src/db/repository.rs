@@ -1,51 +1,238 @@
-use anyhow::Result;
-use chrono::{NaiveDate, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 
+use crate::errors::DatabaseResult;
 use crate::models::{
-    Album, Artist, Scrobble, ScrobbleInfo, StatsArtist, StatsTag, StatsTrack, Tag, Track,
+    Album, Artist, AuditEntry, ChartKind, ChartSnapshot, DuplicateGroup, EntityDay, EntityName,
+    EntityPlayTime, Episode, EpisodeScrobbleInfo, PlayTime, RewriteField, RewriteRule, Scrobble,
+    ScrobbleEdit, ScrobbleInfo, ScrobbleSelection, Show, SkippedPlay, StatsAlbum, StatsArtist,
+    StatsShow, StatsTag, StatsTrack, Tag, Track, User,
 };
+use crate::time::Tz;
 
 #[derive(Clone, Debug)]
 pub struct ParamsForStatsQuery {
     pub start: NaiveDate,
     pub end: NaiveDate,
     pub limit: Option<u64>,
+    pub user_id: Option<i32>,
+    /// The time zone of the days of the range.
+    pub time_zone: Tz,
 }
 
 impl ParamsForStatsQuery {
-    pub fn new(start: NaiveDate, end: Option<NaiveDate>, limit: Option<u64>) -> Self {
+    pub fn new(start: NaiveDate, end: NaiveDate, limit: Option<u64>) -> Self {
         Self {
             start,
-            end: end.unwrap_or_else(|| Utc::now().date_naive()),
+            end,
             limit,
+            user_id: None,
+            time_zone: Tz::UTC,
         }
     }
+
+    /// Restricts the query to the scrobbles of a single user.
+    pub fn for_user(self, user_id: i32) -> Self {
+        Self {
+            user_id: Some(user_id),
+            ..self
+        }
+    }
+
+    /// Takes the days of the range as the ones of a time zone, rather than UTC days.
+    pub fn in_time_zone(self, time_zone: Tz) -> Self {
+        Self { time_zone, ..self }
+    }
 }
 
 #[async_trait::async_trait]
 pub trait Repository: Send + Sync {
     // Tracks
-    async fn insert_track(&self, track: Track) -> Result<()>;
-    async fn get_track_by_id(&self, id: String) -> Result<Option<Track>>;
+    async fn insert_track(&self, track: Track) -> DatabaseResult<()>;
+    async fn get_track_by_id(&self, id: String) -> DatabaseResult<Option<Track>>;
+    async fn update_track(&self, track: Track) -> DatabaseResult<()>;
+    async fn delete_track(&self, id: &str) -> DatabaseResult<()>;
 
     // Albums
-    async fn insert_album(&self, album: Album) -> Result<()>;
+    async fn insert_album(&self, album: Album) -> DatabaseResult<()>;
+    async fn get_album_by_id(&self, id: String) -> DatabaseResult<Option<Album>>;
+    async fn update_album(&self, album: Album) -> DatabaseResult<()>;
+    async fn delete_album(&self, id: &str) -> DatabaseResult<()>;
 
     // Artists
-    async fn insert_artist(&self, artist: Artist) -> Result<()>;
+    async fn insert_artist(&self, artist: Artist) -> DatabaseResult<()>;
+    async fn get_artist_by_id(&self, id: String) -> DatabaseResult<Option<Artist>>;
+    async fn update_artist(&self, artist: Artist) -> DatabaseResult<()>;
+    async fn delete_artist(&self, id: &str) -> DatabaseResult<()>;
 
     // Tags
-    async fn insert_tag(&self, tag: Tag) -> Result<()>;
+    async fn insert_tag(&self, tag: Tag) -> DatabaseResult<()>;
+
+    // Podcasts
+    async fn insert_show(&self, show: Show) -> DatabaseResult<()>;
+    async fn insert_episode(&self, episode: Episode) -> DatabaseResult<()>;
+    async fn get_episode_by_id(&self, id: String) -> DatabaseResult<Option<Episode>>;
+
+    // Users
+    async fn insert_user(&self, name: &str) -> DatabaseResult<User>;
+    async fn get_user_by_name(&self, name: &str) -> DatabaseResult<Option<User>>;
+    async fn list_users(&self) -> DatabaseResult<Vec<User>>;
+    async fn get_password_hash(&self, user_id: i32) -> DatabaseResult<Option<String>>;
+    async fn set_password_hash(&self, user_id: i32, hash: &str) -> DatabaseResult<()>;
+    async fn set_public_stats(&self, user_id: i32, public_stats: bool) -> DatabaseResult<()>;
+
+    // Sessions
+    async fn insert_session(
+        &self,
+        id: &str,
+        user_id: i32,
+        expires_at: DateTime<Utc>,
+    ) -> DatabaseResult<()>;
+    /// The user of a session, if it hasn't expired at `now`; expired sessions are deleted.
+    async fn get_session_user(&self, id: &str, now: DateTime<Utc>) -> DatabaseResult<Option<User>>;
+    async fn delete_session(&self, id: &str) -> DatabaseResult<()>;
+    /// Deletes every session of a user, except the given one if any.
+    async fn delete_user_sessions(&self, user_id: i32, except: Option<&str>) -> DatabaseResult<()>;
 
     // Scrobbles
-    async fn insert_scrobble(&self, scrobble: ScrobbleInfo) -> Result<()>;
-    async fn get_last_scrobble(&self) -> Result<Option<Scrobble>>;
-    async fn list_scrobbles_by_date_range(&self, opts: ParamsForStatsQuery) -> Vec<Scrobble>;
-    async fn list_scrobbles_by_tag(&self, tag: &str) -> Vec<Scrobble>;
-    async fn list_scrobbles_by_artist(&self, artist_id: &str) -> Vec<Scrobble>;
+    async fn insert_scrobble(&self, scrobble: ScrobbleInfo) -> DatabaseResult<()>;
+    async fn get_last_scrobble(&self, user_id: i32) -> DatabaseResult<Option<Scrobble>>;
+    async fn get_first_scrobble_timestamp(
+        &self,
+        user_id: i32,
+    ) -> DatabaseResult<Option<DateTime<Utc>>>;
+    async fn count_scrobbles(&self, user_id: i32) -> DatabaseResult<u64>;
+    /// The n-th scrobble of a user, counting from 1.
+    async fn get_nth_scrobble(&self, user_id: i32, n: u64) -> DatabaseResult<Option<Scrobble>>;
+    async fn get_scrobble(&self, id: i32) -> DatabaseResult<Option<Scrobble>>;
+    async fn update_scrobble(&self, id: i32, scrobble: ScrobbleEdit) -> DatabaseResult<()>;
+    async fn delete_scrobble(&self, id: i32) -> DatabaseResult<()>;
+    async fn delete_scrobbles(&self, selection: ScrobbleSelection) -> DatabaseResult<u64>;
+    async fn reassign_scrobbles(
+        &self,
+        selection: ScrobbleSelection,
+        track_id: &str,
+    ) -> DatabaseResult<u64>;
+    async fn list_scrobbles_by_date_range(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<Scrobble>>;
+    async fn list_scrobbles_by_tag(&self, tag: &str) -> DatabaseResult<Vec<Scrobble>>;
+    /// Just when the scrobbles of the range were played, oldest first.
+    async fn list_play_times(&self, opts: ParamsForStatsQuery) -> DatabaseResult<Vec<PlayTime>>;
+    /// Every play of every artist of the range, oldest first.
+    async fn list_artist_play_times(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<EntityPlayTime>>;
+    /// Every play of every track of the range, oldest first.
+    async fn list_track_play_times(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<EntityPlayTime>>;
+    /// The days of the range in which anything was played, oldest first, in the time zone of
+    /// the range.
+    async fn list_listening_days(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<NaiveDate>>;
+    /// The days of the range in which each artist was played, oldest first.
+    async fn list_artist_listening_days(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<EntityDay>>;
+    /// The days of the range in which each track was played, oldest first.
+    async fn list_track_listening_days(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<EntityDay>>;
+    /// Every play of every genre of the range, oldest first. Genres are named by their ID.
+    async fn list_tag_play_times(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<EntityPlayTime>>;
+    /// The artists a user played for the first time most recently, with that first play.
+    async fn list_first_plays(
+        &self,
+        user_id: i32,
+        limit: u64,
+    ) -> DatabaseResult<Vec<EntityPlayTime>>;
+    async fn list_scrobbles_by_artist(&self, artist_id: &str) -> DatabaseResult<Vec<Scrobble>>;
+    async fn insert_episode_scrobble(&self, scrobble: EpisodeScrobbleInfo) -> DatabaseResult<()>;
+    /// Remembers a play denied by the scrobble rules.
+    async fn insert_skipped_play(&self, play: SkippedPlay) -> DatabaseResult<()>;
+    /// The denied plays of a user started since a time, oldest first.
+    async fn list_skipped_plays(
+        &self,
+        user_id: i32,
+        since: DateTime<Utc>,
+    ) -> DatabaseResult<Vec<SkippedPlay>>;
+    /// Forgets the denied plays of a user started before a time.
+    async fn delete_skipped_plays(&self, user_id: i32, before: DateTime<Utc>)
+        -> DatabaseResult<()>;
+
+    // Rewrite rules
+    async fn insert_rewrite_rule(&self, rule: RewriteRule) -> DatabaseResult<()>;
+    async fn delete_rewrite_rule(&self, id: i32) -> DatabaseResult<()>;
+    async fn list_rewrite_rules(&self) -> DatabaseResult<Vec<RewriteRule>>;
+    async fn list_entity_names(
+        &self,
+        field: RewriteField,
+        origin: Option<String>,
+    ) -> DatabaseResult<Vec<EntityName>>;
+    async fn rename_entity(&self, field: RewriteField, id: &str, name: &str) -> DatabaseResult<()>;
+
+    // Duplicates
+    async fn list_duplicates(&self, field: RewriteField) -> DatabaseResult<Vec<DuplicateGroup>>;
+    async fn merge_entities(
+        &self,
+        field: RewriteField,
+        canonical_id: &str,
+        duplicate_ids: Vec<String>,
+    ) -> DatabaseResult<()>;
+    async fn resolve_merged_id(&self, field: RewriteField, id: &str) -> DatabaseResult<String>;
+
+    // Audit log
+    async fn list_audit_log(&self, limit: u64) -> DatabaseResult<Vec<AuditEntry>>;
 
     // Stats
-    async fn stats_for_popular_tags(&self, opts: ParamsForStatsQuery) -> Vec<StatsTag>;
-    async fn stats_for_popular_tracks(&self, opts: ParamsForStatsQuery) -> Vec<StatsTrack>;
-    async fn stats_for_popular_artists(&self, opts: ParamsForStatsQuery) -> Vec<StatsArtist>;
+    async fn stats_for_popular_tags(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsTag>>;
+    async fn stats_for_popular_tracks(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsTrack>>;
+    async fn stats_for_popular_artists(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsArtist>>;
+    async fn stats_for_popular_shows(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsShow>>;
+    async fn stats_for_popular_albums(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsAlbum>>;
+    /// Artists scrobbled for the first time in the range, by scrobbles in the range.
+    async fn stats_for_new_artists(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsArtist>>;
+
+    // Weekly charts
+    /// Snapshots of the weeks up to `until` included, oldest first.
+    async fn list_chart_snapshots(
+        &self,
+        user_id: i32,
+        kind: ChartKind,
+        until: NaiveDate,
+    ) -> DatabaseResult<Vec<ChartSnapshot>>;
+    async fn insert_chart_snapshot(
+        &self,
+        user_id: i32,
+        kind: ChartKind,
+        snapshot: ChartSnapshot,
+    ) -> DatabaseResult<()>;
 }
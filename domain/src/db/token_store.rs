@@ -0,0 +1,10 @@
+use anyhow::Result;
+
+/// Keeps the OAuth tokens of the users' Spotify accounts, serialized, so that they survive
+/// restarts wherever the binary is run from.
+#[async_trait::async_trait]
+pub trait TokenStore: Send + Sync {
+    async fn load_token(&self, user_id: i32) -> Result<Option<String>>;
+    async fn save_token(&self, user_id: i32, token: &str) -> Result<()>;
+    async fn delete_token(&self, user_id: i32) -> Result<()>;
+}
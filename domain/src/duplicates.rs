@@ -0,0 +1,133 @@
+//! Finds the tracks, artists and albums that are likely the same entity stored under
+//! different IDs, out of what every repository knows about them.
+
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
+
+use crate::{
+    identity::normalize,
+    models::{DuplicateGroup, EntityName, RewriteField},
+};
+
+/// Separates the artists in the grouping keys: names can contain commas, but not this.
+const KEY_SEPARATOR: char = '\u{1f}';
+
+/// A track, artist or album that could be the duplicate of another one.
+#[derive(Clone, Debug)]
+pub struct DuplicateCandidate {
+    pub id: String,
+    pub name: String,
+    /// Tracks only.
+    pub isrc: Option<String>,
+    /// The names of the artists. Tracks and albums only.
+    pub artists: Vec<String>,
+    pub scrobbles: u32,
+}
+
+/// Groups entities that are likely the same one: tracks sharing an ISRC first, then
+/// tracks and albums with the same normalized title and artists, and artists with the
+/// same normalized name. Only groups with more than one entity are returned.
+pub fn group_duplicates(
+    field: RewriteField,
+    candidates: Vec<DuplicateCandidate>,
+) -> Vec<DuplicateGroup> {
+    let mut groups: Vec<DuplicateGroup> = vec![];
+    let mut grouped: HashSet<String> = HashSet::new();
+
+    if field == RewriteField::Title {
+        let by_isrc = group_by(&candidates, |c| {
+            c.isrc
+                .as_deref()
+                .map(|isrc| isrc.trim().to_uppercase())
+                .filter(|isrc| !isrc.is_empty())
+        });
+
+        for (isrc, entities) in by_isrc {
+            grouped.extend(entities.iter().map(|e| e.id.clone()));
+            groups.push(new_group("isrc", isrc, entities));
+        }
+    }
+
+    let remaining: Vec<DuplicateCandidate> = candidates
+        .into_iter()
+        .filter(|c| !grouped.contains(&c.id))
+        .collect();
+
+    let reason = match field {
+        RewriteField::Artist => "name",
+        _ => "title and artists",
+    };
+    let by_name = group_by(&remaining, |c| Some(name_key(c)));
+
+    for (key, entities) in by_name {
+        let key = key.replace(KEY_SEPARATOR, ", ");
+        groups.push(new_group(reason, key, entities));
+    }
+
+    groups.sort_by_key(|group| Reverse(total_scrobbles(group)));
+    groups
+}
+
+fn group_by<F>(
+    candidates: &[DuplicateCandidate],
+    key: F,
+) -> Vec<(String, Vec<EntityName>)>
+where
+    F: Fn(&DuplicateCandidate) -> Option<String>,
+{
+    let mut groups: HashMap<String, Vec<EntityName>> = HashMap::new();
+
+    for candidate in candidates.iter() {
+        if let Some(key) = key(candidate) {
+            groups.entry(key).or_default().push(EntityName {
+                id: candidate.id.clone(),
+                name: candidate.name.clone(),
+                scrobbles: candidate.scrobbles,
+            });
+        }
+    }
+
+    groups
+        .into_iter()
+        .filter(|(_, entities)| entities.len() > 1)
+        .collect()
+}
+
+/// Normalized name followed by the sorted, normalized artists (if any), so that
+/// `Song - A, B` and `song - b, a` end up together.
+fn name_key(candidate: &DuplicateCandidate) -> String {
+    let mut artists: Vec<String> = candidate
+        .artists
+        .iter()
+        .map(|artist| normalize(artist))
+        .filter(|a| !a.is_empty())
+        .collect();
+    artists.sort();
+    artists.dedup();
+
+    if artists.is_empty() {
+        return normalize(&candidate.name);
+    }
+    format!(
+        "{} - {}",
+        normalize(&candidate.name),
+        artists.join(&KEY_SEPARATOR.to_string())
+    )
+}
+
+fn new_group(reason: &str, key: String, mut entities: Vec<EntityName>) -> DuplicateGroup {
+    // the most scrobbled entity is the suggested canonical one
+    entities.sort_by(|a, b| b.scrobbles.cmp(&a.scrobbles).then(a.id.cmp(&b.id)));
+
+    DuplicateGroup {
+        reason: reason.to_string(),
+        key,
+        entities,
+    }
+}
+
+fn total_scrobbles(group: &DuplicateGroup) -> u32 {
+    group.entities.iter().map(|e| e.scrobbles).sum()
+}
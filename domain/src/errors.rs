@@ -1,6 +1,43 @@
+/// A failure of the database. Listings and stats report it rather than coming back empty,
+/// so that an empty chart always means there's nothing to show.
 #[derive(thiserror::Error, Debug)]
-#[error("database error")]
-pub struct DatabaseError {
-    #[from]
-    source: anyhow::Error,
+pub enum DatabaseError {
+    /// A query couldn't run, e.g. the database is unreachable or the schema is outdated.
+    #[error("failed to query the {query}")]
+    Query {
+        query: &'static str,
+        source: anyhow::Error,
+    },
+    /// A row holds a value that can't be read back, e.g. an out of range timestamp.
+    #[error("invalid {field} in the database: {value}")]
+    InvalidData { field: &'static str, value: String },
+    #[error("database error")]
+    Other(#[from] anyhow::Error),
+}
+
+impl DatabaseError {
+    pub fn query(query: &'static str, source: impl Into<anyhow::Error>) -> Self {
+        DatabaseError::Query {
+            query,
+            source: source.into(),
+        }
+    }
+}
+
+pub type DatabaseResult<T> = Result<T, DatabaseError>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+    #[error("failed to read config file `{}`: {source}", .path.display())]
+    Read {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to parse config file `{}`: {source}", .path.display())]
+    Parse {
+        path: std::path::PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("invalid configuration:\n{}", .0.iter().map(|p| format!("  - {}", p)).collect::<Vec<_>>().join("\n"))]
+    Invalid(Vec<String>),
 }
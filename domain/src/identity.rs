@@ -0,0 +1,92 @@
+//! Stable identities for tracks, artists and albums that don't come with an ID from their
+//! source, like Spotify local files or scrobbles imported from other services.
+//!
+//! IDs are derived by hashing normalized metadata, so the same recording always maps to the
+//! same entities and gets aggregated in stats, no matter where it was scrobbled from.
+
+use sha2::{Digest, Sha256};
+
+use crate::models::TrackInfo;
+
+const SYNTHETIC_ID_PREFIX: &str = "local:";
+
+/// Fills every missing ID of the given track, its album and its artists.
+pub fn assign_missing_ids(track: &mut TrackInfo) {
+    for artist in track.artists.iter_mut() {
+        if artist.id.is_empty() {
+            artist.id = artist_id(&artist.name);
+        }
+    }
+
+    let main_artist = track
+        .artists
+        .first()
+        .map(|a| a.name.clone())
+        .unwrap_or_default();
+
+    if track.album.id.is_empty() {
+        track.album.id = album_id(&main_artist, &track.album.title);
+    }
+
+    if track.id.is_empty() {
+        track.id = track_id(&main_artist, &track.title, &track.album.title);
+    }
+}
+
+/// Derives again the synthetic IDs of a track, its album and its artists, e.g. after their
+/// metadata has been rewritten. IDs given by the source are kept.
+pub fn reassign_synthetic_ids(track: &mut TrackInfo) {
+    for artist in track.artists.iter_mut() {
+        if is_synthetic(&artist.id) {
+            artist.id.clear();
+        }
+    }
+    if is_synthetic(&track.album.id) {
+        track.album.id.clear();
+    }
+    if is_synthetic(&track.id) {
+        track.id.clear();
+    }
+
+    assign_missing_ids(track);
+}
+
+pub fn artist_id(name: &str) -> String {
+    synthetic_id("artist", &[name])
+}
+
+pub fn album_id(artist: &str, title: &str) -> String {
+    synthetic_id("album", &[artist, title])
+}
+
+pub fn track_id(artist: &str, title: &str, album: &str) -> String {
+    synthetic_id("track", &[artist, title, album])
+}
+
+/// Returns true when the ID has been generated here rather than by the source service.
+pub fn is_synthetic(id: &str) -> bool {
+    id.starts_with(SYNTHETIC_ID_PREFIX)
+}
+
+/// Lowercases, trims and collapses whitespace, so trivial spelling differences
+/// don't end up in different entities.
+pub fn normalize(value: &str) -> String {
+    value
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn synthetic_id(kind: &str, parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(normalize(part).as_bytes());
+        // separator avoids collisions like ("ab", "c") vs ("a", "bc")
+        hasher.update([0x1f]);
+    }
+    let digest = hasher.finalize();
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+
+    format!("{}{}:{}", SYNTHETIC_ID_PREFIX, kind, hex)
+}
@@ -1,5 +1,9 @@
 pub mod app;
 pub mod bridge;
+pub mod config;
 pub mod db;
+pub mod duplicates;
 pub mod errors;
+pub mod identity;
 pub mod models;
+pub mod time;
@@ -1,38 +1,130 @@
-use chrono::{DateTime, Utc};
-use std::time::Duration;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use std::{str::FromStr, time::Duration};
 
 #[derive(Clone, Debug)]
 pub struct HistoryPlayedTrack {
     pub track: TrackInfo,
     pub played_at: DateTime<Utc>,
+    pub context: Option<PlaybackContext>,
 }
 
 #[derive(Clone, Debug)]
 pub struct CurrentPlayingTrack {
-    pub track: TrackInfo,
+    pub item: PlayingItem,
     pub timestamp: DateTime<Utc>,
     pub progress_secs: Duration,
     pub scrobbled: bool,
+    pub device: Option<PlaybackDevice>,
+    pub context: Option<PlaybackContext>,
 }
 
 impl PartialEq for CurrentPlayingTrack {
     fn eq(&self, other: &Self) -> bool {
-        return self.timestamp == other.timestamp
-            && self.track.clone().id == other.track.clone().id;
+        self.timestamp == other.timestamp && self.item.id() == other.item.id()
+    }
+}
+
+/// What is currently playing: a music track or a podcast episode.
+#[derive(Clone, Debug)]
+pub enum PlayingItem {
+    Track(TrackInfo),
+    Episode(EpisodeInfo),
+}
+
+impl PlayingItem {
+    pub fn id(&self) -> &str {
+        match self {
+            PlayingItem::Track(track) => &track.id,
+            PlayingItem::Episode(episode) => &episode.id,
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        match self {
+            PlayingItem::Track(track) => &track.title,
+            PlayingItem::Episode(episode) => &episode.title,
+        }
+    }
+
+    pub fn duration_secs(&self) -> Duration {
+        match self {
+            PlayingItem::Track(track) => track.duration_secs,
+            PlayingItem::Episode(episode) => episode.duration_secs,
+        }
+    }
+}
+
+/// The device where something is being played.
+#[derive(Clone, Debug)]
+pub struct PlaybackDevice {
+    pub id: Option<String>,
+    pub name: String,
+    pub kind: String,
+    pub is_private_session: bool,
+}
+
+/// A play the scrobble rules denied while it was playing. The recently played history
+/// doesn't tell the device, so it's remembered to leave the play out of the history too.
+#[derive(Clone, Debug)]
+pub struct SkippedPlay {
+    pub user_id: i32,
+    pub track_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub duration_secs: f64,
+}
+
+/// Where the playback has been started from (playlist, album, artist radio, ...).
+#[derive(Clone, Debug)]
+pub struct PlaybackContext {
+    pub kind: String,
+    pub uri: String,
+}
+
+/// Someone scrobbling to this instance, from their own Spotify account.
+/// Stats are visible only to the user and to admins, unless they've been made public.
+#[derive(Clone, Debug)]
+pub struct User {
+    pub id: i32,
+    pub name: String,
+    pub is_admin: bool,
+    pub public_stats: bool,
+}
+
+impl User {
+    /// Tells whether the given visitor, if any, can see the stats of this user.
+    pub fn stats_visible_to(&self, visitor: Option<&User>) -> bool {
+        self.public_stats
+            || visitor.is_some_and(|visitor| visitor.is_admin || visitor.id == self.id)
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct ScrobbleInfo {
+    pub user_id: i32,
     pub timestamp: DateTime<Utc>,
     pub duration_secs: f64,
+    pub origin: String,
     pub track: TrackInfo,
+    pub device: Option<PlaybackDevice>,
+    pub context: Option<PlaybackContext>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EpisodeScrobbleInfo {
+    pub user_id: i32,
+    pub timestamp: DateTime<Utc>,
+    pub duration_secs: f64,
+    pub episode: EpisodeInfo,
+    pub device: Option<PlaybackDevice>,
+    pub context: Option<PlaybackContext>,
 }
 
 #[derive(Clone, Debug)]
 pub struct Scrobble {
+    pub id: i32,
     pub timestamp: DateTime<Utc>,
     pub duration_secs: Duration,
+    pub track_id: String,
     pub track: String,
     pub cover: String,
     pub album: String,
@@ -40,6 +132,33 @@ pub struct Scrobble {
     pub tags: Vec<String>,
 }
 
+/// The editable fields of an existing scrobble.
+#[derive(Clone, Debug)]
+pub struct ScrobbleEdit {
+    pub timestamp: DateTime<Utc>,
+    pub track_id: String,
+    pub duration_secs: f64,
+}
+
+/// Scrobbles of a track, optionally restricted to a time range, for bulk corrections.
+#[derive(Clone, Debug)]
+pub struct ScrobbleSelection {
+    pub track_id: String,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+/// A change made to the scrobbles database, kept so that stats can be trusted.
+#[derive(Clone, Debug)]
+pub struct AuditEntry {
+    pub id: i32,
+    pub timestamp: DateTime<Utc>,
+    pub entity: String,
+    pub entity_id: String,
+    pub action: String,
+    pub details: String,
+}
+
 #[derive(Clone, Debug)]
 pub struct Track {
     pub id: String,
@@ -71,6 +190,45 @@ impl From<TrackInfo> for Track {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct Episode {
+    pub id: String,
+    pub title: String,
+    pub show_id: String,
+    pub duration_secs: Duration,
+    pub release_date: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct EpisodeInfo {
+    pub id: String,
+    pub title: String,
+    pub show: Show,
+    pub duration_secs: Duration,
+    pub release_date: String,
+    pub cover: String,
+}
+
+impl From<EpisodeInfo> for Episode {
+    fn from(episode_info: EpisodeInfo) -> Self {
+        Episode {
+            id: episode_info.id,
+            title: episode_info.title,
+            show_id: episode_info.show.id,
+            duration_secs: episode_info.duration_secs,
+            release_date: episode_info.release_date,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Show {
+    pub id: String,
+    pub name: String,
+    pub publisher: String,
+    pub cover: String,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Tag {
     pub id: String,
@@ -111,3 +269,499 @@ pub struct StatsArtist {
     pub score: u32,
     pub tracks: u32,
 }
+
+#[derive(Clone, Debug)]
+pub struct StatsShow {
+    pub id: String,
+    pub name: String,
+    pub publisher: String,
+    pub cover: String,
+    pub score: u32,
+    pub episodes: u32,
+    pub listened_secs: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct StatsAlbum {
+    pub id: String,
+    pub title: String,
+    pub artists: String,
+    pub cover: String,
+    pub score: u32,
+    pub tracks: u32,
+}
+
+/// What a weekly chart ranks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChartKind {
+    Tracks,
+    Artists,
+    Albums,
+}
+
+impl ChartKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChartKind::Tracks => "tracks",
+            ChartKind::Artists => "artists",
+            ChartKind::Albums => "albums",
+        }
+    }
+}
+
+impl FromStr for ChartKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tracks" => Ok(ChartKind::Tracks),
+            "artists" => Ok(ChartKind::Artists),
+            "albums" => Ok(ChartKind::Albums),
+            _ => Err(anyhow::anyhow!("unknown chart `{}`", s)),
+        }
+    }
+}
+
+/// A ranked track, artist or album of a weekly chart. Artists are empty for artist charts.
+#[derive(Clone, Debug)]
+pub struct ChartItem {
+    pub rank: u32,
+    pub id: String,
+    pub name: String,
+    pub artists: String,
+    pub score: u32,
+}
+
+/// The chart of a week, identified by its Monday, as it was when the week ended.
+#[derive(Clone, Debug)]
+pub struct ChartSnapshot {
+    pub week_start: NaiveDate,
+    pub items: Vec<ChartItem>,
+}
+
+/// How an entry moved since the previous week's chart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChartMovement {
+    New,
+    ReEntry,
+    Up(u32),
+    Down(u32),
+    Same,
+}
+
+impl ChartMovement {
+    pub fn label(&self) -> String {
+        match self {
+            ChartMovement::New => "new".to_string(),
+            ChartMovement::ReEntry => "re-entry".to_string(),
+            ChartMovement::Up(n) => format!("up {}", n),
+            ChartMovement::Down(n) => format!("down {}", n),
+            ChartMovement::Same => "=".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WeeklyChartEntry {
+    pub item: ChartItem,
+    pub movement: ChartMovement,
+    /// Weeks on chart so far, this one included.
+    pub weeks_on_chart: u32,
+    /// Best rank so far, this one included.
+    pub peak: u32,
+}
+
+/// A weekly chart with the movements of its entries. The current week isn't complete
+/// yet, so it's computed on the fly rather than snapshotted.
+#[derive(Clone, Debug)]
+pub struct WeeklyChart {
+    pub kind: ChartKind,
+    pub week_start: NaiveDate,
+    pub complete: bool,
+    pub entries: Vec<WeeklyChartEntry>,
+}
+
+/// Consecutive days with at least a scrobble.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListeningStreak {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub days: u32,
+}
+
+/// The longest streak of a track or an artist.
+#[derive(Clone, Debug)]
+pub struct EntityStreak {
+    pub id: String,
+    pub name: String,
+    pub streak: ListeningStreak,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Streaks {
+    /// The streak going on, which ends today or ended yesterday.
+    pub current: Option<ListeningStreak>,
+    pub longest: Option<ListeningStreak>,
+    pub artists: Vec<EntityStreak>,
+    pub tracks: Vec<EntityStreak>,
+}
+
+/// When a track or an artist was played, for the stats that go through every play.
+#[derive(Clone, Debug)]
+pub struct EntityPlayTime {
+    pub id: String,
+    pub name: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A day in which a track or an artist was played, for the stats that only need the days.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntityDay {
+    pub id: String,
+    pub name: String,
+    pub day: NaiveDate,
+}
+
+/// A round number of scrobbles and the scrobble that reached it.
+#[derive(Clone, Debug)]
+pub struct ScrobbleMilestone {
+    pub count: u64,
+    pub scrobble: Scrobble,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Milestones {
+    pub total_scrobbles: u64,
+    pub reached: Vec<ScrobbleMilestone>,
+    pub next: Option<u64>,
+    /// The artists played for the first time most recently.
+    pub first_plays: Vec<EntityPlayTime>,
+}
+
+/// What was played on this day of a past year.
+#[derive(Clone, Debug)]
+pub struct OnThisDay {
+    pub year: i32,
+    pub scrobbles: Vec<Scrobble>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DayPlays {
+    pub day: NaiveDate,
+    pub scrobbles: u32,
+}
+
+/// A month of a year in review.
+#[derive(Clone, Debug)]
+pub struct WrappedMonth {
+    pub month: u32,
+    pub name: String,
+    pub scrobbles: u32,
+    pub top_track: Option<StatsTrack>,
+    pub top_tags: Vec<StatsTag>,
+}
+
+/// A user's year in review. Artists of tracks and albums are joined by commas.
+#[derive(Clone, Debug)]
+pub struct Wrapped {
+    pub year: i32,
+    pub scrobbles: u32,
+    pub listened_minutes: u64,
+    pub top_tracks: Vec<StatsTrack>,
+    pub top_artists: Vec<StatsArtist>,
+    pub top_albums: Vec<StatsAlbum>,
+    pub top_tags: Vec<StatsTag>,
+    pub most_played_day: Option<DayPlays>,
+    pub longest_streak: Option<ListeningStreak>,
+    pub new_artists: u32,
+    pub top_new_artists: Vec<StatsArtist>,
+    pub months: Vec<WrappedMonth>,
+}
+
+/// When a scrobble was played and for how long, all that activity stats need.
+#[derive(Clone, Debug)]
+pub struct PlayTime {
+    pub timestamp: DateTime<Utc>,
+    pub duration_secs: f64,
+}
+
+/// The size of the buckets of an activity time series.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActivityBucket {
+    Hour,
+    Day,
+    Month,
+}
+
+impl ActivityBucket {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActivityBucket::Hour => "hour",
+            ActivityBucket::Day => "day",
+            ActivityBucket::Month => "month",
+        }
+    }
+}
+
+impl FromStr for ActivityBucket {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hour" => Ok(ActivityBucket::Hour),
+            "day" => Ok(ActivityBucket::Day),
+            "month" => Ok(ActivityBucket::Month),
+            _ => Err(anyhow::anyhow!("unknown bucket `{}`", s)),
+        }
+    }
+}
+
+/// Scrobbles in a bucket of an activity time series, starting at a local time.
+#[derive(Clone, Debug)]
+pub struct ActivityPoint {
+    pub start: NaiveDateTime,
+    pub scrobbles: u32,
+    pub listened_secs: f64,
+}
+
+/// A day of the calendar heatmap, `level` goes from 0 (nothing) to 4 (the busiest days).
+#[derive(Clone, Debug)]
+pub struct HeatmapDay {
+    pub day: NaiveDate,
+    pub scrobbles: u32,
+    pub level: u8,
+}
+
+/// A column of the calendar heatmap, Monday to Sunday. Days out of the range are empty.
+#[derive(Clone, Debug)]
+pub struct HeatmapWeek {
+    pub days: Vec<Option<HeatmapDay>>,
+}
+
+/// An hour of the listening clock, `level` goes from 0 to 4 like the heatmap's.
+#[derive(Clone, Debug)]
+pub struct ClockCell {
+    pub hour: u32,
+    pub scrobbles: u32,
+    pub level: u8,
+}
+
+/// A weekday of the listening clock, with its 24 hours.
+#[derive(Clone, Debug)]
+pub struct ClockRow {
+    pub weekday: String,
+    pub cells: Vec<ClockCell>,
+}
+
+/// Listening activity over a range of local days.
+#[derive(Clone, Debug)]
+pub struct Activity {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub bucket: ActivityBucket,
+    pub series: Vec<ActivityPoint>,
+    pub heatmap: Vec<HeatmapWeek>,
+    /// Scrobbles by weekday, Monday first, and hour of the day.
+    pub clock: Vec<ClockRow>,
+}
+
+/// An artist, track or genre scrobbled for the first time in a range.
+#[derive(Clone, Debug)]
+pub struct Discovered {
+    pub id: String,
+    pub name: String,
+    pub first_played: DateTime<Utc>,
+    /// Scrobbles in the range.
+    pub scrobbles: u32,
+}
+
+/// How many artists, tracks or genres were discovered in a range, the most played first.
+#[derive(Clone, Debug, Default)]
+pub struct Discoveries {
+    pub total: u32,
+    pub top: Vec<Discovered>,
+}
+
+/// Artists played in a bucket of the discovery time series, starting at a local time.
+#[derive(Clone, Debug)]
+pub struct DiscoveryPoint {
+    pub start: NaiveDateTime,
+    pub artists: u32,
+    pub new_artists: u32,
+    /// Percentage of the artists of the bucket that were new.
+    pub ratio: u32,
+}
+
+/// How evenly plays spread across artists and genres, from 0 (always the same one) to
+/// 100 (every one played as much as the others).
+#[derive(Clone, Debug, Default)]
+pub struct Eclecticism {
+    pub artists: u32,
+    pub tags: u32,
+    pub score: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Discovery {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub bucket: ActivityBucket,
+    pub new_artists: Discoveries,
+    pub new_tracks: Discoveries,
+    pub new_tags: Discoveries,
+    pub series: Vec<DiscoveryPoint>,
+    pub eclecticism: Eclecticism,
+}
+
+/// Scrobbles played one after the other, without long gaps.
+#[derive(Clone, Debug)]
+pub struct ListeningSession {
+    pub start: DateTime<Utc>,
+    /// When the last scrobble ended.
+    pub end: DateTime<Utc>,
+    /// Oldest first.
+    pub scrobbles: Vec<Scrobble>,
+}
+
+impl ListeningSession {
+    pub fn minutes(&self) -> i64 {
+        (self.end - self.start).num_minutes()
+    }
+}
+
+/// How many sessions started at an hour of the day, `level` goes from 0 to 4 like the
+/// heatmap's.
+#[derive(Clone, Debug)]
+pub struct SessionStartHour {
+    pub hour: u32,
+    pub sessions: u32,
+    pub level: u8,
+}
+
+/// The listening sessions between two local days, both included.
+#[derive(Clone, Debug)]
+pub struct Sessions {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    /// The latest first.
+    pub sessions: Vec<ListeningSession>,
+    pub average_minutes: i64,
+    pub longest: Option<ListeningSession>,
+    pub start_hours: Vec<SessionStartHour>,
+    /// The hour most sessions start at.
+    pub typical_start_hour: Option<u32>,
+}
+
+/// The scrobbles of one of the periods of a comparison.
+#[derive(Clone, Debug)]
+pub struct PeriodTotals {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub scrobbles: u32,
+    pub listened_minutes: u64,
+}
+
+/// An artist in the top of both periods, and how many places it moved.
+#[derive(Clone, Debug)]
+pub struct RankMove {
+    pub id: String,
+    pub name: String,
+    pub previous_rank: u32,
+    pub current_rank: u32,
+    pub places: u32,
+}
+
+/// How the share of a genre, in percent of the scrobbles, changed between two periods.
+#[derive(Clone, Debug)]
+pub struct TagShift {
+    pub name: String,
+    pub previous_share: u32,
+    pub current_share: u32,
+    pub shift: i32,
+}
+
+/// A period compared against a previous one. Artists and genres are the ones of the top
+/// of each period.
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    pub previous: PeriodTotals,
+    pub current: PeriodTotals,
+    pub scrobbles_delta: i64,
+    pub minutes_delta: i64,
+    /// Artists in the current top that weren't in the previous one.
+    pub gained: Vec<StatsArtist>,
+    /// Artists in the previous top that aren't in the current one.
+    pub lost: Vec<StatsArtist>,
+    pub climbers: Vec<RankMove>,
+    pub fallers: Vec<RankMove>,
+    /// The genres whose share changed the most first.
+    pub tag_shifts: Vec<TagShift>,
+}
+
+/// The metadata field a rewrite rule applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RewriteField {
+    Title,
+    Artist,
+    Album,
+}
+
+impl RewriteField {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RewriteField::Title => "title",
+            RewriteField::Artist => "artist",
+            RewriteField::Album => "album",
+        }
+    }
+}
+
+impl FromStr for RewriteField {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "title" => Ok(RewriteField::Title),
+            "artist" => Ok(RewriteField::Artist),
+            "album" => Ok(RewriteField::Album),
+            _ => Err(anyhow::anyhow!("unknown rewrite field `{}`", s)),
+        }
+    }
+}
+
+/// A regex match-and-replace rule applied to incoming scrobbles metadata.
+/// Rules without an origin apply to scrobbles from every source.
+#[derive(Clone, Debug)]
+pub struct RewriteRule {
+    pub id: Option<i32>,
+    pub field: RewriteField,
+    pub pattern: String,
+    pub replacement: String,
+    pub origin: Option<String>,
+}
+
+/// A track title, artist name or album title, with the number of its scrobbles.
+#[derive(Clone, Debug)]
+pub struct EntityName {
+    pub id: String,
+    pub name: String,
+    pub scrobbles: u32,
+}
+
+/// Entities that are likely the same one stored under different IDs, e.g. a track
+/// released both as a single and on an album. The most scrobbled entity comes first.
+#[derive(Clone, Debug)]
+pub struct DuplicateGroup {
+    pub reason: String,
+    pub key: String,
+    pub entities: Vec<EntityName>,
+}
+
+/// The effect a rewrite rule would have on an existing entity.
+#[derive(Clone, Debug)]
+pub struct RewritePreview {
+    pub id: String,
+    pub before: String,
+    pub after: String,
+    pub scrobbles: u32,
+}
@@ -0,0 +1,53 @@
+//! Time zone helpers. Timestamps are stored in UTC, while days, weeks and hours of the
+//! stats are the ones of a time zone: the configured one, unless a request picks another.
+
+use chrono::{DateTime, NaiveDate, NaiveTime, TimeZone, Utc};
+
+pub use chrono_tz::Tz;
+
+/// Parses an IANA time zone name, like `Europe/Rome`.
+pub fn parse_time_zone(name: &str) -> Result<Tz, String> {
+    name.trim()
+        .parse::<Tz>()
+        .map_err(|_| format!("`{}` is not an IANA time zone", name))
+}
+
+/// When a local day starts. Where daylight saving time skips midnight, the day starts
+/// with its first existing hour.
+pub fn start_of_day(day: NaiveDate, tz: Tz) -> DateTime<Utc> {
+    (0..24)
+        .filter_map(|hour| day.and_hms_opt(hour, 0, 0))
+        .find_map(|time| tz.from_local_datetime(&time).earliest())
+        .map(|start| start.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&day.and_time(NaiveTime::MIN)))
+}
+
+/// When the local day after this one starts, the end of the day excluded. The last day
+/// chrono knows ends with its last instant.
+pub fn start_of_next_day(day: NaiveDate, tz: Tz) -> DateTime<Utc> {
+    match day.succ_opt() {
+        Some(next) => start_of_day(next, tz),
+        None => DateTime::<Utc>::MAX_UTC,
+    }
+}
+
+/// Tells the current time. The app and the scrobbler read it from here, so that tests can
+/// move it forward at will.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    /// The current day in a time zone.
+    fn today(&self, tz: Tz) -> NaiveDate {
+        self.now().with_timezone(&tz).date_naive()
+    }
+}
+
+/// The clock of the system.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
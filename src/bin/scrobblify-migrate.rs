@@ -1,12 +1,25 @@
-use std::env;
+//! Kept for existing deployments: forwards to `scrobblify migrate`, which takes the same
+//! `up`, `down` and `status` actions.
 
-use scrobblify_db::migrator;
-use scrobblify_db::migrator::sea_orm_migration::prelude::*;
+use std::{env, process};
 
-#[tokio::main]
-async fn main() {
-    if env::var_os("DATABASE_URL") == None {
-        panic!("$DATABASE_URL is not set")
+fn main() {
+    let exe = env::current_exe()
+        .map(|exe| exe.with_file_name(format!("scrobblify{}", env::consts::EXE_SUFFIX)))
+        .unwrap_or_else(|err| {
+            eprintln!("cannot locate the scrobblify binary: {}", err);
+            process::exit(1);
+        });
+
+    match process::Command::new(&exe)
+        .arg("migrate")
+        .args(env::args_os().skip(1))
+        .status()
+    {
+        Ok(status) => process::exit(status.code().unwrap_or(1)),
+        Err(err) => {
+            eprintln!("cannot run {}: {}", exe.display(), err);
+            process::exit(1);
+        }
     }
-    cli::run_cli(migrator::Migrator).await;
 }
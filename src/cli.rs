@@ -0,0 +1,233 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+use scrobblify_domain::models::{ChartKind, RewriteField};
+
+/// Self-hosted music scrobble database. Without a command, it starts the server.
+#[derive(Debug, Parser)]
+#[command(name = "scrobblify", version)]
+pub struct Cli {
+    /// Configuration file, instead of `SCROBBLIFY_CONFIG` or `./scrobblify.toml`
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Start the web UI and auto-scrobbling
+    Serve,
+    /// Apply or roll back database migrations
+    Migrate {
+        #[command(subcommand)]
+        action: Option<MigrateAction>,
+    },
+    /// Rebuild the daily rollups stats are read from, out of the scrobbles
+    Rollups,
+    /// Import scrobbles from a JSON lines file, as written by `export`
+    Import(ImportArgs),
+    /// Export scrobbles as JSON lines
+    Export(ExportArgs),
+    /// Show listening stats
+    Stats {
+        #[command(subcommand)]
+        chart: StatsChart,
+    },
+    /// Export a year in review as a standalone HTML page
+    Wrapped(WrappedArgs),
+    /// Manage scrobbles
+    Scrobble {
+        #[command(subcommand)]
+        action: ScrobbleAction,
+    },
+    /// Scrobble the tracks played on Spotify since the last scrobble
+    Backfill(UserArgs),
+    /// List duplicate tracks, artists or albums, or merge them
+    Merge(MergeArgs),
+    /// Link external accounts
+    Auth {
+        #[command(subcommand)]
+        service: AuthService,
+    },
+    /// Work with the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MigrateAction {
+    /// Apply the pending migrations (default)
+    Up,
+    /// Roll back the last migrations
+    Down {
+        #[arg(long, default_value_t = 1)]
+        steps: u32,
+    },
+    /// Show which migrations have been applied
+    Status,
+}
+
+#[derive(Debug, Args)]
+pub struct UserArgs {
+    /// Name of the user
+    #[arg(long, default_value = "default")]
+    pub user: String,
+}
+
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+    #[command(flatten)]
+    pub user: UserArgs,
+    /// File to read, `-` for standard input
+    pub file: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    #[command(flatten)]
+    pub user: UserArgs,
+    #[command(flatten)]
+    pub range: DateRangeArgs,
+    /// File to write, standard output when missing
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct DateRangeArgs {
+    /// First day, e.g. 2022-11-01
+    #[arg(long)]
+    pub from: NaiveDate,
+    /// Last day, included, today when missing
+    #[arg(long)]
+    pub to: Option<NaiveDate>,
+}
+
+#[derive(Debug, Args)]
+pub struct StatsArgs {
+    #[command(flatten)]
+    pub range: DateRangeArgs,
+    /// Only the scrobbles of this user, everyone's when missing
+    #[arg(long)]
+    pub user: Option<String>,
+    #[arg(long, default_value_t = 10)]
+    pub limit: u64,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum StatsChart {
+    TopTracks(StatsArgs),
+    TopArtists(StatsArgs),
+    TopTags(StatsArgs),
+    /// Weekly chart with the rank movements, like the web page
+    Weekly(WeeklyArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct WeeklyArgs {
+    #[command(flatten)]
+    pub user: UserArgs,
+    #[arg(long, value_enum, default_value_t = WeeklyKind::Tracks)]
+    pub kind: WeeklyKind,
+    /// Any day of the week, the current one when missing
+    #[arg(long)]
+    pub week: Option<NaiveDate>,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum WeeklyKind {
+    Tracks,
+    Artists,
+    Albums,
+}
+
+impl From<WeeklyKind> for ChartKind {
+    fn from(kind: WeeklyKind) -> Self {
+        match kind {
+            WeeklyKind::Tracks => ChartKind::Tracks,
+            WeeklyKind::Artists => ChartKind::Artists,
+            WeeklyKind::Albums => ChartKind::Albums,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct WrappedArgs {
+    #[command(flatten)]
+    pub user: UserArgs,
+    /// e.g. 2022
+    pub year: i32,
+    /// File to write, standard output when missing
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ScrobbleAction {
+    /// Scrobble a track by hand
+    Add(ScrobbleAddArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ScrobbleAddArgs {
+    #[command(flatten)]
+    pub user: UserArgs,
+    #[arg(long)]
+    pub title: String,
+    /// Repeat it for every artist, the main one first
+    #[arg(long = "artist", required = true)]
+    pub artists: Vec<String>,
+    #[arg(long, default_value = "")]
+    pub album: String,
+    #[arg(long, default_value_t = 0)]
+    pub duration_secs: u64,
+    /// When it was played, e.g. 2022-11-01T18:30:00Z, now when missing
+    #[arg(long)]
+    pub at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Args)]
+pub struct MergeArgs {
+    #[arg(long, value_enum)]
+    pub field: MergeField,
+    /// ID of the entity to keep, the duplicates are listed when missing
+    #[arg(long, requires = "duplicates")]
+    pub into: Option<String>,
+    /// IDs of the entities to merge into the kept one
+    pub duplicates: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum MergeField {
+    Track,
+    Artist,
+    Album,
+}
+
+impl From<MergeField> for RewriteField {
+    fn from(field: MergeField) -> Self {
+        match field {
+            MergeField::Track => RewriteField::Title,
+            MergeField::Artist => RewriteField::Artist,
+            MergeField::Album => RewriteField::Album,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AuthService {
+    /// Link a Spotify account by pasting the URL Spotify redirects to, so the server
+    /// doesn't need to be reachable from the browser
+    Spotify(UserArgs),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigAction {
+    /// Validate the configuration and show a summary
+    Check,
+}
@@ -0,0 +1,498 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::Mutex;
+use url::Url;
+
+use scrobblify_bridge::spotify::SpotifyClient;
+use scrobblify_core::{App, ScrobbleFilter, Scrobbler};
+use scrobblify_db::{
+    migrator::{sea_orm_migration::MigratorTrait, Migrator},
+    DbTokenStore, Repository,
+};
+use scrobblify_domain::{
+    app::App as DomainApp,
+    config::Config,
+    db::ParamsForStatsQuery,
+    identity,
+    models::{Album, Artist, ScrobbleInfo, TrackInfo, User},
+};
+use scrobblify_web::{render_wrapped, HttpUi};
+
+use crate::cli::{
+    AuthService, ConfigAction, DateRangeArgs, ExportArgs, ImportArgs, MergeArgs, MigrateAction,
+    ScrobbleAction, ScrobbleAddArgs, StatsChart, UserArgs, WeeklyArgs, WrappedArgs,
+};
+
+/// A scrobble as read by `import` and written by `export`, one JSON object per line.
+#[derive(Debug, Deserialize, Serialize)]
+struct ScrobbleRecord {
+    timestamp: DateTime<Utc>,
+    track: String,
+    artists: Vec<String>,
+    #[serde(default)]
+    album: String,
+    #[serde(default)]
+    duration_secs: f64,
+    /// Missing for scrobbles coming from elsewhere, IDs are then derived from the metadata.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    track_id: Option<String>,
+}
+
+impl ScrobbleRecord {
+    fn into_scrobble(self, user_id: i32, origin: &str) -> ScrobbleInfo {
+        let mut track = TrackInfo {
+            id: self.track_id.unwrap_or_default(),
+            title: self.track,
+            album: Album {
+                id: String::new(),
+                title: self.album,
+                cover: String::new(),
+            },
+            artists: self
+                .artists
+                .into_iter()
+                .map(|name| Artist {
+                    id: String::new(),
+                    name,
+                })
+                .collect(),
+            duration_secs: Duration::from_secs_f64(self.duration_secs.max(0.0)),
+            tags: vec![],
+            isrc: String::new(),
+            cover: String::new(),
+        };
+        identity::assign_missing_ids(&mut track);
+
+        ScrobbleInfo {
+            user_id,
+            timestamp: self.timestamp,
+            duration_secs: self.duration_secs,
+            origin: origin.to_string(),
+            track,
+            device: None,
+            context: None,
+        }
+    }
+}
+
+async fn build_app(config: &Config) -> Result<App> {
+    let db = Repository::new(config.database.url.clone())
+        .await?
+        .with_time_zone(config.stats.time_zone());
+    db.sync_rollups().await?;
+    let tokens = DbTokenStore::new(db.conn(), config.spotify.token_key.as_deref())?;
+    let spotify = SpotifyClient::new(&config.spotify).await?;
+    let filter = ScrobbleFilter::from_config(&config.scrobble)?;
+
+    let mut app = App::new(
+        Box::new(db),
+        Arc::new(tokens),
+        Box::new(spotify),
+        filter,
+        config.stats.time_zone(),
+        config.stats.session_gap(),
+    );
+    app.load_users().await?;
+
+    Ok(app)
+}
+
+async fn find_user(app: &App, name: &str) -> Result<User> {
+    app.get_user(name)
+        .await?
+        .ok_or_else(|| anyhow!("user `{}` not found", name))
+}
+
+/// Days of the range are the ones of the configured time zone.
+fn date_range(app: &App, range: &DateRangeArgs, limit: Option<u64>) -> ParamsForStatsQuery {
+    let tz = app.time_zone();
+    let to = range.to.unwrap_or_else(|| app.today(tz));
+    ParamsForStatsQuery::new(range.from, to, limit).in_time_zone(tz)
+}
+
+pub async fn serve(config: Config) -> Result<()> {
+    let mut app = build_app(&config).await?;
+    if let Some(password) = &config.auth.initial_admin_password {
+        app.set_initial_admin_password(password).await?;
+    }
+
+    let app = Arc::new(Mutex::new(app));
+    let http_ui = HttpUi::new(app.clone(), &config.http)?;
+
+    Scrobbler::start_auto_scrobbling(app.clone(), config.sources.spotify.clone()).await;
+    http_ui.serve().await
+}
+
+pub async fn migrate(config: Config, action: Option<MigrateAction>) -> Result<()> {
+    let conn = Repository::new(config.database.url.clone()).await?.conn();
+
+    match action.unwrap_or(MigrateAction::Up) {
+        MigrateAction::Up => Migrator::up(&conn, None).await?,
+        MigrateAction::Down { steps } => Migrator::down(&conn, Some(steps)).await?,
+        MigrateAction::Status => Migrator::status(&conn).await?,
+    }
+
+    Ok(())
+}
+
+pub async fn rollups(config: Config) -> Result<()> {
+    let repository = Repository::new(config.database.url.clone())
+        .await?
+        .with_time_zone(config.stats.time_zone());
+    repository.rebuild_rollups().await?;
+    println!("rollups rebuilt");
+
+    Ok(())
+}
+
+pub async fn import(config: Config, args: ImportArgs) -> Result<()> {
+    let app = build_app(&config).await?;
+    let user = find_user(&app, &args.user.user).await?;
+
+    let reader: Box<dyn BufRead> = if args.file == Path::new("-") {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(&args.file)?))
+    };
+
+    let (mut imported, mut failed) = (0, 0);
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let result = match serde_json::from_str::<ScrobbleRecord>(&line) {
+            Ok(record) => app.scrobble(record.into_scrobble(user.id, "import")).await,
+            Err(err) => Err(err.into()),
+        };
+        match result {
+            Ok(()) => imported += 1,
+            Err(err) => {
+                eprintln!("line {}: {}", number + 1, err);
+                failed += 1;
+            }
+        }
+    }
+
+    // scrobbles already stored are skipped, so importing the same file again is harmless
+    println!(
+        "{} scrobbles imported for {}, {} failed",
+        imported, user.name, failed
+    );
+    Ok(())
+}
+
+pub async fn export(config: Config, args: ExportArgs) -> Result<()> {
+    let app = build_app(&config).await?;
+    let user = find_user(&app, &args.user.user).await?;
+
+    let mut scrobbles = app
+        .list_scrobbles(date_range(&app, &args.range, None).for_user(user.id))
+        .await?;
+    scrobbles.reverse();
+
+    let mut writer: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    for scrobble in scrobbles {
+        let record = ScrobbleRecord {
+            timestamp: scrobble.timestamp,
+            track: scrobble.track,
+            artists: scrobble.artists,
+            album: scrobble.album,
+            duration_secs: scrobble.duration_secs.as_secs_f64(),
+            track_id: Some(scrobble.track_id),
+        };
+        writeln!(writer, "{}", serde_json::to_string(&record)?)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+pub async fn stats(config: Config, chart: StatsChart) -> Result<()> {
+    let app = build_app(&config).await?;
+
+    let args = match &chart {
+        StatsChart::TopTracks(args) | StatsChart::TopArtists(args) | StatsChart::TopTags(args) => {
+            args
+        }
+        StatsChart::Weekly(args) => return weekly_chart(&app, args).await,
+    };
+    let mut opts = date_range(&app, &args.range, Some(args.limit));
+    if let Some(name) = &args.user {
+        opts = opts.for_user(find_user(&app, name).await?.id);
+    }
+
+    match chart {
+        StatsChart::TopTracks(_) => {
+            for (rank, track) in app.stats_for_popular_tracks(opts).await?.iter().enumerate() {
+                println!(
+                    "{:>3}. {} - {} ({} scrobbles)",
+                    rank + 1,
+                    track.artists,
+                    track.title,
+                    track.score
+                );
+            }
+        }
+        StatsChart::TopArtists(_) => {
+            for (rank, artist) in app
+                .stats_for_popular_artists(opts)
+                .await?
+                .iter()
+                .enumerate()
+            {
+                println!(
+                    "{:>3}. {} ({} scrobbles, {} tracks)",
+                    rank + 1,
+                    artist.name,
+                    artist.score,
+                    artist.tracks
+                );
+            }
+        }
+        StatsChart::TopTags(_) => {
+            for (rank, tag) in app.stats_for_popular_tags(opts).await?.iter().enumerate() {
+                println!("{:>3}. {} ({} scrobbles)", rank + 1, tag.name, tag.score);
+            }
+        }
+        StatsChart::Weekly(_) => unreachable!("weekly charts are shown above"),
+    }
+
+    Ok(())
+}
+
+async fn weekly_chart(app: &App, args: &WeeklyArgs) -> Result<()> {
+    let user = find_user(app, &args.user.user).await?;
+    let week = args.week.unwrap_or_else(|| app.today(app.time_zone()));
+    let chart = app
+        .get_weekly_chart(user.id, args.kind.into(), week)
+        .await?;
+
+    println!(
+        "Week of {}{}",
+        chart.week_start,
+        if chart.complete { "" } else { " (so far)" }
+    );
+    for entry in chart.entries {
+        let name = match entry.item.artists.as_str() {
+            "" => entry.item.name,
+            artists => format!("{} - {}", artists, entry.item.name),
+        };
+        println!(
+            "{:>3}. {:<9} {} ({} scrobbles, {} weeks, peak {})",
+            entry.item.rank,
+            entry.movement.label(),
+            name,
+            entry.item.score,
+            entry.weeks_on_chart,
+            entry.peak
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn wrapped(config: Config, args: WrappedArgs) -> Result<()> {
+    let app = build_app(&config).await?;
+    let user = find_user(&app, &args.user.user).await?;
+    let wrapped = app.get_wrapped(user.id, args.year, app.time_zone()).await?;
+    let html = render_wrapped(user, wrapped)?;
+
+    match args.output {
+        Some(path) => std::fs::write(&path, html)?,
+        None => io::stdout().write_all(html.as_bytes())?,
+    }
+
+    Ok(())
+}
+
+pub async fn scrobble(config: Config, action: ScrobbleAction) -> Result<()> {
+    match action {
+        ScrobbleAction::Add(args) => scrobble_add(config, args).await,
+    }
+}
+
+async fn scrobble_add(config: Config, args: ScrobbleAddArgs) -> Result<()> {
+    let app = build_app(&config).await?;
+    let user = find_user(&app, &args.user.user).await?;
+
+    let record = ScrobbleRecord {
+        timestamp: args.at.unwrap_or_else(Utc::now),
+        track: args.title,
+        artists: args.artists,
+        album: args.album,
+        duration_secs: args.duration_secs as f64,
+        track_id: None,
+    };
+    let scrobble = record.into_scrobble(user.id, "manual");
+    let title = scrobble.track.title.clone();
+    app.scrobble(scrobble).await?;
+
+    println!("scrobbled `{}` for {}", title, user.name);
+    Ok(())
+}
+
+pub async fn backfill(config: Config, args: UserArgs) -> Result<()> {
+    let app = build_app(&config).await?;
+    let user = find_user(&app, &args.user).await?;
+    if !app.is_spotify_authenticated(user.id).await {
+        bail!(
+            "{} has no Spotify account linked, run `scrobblify auth spotify --user {}` first",
+            user.name,
+            user.name
+        );
+    }
+
+    Scrobbler::scrobble_recently_played(Arc::new(Mutex::new(app)), user.id).await;
+    Ok(())
+}
+
+pub async fn merge(config: Config, args: MergeArgs) -> Result<()> {
+    let app = build_app(&config).await?;
+    let field = args.field.into();
+
+    let canonical_id = match args.into {
+        Some(canonical_id) => canonical_id,
+        None => {
+            for group in app.list_duplicates(field).await? {
+                println!("{}: {}", group.reason, group.key);
+                for entity in group.entities {
+                    println!(
+                        "  {}  {} ({} scrobbles)",
+                        entity.id, entity.name, entity.scrobbles
+                    );
+                }
+            }
+            return Ok(());
+        }
+    };
+
+    let count = args.duplicates.len();
+    app.merge_entities(field, &canonical_id, args.duplicates)
+        .await?;
+    println!("{} merged into {}", count, canonical_id);
+
+    Ok(())
+}
+
+pub async fn auth(config: Config, service: AuthService) -> Result<()> {
+    match service {
+        AuthService::Spotify(args) => auth_spotify(config, args).await,
+    }
+}
+
+/// OAuth without a browser on the same machine: the URL Spotify redirects to carries the
+/// code, so it's enough to paste it back, even if the page itself didn't load.
+async fn auth_spotify(config: Config, args: UserArgs) -> Result<()> {
+    let mut app = build_app(&config).await?;
+    let user = find_user(&app, &args.user).await?;
+
+    let auth_url = app.get_spotify_auth_url(user.id).await?;
+    println!(
+        "Open this URL in a browser and authorize Scrobblify:\n\n  {}\n",
+        auth_url
+    );
+    println!("Then paste the URL you've been redirected to, even if the page didn't load:");
+
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    let redirect = Url::parse(line.trim())?;
+    let param = |name: &str| {
+        redirect
+            .query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+
+    if let Some(error) = param("error") {
+        bail!("Spotify authorization failed: {}", error);
+    }
+    let (code, state) = match (param("code"), param("state")) {
+        (Some(code), Some(state)) => (code, state),
+        _ => bail!("the URL has no authorization code or state"),
+    };
+    app.store_spotify_auth_token(user.id, &state, &code).await?;
+
+    // a running server reads tokens only when it starts
+    println!(
+        "Spotify account linked to {}, restart the server to start scrobbling",
+        user.name
+    );
+    Ok(())
+}
+
+pub fn config(config: Config, path: Option<&Path>, action: ConfigAction) -> Result<()> {
+    match action {
+        ConfigAction::Check => print_config(&config, path),
+    }
+
+    Ok(())
+}
+
+fn print_config(config: &Config, path: Option<&Path>) {
+    let path = path
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|| "none, environment only".to_string());
+    let address = config
+        .http
+        .address()
+        .map(|addr| addr.to_string())
+        .unwrap_or_default();
+
+    println!("configuration is valid");
+    println!("  file: {}", path);
+    println!(
+        "  database: {}",
+        config.database.url.split(':').next().unwrap_or_default()
+    );
+    println!("  http: {}", address);
+    println!(
+        "  proxy auth header: {}",
+        config.http.auth_header.as_deref().unwrap_or("none")
+    );
+    println!(
+        "  session cookie: {}",
+        if config.http.secure_cookies {
+            "https only"
+        } else {
+            "http and https"
+        }
+    );
+    println!(
+        "  spotify: {}",
+        match config.check_spotify() {
+            Ok(()) => format!("callback {}", config.spotify.auth_callback_uri),
+            Err(_) => "not configured, needed by `serve`, `auth` and `backfill`".to_string(),
+        }
+    );
+    println!(
+        "  spotify tokens: {}",
+        if config.spotify.token_key.is_some() {
+            "encrypted"
+        } else {
+            "plain text"
+        }
+    );
+    println!("  time zone: {}", config.stats.time_zone);
+    println!("  scrobble rules: {}", config.scrobble.rules.len());
+    println!(
+        "  spotify auto-scrobbling: {}",
+        if config.sources.spotify.enabled {
+            format!("every {}s", config.sources.spotify.polling_secs)
+        } else {
+            "disabled".to_string()
+        }
+    );
+}
@@ -1,12 +1,15 @@
 use anyhow::Result;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use clap::Parser;
+use std::process;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use scrobblify_bridge::spotify::SpotifyClient;
-use scrobblify_core::{App, Scrobbler};
-use scrobblify_db::Repository;
-use scrobblify_web::HttpUi;
+use scrobblify_core::ScrobbleFilter;
+use scrobblify_domain::{config::Config, errors::ConfigError};
+
+mod cli;
+mod commands;
+
+use cli::{Cli, Command};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -17,20 +20,51 @@ async fn main() -> Result<()> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let db = Repository::new_from_env()
-        .await
-        .expect("failed to create repository");
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Serve);
+    // the other commands work on the database only
+    let needs_spotify = matches!(
+        command,
+        Command::Serve | Command::Auth { .. } | Command::Backfill(_)
+    );
+    let config_path = cli.config.or_else(Config::default_path);
+    let config = match load_config(config_path.as_deref(), needs_spotify) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
 
-    let spotify = SpotifyClient::new_from_env()
-        .await
-        .expect("failed to initialize spotify client");
-
-    let app = Arc::new(Mutex::new(App::new(Box::new(db), spotify)));
-    let http_ui = HttpUi::new(app.clone());
+    match command {
+        Command::Serve => commands::serve(config).await,
+        Command::Migrate { action } => commands::migrate(config, action).await,
+        Command::Rollups => commands::rollups(config).await,
+        Command::Import(args) => commands::import(config, args).await,
+        Command::Export(args) => commands::export(config, args).await,
+        Command::Stats { chart } => commands::stats(config, chart).await,
+        Command::Wrapped(args) => commands::wrapped(config, args).await,
+        Command::Scrobble { action } => commands::scrobble(config, action).await,
+        Command::Backfill(args) => commands::backfill(config, args).await,
+        Command::Merge(args) => commands::merge(config, args).await,
+        Command::Auth { service } => commands::auth(config, service).await,
+        Command::Config { action } => commands::config(config, config_path.as_deref(), action),
+    }
+}
 
-    Scrobbler::scrobble_recently_played(app.clone()).await;
-    Scrobbler::start_auto_scrobbling(app.clone()).await;
-    http_ui.serve_from_env().await;
+/// Loads and validates the configuration, scrobble rules included, and the Spotify
+/// credentials when the command needs them.
+fn load_config(path: Option<&std::path::Path>, needs_spotify: bool) -> Result<Config, ConfigError> {
+    let config = Config::load(path)?;
+    if needs_spotify {
+        config.check_spotify()?;
+    }
+    if let Err(err) = ScrobbleFilter::from_config(&config.scrobble) {
+        return Err(ConfigError::Invalid(vec![format!(
+            "scrobble.rules: {}",
+            err
+        )]));
+    }
 
-    Ok(())
+    Ok(config)
 }
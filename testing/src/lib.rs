@@ -0,0 +1,12 @@
+//! In-memory stand-ins for the database, the token store, Spotify and the clock, so that
+//! the app and the scrobbler can be covered by fast, deterministic tests.
+
+mod clock;
+mod memory;
+mod spotify;
+mod token_store;
+
+pub use clock::ManualClock;
+pub use memory::MemoryRepository;
+pub use spotify::{FakeSpotify, FakeSpotifyClient};
+pub use token_store::MemoryTokenStore;
@@ -0,0 +1,1596 @@
+use anyhow::anyhow;
+use chrono::{DateTime, NaiveDate, SubsecRound, Utc};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use scrobblify_domain::{
+    db::{ParamsForStatsQuery, Repository},
+    duplicates::{group_duplicates, DuplicateCandidate},
+    errors::DatabaseResult,
+    models::{
+        Album, Artist, AuditEntry, ChartKind, ChartSnapshot, DuplicateGroup, EntityDay, EntityName,
+        EntityPlayTime, Episode, EpisodeScrobbleInfo, PlayTime, RewriteField, RewriteRule,
+        Scrobble, ScrobbleEdit, ScrobbleInfo, ScrobbleSelection, Show, SkippedPlay, StatsAlbum,
+        StatsArtist, StatsShow, StatsTag, StatsTrack, Tag, Track, User,
+    },
+    time::{start_of_day, start_of_next_day, Clock, SystemClock, Tz},
+};
+
+/// The user created along with the database, owning the scrobbles of single-user setups.
+const DEFAULT_USER_ID: i32 = 1;
+const DEFAULT_USER_NAME: &str = "default";
+const DEFAULT_STATS_LIMIT: u64 = 10;
+
+struct StoredUser {
+    user: User,
+    password_hash: Option<String>,
+}
+
+struct StoredScrobble {
+    id: i32,
+    user_id: i32,
+    timestamp: DateTime<Utc>,
+    origin: String,
+    duration_secs: f64,
+    track_id: String,
+}
+
+struct StoredEpisodeScrobble {
+    user_id: i32,
+    timestamp: DateTime<Utc>,
+    duration_secs: f64,
+    episode_id: String,
+}
+
+struct StoredChart {
+    user_id: i32,
+    kind: ChartKind,
+    snapshot: ChartSnapshot,
+}
+
+/// The tables of the database. Links between entities are pairs of IDs, in the order of
+/// the name of their set.
+struct Store {
+    tracks: BTreeMap<String, Track>,
+    albums: BTreeMap<String, Album>,
+    artists: BTreeMap<String, Artist>,
+    tags: BTreeSet<String>,
+    shows: BTreeMap<String, Show>,
+    episodes: BTreeMap<String, Episode>,
+    artists_tracks: BTreeSet<(String, String)>,
+    albums_tracks: BTreeSet<(String, String)>,
+    albums_artists: BTreeSet<(String, String)>,
+    tags_tracks: BTreeSet<(String, String)>,
+    users: Vec<StoredUser>,
+    sessions: HashMap<String, (i32, DateTime<Utc>)>,
+    scrobbles: Vec<StoredScrobble>,
+    episode_scrobbles: Vec<StoredEpisodeScrobble>,
+    skipped_plays: Vec<SkippedPlay>,
+    rewrite_rules: Vec<RewriteRule>,
+    /// Canonical IDs by entity kind and merged ID.
+    merged_ids: HashMap<(&'static str, String), String>,
+    audit_log: Vec<AuditEntry>,
+    charts: Vec<StoredChart>,
+}
+
+/// A repository keeping everything in memory, behaving like the database one: same joins,
+/// same orderings and the same audit entries. Entries with the same score come in the order
+/// of their IDs. Clones share the same data.
+#[derive(Clone)]
+pub struct MemoryRepository {
+    store: Arc<Mutex<Store>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl MemoryRepository {
+    /// An empty repository, with just the default user like a freshly migrated database.
+    pub fn new() -> MemoryRepository {
+        let default_user = StoredUser {
+            user: User {
+                id: DEFAULT_USER_ID,
+                name: DEFAULT_USER_NAME.to_string(),
+                is_admin: true,
+                public_stats: false,
+            },
+            password_hash: None,
+        };
+
+        MemoryRepository {
+            store: Arc::new(Mutex::new(Store {
+                tracks: BTreeMap::new(),
+                albums: BTreeMap::new(),
+                artists: BTreeMap::new(),
+                tags: BTreeSet::new(),
+                shows: BTreeMap::new(),
+                episodes: BTreeMap::new(),
+                artists_tracks: BTreeSet::new(),
+                albums_tracks: BTreeSet::new(),
+                albums_artists: BTreeSet::new(),
+                tags_tracks: BTreeSet::new(),
+                users: vec![default_user],
+                sessions: HashMap::new(),
+                scrobbles: vec![],
+                episode_scrobbles: vec![],
+                skipped_plays: vec![],
+                rewrite_rules: vec![],
+                merged_ids: HashMap::new(),
+                audit_log: vec![],
+                charts: vec![],
+            })),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Expires sessions and dates audit entries with the given clock.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> MemoryRepository {
+        MemoryRepository { clock, ..self }
+    }
+
+    fn store(&self) -> MutexGuard<'_, Store> {
+        self.store.lock().unwrap()
+    }
+}
+
+impl Default for MemoryRepository {
+    fn default() -> Self {
+        MemoryRepository::new()
+    }
+}
+
+impl Store {
+    fn user_mut(&mut self, user_id: i32) -> DatabaseResult<&mut StoredUser> {
+        self.users
+            .iter_mut()
+            .find(|u| u.user.id == user_id)
+            .ok_or_else(|| anyhow!("user `{}` not found", user_id).into())
+    }
+
+    fn scrobble_index(&self, id: i32) -> DatabaseResult<usize> {
+        self.scrobbles
+            .iter()
+            .position(|s| s.id == id)
+            .ok_or_else(|| anyhow!("scrobble `{}` not found", id).into())
+    }
+
+    /// A track belongs to the first of its albums, by ID.
+    fn album_of(&self, track_id: &str) -> Option<&Album> {
+        self.albums_tracks
+            .iter()
+            .find(|(_, track)| track == track_id)
+            .and_then(|(album, _)| self.albums.get(album))
+    }
+
+    fn track_artists(&self, track_id: &str) -> Vec<&Artist> {
+        self.artists_tracks
+            .iter()
+            .filter(|(_, track)| track == track_id)
+            .filter_map(|(artist, _)| self.artists.get(artist))
+            .collect()
+    }
+
+    fn album_artists(&self, album_id: &str) -> Vec<&Artist> {
+        self.albums_artists
+            .iter()
+            .filter(|(album, _)| album == album_id)
+            .filter_map(|(_, artist)| self.artists.get(artist))
+            .collect()
+    }
+
+    fn track_tags(&self, track_id: &str) -> Vec<String> {
+        self.tags_tracks
+            .iter()
+            .filter(|(tag, track)| track == track_id && self.tags.contains(tag))
+            .map(|(tag, _)| tag.clone())
+            .collect()
+    }
+
+    /// The scrobble with its track, album, artists and tags, unless the track or its album
+    /// is missing.
+    fn scrobble(&self, scrobble: &StoredScrobble) -> Option<Scrobble> {
+        let track = self.tracks.get(&scrobble.track_id)?;
+        let album = self.album_of(&track.id)?;
+
+        Some(Scrobble {
+            id: scrobble.id,
+            timestamp: scrobble.timestamp,
+            duration_secs: Duration::from_secs_f64(scrobble.duration_secs),
+            track_id: track.id.clone(),
+            track: track.title.clone(),
+            cover: album.cover.clone(),
+            album: album.title.clone(),
+            artists: distinct_names(self.track_artists(&track.id)),
+            tags: self.track_tags(&track.id),
+        })
+    }
+
+    /// Scrobbles of the range, oldest first.
+    fn scrobbles_in_range(&self, opts: &ParamsForStatsQuery) -> Vec<&StoredScrobble> {
+        let (start, end) = build_dates_range(opts);
+        let mut scrobbles: Vec<&StoredScrobble> = self
+            .scrobbles
+            .iter()
+            .filter(|s| s.timestamp >= start && s.timestamp < end)
+            .filter(|s| opts.user_id.is_none_or(|user_id| s.user_id == user_id))
+            .collect();
+        scrobbles.sort_by_key(|s| s.timestamp);
+        scrobbles
+    }
+
+    /// Scrobbles turned into views, the latest first.
+    fn latest_first<'a>(
+        &self,
+        scrobbles: impl Iterator<Item = &'a StoredScrobble>,
+    ) -> Vec<Scrobble> {
+        let mut scrobbles: Vec<Scrobble> = scrobbles.filter_map(|s| self.scrobble(s)).collect();
+        scrobbles.sort_by_key(|s| Reverse(s.timestamp));
+        scrobbles
+    }
+
+    fn entity_exists(&self, field: RewriteField, id: &str) -> bool {
+        match field {
+            RewriteField::Title => self.tracks.contains_key(id),
+            RewriteField::Artist => self.artists.contains_key(id),
+            RewriteField::Album => self.albums.contains_key(id),
+        }
+    }
+
+    fn audit(
+        &mut self,
+        now: DateTime<Utc>,
+        entity: &str,
+        entity_id: &str,
+        action: &str,
+        details: String,
+    ) {
+        let id = self.audit_log.iter().map(|e| e.id).max().unwrap_or(0) + 1;
+        self.audit_log.push(AuditEntry {
+            id,
+            timestamp: now,
+            entity: entity.to_string(),
+            entity_id: entity_id.to_string(),
+            action: action.to_string(),
+            details,
+        });
+    }
+}
+
+#[async_trait::async_trait]
+impl Repository for MemoryRepository {
+    async fn insert_track(&self, track: Track) -> DatabaseResult<()> {
+        self.store().tracks.entry(track.id.clone()).or_insert(track);
+        Ok(())
+    }
+
+    async fn get_track_by_id(&self, id: String) -> DatabaseResult<Option<Track>> {
+        Ok(self.store().tracks.get(&id).cloned())
+    }
+
+    async fn update_track(&self, track: Track) -> DatabaseResult<()> {
+        let mut store = self.store();
+        let before = store
+            .tracks
+            .insert(track.id.clone(), track.clone())
+            .ok_or_else(|| anyhow!("track `{}` not found", track.id))?;
+
+        let details = describe_changes(vec![
+            ("title", before.title, track.title),
+            (
+                "duration_secs",
+                before.duration_secs.as_secs_f64().to_string(),
+                track.duration_secs.as_secs_f64().to_string(),
+            ),
+            ("isrc", before.isrc, track.isrc),
+        ]);
+        store.audit(self.clock.now(), "track", &track.id, "update", details);
+
+        Ok(())
+    }
+
+    async fn delete_track(&self, id: &str) -> DatabaseResult<()> {
+        let mut store = self.store();
+        let before = store
+            .tracks
+            .remove(id)
+            .ok_or_else(|| anyhow!("track `{}` not found", id))?;
+
+        let scrobbles = store.scrobbles.len();
+        store.scrobbles.retain(|s| s.track_id != id);
+        let deleted = scrobbles - store.scrobbles.len();
+        store.artists_tracks.retain(|(_, track)| track != id);
+        store.albums_tracks.retain(|(_, track)| track != id);
+        store.tags_tracks.retain(|(_, track)| track != id);
+
+        let details = format!("deleted {:?} with {} scrobbles", before.title, deleted);
+        store.audit(self.clock.now(), "track", id, "delete", details);
+
+        Ok(())
+    }
+
+    async fn insert_album(&self, album: Album) -> DatabaseResult<()> {
+        self.store().albums.entry(album.id.clone()).or_insert(album);
+        Ok(())
+    }
+
+    async fn get_album_by_id(&self, id: String) -> DatabaseResult<Option<Album>> {
+        Ok(self.store().albums.get(&id).cloned())
+    }
+
+    async fn update_album(&self, album: Album) -> DatabaseResult<()> {
+        let mut store = self.store();
+        let before = store
+            .albums
+            .insert(album.id.clone(), album.clone())
+            .ok_or_else(|| anyhow!("album `{}` not found", album.id))?;
+
+        let details = describe_changes(vec![
+            ("title", before.title, album.title),
+            ("cover", before.cover, album.cover),
+        ]);
+        store.audit(self.clock.now(), "album", &album.id, "update", details);
+
+        Ok(())
+    }
+
+    async fn delete_album(&self, id: &str) -> DatabaseResult<()> {
+        let mut store = self.store();
+        let before = store
+            .albums
+            .remove(id)
+            .ok_or_else(|| anyhow!("album `{}` not found", id))?;
+
+        store.albums_tracks.retain(|(album, _)| album != id);
+        store.albums_artists.retain(|(album, _)| album != id);
+
+        let details = format!("deleted {:?}", before.title);
+        store.audit(self.clock.now(), "album", id, "delete", details);
+
+        Ok(())
+    }
+
+    async fn insert_artist(&self, artist: Artist) -> DatabaseResult<()> {
+        self.store()
+            .artists
+            .entry(artist.id.clone())
+            .or_insert(artist);
+        Ok(())
+    }
+
+    async fn get_artist_by_id(&self, id: String) -> DatabaseResult<Option<Artist>> {
+        Ok(self.store().artists.get(&id).cloned())
+    }
+
+    async fn update_artist(&self, artist: Artist) -> DatabaseResult<()> {
+        let mut store = self.store();
+        let before = store
+            .artists
+            .insert(artist.id.clone(), artist.clone())
+            .ok_or_else(|| anyhow!("artist `{}` not found", artist.id))?;
+
+        let details = describe_changes(vec![("name", before.name, artist.name)]);
+        store.audit(self.clock.now(), "artist", &artist.id, "update", details);
+
+        Ok(())
+    }
+
+    async fn delete_artist(&self, id: &str) -> DatabaseResult<()> {
+        let mut store = self.store();
+        let before = store
+            .artists
+            .remove(id)
+            .ok_or_else(|| anyhow!("artist `{}` not found", id))?;
+
+        store.artists_tracks.retain(|(artist, _)| artist != id);
+        store.albums_artists.retain(|(_, artist)| artist != id);
+
+        let details = format!("deleted {:?}", before.name);
+        store.audit(self.clock.now(), "artist", id, "delete", details);
+
+        Ok(())
+    }
+
+    async fn insert_tag(&self, tag: Tag) -> DatabaseResult<()> {
+        self.store().tags.insert(tag.id);
+        Ok(())
+    }
+
+    async fn insert_show(&self, show: Show) -> DatabaseResult<()> {
+        self.store().shows.entry(show.id.clone()).or_insert(show);
+        Ok(())
+    }
+
+    async fn insert_episode(&self, episode: Episode) -> DatabaseResult<()> {
+        self.store()
+            .episodes
+            .entry(episode.id.clone())
+            .or_insert(episode);
+        Ok(())
+    }
+
+    async fn get_episode_by_id(&self, id: String) -> DatabaseResult<Option<Episode>> {
+        Ok(self.store().episodes.get(&id).cloned())
+    }
+
+    async fn insert_user(&self, name: &str) -> DatabaseResult<User> {
+        let mut store = self.store();
+        if store.users.iter().any(|u| u.user.name == name) {
+            return Err(anyhow!("user `{}` already exists", name).into());
+        }
+
+        let user = User {
+            id: store.users.iter().map(|u| u.user.id).max().unwrap_or(0) + 1,
+            name: name.to_string(),
+            is_admin: false,
+            public_stats: false,
+        };
+        store.users.push(StoredUser {
+            user: user.clone(),
+            password_hash: None,
+        });
+
+        Ok(user)
+    }
+
+    async fn get_user_by_name(&self, name: &str) -> DatabaseResult<Option<User>> {
+        Ok(self
+            .store()
+            .users
+            .iter()
+            .find(|u| u.user.name == name)
+            .map(|u| u.user.clone()))
+    }
+
+    async fn list_users(&self) -> DatabaseResult<Vec<User>> {
+        let mut users: Vec<User> = self.store().users.iter().map(|u| u.user.clone()).collect();
+        users.sort_by_key(|u| u.id);
+        Ok(users)
+    }
+
+    async fn get_password_hash(&self, user_id: i32) -> DatabaseResult<Option<String>> {
+        Ok(self.store().user_mut(user_id)?.password_hash.clone())
+    }
+
+    async fn set_password_hash(&self, user_id: i32, hash: &str) -> DatabaseResult<()> {
+        self.store().user_mut(user_id)?.password_hash = Some(hash.to_string());
+        Ok(())
+    }
+
+    async fn set_public_stats(&self, user_id: i32, public_stats: bool) -> DatabaseResult<()> {
+        self.store().user_mut(user_id)?.user.public_stats = public_stats;
+        Ok(())
+    }
+
+    async fn insert_session(
+        &self,
+        id: &str,
+        user_id: i32,
+        expires_at: DateTime<Utc>,
+    ) -> DatabaseResult<()> {
+        let mut store = self.store();
+        if store.sessions.contains_key(id) {
+            return Err(anyhow!("session `{}` already exists", id).into());
+        }
+        store.sessions.insert(id.to_string(), (user_id, expires_at));
+
+        Ok(())
+    }
+
+    async fn get_session_user(&self, id: &str, now: DateTime<Utc>) -> DatabaseResult<Option<User>> {
+        let mut store = self.store();
+        let (user_id, expires_at) = match store.sessions.get(id) {
+            Some(session) => *session,
+            None => return Ok(None),
+        };
+
+        if expires_at < now {
+            store.sessions.remove(id);
+            return Ok(None);
+        }
+
+        Ok(store
+            .users
+            .iter()
+            .find(|u| u.user.id == user_id)
+            .map(|u| u.user.clone()))
+    }
+
+    async fn delete_session(&self, id: &str) -> DatabaseResult<()> {
+        self.store().sessions.remove(id);
+        Ok(())
+    }
+
+    async fn delete_user_sessions(&self, user_id: i32, except: Option<&str>) -> DatabaseResult<()> {
+        self.store().sessions.retain(|id, (session_user, _)| {
+            *session_user != user_id || Some(id.as_str()) == except
+        });
+        Ok(())
+    }
+
+    async fn insert_scrobble(&self, scrobble: ScrobbleInfo) -> DatabaseResult<()> {
+        let mut store = self.store();
+        let track = scrobble.track;
+        let timestamp = scrobble.timestamp.trunc_subsecs(3);
+
+        // the same play can be reported more than once, e.g. by recently played tracks
+        let exists = store.scrobbles.iter().any(|s| {
+            s.user_id == scrobble.user_id && s.timestamp == timestamp && s.track_id == track.id
+        });
+        if !exists {
+            let id = store.scrobbles.iter().map(|s| s.id).max().unwrap_or(0) + 1;
+            store.scrobbles.push(StoredScrobble {
+                id,
+                user_id: scrobble.user_id,
+                timestamp,
+                origin: scrobble.origin,
+                duration_secs: track.duration_secs.as_secs_f64(),
+                track_id: track.id.clone(),
+            });
+        }
+
+        for tag in track.tags.iter() {
+            store.tags_tracks.insert((tag.id.clone(), track.id.clone()));
+        }
+        for artist in track.artists.iter() {
+            store
+                .artists_tracks
+                .insert((artist.id.clone(), track.id.clone()));
+            store
+                .albums_artists
+                .insert((track.album.id.clone(), artist.id.clone()));
+        }
+        store
+            .albums_tracks
+            .insert((track.album.id.clone(), track.id.clone()));
+
+        Ok(())
+    }
+
+    async fn get_last_scrobble(&self, user_id: i32) -> DatabaseResult<Option<Scrobble>> {
+        let store = self.store();
+        let scrobbles = store.scrobbles.iter().filter(|s| s.user_id == user_id);
+        Ok(store.latest_first(scrobbles).into_iter().next())
+    }
+
+    async fn get_first_scrobble_timestamp(
+        &self,
+        user_id: i32,
+    ) -> DatabaseResult<Option<DateTime<Utc>>> {
+        Ok(self
+            .store()
+            .scrobbles
+            .iter()
+            .filter(|s| s.user_id == user_id)
+            .map(|s| s.timestamp)
+            .min())
+    }
+
+    async fn count_scrobbles(&self, user_id: i32) -> DatabaseResult<u64> {
+        Ok(self
+            .store()
+            .scrobbles
+            .iter()
+            .filter(|s| s.user_id == user_id)
+            .count() as u64)
+    }
+
+    async fn get_nth_scrobble(&self, user_id: i32, n: u64) -> DatabaseResult<Option<Scrobble>> {
+        if n == 0 {
+            return Ok(None);
+        }
+
+        let store = self.store();
+        let mut scrobbles: Vec<&StoredScrobble> = store
+            .scrobbles
+            .iter()
+            .filter(|s| s.user_id == user_id)
+            .collect();
+        scrobbles.sort_by_key(|s| (s.timestamp, s.id));
+
+        Ok(scrobbles
+            .get(n as usize - 1)
+            .and_then(|scrobble| store.scrobble(scrobble)))
+    }
+
+    async fn get_scrobble(&self, id: i32) -> DatabaseResult<Option<Scrobble>> {
+        let store = self.store();
+        Ok(store
+            .scrobbles
+            .iter()
+            .find(|s| s.id == id)
+            .and_then(|scrobble| store.scrobble(scrobble)))
+    }
+
+    async fn update_scrobble(&self, id: i32, scrobble: ScrobbleEdit) -> DatabaseResult<()> {
+        let mut store = self.store();
+        let index = store.scrobble_index(id)?;
+        if !store.tracks.contains_key(&scrobble.track_id) {
+            return Err(anyhow!("track `{}` not found", scrobble.track_id).into());
+        }
+
+        let before = &mut store.scrobbles[index];
+        let details = describe_changes(vec![
+            (
+                "timestamp",
+                before.timestamp.to_string(),
+                scrobble.timestamp.to_string(),
+            ),
+            (
+                "track_id",
+                before.track_id.clone(),
+                scrobble.track_id.clone(),
+            ),
+            (
+                "duration_secs",
+                before.duration_secs.to_string(),
+                scrobble.duration_secs.to_string(),
+            ),
+        ]);
+        before.timestamp = scrobble.timestamp.trunc_subsecs(3);
+        before.track_id = scrobble.track_id;
+        before.duration_secs = scrobble.duration_secs;
+
+        store.audit(
+            self.clock.now(),
+            "scrobble",
+            &id.to_string(),
+            "update",
+            details,
+        );
+
+        Ok(())
+    }
+
+    async fn delete_scrobble(&self, id: i32) -> DatabaseResult<()> {
+        let mut store = self.store();
+        let index = store.scrobble_index(id)?;
+        let before = store.scrobbles.remove(index);
+
+        let details = format!(
+            "deleted scrobble of track `{}` at {}",
+            before.track_id, before.timestamp
+        );
+        store.audit(
+            self.clock.now(),
+            "scrobble",
+            &id.to_string(),
+            "delete",
+            details,
+        );
+
+        Ok(())
+    }
+
+    async fn delete_scrobbles(&self, selection: ScrobbleSelection) -> DatabaseResult<u64> {
+        let mut store = self.store();
+        let scrobbles = store.scrobbles.len();
+        store.scrobbles.retain(|s| !selects(&selection, s));
+        let deleted = (scrobbles - store.scrobbles.len()) as u64;
+
+        let details = format!(
+            "deleted {} scrobbles {}",
+            deleted,
+            describe_selection(&selection)
+        );
+        store.audit(
+            self.clock.now(),
+            "scrobbles",
+            &selection.track_id,
+            "delete",
+            details,
+        );
+
+        Ok(deleted)
+    }
+
+    async fn reassign_scrobbles(
+        &self,
+        selection: ScrobbleSelection,
+        track_id: &str,
+    ) -> DatabaseResult<u64> {
+        let mut store = self.store();
+        if !store.tracks.contains_key(track_id) {
+            return Err(anyhow!("track `{}` not found", track_id).into());
+        }
+
+        let mut updated = 0;
+        for scrobble in store.scrobbles.iter_mut() {
+            if selects(&selection, scrobble) {
+                scrobble.track_id = track_id.to_string();
+                updated += 1;
+            }
+        }
+
+        let details = format!(
+            "moved {} scrobbles {} to track `{}`",
+            updated,
+            describe_selection(&selection),
+            track_id
+        );
+        store.audit(
+            self.clock.now(),
+            "scrobbles",
+            &selection.track_id,
+            "reassign",
+            details,
+        );
+
+        Ok(updated)
+    }
+
+    async fn list_scrobbles_by_date_range(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<Scrobble>> {
+        let store = self.store();
+        let scrobbles = store.scrobbles_in_range(&opts);
+        Ok(store.latest_first(scrobbles.into_iter()))
+    }
+
+    async fn list_scrobbles_by_tag(&self, tag: &str) -> DatabaseResult<Vec<Scrobble>> {
+        let store = self.store();
+        let scrobbles = store
+            .scrobbles
+            .iter()
+            .filter(|s| store.track_tags(&s.track_id).iter().any(|t| t == tag));
+        Ok(store.latest_first(scrobbles))
+    }
+
+    async fn list_play_times(&self, opts: ParamsForStatsQuery) -> DatabaseResult<Vec<PlayTime>> {
+        Ok(self
+            .store()
+            .scrobbles_in_range(&opts)
+            .into_iter()
+            .map(|s| PlayTime {
+                timestamp: s.timestamp,
+                duration_secs: s.duration_secs,
+            })
+            .collect())
+    }
+
+    async fn list_artist_play_times(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<EntityPlayTime>> {
+        let store = self.store();
+        Ok(store
+            .scrobbles_in_range(&opts)
+            .into_iter()
+            .flat_map(|s| {
+                store
+                    .track_artists(&s.track_id)
+                    .into_iter()
+                    .map(move |artist| EntityPlayTime {
+                        id: artist.id.clone(),
+                        name: artist.name.clone(),
+                        timestamp: s.timestamp,
+                    })
+            })
+            .collect())
+    }
+
+    async fn list_track_play_times(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<EntityPlayTime>> {
+        let store = self.store();
+        Ok(store
+            .scrobbles_in_range(&opts)
+            .into_iter()
+            .filter_map(|s| {
+                store.tracks.get(&s.track_id).map(|track| EntityPlayTime {
+                    id: track.id.clone(),
+                    name: track.title.clone(),
+                    timestamp: s.timestamp,
+                })
+            })
+            .collect())
+    }
+
+    async fn list_listening_days(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<NaiveDate>> {
+        let tz = opts.time_zone;
+        let mut days: Vec<NaiveDate> = self
+            .list_play_times(opts)
+            .await?
+            .iter()
+            .map(|t| t.timestamp.with_timezone(&tz).date_naive())
+            .collect();
+        days.dedup();
+        Ok(days)
+    }
+
+    async fn list_artist_listening_days(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<EntityDay>> {
+        let tz = opts.time_zone;
+        Ok(entity_days(self.list_artist_play_times(opts).await?, tz))
+    }
+
+    async fn list_track_listening_days(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<EntityDay>> {
+        let tz = opts.time_zone;
+        Ok(entity_days(self.list_track_play_times(opts).await?, tz))
+    }
+
+    async fn list_tag_play_times(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<EntityPlayTime>> {
+        let store = self.store();
+        Ok(store
+            .scrobbles_in_range(&opts)
+            .into_iter()
+            .flat_map(|s| {
+                store
+                    .tags_tracks
+                    .iter()
+                    .filter(move |(_, track)| *track == s.track_id)
+                    .map(move |(tag, _)| EntityPlayTime {
+                        id: tag.clone(),
+                        name: tag.clone(),
+                        timestamp: s.timestamp,
+                    })
+            })
+            .collect())
+    }
+
+    async fn list_first_plays(
+        &self,
+        user_id: i32,
+        limit: u64,
+    ) -> DatabaseResult<Vec<EntityPlayTime>> {
+        let store = self.store();
+        let mut first_plays = BTreeMap::<&str, EntityPlayTime>::new();
+        for scrobble in store.scrobbles.iter().filter(|s| s.user_id == user_id) {
+            for artist in store.track_artists(&scrobble.track_id) {
+                let play =
+                    first_plays
+                        .entry(artist.id.as_str())
+                        .or_insert_with(|| EntityPlayTime {
+                            id: artist.id.clone(),
+                            name: artist.name.clone(),
+                            timestamp: scrobble.timestamp,
+                        });
+                play.timestamp = play.timestamp.min(scrobble.timestamp);
+            }
+        }
+
+        let mut first_plays: Vec<EntityPlayTime> = first_plays.into_values().collect();
+        first_plays.sort_by_key(|play| Reverse(play.timestamp));
+        first_plays.truncate(limit as usize);
+        Ok(first_plays)
+    }
+
+    async fn list_scrobbles_by_artist(&self, artist_id: &str) -> DatabaseResult<Vec<Scrobble>> {
+        let store = self.store();
+        let scrobbles = store.scrobbles.iter().filter(|s| {
+            store
+                .track_artists(&s.track_id)
+                .iter()
+                .any(|artist| artist.id == artist_id)
+        });
+        Ok(store.latest_first(scrobbles))
+    }
+
+    async fn insert_episode_scrobble(&self, scrobble: EpisodeScrobbleInfo) -> DatabaseResult<()> {
+        self.store().episode_scrobbles.push(StoredEpisodeScrobble {
+            user_id: scrobble.user_id,
+            timestamp: scrobble.timestamp.trunc_subsecs(3),
+            duration_secs: scrobble.duration_secs,
+            episode_id: scrobble.episode.id,
+        });
+        Ok(())
+    }
+
+    async fn insert_skipped_play(&self, play: SkippedPlay) -> DatabaseResult<()> {
+        let mut store = self.store();
+        let timestamp = play.timestamp.trunc_subsecs(3);
+        let exists = store.skipped_plays.iter().any(|p| {
+            p.user_id == play.user_id && p.timestamp == timestamp && p.track_id == play.track_id
+        });
+        if !exists {
+            store.skipped_plays.push(SkippedPlay { timestamp, ..play });
+        }
+        Ok(())
+    }
+
+    async fn list_skipped_plays(
+        &self,
+        user_id: i32,
+        since: DateTime<Utc>,
+    ) -> DatabaseResult<Vec<SkippedPlay>> {
+        let mut plays: Vec<SkippedPlay> = self
+            .store()
+            .skipped_plays
+            .iter()
+            .filter(|p| p.user_id == user_id && p.timestamp >= since)
+            .cloned()
+            .collect();
+        plays.sort_by_key(|p| p.timestamp);
+        Ok(plays)
+    }
+
+    async fn delete_skipped_plays(
+        &self,
+        user_id: i32,
+        before: DateTime<Utc>,
+    ) -> DatabaseResult<()> {
+        self.store()
+            .skipped_plays
+            .retain(|p| p.user_id != user_id || p.timestamp >= before);
+        Ok(())
+    }
+
+    async fn insert_rewrite_rule(&self, rule: RewriteRule) -> DatabaseResult<()> {
+        let mut store = self.store();
+        let id = store
+            .rewrite_rules
+            .iter()
+            .filter_map(|r| r.id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        store.rewrite_rules.push(RewriteRule {
+            id: Some(id),
+            ..rule
+        });
+
+        Ok(())
+    }
+
+    async fn delete_rewrite_rule(&self, id: i32) -> DatabaseResult<()> {
+        self.store().rewrite_rules.retain(|r| r.id != Some(id));
+        Ok(())
+    }
+
+    async fn list_rewrite_rules(&self) -> DatabaseResult<Vec<RewriteRule>> {
+        Ok(self.store().rewrite_rules.clone())
+    }
+
+    async fn list_entity_names(
+        &self,
+        field: RewriteField,
+        origin: Option<String>,
+    ) -> DatabaseResult<Vec<EntityName>> {
+        let store = self.store();
+        let scrobbles_of = |track_id: &str| {
+            store
+                .scrobbles
+                .iter()
+                .filter(|s| s.track_id == track_id)
+                .filter(|s| origin.as_ref().is_none_or(|origin| s.origin == *origin))
+                .count() as u32
+        };
+
+        let mut names: Vec<EntityName> = match field {
+            RewriteField::Title => store
+                .tracks
+                .values()
+                .map(|track| EntityName {
+                    id: track.id.clone(),
+                    name: track.title.clone(),
+                    scrobbles: scrobbles_of(&track.id),
+                })
+                .collect(),
+            RewriteField::Artist => store
+                .artists
+                .values()
+                .map(|artist| EntityName {
+                    id: artist.id.clone(),
+                    name: artist.name.clone(),
+                    scrobbles: linked_tracks(&store.artists_tracks, &artist.id)
+                        .map(&scrobbles_of)
+                        .sum(),
+                })
+                .collect(),
+            RewriteField::Album => store
+                .albums
+                .values()
+                .map(|album| EntityName {
+                    id: album.id.clone(),
+                    name: album.title.clone(),
+                    scrobbles: linked_tracks(&store.albums_tracks, &album.id)
+                        .map(&scrobbles_of)
+                        .sum(),
+                })
+                .collect(),
+        };
+
+        names.retain(|name| name.scrobbles > 0);
+        names.sort_by_key(|name| Reverse(name.scrobbles));
+        Ok(names)
+    }
+
+    async fn rename_entity(&self, field: RewriteField, id: &str, name: &str) -> DatabaseResult<()> {
+        let mut store = self.store();
+        let entity = match field {
+            RewriteField::Title => {
+                if let Some(track) = store.tracks.get_mut(id) {
+                    track.title = name.to_string();
+                }
+                "track"
+            }
+            RewriteField::Artist => {
+                if let Some(artist) = store.artists.get_mut(id) {
+                    artist.name = name.to_string();
+                }
+                "artist"
+            }
+            RewriteField::Album => {
+                if let Some(album) = store.albums.get_mut(id) {
+                    album.title = name.to_string();
+                }
+                "album"
+            }
+        };
+
+        let details = format!("{} renamed to {:?} by a rewrite rule", field.as_str(), name);
+        store.audit(self.clock.now(), entity, id, "rewrite", details);
+
+        Ok(())
+    }
+
+    async fn list_duplicates(&self, field: RewriteField) -> DatabaseResult<Vec<DuplicateGroup>> {
+        let store = self.store();
+        let scrobbles_of = |track_id: &str| {
+            store
+                .scrobbles
+                .iter()
+                .filter(|s| s.track_id == track_id)
+                .count() as u32
+        };
+        let candidates = match field {
+            RewriteField::Title => store
+                .tracks
+                .values()
+                .map(|track| DuplicateCandidate {
+                    id: track.id.clone(),
+                    name: track.title.clone(),
+                    isrc: Some(track.isrc.clone()),
+                    artists: distinct_names(store.track_artists(&track.id)),
+                    scrobbles: scrobbles_of(&track.id),
+                })
+                .collect(),
+            RewriteField::Artist => store
+                .artists
+                .values()
+                .map(|artist| DuplicateCandidate {
+                    id: artist.id.clone(),
+                    name: artist.name.clone(),
+                    isrc: None,
+                    artists: vec![],
+                    scrobbles: linked_tracks(&store.artists_tracks, &artist.id)
+                        .map(&scrobbles_of)
+                        .sum(),
+                })
+                .collect(),
+            RewriteField::Album => store
+                .albums
+                .values()
+                .map(|album| DuplicateCandidate {
+                    id: album.id.clone(),
+                    name: album.title.clone(),
+                    isrc: None,
+                    artists: distinct_names(store.album_artists(&album.id)),
+                    scrobbles: linked_tracks(&store.albums_tracks, &album.id)
+                        .map(&scrobbles_of)
+                        .sum(),
+                })
+                .collect(),
+        };
+
+        Ok(group_duplicates(field, candidates))
+    }
+
+    async fn merge_entities(
+        &self,
+        field: RewriteField,
+        canonical_id: &str,
+        duplicate_ids: Vec<String>,
+    ) -> DatabaseResult<()> {
+        let duplicate_ids: Vec<String> = duplicate_ids
+            .into_iter()
+            .filter(|id| id != canonical_id)
+            .collect();
+        if duplicate_ids.is_empty() {
+            return Err(anyhow!("nothing to merge into `{}`", canonical_id).into());
+        }
+
+        let entity = entity_kind(field);
+        let mut store = self.store();
+        for id in std::iter::once(canonical_id).chain(duplicate_ids.iter().map(|id| id.as_str())) {
+            if !store.entity_exists(field, id) {
+                return Err(anyhow!("{} `{}` not found", entity, id).into());
+            }
+        }
+
+        let store = &mut *store;
+        for id in duplicate_ids.iter() {
+            let details = match field {
+                RewriteField::Title => {
+                    let mut moved = 0;
+                    for scrobble in store.scrobbles.iter_mut() {
+                        if scrobble.track_id == *id {
+                            scrobble.track_id = canonical_id.to_string();
+                            moved += 1;
+                        }
+                    }
+                    repoint_second(&mut store.artists_tracks, id, canonical_id);
+                    repoint_second(&mut store.albums_tracks, id, canonical_id);
+                    repoint_second(&mut store.tags_tracks, id, canonical_id);
+                    store.tracks.remove(id);
+
+                    format!("{} scrobbles moved", moved)
+                }
+                RewriteField::Artist => {
+                    let moved = repoint_first(&mut store.artists_tracks, id, canonical_id);
+                    repoint_second(&mut store.albums_artists, id, canonical_id);
+                    store.artists.remove(id);
+
+                    format!("{} tracks moved", moved)
+                }
+                RewriteField::Album => {
+                    let moved = repoint_first(&mut store.albums_tracks, id, canonical_id);
+                    repoint_first(&mut store.albums_artists, id, canonical_id);
+                    store.albums.remove(id);
+
+                    format!("{} tracks moved", moved)
+                }
+            };
+
+            // past weekly charts rank the canonical entity in place of the duplicate
+            for chart in store
+                .charts
+                .iter_mut()
+                .filter(|c| c.kind == chart_kind(field))
+            {
+                for item in chart.snapshot.items.iter_mut() {
+                    if item.id == *id {
+                        item.id = canonical_id.to_string();
+                    }
+                }
+            }
+
+            // entities previously merged into the duplicate follow it
+            for (key, canonical) in store.merged_ids.iter_mut() {
+                if key.0 == entity && canonical == id {
+                    *canonical = canonical_id.to_string();
+                }
+            }
+            store
+                .merged_ids
+                .insert((entity, id.clone()), canonical_id.to_string());
+
+            let details = format!("merged into `{}`, {}", canonical_id, details);
+            store.audit(self.clock.now(), entity, id, "merge", details);
+        }
+
+        Ok(())
+    }
+
+    async fn resolve_merged_id(&self, field: RewriteField, id: &str) -> DatabaseResult<String> {
+        Ok(self
+            .store()
+            .merged_ids
+            .get(&(entity_kind(field), id.to_string()))
+            .cloned()
+            .unwrap_or_else(|| id.to_string()))
+    }
+
+    async fn list_audit_log(&self, limit: u64) -> DatabaseResult<Vec<AuditEntry>> {
+        let mut entries = self.store().audit_log.clone();
+        entries.sort_by_key(|entry| Reverse(entry.id));
+        entries.truncate(limit as usize);
+        Ok(entries)
+    }
+
+    async fn stats_for_popular_tags(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsTag>> {
+        let store = self.store();
+        let scrobbles = store.scrobbles_in_range(&opts);
+        let mut plays = BTreeMap::<String, u32>::new();
+        for scrobble in scrobbles.iter() {
+            for tag in store.track_tags(&scrobble.track_id) {
+                *plays.entry(tag).or_default() += 1;
+            }
+        }
+
+        // a genre scores its share of the scrobbles, in percent
+        let mut tags: Vec<(String, u32)> = plays.into_iter().collect();
+        tags.sort_by_key(|tag| Reverse(tag.1));
+        Ok(tags
+            .into_iter()
+            .take(limit(&opts))
+            .map(|(name, plays)| StatsTag {
+                name,
+                score: plays * 100 / scrobbles.len() as u32,
+            })
+            .collect())
+    }
+
+    async fn stats_for_popular_tracks(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsTrack>> {
+        let store = self.store();
+        let mut tracks = BTreeMap::<&str, StatsTrack>::new();
+        for scrobble in store.scrobbles_in_range(&opts) {
+            let track = match store.tracks.get(&scrobble.track_id) {
+                Some(track) => track,
+                None => continue,
+            };
+            let album = match store.album_of(&track.id) {
+                Some(album) => album,
+                None => continue,
+            };
+
+            let stats = tracks
+                .entry(track.id.as_str())
+                .or_insert_with(|| StatsTrack {
+                    id: track.id.clone(),
+                    title: track.title.clone(),
+                    score: 0,
+                    listened_secs: 0.0,
+                    cover: album.cover.clone(),
+                    artists: json_names(store.track_artists(&track.id)),
+                });
+            stats.score += 1;
+            stats.listened_secs += scrobble.duration_secs;
+        }
+
+        let mut tracks: Vec<StatsTrack> = tracks.into_values().collect();
+        tracks.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| b.listened_secs.total_cmp(&a.listened_secs))
+        });
+        tracks.truncate(limit(&opts));
+        Ok(tracks)
+    }
+
+    async fn stats_for_popular_artists(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsArtist>> {
+        let store = self.store();
+        let scrobbles = store.scrobbles_in_range(&opts);
+        Ok(rank_artists(&store, scrobbles, |_| true, limit(&opts)))
+    }
+
+    async fn stats_for_popular_shows(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsShow>> {
+        let store = self.store();
+        let (start, end) = build_dates_range(&opts);
+        let mut shows = BTreeMap::<&str, (StatsShow, BTreeSet<&str>)>::new();
+        for scrobble in store.episode_scrobbles.iter() {
+            if scrobble.timestamp < start
+                || scrobble.timestamp >= end
+                || opts
+                    .user_id
+                    .is_some_and(|user_id| scrobble.user_id != user_id)
+            {
+                continue;
+            }
+            let episode = match store.episodes.get(&scrobble.episode_id) {
+                Some(episode) => episode,
+                None => continue,
+            };
+            let show = match store.shows.get(&episode.show_id) {
+                Some(show) => show,
+                None => continue,
+            };
+
+            let (stats, episodes) = shows.entry(show.id.as_str()).or_insert_with(|| {
+                let stats = StatsShow {
+                    id: show.id.clone(),
+                    name: show.name.clone(),
+                    publisher: show.publisher.clone(),
+                    cover: show.cover.clone(),
+                    score: 0,
+                    episodes: 0,
+                    listened_secs: 0.0,
+                };
+                (stats, BTreeSet::new())
+            });
+            episodes.insert(episode.id.as_str());
+            stats.episodes = episodes.len() as u32;
+            stats.score += 1;
+            stats.listened_secs += scrobble.duration_secs;
+        }
+
+        let mut shows: Vec<StatsShow> = shows.into_values().map(|(stats, _)| stats).collect();
+        shows.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| b.listened_secs.total_cmp(&a.listened_secs))
+        });
+        shows.truncate(limit(&opts));
+        Ok(shows)
+    }
+
+    async fn stats_for_popular_albums(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsAlbum>> {
+        let store = self.store();
+        let mut albums = BTreeMap::<&str, (StatsAlbum, BTreeSet<&str>)>::new();
+        for scrobble in store.scrobbles_in_range(&opts) {
+            let album = match store.album_of(&scrobble.track_id) {
+                Some(album) => album,
+                None => continue,
+            };
+
+            let (stats, tracks) = albums.entry(album.id.as_str()).or_insert_with(|| {
+                let stats = StatsAlbum {
+                    id: album.id.clone(),
+                    title: album.title.clone(),
+                    artists: json_names(store.album_artists(&album.id)),
+                    cover: album.cover.clone(),
+                    score: 0,
+                    tracks: 0,
+                };
+                (stats, BTreeSet::new())
+            });
+            tracks.insert(scrobble.track_id.as_str());
+            stats.tracks = tracks.len() as u32;
+            stats.score += 1;
+        }
+
+        let mut albums: Vec<StatsAlbum> = albums.into_values().map(|(stats, _)| stats).collect();
+        albums.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| b.tracks.cmp(&a.tracks)));
+        albums.truncate(limit(&opts));
+        Ok(albums)
+    }
+
+    async fn stats_for_new_artists(
+        &self,
+        opts: ParamsForStatsQuery,
+    ) -> DatabaseResult<Vec<StatsArtist>> {
+        let store = self.store();
+        let (start, end) = build_dates_range(&opts);
+
+        // when the artists were played for the first time, before the range too
+        let mut first_plays = HashMap::<&str, DateTime<Utc>>::new();
+        for scrobble in store
+            .scrobbles
+            .iter()
+            .filter(|s| opts.user_id.is_none_or(|user_id| s.user_id == user_id))
+        {
+            for (artist, _) in store
+                .artists_tracks
+                .iter()
+                .filter(|(_, track)| *track == scrobble.track_id)
+            {
+                let first = first_plays
+                    .entry(artist.as_str())
+                    .or_insert(scrobble.timestamp);
+                *first = (*first).min(scrobble.timestamp);
+            }
+        }
+
+        let scrobbles = store.scrobbles_in_range(&opts);
+        let is_new = |artist: &Artist| {
+            first_plays
+                .get(artist.id.as_str())
+                .is_some_and(|first| *first >= start && *first < end)
+        };
+        Ok(rank_artists(&store, scrobbles, is_new, limit(&opts)))
+    }
+
+    async fn list_chart_snapshots(
+        &self,
+        user_id: i32,
+        kind: ChartKind,
+        until: NaiveDate,
+    ) -> DatabaseResult<Vec<ChartSnapshot>> {
+        let mut snapshots: Vec<ChartSnapshot> = self
+            .store()
+            .charts
+            .iter()
+            .filter(|c| c.user_id == user_id && c.kind == kind)
+            .filter(|c| c.snapshot.week_start <= until)
+            .map(|c| c.snapshot.clone())
+            .collect();
+        snapshots.sort_by_key(|s| s.week_start);
+        Ok(snapshots)
+    }
+
+    async fn insert_chart_snapshot(
+        &self,
+        user_id: i32,
+        kind: ChartKind,
+        snapshot: ChartSnapshot,
+    ) -> DatabaseResult<()> {
+        let mut store = self.store();
+        if store.charts.iter().any(|c| {
+            c.user_id == user_id && c.kind == kind && c.snapshot.week_start == snapshot.week_start
+        }) {
+            return Err(anyhow!(
+                "the {} chart of {} is already stored",
+                kind.as_str(),
+                snapshot.week_start
+            )
+            .into());
+        }
+
+        store.charts.push(StoredChart {
+            user_id,
+            kind,
+            snapshot,
+        });
+        Ok(())
+    }
+}
+
+/// Ranks the artists of the given scrobbles, among the ones accepted, by scrobbles and
+/// then by tracks.
+fn rank_artists<F>(
+    store: &Store,
+    scrobbles: Vec<&StoredScrobble>,
+    accepts: F,
+    limit: usize,
+) -> Vec<StatsArtist>
+where
+    F: Fn(&Artist) -> bool,
+{
+    let mut artists = BTreeMap::<&str, (StatsArtist, BTreeSet<&str>)>::new();
+    for scrobble in scrobbles {
+        for artist in store.track_artists(&scrobble.track_id) {
+            if !accepts(artist) {
+                continue;
+            }
+
+            let (stats, tracks) = artists.entry(artist.id.as_str()).or_insert_with(|| {
+                let stats = StatsArtist {
+                    id: artist.id.clone(),
+                    name: artist.name.clone(),
+                    score: 0,
+                    tracks: 0,
+                };
+                (stats, BTreeSet::new())
+            });
+            tracks.insert(scrobble.track_id.as_str());
+            stats.tracks = tracks.len() as u32;
+            stats.score += 1;
+        }
+    }
+
+    let mut artists: Vec<StatsArtist> = artists.into_values().map(|(stats, _)| stats).collect();
+    artists.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| b.tracks.cmp(&a.tracks)));
+    artists.truncate(limit);
+    artists
+}
+
+/// The range covers both days entirely, in the time zone of the query: from its start
+/// included to its end excluded.
+fn build_dates_range(opts: &ParamsForStatsQuery) -> (DateTime<Utc>, DateTime<Utc>) {
+    (
+        start_of_day(opts.start, opts.time_zone),
+        start_of_next_day(opts.end, opts.time_zone),
+    )
+}
+
+/// The local days of plays, oldest first, each day of an entity once.
+fn entity_days(plays: Vec<EntityPlayTime>, tz: Tz) -> Vec<EntityDay> {
+    let mut days: Vec<EntityDay> = plays
+        .into_iter()
+        .map(|play| EntityDay {
+            day: play.timestamp.with_timezone(&tz).date_naive(),
+            id: play.id,
+            name: play.name,
+        })
+        .collect();
+    days.sort_by(|a, b| a.day.cmp(&b.day).then_with(|| a.id.cmp(&b.id)));
+    days.dedup();
+    days
+}
+
+fn limit(opts: &ParamsForStatsQuery) -> usize {
+    opts.limit.unwrap_or(DEFAULT_STATS_LIMIT) as usize
+}
+
+fn entity_kind(field: RewriteField) -> &'static str {
+    match field {
+        RewriteField::Title => "track",
+        RewriteField::Artist => "artist",
+        RewriteField::Album => "album",
+    }
+}
+
+fn chart_kind(field: RewriteField) -> ChartKind {
+    match field {
+        RewriteField::Title => ChartKind::Tracks,
+        RewriteField::Artist => ChartKind::Artists,
+        RewriteField::Album => ChartKind::Albums,
+    }
+}
+
+fn distinct_names(artists: Vec<&Artist>) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    for artist in artists {
+        if !names.contains(&artist.name) {
+            names.push(artist.name.clone());
+        }
+    }
+    names
+}
+
+/// The names as a JSON array, like the stats queries give them.
+fn json_names(artists: Vec<&Artist>) -> String {
+    serde_json::to_string(&distinct_names(artists)).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// The tracks linked to an entity, the first of each link.
+fn linked_tracks<'a>(
+    links: &'a BTreeSet<(String, String)>,
+    id: &'a str,
+) -> impl Iterator<Item = &'a str> {
+    links
+        .iter()
+        .filter(move |(entity, _)| entity == id)
+        .map(|(_, track)| track.as_str())
+}
+
+/// Moves the links of a duplicate, first of each pair, to the canonical entity, skipping
+/// the ones it already has. Returns the number of links moved.
+fn repoint_first(links: &mut BTreeSet<(String, String)>, id: &str, canonical_id: &str) -> u64 {
+    let moved: Vec<(String, String)> = links.iter().filter(|(a, _)| a == id).cloned().collect();
+    for (a, b) in moved.iter() {
+        links.remove(&(a.clone(), b.clone()));
+        links.insert((canonical_id.to_string(), b.clone()));
+    }
+    moved.len() as u64
+}
+
+/// Like `repoint_first`, for links whose second element is the duplicate.
+fn repoint_second(links: &mut BTreeSet<(String, String)>, id: &str, canonical_id: &str) -> u64 {
+    let moved: Vec<(String, String)> = links.iter().filter(|(_, b)| b == id).cloned().collect();
+    for (a, b) in moved.iter() {
+        links.remove(&(a.clone(), b.clone()));
+        links.insert((a.clone(), canonical_id.to_string()));
+    }
+    moved.len() as u64
+}
+
+fn selects(selection: &ScrobbleSelection, scrobble: &StoredScrobble) -> bool {
+    scrobble.track_id == selection.track_id
+        && selection
+            .start
+            .is_none_or(|start| scrobble.timestamp >= start.trunc_subsecs(3))
+        && selection
+            .end
+            .is_none_or(|end| scrobble.timestamp <= end.trunc_subsecs(3))
+}
+
+/// Lists the fields whose values differ, e.g. `title: "A" -> "B"`.
+fn describe_changes(changes: Vec<(&str, String, String)>) -> String {
+    changes
+        .into_iter()
+        .filter(|(_, before, after)| before != after)
+        .map(|(field, before, after)| format!("{}: {:?} -> {:?}", field, before, after))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn describe_selection(selection: &ScrobbleSelection) -> String {
+    let mut description = format!("of track `{}`", selection.track_id);
+    if let Some(start) = selection.start {
+        description.push_str(&format!(" from {}", start));
+    }
+    if let Some(end) = selection.end {
+        description.push_str(&format!(" to {}", end));
+    }
+    description
+}
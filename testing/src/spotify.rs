@@ -0,0 +1,176 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use scrobblify_domain::{
+    bridge::spotify::{SpotifyApi, SpotifyConnector},
+    db::TokenStore,
+    models::{CurrentPlayingTrack, HistoryPlayedTrack, Tag},
+};
+
+/// What Spotify would answer, set by the tests.
+#[derive(Default)]
+struct Playback {
+    playing: HashMap<i32, CurrentPlayingTrack>,
+    /// Latest first, like Spotify lists them.
+    recently_played: HashMap<i32, Vec<HistoryPlayedTrack>>,
+    tags: HashMap<String, Vec<Tag>>,
+    offline: bool,
+}
+
+/// Plays what the tests tell it to, for every user. Users are linked as long as the token
+/// store has a token for them, which authorizing with any code gives them.
+#[derive(Clone, Default)]
+pub struct FakeSpotify {
+    playback: Arc<Mutex<Playback>>,
+}
+
+impl FakeSpotify {
+    pub fn new() -> FakeSpotify {
+        FakeSpotify::default()
+    }
+
+    pub fn play(&self, user_id: i32, current: CurrentPlayingTrack) {
+        self.playback
+            .lock()
+            .unwrap()
+            .playing
+            .insert(user_id, current);
+    }
+
+    pub fn stop(&self, user_id: i32) {
+        self.playback.lock().unwrap().playing.remove(&user_id);
+    }
+
+    /// The plays of a user, in any order.
+    pub fn set_recently_played(&self, user_id: i32, mut played: Vec<HistoryPlayedTrack>) {
+        played.sort_by_key(|play| Reverse(play.played_at));
+        self.playback
+            .lock()
+            .unwrap()
+            .recently_played
+            .insert(user_id, played);
+    }
+
+    /// The genres of an artist profile.
+    pub fn set_tags(&self, artist_id: &str, tags: Vec<Tag>) {
+        self.playback
+            .lock()
+            .unwrap()
+            .tags
+            .insert(artist_id.to_string(), tags);
+    }
+
+    /// Makes every request fail, as if Spotify couldn't be reached.
+    pub fn set_offline(&self, offline: bool) {
+        self.playback.lock().unwrap().offline = offline;
+    }
+}
+
+#[async_trait::async_trait]
+impl SpotifyConnector for FakeSpotify {
+    async fn for_user(
+        &self,
+        user_id: i32,
+        store: Arc<dyn TokenStore>,
+    ) -> Result<Box<dyn SpotifyApi>> {
+        Ok(Box::new(FakeSpotifyClient {
+            user_id,
+            store,
+            state: format!("state-{}", user_id),
+            playback: self.playback.clone(),
+        }))
+    }
+}
+
+/// The client of a single user, built by `FakeSpotify`.
+pub struct FakeSpotifyClient {
+    user_id: i32,
+    store: Arc<dyn TokenStore>,
+    state: String,
+    playback: Arc<Mutex<Playback>>,
+}
+
+impl FakeSpotifyClient {
+    fn ensure_online(&self) -> Result<()> {
+        if self.playback.lock().unwrap().offline {
+            return Err(anyhow!("spotify is offline"));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl SpotifyApi for FakeSpotifyClient {
+    async fn has_auth(&self) -> bool {
+        matches!(self.store.load_token(self.user_id).await, Ok(Some(_)))
+    }
+
+    fn get_auth_state(&self) -> &str {
+        &self.state
+    }
+
+    async fn get_auth_url(&self) -> Result<String> {
+        Ok(format!(
+            "https://accounts.spotify.test/authorize?state={}",
+            self.state
+        ))
+    }
+
+    async fn get_auth_token(&mut self, code: &str) -> Result<()> {
+        self.ensure_online()?;
+        self.store.save_token(self.user_id, code).await
+    }
+
+    async fn get_currently_playing(&self) -> Result<Option<CurrentPlayingTrack>> {
+        self.ensure_online()?;
+        Ok(self
+            .playback
+            .lock()
+            .unwrap()
+            .playing
+            .get(&self.user_id)
+            .cloned())
+    }
+
+    async fn get_recently_played(
+        &self,
+        timestamp: DateTime<Utc>,
+    ) -> Result<Vec<HistoryPlayedTrack>> {
+        self.ensure_online()?;
+        Ok(self
+            .playback
+            .lock()
+            .unwrap()
+            .recently_played
+            .get(&self.user_id)
+            .map(|played| {
+                played
+                    .iter()
+                    .filter(|p| p.played_at > timestamp)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn get_tags(&self, artists_ids: Vec<&str>) -> Result<Vec<Tag>> {
+        self.ensure_online()?;
+        let playback = self.playback.lock().unwrap();
+        let mut tags: Vec<Tag> = artists_ids
+            .into_iter()
+            .filter_map(|id| playback.tags.get(id))
+            .flatten()
+            .cloned()
+            .collect();
+
+        tags.sort();
+        tags.dedup();
+
+        Ok(tags)
+    }
+}
@@ -0,0 +1,39 @@
+use anyhow::Result;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use scrobblify_domain::db::TokenStore;
+
+/// Keeps the tokens in memory, as they're given. Clones share the same tokens.
+#[derive(Clone, Default)]
+pub struct MemoryTokenStore {
+    tokens: Arc<Mutex<HashMap<i32, String>>>,
+}
+
+impl MemoryTokenStore {
+    pub fn new() -> MemoryTokenStore {
+        MemoryTokenStore::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl TokenStore for MemoryTokenStore {
+    async fn load_token(&self, user_id: i32) -> Result<Option<String>> {
+        Ok(self.tokens.lock().unwrap().get(&user_id).cloned())
+    }
+
+    async fn save_token(&self, user_id: i32, token: &str) -> Result<()> {
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(user_id, token.to_string());
+        Ok(())
+    }
+
+    async fn delete_token(&self, user_id: i32) -> Result<()> {
+        self.tokens.lock().unwrap().remove(&user_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,709 @@
+use askama::Template;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware,
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form, Router,
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Deserialize;
+use std::{collections::HashMap, time::Duration as StdDuration};
+use tower_http::set_header::SetResponseHeaderLayer;
+
+use scrobblify_domain::{
+    db::ParamsForStatsQuery,
+    models::{
+        Album, Artist, AuditEntry, DuplicateGroup, EntityName, RewriteField, RewritePreview,
+        RewriteRule, Scrobble, ScrobbleEdit, ScrobbleSelection, Track, User,
+    },
+    time::{start_of_day, start_of_next_day, Tz},
+};
+
+use crate::{
+    auth,
+    http_ui::{error_response, request_time_zone, App, HtmlTemplate, TimeZoneParams},
+    utils::format_local_time,
+};
+
+const AUDIT_LOG_LIMIT: u64 = 200;
+const ENTITIES_LIMIT: usize = 200;
+
+/// Routes to edit the scrobbles database and manage users, restricted to admins.
+pub(crate) fn router(app: App) -> Router<App> {
+    Router::with_state(app)
+        .route("/admin", get(|| async { Redirect::to("/admin/scrobbles") }))
+        .route("/admin/scrobbles", get(scrobbles_handler))
+        .route("/admin/scrobbles/bulk", post(bulk_scrobbles_handler))
+        .route("/admin/scrobbles/:id/edit", get(edit_scrobble_handler))
+        .route("/admin/scrobbles/:id", post(update_scrobble_handler))
+        .route("/admin/scrobbles/:id/delete", post(delete_scrobble_handler))
+        .route("/admin/entities/:kind", get(entities_handler))
+        .route("/admin/entities/:kind/:id/edit", get(edit_entity_handler))
+        .route("/admin/entities/:kind/:id", post(update_entity_handler))
+        .route(
+            "/admin/entities/:kind/:id/delete",
+            post(delete_entity_handler),
+        )
+        .route("/admin/duplicates/:kind", get(duplicates_handler))
+        .route("/admin/duplicates/:kind/merge", post(merge_handler))
+        .route("/admin/audit", get(audit_handler))
+        .route("/admin/users", get(users_handler).post(create_user_handler))
+        .route("/admin/users/:id/password", post(user_password_handler))
+        .route("/admin/rules", get(rules_handler).post(create_rule_handler))
+        .route("/admin/rules/preview", post(preview_rule_handler))
+        .route("/admin/rules/:id/delete", post(delete_rule_handler))
+        .route_layer(middleware::from_fn(auth::require_admin))
+        .layer(SetResponseHeaderLayer::overriding(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static("no-store"),
+        ))
+}
+
+// Scrobbles
+#[derive(Debug, Deserialize)]
+struct ScrobblesParams {
+    start: Option<NaiveDate>,
+    end: Option<NaiveDate>,
+    tz: Option<String>,
+}
+
+async fn scrobbles_handler(
+    Query(params): Query<ScrobblesParams>,
+    State(app): State<App>,
+) -> Response {
+    let tz = match request_time_zone(&app, params.tz.as_deref()).await {
+        Ok(tz) => tz,
+        Err(response) => return response,
+    };
+    let end = match params.end {
+        Some(end) => end,
+        None => app.lock().await.today(tz),
+    };
+    let start = params.start.unwrap_or(end - Duration::days(7));
+    let opts = ParamsForStatsQuery::new(start, end, None).in_time_zone(tz);
+
+    let rows = match app.lock().await.list_scrobbles(opts).await {
+        Ok(scrobbles) => scrobbles
+            .into_iter()
+            .map(|scrobble| ScrobbleRow {
+                played_at: format_local_time(scrobble.timestamp, tz),
+                scrobble,
+            })
+            .collect(),
+        Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err.into()),
+    };
+
+    HtmlTemplate(ScrobblesTemplate {
+        start: start.to_string(),
+        end: end.to_string(),
+        tz: tz.name().to_string(),
+        rows,
+    })
+    .into_response()
+}
+
+async fn edit_scrobble_handler(
+    Path(id): Path<i32>,
+    Query(params): Query<TimeZoneParams>,
+    State(app): State<App>,
+) -> Response {
+    let tz = match request_time_zone(&app, params.tz.as_deref()).await {
+        Ok(tz) => tz,
+        Err(response) => return response,
+    };
+
+    match app.lock().await.get_scrobble(id).await {
+        Ok(Some(scrobble)) => HtmlTemplate(EditScrobbleTemplate {
+            timestamp: scrobble.timestamp.with_timezone(&tz).to_rfc3339(),
+            scrobble,
+        })
+        .into_response(),
+        Ok(None) => error_response(StatusCode::NOT_FOUND, anyhow::anyhow!("scrobble not found")),
+        Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ScrobbleForm {
+    timestamp: String,
+    track_id: String,
+    duration_secs: f64,
+}
+
+async fn update_scrobble_handler(
+    Path(id): Path<i32>,
+    State(app): State<App>,
+    Form(form): Form<ScrobbleForm>,
+) -> Response {
+    let new_timestamp = match DateTime::parse_from_rfc3339(form.timestamp.trim()) {
+        Ok(timestamp) => timestamp.with_timezone(&Utc),
+        Err(err) => return error_response(StatusCode::BAD_REQUEST, err.into()),
+    };
+    if let Err(err) = check_duration_secs(form.duration_secs) {
+        return error_response(StatusCode::BAD_REQUEST, err);
+    }
+
+    let edit = ScrobbleEdit {
+        timestamp: new_timestamp,
+        track_id: form.track_id.trim().to_string(),
+        duration_secs: form.duration_secs,
+    };
+
+    match app.lock().await.update_scrobble(id, edit).await {
+        Ok(()) => Redirect::to("/admin/scrobbles").into_response(),
+        Err(err) => error_response(StatusCode::BAD_REQUEST, err),
+    }
+}
+
+async fn delete_scrobble_handler(Path(id): Path<i32>, State(app): State<App>) -> Response {
+    match app.lock().await.delete_scrobble(id).await {
+        Ok(()) => Redirect::to("/admin/scrobbles").into_response(),
+        Err(err) => error_response(StatusCode::BAD_REQUEST, err),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BulkScrobblesForm {
+    action: String,
+    track_id: String,
+    start: Option<String>,
+    end: Option<String>,
+    target_track_id: Option<String>,
+    tz: Option<String>,
+}
+
+async fn bulk_scrobbles_handler(
+    State(app): State<App>,
+    Form(form): Form<BulkScrobblesForm>,
+) -> Response {
+    let tz = match request_time_zone(&app, form.tz.as_deref()).await {
+        Ok(tz) => tz,
+        Err(response) => return response,
+    };
+    let selection = match parse_selection(&form, tz) {
+        Ok(selection) => selection,
+        Err(err) => return error_response(StatusCode::BAD_REQUEST, err),
+    };
+
+    let app = app.lock().await;
+    let result = match (form.action.as_str(), form.target_track_id.as_deref()) {
+        ("delete", _) => app.delete_scrobbles(selection).await,
+        ("reassign", Some(target)) if !target.trim().is_empty() => {
+            app.reassign_scrobbles(selection, target.trim()).await
+        }
+        _ => Err(anyhow::anyhow!("invalid bulk action")),
+    };
+
+    match result {
+        Ok(count) => {
+            tracing::info!(msg = "bulk scrobbles correction", scrobbles = count);
+            Redirect::to("/admin/scrobbles").into_response()
+        }
+        Err(err) => error_response(StatusCode::BAD_REQUEST, err),
+    }
+}
+
+/// Days of the selection are the ones of the time zone, the end day is included.
+fn parse_selection(form: &BulkScrobblesForm, tz: Tz) -> anyhow::Result<ScrobbleSelection> {
+    let parse_date = |date: &Option<String>| -> anyhow::Result<Option<NaiveDate>> {
+        match date.as_deref().map(str::trim) {
+            Some(date) if !date.is_empty() => Ok(Some(date.parse()?)),
+            _ => Ok(None),
+        }
+    };
+
+    Ok(ScrobbleSelection {
+        track_id: form.track_id.trim().to_string(),
+        start: parse_date(&form.start)?.map(|day| start_of_day(day, tz)),
+        end: parse_date(&form.end)?
+            .map(|day| start_of_next_day(day, tz) - Duration::milliseconds(1)),
+    })
+}
+
+/// Durations come from forms, so anything but a positive number of seconds is rejected.
+fn check_duration_secs(duration_secs: f64) -> anyhow::Result<f64> {
+    if !duration_secs.is_finite() || duration_secs < 0.0 {
+        return Err(anyhow::anyhow!(
+            "invalid duration `{}`, it must be a positive number of seconds",
+            duration_secs
+        ));
+    }
+    Ok(duration_secs)
+}
+
+// Tracks, artists and albums
+fn entity_field(kind: &str) -> anyhow::Result<RewriteField> {
+    match kind {
+        "tracks" => Ok(RewriteField::Title),
+        "artists" => Ok(RewriteField::Artist),
+        "albums" => Ok(RewriteField::Album),
+        _ => Err(anyhow::anyhow!("unknown entity `{}`", kind)),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EntitiesParams {
+    q: Option<String>,
+}
+
+async fn entities_handler(
+    Path(kind): Path<String>,
+    Query(params): Query<EntitiesParams>,
+    State(app): State<App>,
+) -> Response {
+    let field = match entity_field(&kind) {
+        Ok(field) => field,
+        Err(err) => return error_response(StatusCode::NOT_FOUND, err),
+    };
+
+    let query = params.q.unwrap_or_default().to_lowercase();
+    let entities = match app.lock().await.list_entity_names(field).await {
+        Ok(entities) => entities
+            .into_iter()
+            .filter(|e| query.is_empty() || e.name.to_lowercase().contains(&query))
+            .take(ENTITIES_LIMIT)
+            .collect(),
+        Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err.into()),
+    };
+
+    HtmlTemplate(EntitiesTemplate {
+        kind,
+        query,
+        entities,
+    })
+    .into_response()
+}
+
+struct EntityField {
+    name: &'static str,
+    value: String,
+}
+
+async fn edit_entity_handler(
+    Path((kind, id)): Path<(String, String)>,
+    State(app): State<App>,
+) -> Response {
+    let app = app.lock().await;
+    let fields = match entity_field(&kind) {
+        Ok(RewriteField::Title) => app.get_track(&id).await.map(|track| {
+            track.map(|t| {
+                vec![
+                    EntityField {
+                        name: "title",
+                        value: t.title,
+                    },
+                    EntityField {
+                        name: "duration_secs",
+                        value: t.duration_secs.as_secs_f64().to_string(),
+                    },
+                    EntityField {
+                        name: "isrc",
+                        value: t.isrc,
+                    },
+                ]
+            })
+        }),
+        Ok(RewriteField::Artist) => app.get_artist(&id).await.map(|artist| {
+            artist.map(|a| {
+                vec![EntityField {
+                    name: "name",
+                    value: a.name,
+                }]
+            })
+        }),
+        Ok(RewriteField::Album) => app.get_album(&id).await.map(|album| {
+            album.map(|a| {
+                vec![
+                    EntityField {
+                        name: "title",
+                        value: a.title,
+                    },
+                    EntityField {
+                        name: "cover",
+                        value: a.cover,
+                    },
+                ]
+            })
+        }),
+        Err(err) => return error_response(StatusCode::NOT_FOUND, err),
+    };
+
+    match fields {
+        Ok(Some(fields)) => HtmlTemplate(EditEntityTemplate { kind, id, fields }).into_response(),
+        Ok(None) => error_response(StatusCode::NOT_FOUND, anyhow::anyhow!("`{}` not found", id)),
+        Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+    }
+}
+
+async fn update_entity_handler(
+    Path((kind, id)): Path<(String, String)>,
+    State(app): State<App>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Response {
+    let value = |name: &str| {
+        form.get(name)
+            .map(|v| v.trim().to_string())
+            .unwrap_or_default()
+    };
+
+    let app = app.lock().await;
+    let result = match entity_field(&kind) {
+        Ok(RewriteField::Title) => {
+            let duration_secs = value("duration_secs")
+                .parse::<f64>()
+                .map_err(anyhow::Error::from)
+                .and_then(check_duration_secs);
+            match duration_secs {
+                Ok(duration_secs) => {
+                    app.update_track(Track {
+                        id: id.clone(),
+                        title: value("title"),
+                        duration_secs: StdDuration::from_secs_f64(duration_secs),
+                        isrc: value("isrc"),
+                    })
+                    .await
+                }
+                Err(err) => return error_response(StatusCode::BAD_REQUEST, err),
+            }
+        }
+        Ok(RewriteField::Artist) => {
+            app.update_artist(Artist {
+                id: id.clone(),
+                name: value("name"),
+            })
+            .await
+        }
+        Ok(RewriteField::Album) => {
+            app.update_album(Album {
+                id: id.clone(),
+                title: value("title"),
+                cover: value("cover"),
+            })
+            .await
+        }
+        Err(err) => return error_response(StatusCode::NOT_FOUND, err),
+    };
+
+    match result {
+        Ok(()) => Redirect::to(&format!("/admin/entities/{}", kind)).into_response(),
+        Err(err) => error_response(StatusCode::BAD_REQUEST, err),
+    }
+}
+
+async fn delete_entity_handler(
+    Path((kind, id)): Path<(String, String)>,
+    State(app): State<App>,
+) -> Response {
+    let app = app.lock().await;
+    let result = match entity_field(&kind) {
+        Ok(RewriteField::Title) => app.delete_track(&id).await,
+        Ok(RewriteField::Artist) => app.delete_artist(&id).await,
+        Ok(RewriteField::Album) => app.delete_album(&id).await,
+        Err(err) => return error_response(StatusCode::NOT_FOUND, err),
+    };
+
+    match result {
+        Ok(()) => Redirect::to(&format!("/admin/entities/{}", kind)).into_response(),
+        Err(err) => error_response(StatusCode::BAD_REQUEST, err),
+    }
+}
+
+// Duplicates
+async fn duplicates_handler(Path(kind): Path<String>, State(app): State<App>) -> Response {
+    let field = match entity_field(&kind) {
+        Ok(field) => field,
+        Err(err) => return error_response(StatusCode::NOT_FOUND, err),
+    };
+
+    let groups = match app.lock().await.list_duplicates(field).await {
+        Ok(groups) => groups,
+        Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err.into()),
+    };
+
+    HtmlTemplate(DuplicatesTemplate { kind, groups }).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct MergeForm {
+    canonical_id: String,
+    // all the IDs of the group, comma separated
+    ids: String,
+}
+
+async fn merge_handler(
+    Path(kind): Path<String>,
+    State(app): State<App>,
+    Form(form): Form<MergeForm>,
+) -> Response {
+    let field = match entity_field(&kind) {
+        Ok(field) => field,
+        Err(err) => return error_response(StatusCode::NOT_FOUND, err),
+    };
+
+    let duplicate_ids = form
+        .ids
+        .split(',')
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect();
+
+    match app
+        .lock()
+        .await
+        .merge_entities(field, &form.canonical_id, duplicate_ids)
+        .await
+    {
+        Ok(()) => Redirect::to(&format!("/admin/duplicates/{}", kind)).into_response(),
+        Err(err) => error_response(StatusCode::BAD_REQUEST, err),
+    }
+}
+
+// Audit log
+async fn audit_handler(Query(params): Query<TimeZoneParams>, State(app): State<App>) -> Response {
+    let tz = match request_time_zone(&app, params.tz.as_deref()).await {
+        Ok(tz) => tz,
+        Err(response) => return response,
+    };
+    let rows = match app.lock().await.list_audit_log(AUDIT_LOG_LIMIT).await {
+        Ok(entries) => entries
+            .into_iter()
+            .map(|entry| AuditRow {
+                at: format_local_time(entry.timestamp, tz),
+                entry,
+            })
+            .collect(),
+        Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err.into()),
+    };
+
+    HtmlTemplate(AuditTemplate { rows }).into_response()
+}
+
+// Users
+async fn users_handler(State(app): State<App>) -> Response {
+    let app = app.lock().await;
+    let all_users = match app.list_users().await {
+        Ok(users) => users,
+        Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err.into()),
+    };
+    let mut users = vec![];
+    for user in all_users {
+        users.push(UserRow {
+            linked: app.is_spotify_authenticated(user.id).await,
+            user,
+        });
+    }
+
+    HtmlTemplate(UsersTemplate { users }).into_response()
+}
+
+struct UserRow {
+    user: User,
+    linked: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct UserForm {
+    name: String,
+    password: String,
+}
+
+async fn create_user_handler(State(app): State<App>, Form(form): Form<UserForm>) -> Response {
+    // users without a password can log in only through the reverse proxy
+    let password = Some(form.password.as_str()).filter(|p| !p.is_empty());
+
+    match app.lock().await.create_user(&form.name, password).await {
+        Ok(_) => Redirect::to("/admin/users").into_response(),
+        Err(err) => error_response(StatusCode::BAD_REQUEST, err),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct UserPasswordForm {
+    password: String,
+}
+
+async fn user_password_handler(
+    Path(id): Path<i32>,
+    State(app): State<App>,
+    Form(form): Form<UserPasswordForm>,
+) -> Response {
+    match app.lock().await.set_password(id, &form.password).await {
+        Ok(()) => Redirect::to("/admin/users").into_response(),
+        Err(err) => error_response(StatusCode::BAD_REQUEST, err),
+    }
+}
+
+// Rewrite rules
+#[derive(Debug, Deserialize)]
+struct RewriteRuleForm {
+    field: String,
+    pattern: String,
+    replacement: String,
+    origin: Option<String>,
+    apply: Option<String>,
+}
+
+impl TryFrom<RewriteRuleForm> for RewriteRule {
+    type Error = anyhow::Error;
+
+    fn try_from(form: RewriteRuleForm) -> Result<Self, Self::Error> {
+        Ok(RewriteRule {
+            id: None,
+            field: form.field.parse()?,
+            pattern: form.pattern,
+            replacement: form.replacement,
+            origin: form.origin.filter(|o| !o.trim().is_empty()),
+        })
+    }
+}
+
+async fn rules_handler(State(app): State<App>) -> Response {
+    let rules = match app.lock().await.list_rewrite_rules().await {
+        Ok(rules) => rules,
+        Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err.into()),
+    };
+
+    HtmlTemplate(RulesTemplate { rules }).into_response()
+}
+
+async fn preview_rule_handler(
+    State(app): State<App>,
+    Form(form): Form<RewriteRuleForm>,
+) -> Response {
+    let rule = match RewriteRule::try_from(form) {
+        Ok(rule) => rule,
+        Err(err) => return error_response(StatusCode::BAD_REQUEST, err),
+    };
+
+    match app.lock().await.preview_rewrite_rule(rule.clone()).await {
+        Ok(previews) => HtmlTemplate(RulePreviewTemplate {
+            field: rule.field.as_str().to_string(),
+            pattern: rule.pattern,
+            replacement: rule.replacement,
+            origin: rule.origin.unwrap_or_default(),
+            previews,
+        })
+        .into_response(),
+        Err(err) => error_response(StatusCode::BAD_REQUEST, err),
+    }
+}
+
+async fn create_rule_handler(
+    State(app): State<App>,
+    Form(form): Form<RewriteRuleForm>,
+) -> Response {
+    let apply = form.apply.is_some();
+    let rule = match RewriteRule::try_from(form) {
+        Ok(rule) => rule,
+        Err(err) => return error_response(StatusCode::BAD_REQUEST, err),
+    };
+    if apply && rule.origin.is_some() {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("rules limited to an origin only apply to new scrobbles"),
+        );
+    }
+
+    let app = app.lock().await;
+    if let Err(err) = app.add_rewrite_rule(rule.clone()).await {
+        return error_response(StatusCode::BAD_REQUEST, err);
+    }
+
+    // retroactively fix the metadata already stored
+    if apply {
+        match app.apply_rewrite_rule(rule).await {
+            Ok(count) => tracing::info!(msg = "rewrite rule applied", entities = count),
+            Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+        }
+    }
+
+    Redirect::to("/admin/rules").into_response()
+}
+
+async fn delete_rule_handler(Path(id): Path<i32>, State(app): State<App>) -> Response {
+    if let Err(err) = app.lock().await.delete_rewrite_rule(id).await {
+        return error_response(StatusCode::INTERNAL_SERVER_ERROR, err);
+    }
+
+    Redirect::to("/admin/rules").into_response()
+}
+
+// Templates
+#[derive(Template)]
+#[template(path = "admin/scrobbles.html")]
+struct ScrobblesTemplate {
+    pub start: String,
+    pub end: String,
+    pub tz: String,
+    pub rows: Vec<ScrobbleRow>,
+}
+
+struct ScrobbleRow {
+    scrobble: Scrobble,
+    played_at: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin/edit_scrobble.html")]
+struct EditScrobbleTemplate {
+    pub scrobble: Scrobble,
+    pub timestamp: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin/entities.html")]
+struct EntitiesTemplate {
+    pub kind: String,
+    pub query: String,
+    pub entities: Vec<EntityName>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/edit_entity.html")]
+struct EditEntityTemplate {
+    pub kind: String,
+    pub id: String,
+    pub fields: Vec<EntityField>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/duplicates.html")]
+struct DuplicatesTemplate {
+    pub kind: String,
+    pub groups: Vec<DuplicateGroup>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/audit.html")]
+struct AuditTemplate {
+    pub rows: Vec<AuditRow>,
+}
+
+struct AuditRow {
+    entry: AuditEntry,
+    at: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin/users.html")]
+struct UsersTemplate {
+    pub users: Vec<UserRow>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/rules.html")]
+struct RulesTemplate {
+    pub rules: Vec<RewriteRule>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/rule_preview.html")]
+struct RulePreviewTemplate {
+    pub field: String,
+    pub pattern: String,
+    pub replacement: String,
+    pub origin: String,
+    pub previews: Vec<RewritePreview>,
+}
@@ -0,0 +1,256 @@
+use askama::Template;
+use axum::{
+    async_trait,
+    body::Body,
+    extract::{FromRequestParts, State},
+    http::{request::Parts, HeaderName, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form, Router,
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use serde::Deserialize;
+
+use scrobblify_domain::models::User;
+
+use crate::http_ui::{error_response, App, HtmlTemplate};
+
+const SESSION_COOKIE: &str = "scrobblify_session";
+
+/// Routes to log in and out, and to manage the account of the logged in user. Session
+/// cookies are sent over HTTPS only, unless `secure_cookies` is off.
+pub(crate) fn router(app: App, secure_cookies: bool) -> Router<App> {
+    Router::with_state(app)
+        .route(
+            "/login",
+            get(login_form_handler).post(
+                move |state: State<App>, jar: CookieJar, form: Form<LoginForm>| {
+                    login_handler(state, jar, form, secure_cookies)
+                },
+            ),
+        )
+        .route("/logout", post(logout_handler))
+        .route("/account", get(account_handler))
+        .route("/account/password", post(password_handler))
+        .route("/account/visibility", post(visibility_handler))
+        .route("/account/spotify/unlink", post(unlink_spotify_handler))
+}
+
+/// Who is visiting, if they've logged in. It's loaded for every request by `load_user`.
+#[derive(Clone)]
+pub(crate) struct CurrentUser(pub Option<User>);
+
+/// Extracts the logged in user, sending everyone else to the login page.
+pub(crate) struct LoggedUser(pub User);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for LoggedUser
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match parts.extensions.get::<CurrentUser>() {
+            Some(CurrentUser(Some(user))) => Ok(LoggedUser(user.clone())),
+            _ => Err(Redirect::to("/login").into_response()),
+        }
+    }
+}
+
+/// Finds the user of the request from the session cookie or, when a header is configured,
+/// from the name set by an authenticating reverse proxy. The proxy must strip that header
+/// from incoming requests, otherwise anyone could send it.
+pub(crate) async fn load_user(
+    mut req: Request<Body>,
+    next: Next<Body>,
+    app: App,
+    auth_header: Option<HeaderName>,
+) -> Response {
+    let proxy_user = auth_header
+        .and_then(|header| req.headers().get(header).cloned())
+        .and_then(|value| value.to_str().map(|v| v.trim().to_string()).ok());
+    let session_token = CookieJar::from_headers(req.headers())
+        .get(SESSION_COOKIE)
+        .map(|cookie| cookie.value().to_string());
+
+    let user = match (proxy_user, session_token) {
+        (Some(name), _) => app.lock().await.get_user(&name).await,
+        (None, Some(token)) => app.lock().await.get_session_user(&token).await,
+        (None, None) => Ok(None),
+    };
+
+    match user {
+        Ok(user) => {
+            req.extensions_mut().insert(CurrentUser(user));
+            next.run(req).await
+        }
+        Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+    }
+}
+
+pub(crate) async fn require_admin(req: Request<Body>, next: Next<Body>) -> Response {
+    let user = req
+        .extensions()
+        .get::<CurrentUser>()
+        .and_then(|current| current.0.clone());
+
+    match user {
+        Some(user) if user.is_admin => next.run(req).await,
+        Some(_) => error_response(
+            StatusCode::FORBIDDEN,
+            anyhow::anyhow!("only admins can access this page"),
+        ),
+        None => Redirect::to("/login").into_response(),
+    }
+}
+
+// Handlers
+async fn login_form_handler() -> impl IntoResponse {
+    HtmlTemplate(LoginTemplate {
+        error: String::new(),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct LoginForm {
+    name: String,
+    password: String,
+}
+
+async fn login_handler(
+    State(app): State<App>,
+    jar: CookieJar,
+    Form(form): Form<LoginForm>,
+    secure_cookies: bool,
+) -> Response {
+    let token = match app
+        .lock()
+        .await
+        .login(form.name.trim(), &form.password)
+        .await
+    {
+        Ok(Some(token)) => token,
+        Ok(None) => {
+            return (
+                StatusCode::UNAUTHORIZED,
+                HtmlTemplate(LoginTemplate {
+                    error: "wrong name or password".to_string(),
+                }),
+            )
+                .into_response()
+        }
+        Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+    };
+
+    let cookie = Cookie::build(SESSION_COOKIE, token)
+        .path("/")
+        .http_only(true)
+        .secure(secure_cookies)
+        .same_site(SameSite::Lax)
+        .permanent()
+        .finish();
+
+    (jar.add(cookie), Redirect::to("/")).into_response()
+}
+
+async fn logout_handler(State(app): State<App>, jar: CookieJar) -> Response {
+    if let Some(cookie) = jar.get(SESSION_COOKIE) {
+        if let Err(err) = app.lock().await.logout(cookie.value()).await {
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, err);
+        }
+    }
+
+    let cookie = Cookie::build(SESSION_COOKIE, "").path("/").finish();
+    (jar.remove(cookie), Redirect::to("/")).into_response()
+}
+
+async fn account_handler(LoggedUser(user): LoggedUser, State(app): State<App>) -> Response {
+    let spotify_linked = app.lock().await.is_spotify_authenticated(user.id).await;
+    let auth_url = match app.lock().await.get_spotify_auth_url(user.id).await {
+        Ok(auth_url) => auth_url,
+        Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+    };
+
+    HtmlTemplate(AccountTemplate {
+        user,
+        spotify_linked,
+        auth_url,
+    })
+    .into_response()
+}
+
+async fn unlink_spotify_handler(LoggedUser(user): LoggedUser, State(app): State<App>) -> Response {
+    match app.lock().await.unlink_spotify(user.id).await {
+        Ok(()) => Redirect::to("/account").into_response(),
+        Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PasswordForm {
+    current_password: String,
+    password: String,
+}
+
+/// Other sessions of the user are logged out, in case someone else got one.
+async fn password_handler(
+    LoggedUser(user): LoggedUser,
+    State(app): State<App>,
+    jar: CookieJar,
+    Form(form): Form<PasswordForm>,
+) -> Response {
+    let session_token = jar.get(SESSION_COOKIE).map(|cookie| cookie.value());
+    match app
+        .lock()
+        .await
+        .change_password(
+            user.id,
+            &form.current_password,
+            &form.password,
+            session_token,
+        )
+        .await
+    {
+        Ok(()) => Redirect::to("/account").into_response(),
+        Err(err) => error_response(StatusCode::BAD_REQUEST, err),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct VisibilityForm {
+    public_stats: Option<String>,
+}
+
+async fn visibility_handler(
+    LoggedUser(user): LoggedUser,
+    State(app): State<App>,
+    Form(form): Form<VisibilityForm>,
+) -> Response {
+    let public_stats = form.public_stats.is_some();
+    match app
+        .lock()
+        .await
+        .set_public_stats(user.id, public_stats)
+        .await
+    {
+        Ok(()) => Redirect::to("/account").into_response(),
+        Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+    }
+}
+
+// Templates
+#[derive(Template)]
+#[template(path = "login.html")]
+struct LoginTemplate {
+    pub error: String,
+}
+
+#[derive(Template)]
+#[template(path = "account.html")]
+struct AccountTemplate {
+    pub user: User,
+    pub spotify_linked: bool,
+    pub auth_url: String,
+}
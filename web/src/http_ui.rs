@@ -1,15 +1,17 @@
 use askama::Template;
 use axum::{
-    extract::{Query, State},
-    http::{header, HeaderValue, StatusCode},
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderName, HeaderValue, Request, StatusCode},
+    middleware::{self, Next},
     response::{Html, IntoResponse, Redirect, Response},
     routing::get,
-    Router,
+    Extension, Router,
 };
 use axum_extra::routing::SpaRouter;
-use chrono::NaiveDate;
+use chrono::{Datelike, Duration, NaiveDate};
 use serde::Deserialize;
-use std::{env, net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, sync::Arc};
 use tokio::sync::Mutex;
 use tower_http::{
     set_header::SetResponseHeaderLayer,
@@ -17,24 +19,68 @@ use tower_http::{
     LatencyUnit,
 };
 
+use crate::{
+    admin,
+    auth::{self, CurrentUser, LoggedUser},
+    utils::{format_local_day, format_local_time},
+};
+
 use scrobblify_domain::{
     app::App as DomainApp,
+    config::HttpConfig,
     db::ParamsForStatsQuery,
-    models::{StatsArtist, StatsTag, StatsTrack},
+    models::{
+        Activity, ActivityBucket, ChartKind, Comparison, Discovery, EntityPlayTime,
+        ListeningSession, Scrobble, ScrobbleMilestone, SessionStartHour, StatsArtist, StatsShow,
+        StatsTag, StatsTrack, Streaks, User, WeeklyChart, Wrapped,
+    },
+    time::{parse_time_zone, Tz},
 };
 
-type App = Arc<Mutex<dyn DomainApp>>;
+pub(crate) type App = Arc<Mutex<dyn DomainApp>>;
+
+/// Days the charts of a user's dashboard cover, today included.
+const DASHBOARD_DAYS: i64 = 30;
 
 // HTTP interaface to the app
 pub struct HttpUi {
     router: Router<App>,
+    addr: SocketAddr,
 }
 
 impl HttpUi {
-    pub fn new(app: Arc<Mutex<dyn DomainApp>>) -> Self {
+    pub fn new(app: Arc<Mutex<dyn DomainApp>>, config: &HttpConfig) -> anyhow::Result<Self> {
+        let addr = config.address().map_err(|err| anyhow::anyhow!(err))?;
+        // name of the header with the user authenticated by a reverse proxy, if any
+        let auth_header = config
+            .auth_header
+            .as_ref()
+            .map(|h| HeaderName::from_bytes(h.as_bytes()))
+            .transpose()?;
+        let users_app = app.clone();
+
         let router = Router::with_state(app.clone())
             .route("/auth/callback", get(auth_callback_handler))
             .route("/", get(index_handler))
+            .route("/users/:name", get(user_handler))
+            .route("/users/:name/charts", get(charts_handler))
+            .route("/users/:name/wrapped/:year", get(wrapped_handler))
+            .route("/users/:name/activity", get(activity_handler))
+            .route("/users/:name/discovery", get(discovery_handler))
+            .route("/users/:name/compare", get(compare_handler))
+            .route("/users/:name/sessions", get(sessions_handler))
+            .merge(auth::router(app.clone(), config.secure_cookies))
+            .merge(admin::router(app.clone()))
+            .layer(middleware::from_fn(
+                move |req: Request<Body>, next: Next<Body>| {
+                    auth::load_user(req, next, users_app.clone(), auth_header.clone())
+                },
+            ))
+            // pages depend on who's visiting, only assets can be cached
+            .layer(SetResponseHeaderLayer::if_not_present(
+                header::CACHE_CONTROL,
+                HeaderValue::from_static("no-store"),
+            ))
             .merge(SpaRouter::new("/assets", "web/assets"))
             .layer(SetResponseHeaderLayer::if_not_present(
                 header::SERVER,
@@ -49,85 +95,571 @@ impl HttpUi {
                     .on_response(DefaultOnResponse::new().latency_unit(LatencyUnit::Micros)),
             );
 
-        HttpUi { router }
+        Ok(HttpUi { router, addr })
     }
 
-    pub async fn serve_from_env(&self) {
-        let host = env::var("SCRUBBLIFY_HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
-        let port = env::var("PORT").unwrap_or_else(|_| "8000".to_string());
+    pub async fn serve(&self) -> anyhow::Result<()> {
+        tracing::info!(msg = "server started", address = self.addr.to_string());
 
-        self.serve(host, port).await;
-    }
+        axum::Server::try_bind(&self.addr)?
+            .serve(self.router.clone().into_make_service())
+            .await?;
 
-    pub async fn serve(&self, host: String, port: String) {
-        let addr = format!("{}:{}", host, port)
-            .parse::<SocketAddr>()
-            .expect(format!("unable to parse socket address with `{}:{}`", host, port).as_str());
+        Ok(())
+    }
+}
 
-        tracing::info!(msg = "server started", address = addr.to_string());
+// Handlers
+async fn index_handler(
+    Extension(CurrentUser(visitor)): Extension<CurrentUser>,
+    State(app): State<App>,
+) -> Response {
+    let users: Vec<User> = match app.lock().await.list_users().await {
+        Ok(users) => users
+            .into_iter()
+            .filter(|user| user.stats_visible_to(visitor.as_ref()))
+            .collect(),
+        Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err.into()),
+    };
 
-        axum::Server::bind(&addr)
-            .serve(self.router.clone().into_make_service())
-            .await
-            .unwrap();
+    match (users.as_slice(), visitor) {
+        ([], None) => Redirect::to("/login").into_response(),
+        // a single user instance goes straight to the stats
+        ([user], _) => Redirect::to(&format!("/users/{}", user.name)).into_response(),
+        _ => HtmlTemplate(UsersTemplate { users }).into_response(),
     }
 }
 
-// Handlers
-async fn index_handler(State(app): State<App>) -> Response {
-    if !app.lock().await.is_spotify_authenticated() {
-        let auth_url = app.lock().await.get_spotify_auth_url().await.unwrap();
+async fn user_handler(
+    Path(name): Path<String>,
+    Query(params): Query<TimeZoneParams>,
+    Extension(CurrentUser(visitor)): Extension<CurrentUser>,
+    State(app): State<App>,
+) -> Response {
+    let user = match visible_user(&app, &name, visitor.as_ref()).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    // only the user can link their own Spotify account
+    let is_owner = visitor.is_some_and(|visitor| visitor.id == user.id);
+    if is_owner && !app.lock().await.is_spotify_authenticated(user.id).await {
+        let auth_url = match app.lock().await.get_spotify_auth_url(user.id).await {
+            Ok(auth_url) => auth_url,
+            Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+        };
 
         // OAuth2 step 1: send user to Spotify auth page
-        return HtmlTemplate(AuthorizeTemplate { auth_url }).into_response();
+        return HtmlTemplate(AuthorizeTemplate { user, auth_url }).into_response();
     }
 
-    let opts = ParamsForStatsQuery {
-        start: NaiveDate::from_ymd(2022, 11, 1),
-        end: NaiveDate::from_ymd(2022, 11, 18),
-        limit: None,
+    let tz = match request_time_zone(&app, params.tz.as_deref()).await {
+        Ok(tz) => tz,
+        Err(response) => return response,
     };
+    let today = app.lock().await.today(tz);
+    let opts = ParamsForStatsQuery::new(today - Duration::days(DASHBOARD_DAYS - 1), today, None)
+        .for_user(user.id)
+        .in_time_zone(tz);
 
-    let top_tracks = app
+    let top_tracks = match app
         .lock()
         .await
         .stats_for_popular_tracks(opts.clone())
-        .await;
-    let top_artists = app
+        .await
+    {
+        Ok(top_tracks) => top_tracks,
+        Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err.into()),
+    };
+    let top_artists = match app
         .lock()
         .await
         .stats_for_popular_artists(opts.clone())
-        .await;
-    let top_tags = app.lock().await.stats_for_popular_tags(opts.clone()).await;
+        .await
+    {
+        Ok(top_artists) => top_artists,
+        Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err.into()),
+    };
+    let top_tags = match app.lock().await.stats_for_popular_tags(opts.clone()).await {
+        Ok(top_tags) => top_tags,
+        Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err.into()),
+    };
+    let top_shows = match app.lock().await.stats_for_popular_shows(opts.clone()).await {
+        Ok(top_shows) => top_shows,
+        Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err.into()),
+    };
+
+    let streaks = match app.lock().await.get_streaks(user.id, tz).await {
+        Ok(streaks) => streaks,
+        Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+    };
+    let milestones = match app.lock().await.get_milestones(user.id).await {
+        Ok(milestones) => milestones,
+        Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+    };
+    let on_this_day = match app.lock().await.get_on_this_day(user.id, tz).await {
+        Ok(on_this_day) => on_this_day,
+        Err(err) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, err),
+    };
 
     HtmlTemplate(HomeTemplate {
+        user,
+        year: today.year(),
         top_tracks,
         top_artists,
         top_tags,
+        top_shows,
+        streaks,
+        total_scrobbles: milestones.total_scrobbles,
+        next_milestone: milestones.next,
+        milestones: milestones
+            .reached
+            .into_iter()
+            .map(|milestone| MilestoneRow {
+                reached_on: format_local_day(milestone.scrobble.timestamp, tz),
+                milestone,
+            })
+            .collect(),
+        first_plays: milestones
+            .first_plays
+            .into_iter()
+            .map(|play| FirstPlayRow {
+                played_on: format_local_day(play.timestamp, tz),
+                play,
+            })
+            .collect(),
+        on_this_day: on_this_day
+            .into_iter()
+            .map(|day| OnThisDayRow {
+                year: day.year,
+                scrobbles: day
+                    .scrobbles
+                    .into_iter()
+                    .map(|scrobble| {
+                        let played_at = scrobble.timestamp.with_timezone(&tz).format("%H:%M");
+                        (played_at.to_string(), scrobble)
+                    })
+                    .collect(),
+            })
+            .collect(),
+    })
+    .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct ChartsParams {
+    kind: Option<String>,
+    week: Option<NaiveDate>,
+}
+
+async fn charts_handler(
+    Path(name): Path<String>,
+    Query(params): Query<ChartsParams>,
+    Extension(CurrentUser(visitor)): Extension<CurrentUser>,
+    State(app): State<App>,
+) -> Response {
+    let user = match visible_user(&app, &name, visitor.as_ref()).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let kind = match params
+        .kind
+        .as_deref()
+        .unwrap_or("tracks")
+        .parse::<ChartKind>()
+    {
+        Ok(kind) => kind,
+        Err(err) => return error_response(StatusCode::BAD_REQUEST, err),
+    };
+    // charts follow the configured time zone, their snapshots are shared
+    let week = match params.week {
+        Some(week) => week,
+        None => {
+            let app = app.lock().await;
+            app.today(app.time_zone())
+        }
+    };
+
+    let chart = match app.lock().await.get_weekly_chart(user.id, kind, week).await {
+        Ok(chart) => chart,
+        Err(err) => return error_response(StatusCode::BAD_REQUEST, err),
+    };
+
+    HtmlTemplate(ChartsTemplate {
+        previous_week: chart.week_start - Duration::weeks(1),
+        next_week: Some(chart.week_start + Duration::weeks(1)).filter(|_| chart.complete),
+        kinds: vec![ChartKind::Tracks, ChartKind::Artists, ChartKind::Albums],
+        user,
+        chart,
+    })
+    .into_response()
+}
+
+async fn wrapped_handler(
+    Path((name, year)): Path<(String, i32)>,
+    Query(params): Query<TimeZoneParams>,
+    Extension(CurrentUser(visitor)): Extension<CurrentUser>,
+    State(app): State<App>,
+) -> Response {
+    let user = match visible_user(&app, &name, visitor.as_ref()).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let tz = match request_time_zone(&app, params.tz.as_deref()).await {
+        Ok(tz) => tz,
+        Err(response) => return response,
+    };
+
+    match app.lock().await.get_wrapped(user.id, year, tz).await {
+        Ok(wrapped) => HtmlTemplate(WrappedTemplate { user, wrapped }).into_response(),
+        Err(err) => error_response(StatusCode::BAD_REQUEST, err),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ActivityParams {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    bucket: Option<String>,
+    tz: Option<String>,
+}
+
+async fn activity_handler(
+    Path(name): Path<String>,
+    Query(params): Query<ActivityParams>,
+    Extension(CurrentUser(visitor)): Extension<CurrentUser>,
+    State(app): State<App>,
+) -> Response {
+    let user = match visible_user(&app, &name, visitor.as_ref()).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let bucket = match params
+        .bucket
+        .as_deref()
+        .unwrap_or("day")
+        .parse::<ActivityBucket>()
+    {
+        Ok(bucket) => bucket,
+        Err(err) => return error_response(StatusCode::BAD_REQUEST, err),
+    };
+    let tz = match request_time_zone(&app, params.tz.as_deref()).await {
+        Ok(tz) => tz,
+        Err(response) => return response,
+    };
+    let to = match params.to {
+        Some(to) => to,
+        None => app.lock().await.today(tz),
+    };
+    let from = params.from.unwrap_or(to - Duration::days(364));
+
+    let activity = match app
+        .lock()
+        .await
+        .get_activity(user.id, from, to, bucket, tz)
+        .await
+    {
+        Ok(activity) => activity,
+        Err(err) => return error_response(StatusCode::BAD_REQUEST, err),
+    };
+
+    HtmlTemplate(ActivityTemplate {
+        max_scrobbles: activity
+            .series
+            .iter()
+            .map(|point| point.scrobbles)
+            .max()
+            .unwrap_or(0)
+            .max(1),
+        label_format: match bucket {
+            ActivityBucket::Hour => "%a %b %e, %H:00",
+            ActivityBucket::Day => "%a %b %e, %Y",
+            ActivityBucket::Month => "%B %Y",
+        },
+        tz: tz.name(),
+        user,
+        activity,
+    })
+    .into_response()
+}
+
+async fn discovery_handler(
+    Path(name): Path<String>,
+    Query(params): Query<ActivityParams>,
+    Extension(CurrentUser(visitor)): Extension<CurrentUser>,
+    State(app): State<App>,
+) -> Response {
+    let user = match visible_user(&app, &name, visitor.as_ref()).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let bucket = match params
+        .bucket
+        .as_deref()
+        .unwrap_or("month")
+        .parse::<ActivityBucket>()
+    {
+        Ok(bucket) => bucket,
+        Err(err) => return error_response(StatusCode::BAD_REQUEST, err),
+    };
+    let tz = match request_time_zone(&app, params.tz.as_deref()).await {
+        Ok(tz) => tz,
+        Err(response) => return response,
+    };
+    let to = match params.to {
+        Some(to) => to,
+        None => app.lock().await.today(tz),
+    };
+    let from = params.from.unwrap_or(to - Duration::days(364));
+
+    let discovery = match app
+        .lock()
+        .await
+        .get_discovery(user.id, from, to, bucket, tz)
+        .await
+    {
+        Ok(discovery) => discovery,
+        Err(err) => return error_response(StatusCode::BAD_REQUEST, err),
+    };
+
+    HtmlTemplate(DiscoveryTemplate {
+        label_format: match bucket {
+            ActivityBucket::Month => "%B %Y",
+            _ => "%a %b %e, %Y",
+        },
+        tz: tz.name(),
+        user,
+        discovery,
     })
     .into_response()
 }
 
 #[derive(Debug, Deserialize)]
-#[allow(dead_code)]
+struct CompareParams {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    previous_from: Option<NaiveDate>,
+    previous_to: Option<NaiveDate>,
+    top: Option<u64>,
+    tz: Option<String>,
+}
+
+async fn compare_handler(
+    Path(name): Path<String>,
+    Query(params): Query<CompareParams>,
+    Extension(CurrentUser(visitor)): Extension<CurrentUser>,
+    State(app): State<App>,
+) -> Response {
+    let user = match visible_user(&app, &name, visitor.as_ref()).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let tz = match request_time_zone(&app, params.tz.as_deref()).await {
+        Ok(tz) => tz,
+        Err(response) => return response,
+    };
+
+    // this month so far against the whole previous month, unless picked otherwise
+    let to = match params.to {
+        Some(to) => to,
+        None => app.lock().await.today(tz),
+    };
+    let (from, previous_from, previous_to) = match params.from {
+        Some(from) => {
+            // a range as long as the current one, right before it
+            let previous_to = params.previous_to.unwrap_or(from - Duration::days(1));
+            let previous_from = params.previous_from.unwrap_or(previous_to - (to - from));
+            (from, previous_from, previous_to)
+        }
+        None => {
+            let from = to.with_day(1).unwrap_or(to);
+            let previous_to = params.previous_to.unwrap_or(from - Duration::days(1));
+            let previous_from = params
+                .previous_from
+                .unwrap_or_else(|| previous_to.with_day(1).unwrap_or(previous_to));
+            (from, previous_from, previous_to)
+        }
+    };
+    let opts = |start: NaiveDate, end: NaiveDate| {
+        ParamsForStatsQuery::new(start, end, params.top)
+            .for_user(user.id)
+            .in_time_zone(tz)
+    };
+
+    match app
+        .lock()
+        .await
+        .compare_periods(opts(previous_from, previous_to), opts(from, to))
+        .await
+    {
+        Ok(comparison) => HtmlTemplate(CompareTemplate {
+            tz: tz.name(),
+            user,
+            comparison,
+        })
+        .into_response(),
+        Err(err) => error_response(StatusCode::BAD_REQUEST, err),
+    }
+}
+
+async fn sessions_handler(
+    Path(name): Path<String>,
+    Query(params): Query<ActivityParams>,
+    Extension(CurrentUser(visitor)): Extension<CurrentUser>,
+    State(app): State<App>,
+) -> Response {
+    let user = match visible_user(&app, &name, visitor.as_ref()).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let tz = match request_time_zone(&app, params.tz.as_deref()).await {
+        Ok(tz) => tz,
+        Err(response) => return response,
+    };
+    let to = match params.to {
+        Some(to) => to,
+        None => app.lock().await.today(tz),
+    };
+    let from = params.from.unwrap_or(to - Duration::days(29));
+
+    let sessions = match app.lock().await.get_sessions(user.id, from, to, tz).await {
+        Ok(sessions) => sessions,
+        Err(err) => return error_response(StatusCode::BAD_REQUEST, err),
+    };
+
+    let row = |session: &ListeningSession| SessionRow {
+        started_at: format_local_time(session.start, tz),
+        minutes: session.minutes(),
+        scrobbles: session
+            .scrobbles
+            .iter()
+            .map(|scrobble| {
+                let played_at = scrobble.timestamp.with_timezone(&tz).format("%H:%M");
+                (played_at.to_string(), scrobble.clone())
+            })
+            .collect(),
+    };
+
+    HtmlTemplate(SessionsTemplate {
+        start: sessions.start,
+        end: sessions.end,
+        average_minutes: sessions.average_minutes,
+        longest: sessions.longest.as_ref().map(row),
+        rows: sessions.sessions.iter().map(row).collect(),
+        start_hours: sessions.start_hours,
+        typical_start_hour: sessions.typical_start_hour,
+        tz: tz.name(),
+        user,
+    })
+    .into_response()
+}
+
+/// Renders a year in review as a standalone HTML page, to share it without the server.
+pub fn render_wrapped(user: User, wrapped: Wrapped) -> anyhow::Result<String> {
+    Ok(WrappedExportTemplate { user, wrapped }.render()?)
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct TimeZoneParams {
+    pub tz: Option<String>,
+}
+
+/// The time zone picked by the request with `?tz=`, or the configured one.
+pub(crate) async fn request_time_zone(app: &App, tz: Option<&str>) -> Result<Tz, Response> {
+    match tz.map(str::trim).filter(|tz| !tz.is_empty()) {
+        Some(tz) => parse_time_zone(tz)
+            .map_err(|err| error_response(StatusCode::BAD_REQUEST, anyhow::anyhow!(err))),
+        None => Ok(app.lock().await.time_zone()),
+    }
+}
+
+/// Finds a user whose stats the visitor is allowed to see, or the response to send instead.
+async fn visible_user(app: &App, name: &str, visitor: Option<&User>) -> Result<User, Response> {
+    match app.lock().await.get_user(name).await {
+        Ok(Some(user)) if user.stats_visible_to(visitor) => Ok(user),
+        // private stats look like missing ones, so that nobody can guess user names
+        Ok(_) if visitor.is_none() => Err(Redirect::to("/login").into_response()),
+        Ok(_) => Err(error_response(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("user `{}` not found", name),
+        )),
+        Err(err) => Err(error_response(StatusCode::INTERNAL_SERVER_ERROR, err)),
+    }
+}
+
+#[derive(Debug, Deserialize)]
 struct AuthCallbackParams {
     code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
 }
 
 async fn auth_callback_handler(
+    LoggedUser(user): LoggedUser,
     Query(params): Query<AuthCallbackParams>,
     State(app): State<App>,
-) -> impl IntoResponse {
-    // OAuth2 step 2: user is redirected to callback with a `code`
-    let code = params.code.unwrap();
+) -> Response {
+    // OAuth2 step 2: user is redirected to callback with a `code` and the `state` of its
+    // client, or with an `error` when the authorization didn't go through
+    let (code, state) = match params {
+        AuthCallbackParams {
+            error: Some(error), ..
+        } if error == "access_denied" => {
+            return error_response(
+                StatusCode::FORBIDDEN,
+                anyhow::anyhow!(
+                    "Spotify authorization was denied, your account hasn't been linked"
+                ),
+            )
+        }
+        AuthCallbackParams {
+            error: Some(error), ..
+        } => {
+            return error_response(
+                StatusCode::BAD_GATEWAY,
+                anyhow::anyhow!("Spotify authorization failed: {}", error),
+            )
+        }
+        AuthCallbackParams {
+            code: Some(code),
+            state: Some(state),
+            ..
+        } => (code, state),
+        _ => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                anyhow::anyhow!("missing authorization code or state"),
+            )
+        }
+    };
+
     // OAuth2 step 3: fetch the token/refresh for API requests
-    let _ = app.lock().await.store_spotify_auth_token(&code).await;
+    match app
+        .lock()
+        .await
+        .store_spotify_auth_token(user.id, &state, &code)
+        .await
+    {
+        Ok(()) => Redirect::to(&format!("/users/{}", user.name)).into_response(),
+        Err(err) => error_response(StatusCode::BAD_REQUEST, err),
+    }
+}
 
-    Redirect::to("/").into_response()
+pub(crate) fn error_response(status: StatusCode, err: anyhow::Error) -> Response {
+    (
+        status,
+        HtmlTemplate(ErrorTemplate {
+            error: err.to_string(),
+        }),
+    )
+        .into_response()
 }
 
 // Templates
-struct HtmlTemplate<T>(T);
+pub(crate) struct HtmlTemplate<T>(pub T);
 
 impl<T> IntoResponse for HtmlTemplate<T>
 where
@@ -150,15 +682,119 @@ where
 #[derive(Template)]
 #[template(path = "authorize.html")]
 struct AuthorizeTemplate {
+    user: User,
     auth_url: String,
 }
 
+#[derive(Template)]
+#[template(path = "users.html")]
+struct UsersTemplate {
+    users: Vec<User>,
+}
+
 #[derive(Template)]
 #[template(path = "index.html")]
 struct HomeTemplate {
+    pub user: User,
+    pub year: i32,
     pub top_tracks: Vec<StatsTrack>,
     pub top_tags: Vec<StatsTag>,
     pub top_artists: Vec<StatsArtist>,
+    pub top_shows: Vec<StatsShow>,
+    pub streaks: Streaks,
+    pub total_scrobbles: u64,
+    pub next_milestone: Option<u64>,
+    pub milestones: Vec<MilestoneRow>,
+    pub first_plays: Vec<FirstPlayRow>,
+    pub on_this_day: Vec<OnThisDayRow>,
+}
+
+struct MilestoneRow {
+    milestone: ScrobbleMilestone,
+    reached_on: String,
+}
+
+struct FirstPlayRow {
+    play: EntityPlayTime,
+    played_on: String,
+}
+
+/// The scrobbles of a past year, with the local time they were played at.
+struct OnThisDayRow {
+    year: i32,
+    scrobbles: Vec<(String, Scrobble)>,
+}
+
+#[derive(Template)]
+#[template(path = "charts.html")]
+struct ChartsTemplate {
+    user: User,
+    chart: WeeklyChart,
+    kinds: Vec<ChartKind>,
+    previous_week: NaiveDate,
+    next_week: Option<NaiveDate>,
+}
+
+#[derive(Template)]
+#[template(path = "wrapped.html")]
+struct WrappedTemplate {
+    user: User,
+    wrapped: Wrapped,
+}
+
+#[derive(Template)]
+#[template(path = "wrapped_export.html")]
+struct WrappedExportTemplate {
+    user: User,
+    wrapped: Wrapped,
+}
+
+#[derive(Template)]
+#[template(path = "activity.html")]
+struct ActivityTemplate {
+    user: User,
+    activity: Activity,
+    max_scrobbles: u32,
+    label_format: &'static str,
+    tz: &'static str,
+}
+
+#[derive(Template)]
+#[template(path = "discovery.html")]
+struct DiscoveryTemplate {
+    user: User,
+    discovery: Discovery,
+    label_format: &'static str,
+    tz: &'static str,
+}
+
+#[derive(Template)]
+#[template(path = "compare.html")]
+struct CompareTemplate {
+    user: User,
+    comparison: Comparison,
+    tz: &'static str,
+}
+
+#[derive(Template)]
+#[template(path = "sessions.html")]
+struct SessionsTemplate {
+    user: User,
+    start: NaiveDate,
+    end: NaiveDate,
+    rows: Vec<SessionRow>,
+    average_minutes: i64,
+    longest: Option<SessionRow>,
+    start_hours: Vec<SessionStartHour>,
+    typical_start_hour: Option<u32>,
+    tz: &'static str,
+}
+
+/// A session with the local times it and its scrobbles started at.
+struct SessionRow {
+    started_at: String,
+    minutes: i64,
+    scrobbles: Vec<(String, Scrobble)>,
 }
 
 #[derive(Template)]
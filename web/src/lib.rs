@@ -1,4 +1,6 @@
+mod admin;
+mod auth;
 mod http_ui;
 mod utils;
 
-pub use http_ui::HttpUi;
+pub use http_ui::{render_wrapped, HttpUi};
@@ -1,5 +1,8 @@
+use chrono::{DateTime, Utc};
 use std::time::Duration;
 
+use scrobblify_domain::time::Tz;
+
 pub fn secs_to_hours_and_minutes(duration: Duration) -> (u64, u64) {
     let duration = duration.as_secs();
 
@@ -12,3 +15,16 @@ pub fn secs_to_hours_and_minutes(duration: Duration) -> (u64, u64) {
 
     (hours, minutes)
 }
+
+/// Formats a timestamp for display, in the time zone of the request.
+pub fn format_local_time(timestamp: DateTime<Utc>, tz: Tz) -> String {
+    timestamp
+        .with_timezone(&tz)
+        .format("%Y-%m-%d %H:%M:%S %Z")
+        .to_string()
+}
+
+/// Formats the local day of a timestamp for display, in the time zone of the request.
+pub fn format_local_day(timestamp: DateTime<Utc>, tz: Tz) -> String {
+    timestamp.with_timezone(&tz).format("%b %e, %Y").to_string()
+}